@@ -0,0 +1,913 @@
+//! The devices an [`AudioMixer`](crate::AudioMixer) can run on: the host's
+//! sound cards, listed by [`output_devices`] and [`input_devices`], or stand-ins
+//! for running without one. A null device mixes into nothing (or captures
+//! silence), and WAV files stand in for a speaker or a microphone. They run the
+//! very callbacks a real device would, driven by a [`Clock`] instead of the
+//! hardware, so whatever sits on top — effects, resampling, a voice connection
+//! — is exercised the same way.
+
+use std::{
+    error::Error,
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use cpal::{
+    ChannelCount, DevicesError, SampleFormat, SampleRate, SupportedStreamConfigRange,
+    SupportedStreamConfigsError,
+    traits::{DeviceTrait as _, HostTrait as _},
+};
+use tracing::error;
+
+use crate::{
+    AudioSampleType, MIX_SCRATCH_LEN, StreamFormat,
+    input::InputRxEvent,
+    output::OutputRxEvent,
+    stream::{Device, Master, open_master},
+};
+
+/// How much audio a real-time clock hands over per callback, about what a
+/// sound card's period is.
+const REALTIME_PERIOD: Duration = Duration::from_millis(10);
+
+/// Where an [`AudioMixer`](crate::AudioMixer)'s output goes.
+pub enum OutputBackend {
+    /// The host's default output device.
+    System,
+    /// The host output device with this name, as listed by [`output_devices`].
+    Named(String),
+    /// Mixes on `clock` and throws the result away.
+    Null {
+        channels: ChannelCount,
+        sample_rate: SampleRate,
+        clock: Clock,
+    },
+    /// Mixes on `clock` into a 32-bit float WAV file at `path`. The file is
+    /// replaced each time the output stream starts.
+    WavFile {
+        path: PathBuf,
+        channels: ChannelCount,
+        sample_rate: SampleRate,
+        clock: Clock,
+    },
+}
+
+/// Where an [`AudioMixer`](crate::AudioMixer)'s input comes from.
+pub enum InputBackend {
+    /// The host's default input device.
+    System,
+    /// The host input device with this name, as listed by [`input_devices`].
+    Named(String),
+    /// Captures silence on `clock`.
+    Null {
+        channels: ChannelCount,
+        sample_rate: SampleRate,
+        clock: Clock,
+    },
+    /// Captures the WAV file at `path`, in its own format, on `clock`. Each
+    /// start of the input stream plays it from the top, once; after the end
+    /// nothing more is captured.
+    WavFile { path: PathBuf, clock: Clock },
+}
+
+/// A host sound card, as offered to [`OutputBackend::Named`] and
+/// [`InputBackend::Named`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeviceInfo {
+    pub name: String,
+    /// Whether this is the host's default device for its direction.
+    pub is_default: bool,
+    /// The formats the device can stream in; the mixer uses its default one.
+    pub configs: Vec<SupportedConfig>,
+}
+
+/// A range of sample rates a device supports at one channel count and sample
+/// format.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SupportedConfig {
+    pub channels: ChannelCount,
+    pub sample_format: SampleFormat,
+    pub min_sample_rate: SampleRate,
+    pub max_sample_rate: SampleRate,
+}
+
+impl From<SupportedStreamConfigRange> for SupportedConfig {
+    fn from(range: SupportedStreamConfigRange) -> Self {
+        SupportedConfig {
+            channels: range.channels(),
+            sample_format: range.sample_format(),
+            min_sample_rate: range.min_sample_rate(),
+            max_sample_rate: range.max_sample_rate(),
+        }
+    }
+}
+
+/// The host's playback devices.
+pub fn output_devices() -> Result<Vec<DeviceInfo>, Box<dyn Error>> {
+    let host = cpal::default_host();
+    Ok(describe_devices(
+        host.output_devices()?,
+        host.default_output_device(),
+        |device| device.supported_output_configs(),
+    ))
+}
+
+/// The host's capture devices.
+pub fn input_devices() -> Result<Vec<DeviceInfo>, Box<dyn Error>> {
+    let host = cpal::default_host();
+    Ok(describe_devices(
+        host.input_devices()?,
+        host.default_input_device(),
+        |device| device.supported_input_configs(),
+    ))
+}
+
+fn describe_devices<C: Iterator<Item = SupportedStreamConfigRange>>(
+    devices: impl Iterator<Item = cpal::Device>,
+    default: Option<cpal::Device>,
+    configs_of: impl Fn(&cpal::Device) -> Result<C, SupportedStreamConfigsError>,
+) -> Vec<DeviceInfo> {
+    let default = default.as_ref().and_then(device_name);
+    devices
+        // A device that can't even name itself can't be picked by name.
+        .filter_map(|device| {
+            let name = device_name(&device)?;
+            Some(DeviceInfo {
+                is_default: default.as_ref() == Some(&name),
+                configs: configs_of(&device)
+                    .map(|configs| configs.map(SupportedConfig::from).collect())
+                    .unwrap_or_default(),
+                name,
+            })
+        })
+        .collect()
+}
+
+fn device_name(device: &cpal::Device) -> Option<String> {
+    device
+        .description()
+        .ok()
+        .map(|description| description.name().to_owned())
+}
+
+fn named_device(
+    devices: Result<impl Iterator<Item = cpal::Device>, DevicesError>,
+    name: &str,
+) -> Result<cpal::Device, Box<dyn Error>> {
+    devices?
+        .find(|device| device_name(device).as_deref() == Some(name))
+        .ok_or_else(|| format!("no device named {name:?}").into())
+}
+
+/// What drives a virtual device's callbacks.
+#[derive(Clone, Default)]
+pub enum Clock {
+    /// A background thread hands over audio in real time, like a sound card.
+    #[default]
+    Realtime,
+    /// Audio only moves when the [`ManualClock`] is advanced, which makes
+    /// tests independent of wall time.
+    Manual(ManualClock),
+}
+
+/// A clock that runs the streams on it only when told to. Cloning shares the
+/// clock; one clock can drive both directions of a mixer.
+#[derive(Clone, Default)]
+pub struct ManualClock {
+    ticks: Arc<Mutex<Ticks>>,
+}
+
+#[derive(Default)]
+struct Ticks {
+    next_id: u64,
+    streams: Vec<Ticking>,
+}
+
+struct Ticking {
+    id: u64,
+    sample_rate: SampleRate,
+    /// Elapsed time not yet worth a whole frame, in nanoseconds × rate.
+    carry: u128,
+    tick: Box<dyn FnMut(usize) + Send>,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Let `elapsed` pass: every running stream on this clock processes that
+    /// much audio before this returns.
+    pub fn advance(&self, elapsed: Duration) {
+        let mut ticks = self.ticks.lock().unwrap();
+        for stream in &mut ticks.streams {
+            let total = elapsed.as_nanos() * stream.sample_rate as u128 + stream.carry;
+            stream.carry = total % 1_000_000_000;
+            (stream.tick)((total / 1_000_000_000) as usize);
+        }
+    }
+
+    fn register(&self, sample_rate: SampleRate, tick: Box<dyn FnMut(usize) + Send>) -> u64 {
+        let mut ticks = self.ticks.lock().unwrap();
+        let id = ticks.next_id;
+        ticks.next_id += 1;
+        ticks.streams.push(Ticking {
+            id,
+            sample_rate,
+            carry: 0,
+            tick,
+        });
+        id
+    }
+
+    fn unregister(&self, id: u64) {
+        self.ticks
+            .lock()
+            .unwrap()
+            .streams
+            .retain(|stream| stream.id != id);
+    }
+}
+
+/// A device standing in for a sound card.
+pub(crate) struct VirtualDevice {
+    kind: VirtualKind,
+    clock: Clock,
+}
+
+enum VirtualKind {
+    Null,
+    WavSink(PathBuf),
+    WavSource(PathBuf),
+}
+
+/// Virtual devices always run in the mixer's own sample type.
+fn virtual_format(channels: ChannelCount, sample_rate: SampleRate) -> StreamFormat {
+    StreamFormat {
+        channels,
+        sample_format: SampleFormat::F32,
+        sample_rate,
+    }
+}
+
+fn virtual_master<E>(
+    kind: VirtualKind,
+    clock: Clock,
+    format: StreamFormat,
+) -> Result<Master<E>, Box<dyn Error>> {
+    if format.channels == 0 || format.sample_rate == 0 {
+        return Err("device has zero channels or sample rate".into());
+    }
+    Ok(Master::new(
+        Device::Virtual(VirtualDevice { kind, clock }),
+        format,
+    ))
+}
+
+impl OutputBackend {
+    pub(crate) fn open(self) -> Result<Master<OutputRxEvent>, Box<dyn Error>> {
+        match self {
+            OutputBackend::System => open_master(
+                cpal::default_host()
+                    .default_output_device()
+                    .ok_or("no default output device")?,
+                |device| device.default_output_config(),
+            ),
+            OutputBackend::Named(name) => open_master(
+                named_device(cpal::default_host().output_devices(), &name)?,
+                |device| device.default_output_config(),
+            ),
+            OutputBackend::Null {
+                channels,
+                sample_rate,
+                clock,
+            } => virtual_master(
+                VirtualKind::Null,
+                clock,
+                virtual_format(channels, sample_rate),
+            ),
+            OutputBackend::WavFile {
+                path,
+                channels,
+                sample_rate,
+                clock,
+            } => virtual_master(
+                VirtualKind::WavSink(path),
+                clock,
+                virtual_format(channels, sample_rate),
+            ),
+        }
+    }
+}
+
+impl InputBackend {
+    pub(crate) fn open(self) -> Result<Master<InputRxEvent>, Box<dyn Error>> {
+        match self {
+            InputBackend::System => open_master(
+                cpal::default_host()
+                    .default_input_device()
+                    .ok_or("no default input device")?,
+                |device| device.default_input_config(),
+            ),
+            InputBackend::Named(name) => open_master(
+                named_device(cpal::default_host().input_devices(), &name)?,
+                |device| device.default_input_config(),
+            ),
+            InputBackend::Null {
+                channels,
+                sample_rate,
+                clock,
+            } => virtual_master(
+                VirtualKind::Null,
+                clock,
+                virtual_format(channels, sample_rate),
+            ),
+            InputBackend::WavFile { path, clock } => {
+                // The file's header is the device's format.
+                let spec = hound::WavReader::open(&path)
+                    .map_err(|err| format!("{} can't be read: {err}", path.display()))?
+                    .spec();
+                virtual_master(
+                    VirtualKind::WavSource(path),
+                    clock,
+                    virtual_format(spec.channels, spec.sample_rate),
+                )
+            }
+        }
+    }
+}
+
+impl VirtualDevice {
+    /// Run `mix` as this device's playback callback.
+    pub(crate) fn run_output(
+        &self,
+        channels: usize,
+        sample_rate: SampleRate,
+        mut mix: impl FnMut(&mut [AudioSampleType]) + Send + 'static,
+    ) -> Result<VirtualStream, Box<dyn Error>> {
+        let mut sink = match &self.kind {
+            VirtualKind::Null => None,
+            VirtualKind::WavSink(path) => Some(WavSink::create(path, channels, sample_rate)?),
+            VirtualKind::WavSource(_) => return Err("a WAV source can't play output".into()),
+        };
+        let mut buf = vec![0.0; (MIX_SCRATCH_LEN / channels) * channels];
+        Ok(VirtualStream::run(
+            &self.clock,
+            sample_rate,
+            move |frames| {
+                let mut left = frames * channels;
+                while left > 0 {
+                    let len = left.min(buf.len());
+                    mix(&mut buf[..len]);
+                    if let Some(sink) = &mut sink {
+                        sink.write(&buf[..len]);
+                    }
+                    left -= len;
+                }
+            },
+        ))
+    }
+
+    /// Run `capture` as this device's capture callback.
+    pub(crate) fn run_input(
+        &self,
+        channels: usize,
+        sample_rate: SampleRate,
+        mut capture: impl FnMut(&[AudioSampleType]) + Send + 'static,
+    ) -> Result<VirtualStream, Box<dyn Error>> {
+        let mut source = match &self.kind {
+            VirtualKind::Null => None,
+            VirtualKind::WavSource(path) => Some(WavSource::open(path, channels)?),
+            VirtualKind::WavSink(_) => return Err("a WAV sink can't capture input".into()),
+        };
+        let mut buf = vec![0.0; (MIX_SCRATCH_LEN / channels) * channels];
+        Ok(VirtualStream::run(
+            &self.clock,
+            sample_rate,
+            move |frames| {
+                let mut left = frames * channels;
+                while left > 0 {
+                    let len = left.min(buf.len());
+                    let len = match &mut source {
+                        Some(source) => source.read(&mut buf[..len]),
+                        None => {
+                            buf[..len].fill(0.0);
+                            len
+                        }
+                    };
+                    if len == 0 {
+                        break;
+                    }
+                    capture(&buf[..len]);
+                    left -= len;
+                }
+            },
+        ))
+    }
+}
+
+/// A running virtual stream; dropping it stops the callbacks.
+pub(crate) enum VirtualStream {
+    Realtime {
+        stop: Arc<AtomicBool>,
+        thread: Option<JoinHandle<()>>,
+    },
+    Manual {
+        clock: ManualClock,
+        id: u64,
+    },
+}
+
+impl VirtualStream {
+    /// Call `tick` with a number of frames as `clock` lets time pass.
+    fn run(
+        clock: &Clock,
+        sample_rate: SampleRate,
+        mut tick: impl FnMut(usize) + Send + 'static,
+    ) -> Self {
+        match clock {
+            Clock::Realtime => {
+                let stop = Arc::new(AtomicBool::new(false));
+                let frames =
+                    (REALTIME_PERIOD.as_nanos() * sample_rate as u128 / 1_000_000_000) as usize;
+                let thread = thread::spawn({
+                    let stop = stop.clone();
+                    move || {
+                        // Deadlines rather than fixed sleeps, so the stream
+                        // doesn't drift behind wall time.
+                        let mut deadline = Instant::now();
+                        while !stop.load(Ordering::Relaxed) {
+                            tick(frames);
+                            deadline += REALTIME_PERIOD;
+                            if let Some(wait) = deadline.checked_duration_since(Instant::now()) {
+                                thread::sleep(wait);
+                            }
+                        }
+                    }
+                });
+                VirtualStream::Realtime {
+                    stop,
+                    thread: Some(thread),
+                }
+            }
+            Clock::Manual(clock) => VirtualStream::Manual {
+                clock: clock.clone(),
+                id: clock.register(sample_rate, Box::new(tick)),
+            },
+        }
+    }
+}
+
+impl Drop for VirtualStream {
+    fn drop(&mut self) {
+        match self {
+            VirtualStream::Realtime { stop, thread } => {
+                stop.store(true, Ordering::Relaxed);
+                if let Some(thread) = thread.take()
+                    && thread.join().is_err()
+                {
+                    error!("Virtual audio device thread panicked");
+                }
+            }
+            VirtualStream::Manual { clock, id } => clock.unregister(*id),
+        }
+    }
+}
+
+/// Output written to a WAV file, finished when dropped.
+struct WavSink(Option<hound::WavWriter<BufWriter<File>>>);
+
+impl WavSink {
+    fn create(
+        path: &Path,
+        channels: usize,
+        sample_rate: SampleRate,
+    ) -> Result<Self, Box<dyn Error>> {
+        let spec = hound::WavSpec {
+            channels: channels as u16,
+            sample_rate,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        Ok(WavSink(Some(hound::WavWriter::create(path, spec)?)))
+    }
+
+    fn write(&mut self, samples: &[AudioSampleType]) {
+        let Some(writer) = &mut self.0 else {
+            return;
+        };
+        for sample in samples {
+            if let Err(err) = writer.write_sample(*sample) {
+                // Keep mixing; a full disk shouldn't take the stream down.
+                error!("Failed to write to the output WAV file: {err}");
+                self.0 = None;
+                return;
+            }
+        }
+    }
+}
+
+impl Drop for WavSink {
+    fn drop(&mut self) {
+        if let Some(writer) = self.0.take()
+            && let Err(err) = writer.finalize()
+        {
+            error!("Failed to finish the output WAV file: {err}");
+        }
+    }
+}
+
+/// Input read from a WAV file, as interleaved samples in `[-1, 1]`.
+struct WavSource {
+    samples: Box<dyn Iterator<Item = AudioSampleType> + Send>,
+    channels: usize,
+}
+
+impl WavSource {
+    fn open(path: &Path, channels: usize) -> Result<Self, Box<dyn Error>> {
+        let reader = hound::WavReader::open(path)?;
+        let spec = reader.spec();
+        if spec.channels as usize != channels {
+            return Err(format!("{} changed format since it was opened", path.display()).into());
+        }
+        // A read error (a truncated file) ends the input like the end of file does.
+        let samples: Box<dyn Iterator<Item = AudioSampleType> + Send> = match spec.sample_format {
+            hound::SampleFormat::Float => {
+                Box::new(reader.into_samples::<f32>().map_while(Result::ok))
+            }
+            hound::SampleFormat::Int => {
+                let scale = 1.0 / (1u64 << (spec.bits_per_sample - 1)) as AudioSampleType;
+                Box::new(
+                    reader
+                        .into_samples::<i32>()
+                        .map_while(Result::ok)
+                        .map(move |sample| sample as AudioSampleType * scale),
+                )
+            }
+        };
+        Ok(WavSource { samples, channels })
+    }
+
+    /// Fill `buf` with the next samples. Returns how many were written, in
+    /// whole frames; 0 once the file is used up.
+    fn read(&mut self, buf: &mut [AudioSampleType]) -> usize {
+        let mut len = 0;
+        for (slot, sample) in buf.iter_mut().zip(&mut self.samples) {
+            *slot = sample;
+            len += 1;
+        }
+        len - len % self.channels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AudioMixer, Resampling};
+
+    fn temp_wav(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("audio-{name}-{}.wav", std::process::id()))
+    }
+
+    fn f32_format(channels: ChannelCount) -> StreamFormat {
+        virtual_format(channels, 48_000)
+    }
+
+    #[test]
+    fn wav_sink_records_the_mix() {
+        let path = temp_wav("sink");
+        let clock = ManualClock::new();
+        let mut mixer = AudioMixer::new(
+            OutputBackend::WavFile {
+                path: path.clone(),
+                channels: 2,
+                sample_rate: 48_000,
+                clock: Clock::Manual(clock.clone()),
+            },
+            InputBackend::Null {
+                channels: 1,
+                sample_rate: 48_000,
+                clock: Clock::Manual(clock.clone()),
+            },
+        );
+        let mut a = mixer
+            .create_output_channel(f32_format(2), Resampling::Linear, Vec::new())
+            .unwrap();
+        let mut b = mixer
+            .create_output_channel(f32_format(2), Resampling::Linear, Vec::new())
+            .unwrap();
+        assert_eq!(a.push_iter(&[0.25f32; 960]), 960);
+        assert_eq!(b.push_iter(&[0.5f32; 480]), 480);
+
+        assert!(mixer.start_stream_output().unwrap().is_some());
+        clock.advance(Duration::from_millis(10));
+        mixer.stop_stream_output();
+
+        let samples: Vec<f32> = hound::WavReader::open(&path)
+            .unwrap()
+            .into_samples::<f32>()
+            .map(Result::unwrap)
+            .collect();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(samples.len(), 960);
+        assert!(samples[..480].iter().all(|s| (s - 0.75).abs() < 1e-6));
+        assert!(samples[480..].iter().all(|s| (s - 0.25).abs() < 1e-6));
+    }
+
+    #[test]
+    fn channel_controls_scale_the_mix() {
+        let path = temp_wav("controls");
+        let clock = ManualClock::new();
+        let mut mixer = AudioMixer::new(
+            OutputBackend::WavFile {
+                path: path.clone(),
+                channels: 2,
+                sample_rate: 48_000,
+                clock: Clock::Manual(clock.clone()),
+            },
+            InputBackend::Null {
+                channels: 1,
+                sample_rate: 48_000,
+                clock: Clock::Manual(clock.clone()),
+            },
+        );
+        let mut quiet = mixer
+            .create_output_channel(f32_format(2), Resampling::Linear, Vec::new())
+            .unwrap();
+        let mut muted = mixer
+            .create_output_channel(f32_format(2), Resampling::Linear, Vec::new())
+            .unwrap();
+        quiet.control().set_volume(0.5);
+        quiet.control().set_pan(1.0);
+        muted.control().set_muted(true);
+        assert_eq!(quiet.push_iter(&[0.5f32; 960]), 960);
+        assert_eq!(muted.push_iter(&[0.5f32; 960]), 960);
+
+        mixer.start_stream_output().unwrap();
+        clock.advance(Duration::from_millis(10));
+        mixer.stop_stream_output();
+
+        let samples: Vec<f32> = hound::WavReader::open(&path)
+            .unwrap()
+            .into_samples::<f32>()
+            .map(Result::unwrap)
+            .collect();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(samples.len(), 960);
+        for frame in samples.chunks_exact(2) {
+            assert_eq!(frame, [0.0, 0.25]);
+        }
+    }
+
+    #[test]
+    fn monitors_hear_the_mix() {
+        let clock = ManualClock::new();
+        let mut mixer = AudioMixer::new(
+            OutputBackend::Null {
+                channels: 2,
+                sample_rate: 48_000,
+                clock: Clock::Manual(clock.clone()),
+            },
+            InputBackend::Null {
+                channels: 1,
+                sample_rate: 48_000,
+                clock: Clock::Manual(clock.clone()),
+            },
+        );
+        let mut channel = mixer
+            .create_output_channel(f32_format(2), Resampling::Linear, Vec::new())
+            .unwrap();
+        channel.control().set_volume(0.5);
+        let mut early = mixer
+            .create_monitor_channel(f32_format(2), Resampling::Linear, Vec::new())
+            .unwrap();
+        assert_eq!(channel.push_iter(&[0.5f32; 1920]), 1920);
+
+        mixer.start_stream_output().unwrap();
+        clock.advance(Duration::from_millis(10));
+        let mut late = mixer
+            .create_monitor_channel(f32_format(2), Resampling::Linear, Vec::new())
+            .unwrap();
+        clock.advance(Duration::from_millis(10));
+        mixer.stop_stream_output();
+
+        let mut out = [0.0f32; 4096];
+        assert_eq!(early.pop_now(&mut out), 1920);
+        assert!(out[..1920].iter().all(|s| (s - 0.25).abs() < 1e-6));
+        // Only what played after it was made.
+        assert_eq!(late.pop_now(&mut out), 960);
+        assert!(out[..960].iter().all(|s| (s - 0.25).abs() < 1e-6));
+    }
+
+    #[test]
+    fn wav_source_feeds_input_channels() {
+        let path = temp_wav("source");
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 48_000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for n in 0..600 {
+            writer.write_sample((n * 16) as i16).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let clock = ManualClock::new();
+        let mut mixer = AudioMixer::new(
+            OutputBackend::Null {
+                channels: 2,
+                sample_rate: 48_000,
+                clock: Clock::Manual(clock.clone()),
+            },
+            InputBackend::WavFile {
+                path: path.clone(),
+                clock: Clock::Manual(clock.clone()),
+            },
+        );
+        let mut input = mixer
+            .create_input_channel(f32_format(1), Resampling::Linear, Vec::new())
+            .unwrap();
+        assert!(mixer.start_stream_input().unwrap().is_some());
+
+        let mut out = [0.0f32; 1024];
+        clock.advance(Duration::from_millis(10));
+        assert_eq!(input.pop_now(&mut out), 480);
+        assert!((out[1] - 16.0 / 32_768.0).abs() < 1e-6);
+        // The file runs out partway through the next period.
+        clock.advance(Duration::from_millis(10));
+        assert_eq!(input.pop_now(&mut out), 120);
+        clock.advance(Duration::from_millis(10));
+        assert_eq!(input.pop_now(&mut out), 0);
+
+        mixer.stop_stream_input();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn cleared_input_skips_what_was_captured() {
+        let path = temp_wav("clear");
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 48_000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for n in 0..960 {
+            writer.write_sample(n as i16).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let clock = ManualClock::new();
+        let mut mixer = AudioMixer::new(
+            OutputBackend::Null {
+                channels: 2,
+                sample_rate: 48_000,
+                clock: Clock::Manual(clock.clone()),
+            },
+            InputBackend::WavFile {
+                path: path.clone(),
+                clock: Clock::Manual(clock.clone()),
+            },
+        );
+        let mut input = mixer
+            .create_input_channel(f32_format(1), Resampling::Linear, Vec::new())
+            .unwrap();
+        assert!(mixer.start_stream_input().unwrap().is_some());
+
+        let mut out = [0.0f32; 1024];
+        clock.advance(Duration::from_millis(10));
+        input.clear();
+        assert_eq!(input.pop_now(&mut out), 0);
+        // Only the second period comes through.
+        clock.advance(Duration::from_millis(10));
+        assert_eq!(input.pop_now(&mut out), 480);
+        assert!((out[0] - 480.0 / 32_768.0).abs() < 1e-6);
+
+        mixer.stop_stream_input();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn output_swap_carries_channels_over() {
+        let path = temp_wav("swap-out");
+        let clock = ManualClock::new();
+        let mut mixer = AudioMixer::new(
+            OutputBackend::Null {
+                channels: 2,
+                sample_rate: 48_000,
+                clock: Clock::Manual(clock.clone()),
+            },
+            InputBackend::Null {
+                channels: 1,
+                sample_rate: 48_000,
+                clock: Clock::Manual(clock.clone()),
+            },
+        );
+        let mut producer = mixer
+            .create_output_channel(f32_format(2), Resampling::Linear, Vec::new())
+            .unwrap();
+        let close_notify = mixer.start_stream_output().unwrap().unwrap();
+        // Buffered for the stereo device, so dropped by the swap to mono.
+        assert_eq!(producer.push_iter(&[0.25f32; 960]), 960);
+
+        mixer
+            .set_output_device(OutputBackend::WavFile {
+                path: path.clone(),
+                channels: 1,
+                sample_rate: 48_000,
+                clock: Clock::Manual(clock.clone()),
+            })
+            .unwrap();
+        let running = mixer.start_stream_output().unwrap().unwrap();
+        assert!(Arc::ptr_eq(&close_notify, &running));
+
+        assert_eq!(producer.push_iter(&[0.5f32; 960]), 960);
+        clock.advance(Duration::from_millis(10));
+        mixer.stop_stream_output();
+
+        let samples: Vec<f32> = hound::WavReader::open(&path)
+            .unwrap()
+            .into_samples::<f32>()
+            .map(Result::unwrap)
+            .collect();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(samples.len(), 480);
+        assert!(samples.iter().all(|s| (s - 0.5).abs() < 1e-6));
+    }
+
+    #[test]
+    fn input_swap_carries_channels_over() {
+        let path = temp_wav("swap-in");
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 24_000,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for _ in 0..240 {
+            writer.write_sample(0.5f32).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let clock = ManualClock::new();
+        let mut mixer = AudioMixer::new(
+            OutputBackend::Null {
+                channels: 2,
+                sample_rate: 48_000,
+                clock: Clock::Manual(clock.clone()),
+            },
+            InputBackend::Null {
+                channels: 2,
+                sample_rate: 48_000,
+                clock: Clock::Manual(clock.clone()),
+            },
+        );
+        let mut consumer = mixer
+            .create_input_channel(f32_format(1), Resampling::Linear, Vec::new())
+            .unwrap();
+        mixer.start_stream_input().unwrap();
+        // Silence captured from the old device, never popped.
+        clock.advance(Duration::from_millis(10));
+
+        mixer
+            .set_input_device(InputBackend::WavFile {
+                path: path.clone(),
+                clock: Clock::Manual(clock.clone()),
+            })
+            .unwrap();
+        assert!(mixer.is_streaming_input());
+        clock.advance(Duration::from_millis(10));
+
+        let mut out = [0.0f32; 1024];
+        let popped = consumer.pop_now(&mut out);
+        std::fs::remove_file(&path).unwrap();
+        // 240 frames at 24kHz come out as about 480 at 48kHz.
+        assert!((470..=480).contains(&popped), "popped {popped}");
+        assert!(out[..popped].iter().all(|s| (s - 0.5).abs() < 1e-6));
+    }
+
+    #[test]
+    fn manual_clock_carries_partial_frames() {
+        let clock = ManualClock::new();
+        let frames = Arc::new(Mutex::new(0));
+        let _stream = VirtualStream::run(&Clock::Manual(clock.clone()), 44_100, {
+            let frames = frames.clone();
+            move |n| *frames.lock().unwrap() += n
+        });
+        for _ in 0..1000 {
+            clock.advance(Duration::from_millis(1));
+        }
+        assert_eq!(*frames.lock().unwrap(), 44_100);
+    }
+}
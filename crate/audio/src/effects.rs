@@ -1,10 +1,23 @@
+use std::time::Duration;
+
 use crate::{AudioSampleType, StreamFormat};
 
+mod agc;
+mod echo;
 mod gain;
 mod gate;
+mod limiter;
+mod noise;
+mod spectral;
+mod switch;
 
+pub use agc::{AutoGain, AutoGainSettings};
+pub use echo::{EchoCanceller, EchoCancellerSettings};
 pub use gain::Gain;
 pub use gate::{Gate, GateSettings};
+pub use limiter::{Limiter, LimiterSettings};
+pub use noise::{NoiseSuppressor, NoiseSuppressorSettings};
+pub use switch::{Switch, SwitchHandle};
 
 /// A boxed effect chain, as accepted by the channel constructors.
 pub type EffectChain = Vec<Box<dyn Afx + Send>>;
@@ -35,3 +48,22 @@ pub trait Afx {
 
     fn apply_to(&mut self, audio: &mut [AudioSampleType]) -> bool;
 }
+
+fn db_to_linear(db: AudioSampleType) -> AudioSampleType {
+    if db == AudioSampleType::NEG_INFINITY {
+        0.0
+    } else {
+        10.0_f32.powf(db / 20.0)
+    }
+}
+
+/// One-pole smoothing coefficient that reaches ~63% of a step over `time`.
+/// A stage of one frame or less is treated as instantaneous.
+fn time_to_coef(time: Duration, sample_rate: AudioSampleType) -> AudioSampleType {
+    let frames = time.as_secs_f32() * sample_rate;
+    if frames <= 1.0 {
+        1.0
+    } else {
+        1.0 - (-1.0 / frames).exp()
+    }
+}
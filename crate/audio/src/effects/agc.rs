@@ -0,0 +1,219 @@
+use std::time::Duration;
+
+use crate::{AudioSampleType, StreamFormat};
+
+use super::{Afx, db_to_linear, time_to_coef};
+
+/// Tunable parameters for an [`AutoGain`]. Levels are dBFS RMS; the stages are
+/// wall-clock durations, resolved once the effect is
+/// [`prepare`](Afx::prepare)d with a concrete format.
+#[derive(Clone, Copy, Debug)]
+pub struct AutoGainSettings {
+    /// Loudness the gain steers the signal towards.
+    pub target_db: AudioSampleType,
+    /// The most a quiet signal is boosted. Caps how far a whisper (or the
+    /// room behind it) is pulled up.
+    pub max_gain_db: AudioSampleType,
+    /// The most a loud signal is cut, as a (negative) gain.
+    pub min_gain_db: AudioSampleType,
+    /// Below this level the signal counts as a pause: the gain is held rather
+    /// than raised into the background noise.
+    pub floor_db: AudioSampleType,
+    /// Window the level is measured over. Shorter follows syllables, longer
+    /// follows phrases.
+    pub window: Duration,
+    /// Time for the gain to come down once the signal is louder than the
+    /// target.
+    pub attack: Duration,
+    /// Time for the gain to come back up once the signal is quieter than the
+    /// target. Kept slow so the gaps between words aren't pumped up.
+    pub release: Duration,
+}
+
+impl Default for AutoGainSettings {
+    fn default() -> Self {
+        // Voice-tuned: level out speech around a comfortable loudness,
+        // backing off quickly from shouts and recovering over a few words.
+        AutoGainSettings {
+            target_db: -20.0,
+            max_gain_db: 18.0,
+            min_gain_db: -18.0,
+            floor_db: -50.0,
+            window: Duration::from_millis(50),
+            attack: Duration::from_millis(50),
+            release: Duration::from_millis(1500),
+        }
+    }
+}
+
+/// Automatic gain control: steers a microphone towards
+/// [`AutoGainSettings::target_db`] whatever its input level.
+///
+/// The detector is the mean power across the frame's channels, smoothed over
+/// [`window`](AutoGainSettings::window). The gain that would bring it to the
+/// target, clamped to the allowed range, is followed with the attack time
+/// when it falls and the release time when it rises, and held while the level
+/// sits under the floor. All channels share one gain. Every buffer is kept;
+/// the AGC never silences, so put it after a [`Gate`] (which has already
+/// dropped the silence) and before a [`Limiter`] (which catches the peaks a
+/// raised gain lets through).
+///
+/// [`Gate`]: super::Gate
+/// [`Limiter`]: super::Limiter
+pub struct AutoGain {
+    settings: AutoGainSettings,
+    // Resolved against the stream format in `configure` (seeded in `new`).
+    channels: usize,
+    target: AudioSampleType,
+    max_gain: AudioSampleType,
+    min_gain: AudioSampleType,
+    floor: AudioSampleType,
+    window_coef: AudioSampleType,
+    attack_coef: AudioSampleType,
+    release_coef: AudioSampleType,
+    // Detector and gain state, carried across buffers for a continuous stream.
+    power: AudioSampleType,
+    gain: AudioSampleType,
+}
+
+impl AutoGain {
+    pub fn new(settings: AutoGainSettings) -> Self {
+        let mut agc = AutoGain {
+            settings,
+            channels: 1,
+            target: 0.0,
+            max_gain: 1.0,
+            min_gain: 1.0,
+            floor: 0.0,
+            window_coef: 1.0,
+            attack_coef: 1.0,
+            release_coef: 1.0,
+            power: 0.0,
+            gain: 1.0,
+        };
+        // Seed against a default format so the AGC is usable even if it is
+        // never `prepare`d; the real format overwrites this.
+        agc.configure(48_000.0, 2);
+        agc
+    }
+
+    /// Resolve the dB/duration settings into the linear levels and smoothing
+    /// coefficients used per sample, then reset to unity gain.
+    fn configure(&mut self, sample_rate: AudioSampleType, channels: usize) {
+        self.channels = channels.max(1);
+        self.target = db_to_linear(self.settings.target_db);
+        self.max_gain = db_to_linear(self.settings.max_gain_db);
+        self.min_gain = db_to_linear(self.settings.min_gain_db).min(self.max_gain);
+        self.floor = db_to_linear(self.settings.floor_db);
+        self.window_coef = time_to_coef(self.settings.window, sample_rate);
+        self.attack_coef = time_to_coef(self.settings.attack, sample_rate);
+        self.release_coef = time_to_coef(self.settings.release, sample_rate);
+        self.power = 0.0;
+        self.gain = 1.0_f32.clamp(self.min_gain, self.max_gain);
+    }
+}
+
+impl Afx for AutoGain {
+    fn prepare(&mut self, format: StreamFormat) {
+        self.configure(
+            format.sample_rate as AudioSampleType,
+            format.channels as usize,
+        );
+    }
+
+    fn apply_to(&mut self, audio: &mut [AudioSampleType]) -> bool {
+        let channels = self.channels as AudioSampleType;
+        for frame in audio.chunks_mut(self.channels) {
+            let power = frame
+                .iter()
+                .map(|sample| sample * sample)
+                .sum::<AudioSampleType>()
+                / channels;
+            self.power += (power - self.power) * self.window_coef;
+            let level = self.power.sqrt();
+
+            if level >= self.floor {
+                let wanted = (self.target / level).clamp(self.min_gain, self.max_gain);
+                let coef = if wanted < self.gain {
+                    self.attack_coef
+                } else {
+                    self.release_coef
+                };
+                self.gain += (wanted - self.gain) * coef;
+            }
+            for sample in frame.iter_mut() {
+                *sample *= self.gain;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn agc() -> AutoGain {
+        let mut agc = AutoGain::new(AutoGainSettings {
+            release: Duration::from_millis(200),
+            ..Default::default()
+        });
+        agc.configure(48_000.0, 1);
+        agc
+    }
+
+    /// One second of a 220 Hz sine at `amplitude`.
+    fn tone(amplitude: AudioSampleType) -> Vec<AudioSampleType> {
+        (0..48_000)
+            .map(|n| amplitude * (2.0 * std::f32::consts::PI * 220.0 * n as f32 / 48_000.0).sin())
+            .collect()
+    }
+
+    fn rms_db(audio: &[AudioSampleType]) -> AudioSampleType {
+        let power = audio
+            .iter()
+            .map(|sample| sample * sample)
+            .sum::<AudioSampleType>()
+            / audio.len() as AudioSampleType;
+        10.0 * power.log10()
+    }
+
+    #[test]
+    fn quiet_and_loud_voices_meet_at_the_target() {
+        for amplitude in [0.02, 0.8] {
+            let mut agc = agc();
+            let mut audio = tone(amplitude);
+            for buffer in audio.chunks_mut(480) {
+                assert!(agc.apply_to(buffer));
+            }
+            let level = rms_db(&audio[24_000..]);
+            assert!((level - -20.0).abs() < 1.0, "{amplitude}: {level} dB");
+        }
+    }
+
+    #[test]
+    fn boost_is_capped() {
+        let mut agc = agc();
+        let mut audio = tone(0.005);
+        agc.apply_to(&mut audio);
+        // -49 dB RMS in, at most 18 dB of boost.
+        let level = rms_db(&audio[24_000..]);
+        assert!(level < -30.5, "{level} dB");
+    }
+
+    #[test]
+    fn pauses_hold_the_gain() {
+        let mut agc = agc();
+        let mut speech = tone(0.02);
+        agc.apply_to(&mut speech);
+        let mut hiss = tone(0.001);
+        // Once the measuring window has emptied out, the gain stops moving
+        // well short of the cap.
+        let (settling, paused) = hiss.split_at_mut(24_000);
+        agc.apply_to(settling);
+        let gain = agc.gain;
+        agc.apply_to(paused);
+        assert_eq!(agc.gain, gain);
+        assert!(gain < agc.max_gain);
+    }
+}
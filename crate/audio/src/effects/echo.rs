@@ -0,0 +1,406 @@
+use std::{collections::VecDeque, time::Duration};
+
+use tracing::warn;
+
+use crate::{
+    AudioSampleType, CONVERT_SCRATCH_LEN, SampleFormat, StreamFormat, input::SampleConsumer,
+};
+
+use super::{
+    Afx,
+    spectral::{Complex, Fft, MonoBlocks, block_len},
+};
+
+/// Tunable parameters for an [`EchoCanceller`].
+#[derive(Clone, Copy, Debug)]
+pub struct EchoCancellerSettings {
+    /// The longest echo to cancel: how long after being mixed the speakers'
+    /// sound can still reach the microphone, device and host buffering
+    /// included. Longer tails cost more and adapt more slowly.
+    pub tail: Duration,
+    /// How long a microphone block waits for the playback it is cancelled
+    /// against before going ahead as if nothing played. Adds to the latency,
+    /// and should cover the output device's buffer period.
+    pub reference_wait: Duration,
+}
+
+impl Default for EchoCancellerSettings {
+    fn default() -> Self {
+        EchoCancellerSettings {
+            tail: Duration::from_millis(200),
+            reference_wait: Duration::from_millis(20),
+        }
+    }
+}
+
+/// Removes what the speakers play from a microphone, so a call without
+/// headphones doesn't hear itself back.
+///
+/// `reference` is a [monitor](crate::AudioMixer::create_monitor_channel) of
+/// the output mix, declared as [`SampleFormat::F32`] at the microphone
+/// channel's sample rate; anything else leaves the effect bypassed. An
+/// adaptive filter learns how that mix comes back through the room into the
+/// microphone, over up to [`tail`](EchoCancellerSettings::tail), and
+/// subtracts its prediction. Learning pauses while the microphone is louder
+/// than the playback could explain (someone talking over it), so speech isn't
+/// learned as echo.
+///
+/// Works on the mono downmix and writes the result to every channel, a few
+/// milliseconds plus [`reference_wait`](EchoCancellerSettings::reference_wait)
+/// late. Every buffer is kept. Put it before effects that change the level,
+/// such as a [`NoiseSuppressor`](super::NoiseSuppressor) or a
+/// [`Gate`](super::Gate), and before any that may drop buffers, so it sees
+/// the microphone continuously.
+pub struct EchoCanceller {
+    settings: EchoCancellerSettings,
+    reference: SampleConsumer,
+    /// Staging for `reference`, interleaved in its own channel count.
+    popped: Box<[AudioSampleType]>,
+    /// Mono playback not yet paired with the microphone.
+    far: VecDeque<AudioSampleType>,
+    /// Playback that blocks already went ahead without; it is skipped when it
+    /// turns up late.
+    owed: usize,
+    // Built for the stream format in `prepare` (seeded in `new`).
+    wait: usize,
+    blocks: MonoBlocks,
+    filter: EchoFilter,
+    enabled: bool,
+}
+
+impl EchoCanceller {
+    pub fn new(reference: SampleConsumer, settings: EchoCancellerSettings) -> Self {
+        let mut canceller = EchoCanceller {
+            settings,
+            reference,
+            popped: vec![0.0; CONVERT_SCRATCH_LEN].into_boxed_slice(),
+            far: VecDeque::new(),
+            owed: 0,
+            wait: 0,
+            blocks: MonoBlocks::new(1, 1, 1),
+            filter: EchoFilter::new(1, 1),
+            enabled: false,
+        };
+        // Seed against a default format so the canceller is usable even if it
+        // is never `prepare`d; the real format overwrites this.
+        canceller.configure(StreamFormat {
+            channels: 2,
+            sample_format: SampleFormat::F32,
+            sample_rate: 48_000,
+        });
+        canceller
+    }
+
+    fn configure(&mut self, format: StreamFormat) {
+        let reference = self.reference.format();
+        self.enabled = reference.sample_format == SampleFormat::F32
+            && reference.sample_rate == format.sample_rate;
+        let rate = format.sample_rate as f64;
+        let block = block_len(0.005, format.sample_rate);
+        let tail = (self.settings.tail.as_secs_f64() * rate).ceil() as usize;
+        self.wait = (self.settings.reference_wait.as_secs_f64() * rate).ceil() as usize;
+        self.blocks = MonoBlocks::new(format.channels as usize, block, block + self.wait);
+        self.filter = EchoFilter::new(block, tail.div_ceil(block).max(1));
+        self.far.clear();
+        self.owed = 0;
+    }
+
+    /// Queue whatever was played since the last call, skipping what blocks
+    /// already went ahead without.
+    fn pull_reference(&mut self) {
+        let channels = self.reference.format().channels.max(1) as usize;
+        loop {
+            let popped = self.reference.pop_now(&mut self.popped);
+            if popped == 0 {
+                break;
+            }
+            self.far.extend(
+                self.popped[..popped].chunks_exact(channels).map(|frame| {
+                    frame.iter().sum::<AudioSampleType>() / channels as AudioSampleType
+                }),
+            );
+        }
+        let skipped = self.owed.min(self.far.len());
+        self.far.drain(..skipped);
+        self.owed -= skipped;
+    }
+}
+
+impl Afx for EchoCanceller {
+    fn prepare(&mut self, format: StreamFormat) {
+        self.configure(format);
+        if !self.enabled {
+            warn!(
+                "Echo cancellation off: the reference is {:?}, but the microphone runs at {} Hz",
+                self.reference.format(),
+                format.sample_rate
+            );
+        }
+    }
+
+    fn apply_to(&mut self, audio: &mut [AudioSampleType]) -> bool {
+        if !self.enabled {
+            return true;
+        }
+        self.pull_reference();
+        let EchoCanceller {
+            far,
+            owed,
+            wait,
+            blocks,
+            filter,
+            ..
+        } = self;
+        blocks.run(audio, |block, queued| {
+            let len = block.len();
+            // Playback too far ahead of the microphone (it paused, or the
+            // devices' clocks drift apart) pairs with audio it never reached.
+            let excess = far.len().saturating_sub(queued + len + *wait);
+            far.drain(..excess);
+            let available = far.len().min(len);
+            if available < len {
+                if queued < *wait {
+                    return false;
+                }
+                // Nothing came in time: presumably nothing is playing.
+                *owed = (*owed + len - available).min(*wait);
+            }
+            for (slot, sample) in filter.reference.iter_mut().zip(far.drain(..available)) {
+                *slot = sample;
+            }
+            filter.reference[available..].fill(0.0);
+            filter.process(block);
+            true
+        });
+        true
+    }
+}
+
+/// A microphone block louder than this share of the playback's recent peak
+/// has someone speaking in it, and isn't learned from. Assumes the room
+/// weakens the echo by at least 6 dB.
+const DOUBLE_TALK: AudioSampleType = 0.5;
+/// Playback quieter than this leaves nothing worth learning.
+const SILENCE: AudioSampleType = 1e-4;
+/// The adaptation step, in (0, 1]: larger learns faster, smaller settles
+/// closer.
+const STEP: AudioSampleType = 0.5;
+/// Weight of the previous block in the smoothed playback power.
+const POWER_SMOOTHING: AudioSampleType = 0.9;
+
+/// A partitioned-block frequency-domain NLMS filter. The echo path is cut
+/// into `block`-long partitions, each a spectrum multiplied with the playback
+/// spectrum from as many blocks ago; overlap-save keeps the result linear.
+struct EchoFilter {
+    fft: Fft,
+    block: usize,
+    /// The playback spectra of the last blocks, one per partition; the
+    /// newest is at `newest`.
+    history: Box<[Box<[Complex]>]>,
+    newest: usize,
+    /// Peak playback level of each block in `history`.
+    peaks: Box<[AudioSampleType]>,
+    weights: Box<[Box<[Complex]>]>,
+    /// Smoothed playback power per bin, which normalises the step.
+    power: Box<[AudioSampleType]>,
+    /// The playback block paired with the next microphone block.
+    reference: Box<[AudioSampleType]>,
+    /// The block before it: the first half of the overlap-save transform.
+    previous: Box<[AudioSampleType]>,
+    /// The microphone block minus the predicted echo.
+    error: Box<[AudioSampleType]>,
+    spectrum: Box<[Complex]>,
+    /// One partition is constrained back to a causal, block-long response
+    /// per block, in turn.
+    constrain: usize,
+}
+
+impl EchoFilter {
+    fn new(block: usize, partitions: usize) -> Self {
+        let size = 2 * block;
+        let spectra = || {
+            (0..partitions)
+                .map(|_| vec![Complex::ZERO; size].into_boxed_slice())
+                .collect()
+        };
+        EchoFilter {
+            fft: Fft::new(size),
+            block,
+            history: spectra(),
+            newest: 0,
+            peaks: vec![0.0; partitions].into_boxed_slice(),
+            weights: spectra(),
+            power: vec![0.0; size].into_boxed_slice(),
+            reference: vec![0.0; block].into_boxed_slice(),
+            previous: vec![0.0; block].into_boxed_slice(),
+            error: vec![0.0; block].into_boxed_slice(),
+            spectrum: vec![Complex::ZERO; size].into_boxed_slice(),
+            constrain: 0,
+        }
+    }
+
+    /// Replace `mic` with what is left of it once the echo of `reference` is
+    /// taken out, and learn from the result.
+    fn process(&mut self, mic: &mut [AudioSampleType]) {
+        let block = self.block;
+        let partitions = self.history.len();
+
+        self.newest = (self.newest + 1) % partitions;
+        let newest = &mut self.history[self.newest];
+        for (bin, sample) in newest
+            .iter_mut()
+            .zip(self.previous.iter().chain(&*self.reference))
+        {
+            *bin = Complex::real(*sample);
+        }
+        self.fft.forward(newest);
+        for (power, bin) in self.power.iter_mut().zip(newest.iter()) {
+            *power = POWER_SMOOTHING * *power + (1.0 - POWER_SMOOTHING) * bin.norm_sqr();
+        }
+        self.peaks[self.newest] = self.reference.iter().fold(0.0, |peak, s| peak.max(s.abs()));
+        self.previous.copy_from_slice(&self.reference);
+
+        // Predict the echo: every partition against its block of playback.
+        self.spectrum.fill(Complex::ZERO);
+        for (partition, weights) in self.weights.iter().enumerate() {
+            let playback = &self.history[(self.newest + partitions - partition) % partitions];
+            for ((sum, weight), bin) in self
+                .spectrum
+                .iter_mut()
+                .zip(weights.iter())
+                .zip(playback.iter())
+            {
+                *sum += *weight * *bin;
+            }
+        }
+        self.fft.inverse(&mut self.spectrum);
+        let mut mic_energy = 0.0;
+        let mut error_energy = 0.0;
+        let mut mic_peak: AudioSampleType = 0.0;
+        for ((error, sample), echo) in self
+            .error
+            .iter_mut()
+            .zip(mic.iter())
+            .zip(&self.spectrum[block..])
+        {
+            *error = sample - echo.re;
+            mic_energy += sample * sample;
+            error_energy += *error * *error;
+            mic_peak = mic_peak.max(sample.abs());
+        }
+
+        let far_peak = self
+            .peaks
+            .iter()
+            .fold(0.0, |peak: AudioSampleType, s| peak.max(*s));
+        if far_peak > SILENCE && mic_peak < DOUBLE_TALK * far_peak {
+            self.adapt();
+        }
+
+        if error_energy <= mic_energy {
+            mic.copy_from_slice(&self.error);
+        } else if error_energy > 4.0 * mic_energy {
+            // Diverged (the room changed under it, or a missed double-talk):
+            // start learning over rather than add echo.
+            for weights in self.weights.iter_mut() {
+                weights.fill(Complex::ZERO);
+            }
+        }
+    }
+
+    /// One NLMS step on the last block's `error`.
+    fn adapt(&mut self) {
+        let block = self.block;
+        let partitions = self.history.len();
+        for (bin, error) in self
+            .spectrum
+            .iter_mut()
+            .zip(std::iter::repeat_n(&0.0, block).chain(self.error.iter()))
+        {
+            *bin = Complex::real(*error);
+        }
+        self.fft.forward(&mut self.spectrum);
+        let scale = partitions as AudioSampleType;
+        for (partition, weights) in self.weights.iter_mut().enumerate() {
+            let playback = &self.history[(self.newest + partitions - partition) % partitions];
+            for (((weight, bin), error), power) in weights
+                .iter_mut()
+                .zip(playback.iter())
+                .zip(self.spectrum.iter())
+                .zip(self.power.iter())
+            {
+                let step = STEP / (scale * power + AudioSampleType::EPSILON);
+                *weight += (bin.conj() * *error).scale(step);
+            }
+        }
+
+        let weights = &mut self.weights[self.constrain];
+        self.fft.inverse(weights);
+        weights[block..].fill(Complex::ZERO);
+        for bin in weights.iter_mut() {
+            bin.im = 0.0;
+        }
+        self.fft.forward(weights);
+        self.constrain = (self.constrain + 1) % partitions;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Uniform white noise from a fixed seed.
+    fn noise(len: usize, seed: u32) -> Vec<AudioSampleType> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as AudioSampleType / u32::MAX as AudioSampleType - 0.5
+            })
+            .collect()
+    }
+
+    fn energy(audio: &[AudioSampleType]) -> AudioSampleType {
+        audio.iter().map(|sample| sample * sample).sum()
+    }
+
+    fn cancel(filter: &mut EchoFilter, playback: &[AudioSampleType], mic: &mut [AudioSampleType]) {
+        for (reference, block) in playback
+            .chunks_exact(filter.block)
+            .zip(mic.chunks_exact_mut(filter.block))
+        {
+            filter.reference.copy_from_slice(reference);
+            filter.process(block);
+        }
+    }
+
+    #[test]
+    fn learns_and_removes_a_delayed_echo() {
+        let mut filter = EchoFilter::new(256, 38);
+        let playback = noise(4 * 48_000, 0x2545_f491);
+        // The room: two reflections, 20 and 60 ms late.
+        let mut mic: Vec<AudioSampleType> = (0..playback.len())
+            .map(|n| {
+                let first = n.checked_sub(960).map_or(0.0, |n| 0.3 * playback[n]);
+                let second = n.checked_sub(2_880).map_or(0.0, |n| -0.1 * playback[n]);
+                first + second
+            })
+            .collect();
+        let settled = mic.len() - 24_000;
+        let before = energy(&mic[settled..]);
+        cancel(&mut filter, &playback, &mut mic);
+        let after = energy(&mic[settled..]);
+        assert!(after < before / 100.0, "{before} -> {after}");
+    }
+
+    #[test]
+    fn speech_without_playback_passes_untouched() {
+        let mut filter = EchoFilter::new(256, 8);
+        let speech = noise(4_096, 7);
+        let mut mic = speech.clone();
+        cancel(&mut filter, &[0.0; 4_096], &mut mic);
+        assert_eq!(mic, speech);
+    }
+}
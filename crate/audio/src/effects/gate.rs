@@ -2,7 +2,7 @@ use std::time::Duration;
 
 use crate::{AudioSampleType, StreamFormat};
 
-use super::Afx;
+use super::{Afx, db_to_linear, time_to_coef};
 
 /// Tunable parameters for a [`Gate`]. Levels are dBFS; the envelope stages are
 /// wall-clock durations, resolved into sample counts once the gate is
@@ -168,25 +168,6 @@ impl Afx for Gate {
     }
 }
 
-fn db_to_linear(db: AudioSampleType) -> AudioSampleType {
-    if db == AudioSampleType::NEG_INFINITY {
-        0.0
-    } else {
-        10.0_f32.powf(db / 20.0)
-    }
-}
-
-/// One-pole smoothing coefficient that reaches ~63% of a step over `time`.
-/// A stage of one frame or less is treated as instantaneous.
-fn time_to_coef(time: Duration, sample_rate: AudioSampleType) -> AudioSampleType {
-    let frames = time.as_secs_f32() * sample_rate;
-    if frames <= 1.0 {
-        1.0
-    } else {
-        1.0 - (-1.0 / frames).exp()
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
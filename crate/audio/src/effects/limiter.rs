@@ -0,0 +1,179 @@
+use std::{collections::VecDeque, time::Duration};
+
+use crate::{AudioSampleType, StreamFormat};
+
+use super::{Afx, db_to_linear, time_to_coef};
+
+/// Tunable parameters for a [`Limiter`]. The ceiling is dBFS; the stages are
+/// wall-clock durations, resolved once the limiter is
+/// [`prepare`](Afx::prepare)d with a concrete format.
+#[derive(Clone, Copy, Debug)]
+pub struct LimiterSettings {
+    /// No sample leaves louder than this.
+    pub ceiling_db: AudioSampleType,
+    /// How far ahead peaks are seen coming, so the gain can fade down in time
+    /// instead of clipping. Also the latency the limiter adds.
+    pub lookahead: Duration,
+    /// Time for the gain to recover once a peak has passed.
+    pub release: Duration,
+}
+
+impl Default for LimiterSettings {
+    fn default() -> Self {
+        LimiterSettings {
+            ceiling_db: -1.0,
+            lookahead: Duration::from_millis(2),
+            release: Duration::from_millis(100),
+        }
+    }
+}
+
+/// A brickwall peak limiter.
+///
+/// Audio is delayed by the [`lookahead`](LimiterSettings::lookahead), and the
+/// gain fades down over that delay to whatever the loudest frame inside it
+/// needs to stay under [`ceiling_db`](LimiterSettings::ceiling_db), then
+/// releases back towards unity once the peak has passed. All channels share
+/// one gain. Whatever the fade leaves over the ceiling is clamped, so the
+/// ceiling holds for every sample. Every buffer is kept.
+pub struct Limiter {
+    settings: LimiterSettings,
+    // Resolved against the stream format in `configure` (seeded in `new`).
+    channels: usize,
+    ceiling: AudioSampleType,
+    lookahead_frames: usize,
+    attack_coef: AudioSampleType,
+    release_coef: AudioSampleType,
+    /// The last `lookahead_frames` frames, interleaved, waiting to go out.
+    delay: VecDeque<AudioSampleType>,
+    /// Gains the frames in the delay need, as `(frame, gain)`, increasing in
+    /// both: the front is the lowest gain still ahead.
+    needed: VecDeque<(u64, AudioSampleType)>,
+    frame: u64,
+    gain: AudioSampleType,
+}
+
+impl Limiter {
+    pub fn new(settings: LimiterSettings) -> Self {
+        let mut limiter = Limiter {
+            settings,
+            channels: 1,
+            ceiling: 1.0,
+            lookahead_frames: 0,
+            attack_coef: 1.0,
+            release_coef: 1.0,
+            delay: VecDeque::new(),
+            needed: VecDeque::new(),
+            frame: 0,
+            gain: 1.0,
+        };
+        // Seed against a default format so the limiter is usable even if it
+        // is never `prepare`d; the real format overwrites this.
+        limiter.configure(48_000.0, 2);
+        limiter
+    }
+
+    /// Resolve the settings for the format and reset to an empty delay at
+    /// unity gain.
+    fn configure(&mut self, sample_rate: AudioSampleType, channels: usize) {
+        self.channels = channels.max(1);
+        self.ceiling = db_to_linear(self.settings.ceiling_db);
+        self.lookahead_frames = (self.settings.lookahead.as_secs_f32() * sample_rate) as usize;
+        // Most of the way down (~99%) by the time the peak comes out.
+        self.attack_coef = time_to_coef(self.settings.lookahead / 5, sample_rate);
+        self.release_coef = time_to_coef(self.settings.release, sample_rate);
+        self.delay = std::iter::repeat_n(0.0, self.lookahead_frames * self.channels).collect();
+        self.needed = VecDeque::with_capacity(self.lookahead_frames + 1);
+        self.frame = 0;
+        self.gain = 1.0;
+    }
+}
+
+impl Afx for Limiter {
+    fn prepare(&mut self, format: StreamFormat) {
+        self.configure(
+            format.sample_rate as AudioSampleType,
+            format.channels as usize,
+        );
+    }
+
+    fn apply_to(&mut self, audio: &mut [AudioSampleType]) -> bool {
+        for frame in audio.chunks_mut(self.channels) {
+            let peak: AudioSampleType = frame.iter().fold(0.0, |peak, &s| peak.max(s.abs()));
+            let needed = if peak > self.ceiling {
+                self.ceiling / peak
+            } else {
+                1.0
+            };
+            while self.needed.back().is_some_and(|&(_, gain)| gain >= needed) {
+                self.needed.pop_back();
+            }
+            self.needed.push_back((self.frame, needed));
+            while self
+                .needed
+                .front()
+                .is_some_and(|&(frame, _)| frame + (self.lookahead_frames as u64) < self.frame)
+            {
+                self.needed.pop_front();
+            }
+            let target = self.needed.front().map_or(1.0, |&(_, gain)| gain);
+            self.frame += 1;
+
+            let coef = if target < self.gain {
+                self.attack_coef
+            } else {
+                self.release_coef
+            };
+            self.gain += (target - self.gain) * coef;
+
+            self.delay.extend(frame.iter());
+            for sample in frame.iter_mut() {
+                let delayed = self.delay.pop_front().unwrap_or(0.0);
+                *sample = (delayed * self.gain).clamp(-self.ceiling, self.ceiling);
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter() -> Limiter {
+        let mut limiter = Limiter::new(Default::default());
+        limiter.configure(48_000.0, 1);
+        limiter
+    }
+
+    #[test]
+    fn peaks_never_cross_the_ceiling() {
+        let mut limiter = limiter();
+        let ceiling = db_to_linear(-1.0);
+        let mut audio: Vec<AudioSampleType> = (0..4_800)
+            .map(|n| 3.0 * (2.0 * std::f32::consts::PI * 440.0 * n as f32 / 48_000.0).sin())
+            .collect();
+        for buffer in audio.chunks_mut(480) {
+            assert!(limiter.apply_to(buffer));
+        }
+        assert!(audio.iter().all(|sample| sample.abs() <= ceiling));
+        // Limited, not clipped: the gain settles and most of the wave keeps
+        // its shape under the ceiling.
+        let flat = audio[2_400..]
+            .iter()
+            .filter(|sample| sample.abs() >= ceiling)
+            .count();
+        assert!(flat < 48, "{flat} samples at the ceiling");
+    }
+
+    #[test]
+    fn quiet_audio_passes_late_and_untouched() {
+        let mut limiter = limiter();
+        let mut audio: Vec<AudioSampleType> = (0..480).map(|n| (n % 10) as f32 / 20.0).collect();
+        let original = audio.clone();
+        assert!(limiter.apply_to(&mut audio));
+        // Two milliseconds of lookahead at 48 kHz.
+        assert_eq!(audio[..96], [0.0; 96]);
+        assert_eq!(audio[96..], original[..384]);
+    }
+}
@@ -0,0 +1,250 @@
+use std::f64::consts::PI;
+
+use crate::{AudioSampleType, StreamFormat};
+
+use super::{
+    Afx,
+    spectral::{Complex, Fft, MonoBlocks, block_len},
+};
+
+/// Tunable parameters for a [`NoiseSuppressor`].
+#[derive(Clone, Copy, Debug)]
+pub struct NoiseSuppressorSettings {
+    /// The most any frequency is turned down, in dB. Deeper cuts remove more
+    /// noise but leave speech sounding hollow and "musical".
+    pub reduction_db: AudioSampleType,
+    /// How fast the noise estimate may rise, in dB per second, when the
+    /// background gets louder. It follows drops at once. Too fast and long
+    /// vowels start to count as noise.
+    pub rise_db_per_sec: AudioSampleType,
+}
+
+impl Default for NoiseSuppressorSettings {
+    fn default() -> Self {
+        NoiseSuppressorSettings {
+            reduction_db: 20.0,
+            rise_db_per_sec: 3.0,
+        }
+    }
+}
+
+/// Spectral noise suppression for a microphone.
+///
+/// Audio is taken apart into ~5 ms hops of overlapping spectra. The noise in
+/// each frequency is tracked as the minimum of its smoothed power, which
+/// speech keeps leaving but steady background (fans, hiss, hum) never does,
+/// and every frequency is then turned down by a Wiener gain on its estimated
+/// speech-to-noise ratio. The ratio is smoothed across hops (the
+/// "decision-directed" estimate) so the leftover noise doesn't twinkle.
+///
+/// Works on the mono downmix and writes the result to every channel, about
+/// two hops late. Every buffer is kept.
+pub struct NoiseSuppressor {
+    settings: NoiseSuppressorSettings,
+    // Built for the stream format in `prepare` (seeded in `new`).
+    blocks: MonoBlocks,
+    spectra: Spectra,
+}
+
+/// Noise below this power (full-scale squared, per bin) is treated as none.
+const POWER_EPS: AudioSampleType = 1e-12;
+/// The tracked minimum sits below the noise's mean power; scale it back up.
+const NOISE_BIAS: AudioSampleType = 4.0;
+/// Weight of the previous hop's power in the smoothed one that is tracked.
+const POWER_SMOOTHING: AudioSampleType = 0.8;
+/// Weight of the previous hop in the decision-directed speech-to-noise ratio.
+const RATIO_SMOOTHING: AudioSampleType = 0.98;
+
+/// The short-time spectral analysis, and the estimates carried between hops.
+struct Spectra {
+    fft: Fft,
+    /// Square-root Hann, applied before and after the transform; squared, its
+    /// overlapping halves sum to one.
+    window: Box<[AudioSampleType]>,
+    /// The last two hops of input.
+    frame: Box<[AudioSampleType]>,
+    /// The second half of the last hop's output, still to be overlapped.
+    overlap: Box<[AudioSampleType]>,
+    spectrum: Box<[Complex]>,
+    // Per bin, up to Nyquist.
+    smoothed: Box<[AudioSampleType]>,
+    noise: Box<[AudioSampleType]>,
+    gains: Box<[AudioSampleType]>,
+    ratios: Box<[AudioSampleType]>,
+    primed: bool,
+    floor_gain: AudioSampleType,
+    /// Growth allowed to the noise estimate per hop.
+    rise: AudioSampleType,
+}
+
+impl NoiseSuppressor {
+    pub fn new(settings: NoiseSuppressorSettings) -> Self {
+        let (blocks, spectra) = Self::build(settings, 48_000, 2);
+        NoiseSuppressor {
+            settings,
+            blocks,
+            spectra,
+        }
+    }
+
+    fn build(
+        settings: NoiseSuppressorSettings,
+        sample_rate: u32,
+        channels: usize,
+    ) -> (MonoBlocks, Spectra) {
+        let hop = block_len(0.005, sample_rate);
+        let size = 2 * hop;
+        let bins = hop + 1;
+        let hops_per_sec = sample_rate as AudioSampleType / hop as AudioSampleType;
+        let spectra = Spectra {
+            fft: Fft::new(size),
+            window: (0..size)
+                .map(|n| (0.5 - 0.5 * (2.0 * PI * n as f64 / size as f64).cos()).sqrt())
+                .map(|weight| weight as AudioSampleType)
+                .collect(),
+            frame: vec![0.0; size].into_boxed_slice(),
+            overlap: vec![0.0; hop].into_boxed_slice(),
+            spectrum: vec![Complex::ZERO; size].into_boxed_slice(),
+            smoothed: vec![0.0; bins].into_boxed_slice(),
+            noise: vec![0.0; bins].into_boxed_slice(),
+            gains: vec![1.0; bins].into_boxed_slice(),
+            ratios: vec![0.0; bins].into_boxed_slice(),
+            primed: false,
+            floor_gain: 10.0_f32.powf(-settings.reduction_db.max(0.0) / 20.0),
+            rise: 10.0_f32.powf(settings.rise_db_per_sec.max(0.0) / 10.0 / hops_per_sec),
+        };
+        (MonoBlocks::new(channels, hop, hop), spectra)
+    }
+}
+
+impl Spectra {
+    /// Replace one hop of input with the output from a hop ago.
+    fn process(&mut self, block: &mut [AudioSampleType]) {
+        let hop = block.len();
+        self.frame.copy_within(hop.., 0);
+        self.frame[hop..].copy_from_slice(block);
+        for ((bin, sample), weight) in self.spectrum.iter_mut().zip(&self.frame).zip(&self.window) {
+            *bin = Complex::real(sample * weight);
+        }
+        self.fft.forward(&mut self.spectrum);
+
+        for bin in 0..=hop {
+            let power = self.spectrum[bin].norm_sqr();
+            if self.primed {
+                self.smoothed[bin] =
+                    POWER_SMOOTHING * self.smoothed[bin] + (1.0 - POWER_SMOOTHING) * power;
+                self.noise[bin] = (self.noise[bin] * self.rise).min(self.smoothed[bin]);
+            } else {
+                self.smoothed[bin] = power;
+                self.noise[bin] = power;
+            }
+            let noise = (self.noise[bin] * NOISE_BIAS).max(POWER_EPS);
+            let ratio = power / noise;
+            let speech = RATIO_SMOOTHING * self.gains[bin] * self.gains[bin] * self.ratios[bin]
+                + (1.0 - RATIO_SMOOTHING) * (ratio - 1.0).max(0.0);
+            let gain = (speech / (1.0 + speech)).max(self.floor_gain);
+            self.gains[bin] = gain;
+            self.ratios[bin] = ratio;
+
+            self.spectrum[bin] = self.spectrum[bin].scale(gain);
+            if bin > 0 && bin < hop {
+                self.spectrum[2 * hop - bin] = self.spectrum[bin].conj();
+            }
+        }
+        self.primed = true;
+
+        self.fft.inverse(&mut self.spectrum);
+        let (head, tail) = self.spectrum.split_at(hop);
+        let (head_window, tail_window) = self.window.split_at(hop);
+        for (((out, overlap), bin), weight) in block
+            .iter_mut()
+            .zip(self.overlap.iter())
+            .zip(head)
+            .zip(head_window)
+        {
+            *out = overlap + bin.re * weight;
+        }
+        for ((overlap, bin), weight) in self.overlap.iter_mut().zip(tail).zip(tail_window) {
+            *overlap = bin.re * weight;
+        }
+    }
+}
+
+impl Afx for NoiseSuppressor {
+    fn prepare(&mut self, format: StreamFormat) {
+        (self.blocks, self.spectra) =
+            Self::build(self.settings, format.sample_rate, format.channels as usize);
+    }
+
+    fn apply_to(&mut self, audio: &mut [AudioSampleType]) -> bool {
+        let spectra = &mut self.spectra;
+        self.blocks.run(audio, |block, _| {
+            spectra.process(block);
+            true
+        });
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Uniform white noise from a fixed seed.
+    fn noise(len: usize, amplitude: AudioSampleType) -> Vec<AudioSampleType> {
+        let mut state = 0x2545_f491_u32;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                (state as AudioSampleType / u32::MAX as AudioSampleType * 2.0 - 1.0) * amplitude
+            })
+            .collect()
+    }
+
+    fn energy(audio: &[AudioSampleType]) -> AudioSampleType {
+        audio.iter().map(|sample| sample * sample).sum()
+    }
+
+    fn mono() -> NoiseSuppressor {
+        let mut suppressor = NoiseSuppressor::new(Default::default());
+        suppressor.prepare(StreamFormat {
+            channels: 1,
+            sample_format: crate::SampleFormat::F32,
+            sample_rate: 48_000,
+        });
+        suppressor
+    }
+
+    #[test]
+    fn steady_noise_is_turned_down() {
+        let mut suppressor = mono();
+        let mut audio = noise(48_000, 0.05);
+        let before = energy(&audio[24_000..]);
+        for buffer in audio.chunks_mut(960) {
+            assert!(suppressor.apply_to(buffer));
+        }
+        let after = energy(&audio[24_000..]);
+        // Well on the way to the 20 dB floor once the estimate settled.
+        assert!(after < before / 15.0, "{before} -> {after}");
+    }
+
+    #[test]
+    fn a_tone_over_the_noise_survives() {
+        let mut suppressor = mono();
+        let mut audio = noise(96_000, 0.01);
+        for buffer in audio[..48_000].chunks_mut(960) {
+            suppressor.apply_to(buffer);
+        }
+        let mut tone: Vec<AudioSampleType> = (0..9_600)
+            .map(|n| 0.3 * (2.0 * std::f32::consts::PI * 440.0 * n as f32 / 48_000.0).sin())
+            .collect();
+        let before = energy(&tone[4_800..]);
+        for buffer in tone.chunks_mut(960) {
+            suppressor.apply_to(buffer);
+        }
+        let after = energy(&tone[4_800..]);
+        assert!(after > before * 0.8, "{before} -> {after}");
+    }
+}
@@ -0,0 +1,266 @@
+//! Shared plumbing for the effects that work on a mono spectrum: a radix-2
+//! FFT, and [`MonoBlocks`], which turns the arbitrary interleaved buffers an
+//! [`Afx`](super::Afx) is handed into fixed-size mono blocks and back.
+
+use std::{
+    collections::VecDeque,
+    f64::consts::PI,
+    ops::{Add, AddAssign, Mul, Sub},
+};
+
+use crate::AudioSampleType;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(super) struct Complex {
+    pub(super) re: AudioSampleType,
+    pub(super) im: AudioSampleType,
+}
+
+impl Complex {
+    pub(super) const ZERO: Complex = Complex { re: 0.0, im: 0.0 };
+
+    pub(super) fn real(re: AudioSampleType) -> Self {
+        Complex { re, im: 0.0 }
+    }
+
+    pub(super) fn conj(self) -> Self {
+        Complex {
+            re: self.re,
+            im: -self.im,
+        }
+    }
+
+    pub(super) fn norm_sqr(self) -> AudioSampleType {
+        self.re * self.re + self.im * self.im
+    }
+
+    pub(super) fn scale(self, factor: AudioSampleType) -> Self {
+        Complex {
+            re: self.re * factor,
+            im: self.im * factor,
+        }
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+    fn add(self, other: Complex) -> Complex {
+        Complex {
+            re: self.re + other.re,
+            im: self.im + other.im,
+        }
+    }
+}
+
+impl AddAssign for Complex {
+    fn add_assign(&mut self, other: Complex) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+    fn sub(self, other: Complex) -> Complex {
+        Complex {
+            re: self.re - other.re,
+            im: self.im - other.im,
+        }
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+    fn mul(self, other: Complex) -> Complex {
+        Complex {
+            re: self.re * other.re - self.im * other.im,
+            im: self.re * other.im + self.im * other.re,
+        }
+    }
+}
+
+/// An in-place radix-2 FFT of one power-of-two size.
+pub(super) struct Fft {
+    /// `e^(-2πik/size)` for the first half of the circle.
+    twiddles: Box<[Complex]>,
+    /// Where each index lands in bit-reversed order.
+    reversed: Box<[usize]>,
+}
+
+impl Fft {
+    pub(super) fn new(size: usize) -> Self {
+        assert!(size.is_power_of_two(), "FFT size must be a power of two");
+        let bits = size.trailing_zeros();
+        Fft {
+            twiddles: (0..size / 2)
+                .map(|k| {
+                    let angle = -2.0 * PI * k as f64 / size as f64;
+                    Complex {
+                        re: angle.cos() as AudioSampleType,
+                        im: angle.sin() as AudioSampleType,
+                    }
+                })
+                .collect(),
+            reversed: (0..size)
+                .map(|index| {
+                    index
+                        .reverse_bits()
+                        .checked_shr(usize::BITS - bits)
+                        .unwrap_or(0)
+                })
+                .collect(),
+        }
+    }
+
+    pub(super) fn size(&self) -> usize {
+        self.reversed.len()
+    }
+
+    pub(super) fn forward(&self, data: &mut [Complex]) {
+        debug_assert_eq!(data.len(), self.size());
+        for (index, &reversed) in self.reversed.iter().enumerate() {
+            if index < reversed {
+                data.swap(index, reversed);
+            }
+        }
+        let size = self.size();
+        let mut len = 2;
+        while len <= size {
+            let stride = size / len;
+            for start in (0..size).step_by(len) {
+                for k in 0..len / 2 {
+                    let even = data[start + k];
+                    let odd = data[start + k + len / 2] * self.twiddles[k * stride];
+                    data[start + k] = even + odd;
+                    data[start + k + len / 2] = even - odd;
+                }
+            }
+            len *= 2;
+        }
+    }
+
+    /// The inverse of [`Self::forward`], scaling included.
+    pub(super) fn inverse(&self, data: &mut [Complex]) {
+        for bin in data.iter_mut() {
+            *bin = bin.conj();
+        }
+        self.forward(data);
+        let scale = 1.0 / self.size() as AudioSampleType;
+        for bin in data.iter_mut() {
+            *bin = bin.conj().scale(scale);
+        }
+    }
+}
+
+/// Runs a mono block process over interleaved buffers of any length. Frames
+/// are downmixed into a queue and handed over `block` at a time; what comes
+/// back is written to every channel `latency` frames after it went in, so a
+/// buffer always leaves as long as it came.
+pub(super) struct MonoBlocks {
+    channels: usize,
+    block: usize,
+    input: VecDeque<AudioSampleType>,
+    output: VecDeque<AudioSampleType>,
+    scratch: Box<[AudioSampleType]>,
+}
+
+impl MonoBlocks {
+    /// `latency` must be at least `block`; a process that sometimes holds a
+    /// block back (see [`Self::run`]) needs that much more.
+    pub(super) fn new(channels: usize, block: usize, latency: usize) -> Self {
+        debug_assert!(latency >= block);
+        MonoBlocks {
+            channels: channels.max(1),
+            block,
+            input: VecDeque::with_capacity(latency + block),
+            output: std::iter::repeat_n(0.0, latency).collect(),
+            scratch: vec![0.0; block].into_boxed_slice(),
+        }
+    }
+
+    /// Feed `audio` through `process`, which gets each full block along with
+    /// how many frames are queued behind it and transforms it in place. It
+    /// may return `false` to hold the block until the next buffer arrives.
+    pub(super) fn run(
+        &mut self,
+        audio: &mut [AudioSampleType],
+        mut process: impl FnMut(&mut [AudioSampleType], usize) -> bool,
+    ) {
+        let channels = self.channels as AudioSampleType;
+        self.input.extend(
+            audio
+                .chunks_exact(self.channels)
+                .map(|frame| frame.iter().sum::<AudioSampleType>() / channels),
+        );
+        while self.input.len() >= self.block {
+            for (slot, sample) in self.scratch.iter_mut().zip(&self.input) {
+                *slot = *sample;
+            }
+            if !process(&mut self.scratch, self.input.len() - self.block) {
+                break;
+            }
+            self.input.drain(..self.block);
+            self.output.extend(self.scratch.iter());
+        }
+        for frame in audio.chunks_exact_mut(self.channels) {
+            frame.fill(self.output.pop_front().unwrap_or(0.0));
+        }
+    }
+}
+
+/// The smallest power of two spanning at least `seconds` at `sample_rate`.
+pub(super) fn block_len(seconds: f64, sample_rate: u32) -> usize {
+    ((seconds * sample_rate as f64).ceil() as usize)
+        .max(1)
+        .next_power_of_two()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fft_finds_a_tone_and_round_trips() {
+        let fft = Fft::new(16);
+        let signal: Vec<Complex> = (0..16)
+            .map(|n| Complex::real((2.0 * std::f32::consts::PI * 3.0 * n as f32 / 16.0).cos()))
+            .collect();
+        let mut spectrum = signal.clone();
+        fft.forward(&mut spectrum);
+        for (bin, value) in spectrum.iter().enumerate() {
+            let expected = if bin == 3 || bin == 13 { 8.0 } else { 0.0 };
+            assert!((value.re - expected).abs() < 1e-4 && value.im.abs() < 1e-4);
+        }
+        fft.inverse(&mut spectrum);
+        for (back, original) in spectrum.iter().zip(&signal) {
+            assert!((back.re - original.re).abs() < 1e-5 && back.im.abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn blocks_come_back_late_on_every_channel() {
+        let mut blocks = MonoBlocks::new(2, 4, 4);
+        let mut seen = Vec::new();
+        let mut audio = [1.0, 3.0, 2.0, 2.0, 4.0, 0.0];
+        blocks.run(&mut audio, |block, _| {
+            seen.push(block.to_vec());
+            true
+        });
+        // Three frames can't fill a block: only the latency comes out.
+        assert!(seen.is_empty());
+        assert_eq!(audio, [0.0; 6]);
+
+        let mut audio = [1.0, 1.0, 5.0, 5.0, 6.0, 6.0];
+        blocks.run(&mut audio, |block, queued| {
+            assert_eq!(queued, 2);
+            block.iter_mut().for_each(|sample| *sample *= 10.0);
+            true
+        });
+        assert_eq!(audio, [0.0, 0.0, 20.0, 20.0, 20.0, 20.0]);
+    }
+
+    #[test]
+    fn block_len_rounds_up_to_a_power_of_two() {
+        assert_eq!(block_len(0.005, 48_000), 256);
+        assert_eq!(block_len(0.005, 16_000), 128);
+    }
+}
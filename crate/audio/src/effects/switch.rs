@@ -0,0 +1,67 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+
+use crate::AudioSampleType;
+
+use super::Afx;
+
+/// Keeps audio only while its [`SwitchHandle`] is on, e.g. for push-to-talk.
+/// Discarded buffers never reach the caller, exactly like a closed [`Gate`].
+///
+/// [`Gate`]: super::Gate
+pub struct Switch {
+    on: Arc<AtomicBool>,
+}
+
+/// Flips every [`Switch`] made from it, from any thread.
+#[derive(Debug, Clone)]
+pub struct SwitchHandle {
+    on: Arc<AtomicBool>,
+}
+
+impl SwitchHandle {
+    pub fn new(on: bool) -> Self {
+        Self {
+            on: Arc::new(AtomicBool::new(on)),
+        }
+    }
+
+    /// A new effect following this handle.
+    pub fn switch(&self) -> Switch {
+        Switch {
+            on: self.on.clone(),
+        }
+    }
+
+    pub fn set(&self, on: bool) {
+        self.on.store(on, Ordering::Relaxed);
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.on.load(Ordering::Relaxed)
+    }
+}
+
+impl Afx for Switch {
+    fn apply_to(&mut self, _audio: &mut [AudioSampleType]) -> bool {
+        self.on.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn switch_follows_its_handle() {
+        let handle = SwitchHandle::new(false);
+        let mut switch = handle.switch();
+        let mut audio = [0.1, -0.2];
+        assert!(!switch.apply_to(&mut audio));
+        handle.set(true);
+        assert!(switch.apply_to(&mut audio));
+        assert_eq!(audio, [0.1, -0.2]);
+    }
+}
@@ -1,4 +1,12 @@
-use std::{error::Error, fmt::Debug, io, sync::Arc};
+use std::{
+    error::Error,
+    fmt::Debug,
+    io,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
 
 use cpal::{FromSample, Sample, SizedSample, traits::DeviceTrait as _};
 use ringbuf::{
@@ -10,9 +18,13 @@ use tracing::error;
 use crate::{
     AudioMixer, AudioSampleType, CHANNEL_BUFFER_SIZE, CHANNEL_HEADROOM, CONVERT_SCRATCH_LEN,
     MIX_SCRATCH_LEN, Notify, SampleConsum, SampleProd, SampleRb, StreamFormat,
-    resample::Resampler,
+    backend::InputBackend,
     effects::EffectChain,
-    stream::{for_each_sample_format, update_presence},
+    resample::{Resampler, Resampling},
+    stream::{
+        Device, DeviceFormat, Master, RunningStream, SharedFormat, for_each_sample_format,
+        update_presence,
+    },
 };
 
 pub(crate) enum InputRxEvent {
@@ -35,13 +47,63 @@ pub struct SampleConsumer {
     cons: SampleConsum<CHANNEL_BUFFER_SIZE>,
     notify: Arc<Notify>,
     resampler: Resampler,
+    resampling: Resampling,
     effects: EffectChain,
     /// Declared-format staging between conversion/effects and the caller.
     scratch: Box<[AudioSampleType]>,
     format: StreamFormat,
+    /// The device format `resampler` is set up for, and the mixer's current
+    /// one, which moves when the device is swapped.
+    device_format: DeviceFormat,
+    shared_format: SharedFormat,
+    /// Samples the previous device left in the ring, counted when it was
+    /// swapped out; skipped rather than converted as the new device's.
+    stale: Arc<AtomicUsize>,
 }
 
 impl SampleConsumer {
+    /// A consumer for `rb`, converting from `master`'s format into `format`
+    /// and following it across device swaps.
+    pub(crate) fn new<E>(
+        rb: &SampleRb<CHANNEL_BUFFER_SIZE>,
+        notify: Arc<Notify>,
+        stale: Arc<AtomicUsize>,
+        format: StreamFormat,
+        resampling: Resampling,
+        mut effects: EffectChain,
+        master: &Master<E>,
+    ) -> Self {
+        let device = master.format();
+        // Effects run in the channel's declared format (post-conversion);
+        // give each the format it will see before any audio flows.
+        for effect in effects.iter_mut() {
+            effect.prepare(format);
+        }
+        SampleConsumer {
+            cons: CachingCons::new(rb.clone()),
+            notify,
+            resampler: Resampler::new(
+                resampling,
+                device.format.channels as usize,
+                device.format.sample_rate,
+                format.channels as usize,
+                format.sample_rate,
+            ),
+            resampling,
+            effects,
+            scratch: vec![0.0; CONVERT_SCRATCH_LEN].into_boxed_slice(),
+            format,
+            device_format: device,
+            shared_format: master.shared_format(),
+            stale,
+        }
+    }
+
+    /// The format samples are popped in.
+    pub fn format(&self) -> StreamFormat {
+        self.format
+    }
+
     /// Non-blocking pop of converted samples into `out`. Returns the number
     /// of samples written (always whole frames).
     pub fn pop_now<T>(&mut self, out: &mut [T]) -> usize
@@ -53,6 +115,12 @@ impl SampleConsumer {
             self.format.sample_format,
             "popped sample type does not match the channel's declared format",
         );
+        let shared_format = self.shared_format.clone();
+        // Held for the whole pop, so a device swap can't land halfway through.
+        let device_format = shared_format.read().unwrap();
+        if *device_format != self.device_format {
+            self.follow_device(*device_format);
+        }
         let dst_ch = self.format.channels as usize;
         let usable = out.len() - out.len() % dst_ch;
         let mut written = 0;
@@ -95,6 +163,16 @@ impl SampleConsumer {
         written
     }
 
+    /// Drop everything captured but not yet popped, so the next pop starts
+    /// with fresh audio, e.g. after the caller stopped popping for a while.
+    pub fn clear(&mut self) {
+        // Under the format lock, so a device swap can't count samples this
+        // is about to drop.
+        let _device_format = self.shared_format.read().unwrap();
+        self.cons.clear();
+        self.stale.store(0, Ordering::Relaxed);
+    }
+
     /// Pop converted samples, waiting until at least one frame is available.
     /// Returns 0 only if `out` cannot hold a single frame.
     pub async fn pop<T>(&mut self, out: &mut [T]) -> usize
@@ -112,17 +190,34 @@ impl SampleConsumer {
             self.notify.notified().await;
         }
     }
+
+    /// Convert from a newly swapped-in device from here on, dropping whatever
+    /// is still buffered in the old device's format.
+    fn follow_device(&mut self, device_format: DeviceFormat) {
+        // Only what the old device captured; the new one may already have
+        // written after it.
+        self.cons.skip(self.stale.swap(0, Ordering::Relaxed));
+        self.resampler = Resampler::new(
+            self.resampling,
+            device_format.format.channels as usize,
+            device_format.format.sample_rate,
+            self.format.channels as usize,
+            self.format.sample_rate,
+        );
+        self.device_format = device_format;
+    }
 }
 
 impl AudioMixer {
     /// Create an input (capture) channel. `format` declares what the
     /// application wants to receive; captured audio is converted from the
-    /// device's preferred format, with `effects` applied (in the declared
-    /// format) at pop time.
+    /// device's preferred format with `resampling`, and `effects` applied (in
+    /// the declared format) at pop time.
     pub fn create_input_channel(
         &mut self,
         format: StreamFormat,
-        mut effects: EffectChain,
+        resampling: Resampling,
+        effects: EffectChain,
     ) -> Result<SampleConsumer, Box<dyn Error>> {
         if format.channels == 0 || format.sample_rate == 0 {
             return Err("invalid stream format: zero channels or sample rate".into());
@@ -134,29 +229,18 @@ impl AudioMixer {
         let Some(master) = &mut self.input else {
             return Err("no input device available".into());
         };
-        let device_format = StreamFormat::of_device(&master.config);
-
-        // Input effects run in the channel's declared format (post-conversion);
-        // give each the format it will see before any audio flows.
-        for effect in effects.iter_mut() {
-            effect.prepare(format);
-        }
-
         let rb = SampleRb::<CHANNEL_BUFFER_SIZE>::default();
         let notify = Arc::new(Notify::new());
-        let consumer = SampleConsumer {
-            cons: CachingCons::new(rb.clone()),
-            notify: notify.clone(),
-            resampler: Resampler::new(
-                device_format.channels as usize,
-                device_format.sample_rate,
-                format.channels as usize,
-                format.sample_rate,
-            ),
-            effects,
-            scratch: vec![0.0; CONVERT_SCRATCH_LEN].into_boxed_slice(),
+        let stale = Arc::new(AtomicUsize::new(0));
+        let consumer = SampleConsumer::new(
+            &rb,
+            notify.clone(),
+            stale.clone(),
             format,
-        };
+            resampling,
+            effects,
+            master,
+        );
 
         if let Some(event_tx) = master.event_tx() {
             let sample_producer = CachingProd::new(rb.clone());
@@ -169,7 +253,7 @@ impl AudioMixer {
                     io::Error::other(format!("failed to push input channel event: {err:?}"))
                 })?;
         }
-        self.input_channels.push((rb, notify));
+        self.input_channels.push((rb, notify, stale));
 
         Ok(consumer)
     }
@@ -181,112 +265,180 @@ impl AudioMixer {
     /// already running returns the running stream's notification.
     pub fn start_stream_input(&mut self) -> Result<Option<Arc<Notify>>, Box<dyn Error>> {
         self.prune_input_channels();
-        let Some(master) = &mut self.input else {
+        let Some(master) = &self.input else {
             return Ok(None);
         };
         if let Some(notify) = master.running_notify() {
             return Ok(Some(notify));
         }
+        let close_notify = Arc::new(Notify::new());
+        self.run_input(close_notify.clone())?;
+        Ok(Some(close_notify))
+    }
 
+    /// Stop the capture stream. Channels survive; a later
+    /// `start_stream_input` picks them up again.
+    pub fn stop_stream_input(&mut self) {
+        if let Some(input) = &mut self.input {
+            input.stop();
+        }
+        self.prune_input_channels();
+    }
+
+    /// Move capture to another device. Channels carry over: their consumers
+    /// keep working, converting from the new device's format from their next
+    /// pop, and a running stream restarts on the new device with the same
+    /// close notification. If the device can't be opened, the current one
+    /// stays.
+    pub fn set_input_device(&mut self, backend: InputBackend) -> Result<(), Box<dyn Error>> {
+        let new = backend.open()?;
+        self.prune_input_channels();
+        let Some(master) = &mut self.input else {
+            self.input = Some(new);
+            return Ok(());
+        };
+        let running = master.running_notify();
+        let channels = &self.input_channels;
+        // Only a channel's consumer may drain its ring; each skips what the
+        // old device captured when it notices the new format. The old stream
+        // is gone, so that is everything the ring holds now.
+        master.replace_device(new, || {
+            for (rb, _, stale) in channels {
+                stale.store(rb.occupied_len(), Ordering::Relaxed);
+            }
+        });
+        if let Some(close_notify) = running {
+            self.run_input(close_notify.clone()).inspect_err(|_| {
+                // Nothing will close the stream now; don't leave its waiter hanging.
+                close_notify.notify_one();
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Run the capture stream over every live channel.
+    fn run_input(&mut self, close_notify: Arc<Notify>) -> Result<(), Box<dyn Error>> {
+        let Some(master) = &mut self.input else {
+            return Ok(());
+        };
         let producers = self
             .input_channels
             .iter()
-            .map(|(rb, notify)| (CachingProd::new(rb.clone()), notify.clone()))
+            .map(|(rb, notify, _)| (CachingProd::new(rb.clone()), notify.clone()))
             .collect::<Vec<_>>();
 
         master.start(
             "input",
+            close_notify,
             producers,
-            |device, config, sample_format, producers, events, close_notify| {
-                for_each_sample_format!(
-                    sample_format,
-                    build_input_stream_typed,
-                    device,
-                    config,
-                    producers,
-                    events,
-                    close_notify
-                )
+            |device, config, sample_format, producers, events, close_notify| match device {
+                Device::System(device) => {
+                    let stream: Result<cpal::Stream, Box<dyn Error>> = for_each_sample_format!(
+                        sample_format,
+                        build_input_stream_typed,
+                        device,
+                        config,
+                        producers,
+                        events,
+                        close_notify
+                    );
+                    stream.map(RunningStream::System)
+                }
+                Device::Virtual(device) => device
+                    .run_input(
+                        config.channels as usize,
+                        config.sample_rate,
+                        input_callback(config.channels as usize, producers, events, close_notify),
+                    )
+                    .map(|stream| RunningStream::Virtual { _stream: stream }),
             },
         )
     }
-
-    /// Stop the capture stream. Channels survive; a later
-    /// `start_stream_input` picks them up again.
-    pub fn stop_stream_input(&mut self) {
-        if let Some(input) = &mut self.input {
-            input.stop();
-        }
-        self.prune_input_channels();
-    }
 }
 
-/// Build the real-time capture callback. It must stay free of locks,
-/// allocation and blocking: it drains the channel-event ring and copies
-/// device frames into per-channel rings. The per-channel
-/// `notify_one` is lock-free unless that channel's consumer task is parked —
-/// waking it is the point — and the close notification fires once, on the
-/// transition to zero channels.
 fn build_input_stream_typed<T>(
     device: &cpal::Device,
     config: &cpal::StreamConfig,
-    mut sample_producers: Vec<(SampleProd<CHANNEL_BUFFER_SIZE>, Arc<Notify>)>,
-    mut events: CachingCons<Arc<StaticRb<InputRxEvent, 8>>>,
+    sample_producers: Vec<(SampleProd<CHANNEL_BUFFER_SIZE>, Arc<Notify>)>,
+    events: CachingCons<Arc<StaticRb<InputRxEvent, 8>>>,
     close_notify: Arc<Notify>,
 ) -> Result<cpal::Stream, cpal::BuildStreamError>
 where
     T: SizedSample,
     AudioSampleType: FromSample<T>,
 {
-    let device_channels = config.channels as usize;
+    let mut callback = input_callback(
+        config.channels as usize,
+        sample_producers,
+        events,
+        close_notify,
+    );
+    device.build_input_stream(
+        config,
+        move |data: &[T], _| callback(data),
+        move |err| {
+            error!("Audio input stream error: {err:?}");
+        },
+        None,
+    )
+}
+
+/// The real-time capture callback. It must stay free of locks, allocation
+/// and blocking: it drains the channel-event ring and copies device frames
+/// into per-channel rings. The per-channel `notify_one` is lock-free unless
+/// that channel's consumer task is parked — waking it is the point — and the
+/// close notification fires once, on the transition to zero channels.
+pub(crate) fn input_callback<T>(
+    device_channels: usize,
+    mut sample_producers: Vec<(SampleProd<CHANNEL_BUFFER_SIZE>, Arc<Notify>)>,
+    mut events: CachingCons<Arc<StaticRb<InputRxEvent, 8>>>,
+    close_notify: Arc<Notify>,
+) -> impl FnMut(&[T]) + Send + 'static
+where
+    T: Sample,
+    AudioSampleType: FromSample<T>,
+{
     // Frame-aligned chunking so a partial push can never shift interleaving.
     let chunk_len = (MIX_SCRATCH_LEN / device_channels) * device_channels;
     sample_producers.reserve(CHANNEL_HEADROOM);
     let mut had_channels = !sample_producers.is_empty();
     let mut in_buf = [0.0 as AudioSampleType; MIX_SCRATCH_LEN];
 
-    device.build_input_stream(
-        config,
-        move |data: &[T], _| {
-            for event in events.pop_iter() {
-                match event {
-                    InputRxEvent::AddInputChannel(prod, notify) => {
-                        sample_producers.push((prod, notify))
-                    }
+    move |data: &[T]| {
+        for event in events.pop_iter() {
+            match event {
+                InputRxEvent::AddInputChannel(prod, notify) => {
+                    sample_producers.push((prod, notify))
                 }
             }
-            // Dropped here when the consumer is gone, but the mixer still
-            // holds each ring's `Arc`, so the deallocating drop happens on
-            // the control thread (`AudioMixer::prune_input_channels`).
-            sample_producers.retain(|(prod, _)| prod.read_is_held());
-            update_presence(
-                !sample_producers.is_empty(),
-                &mut had_channels,
-                &close_notify,
-            );
-            if sample_producers.is_empty() {
-                return;
-            }
+        }
+        // Dropped here when the consumer is gone, but the mixer still
+        // holds each ring's `Arc`, so the deallocating drop happens on
+        // the control thread (`AudioMixer::prune_input_channels`).
+        sample_producers.retain(|(prod, _)| prod.read_is_held());
+        update_presence(
+            !sample_producers.is_empty(),
+            &mut had_channels,
+            &close_notify,
+        );
+        if sample_producers.is_empty() {
+            return;
+        }
 
-            for chunk in data.chunks(chunk_len) {
-                let len = chunk.len();
-                for (slot, sample) in in_buf[..len].iter_mut().zip(chunk) {
-                    *slot = AudioSampleType::from_sample(*sample);
-                }
+        for chunk in data.chunks(chunk_len) {
+            let len = chunk.len();
+            for (slot, sample) in in_buf[..len].iter_mut().zip(chunk) {
+                *slot = AudioSampleType::from_sample(*sample);
+            }
 
-                for (prod, notify) in sample_producers.iter_mut() {
-                    let vacant = prod.vacant_len();
-                    // Whole frames only: an overrun must not flip parity.
-                    let take = (vacant - vacant % device_channels).min(len);
-                    if prod.push_slice(&in_buf[..take]) > 0 {
-                        notify.notify_one();
-                    }
+            for (prod, notify) in sample_producers.iter_mut() {
+                let vacant = prod.vacant_len();
+                // Whole frames only: an overrun must not flip parity.
+                let take = (vacant - vacant % device_channels).min(len);
+                if prod.push_slice(&in_buf[..take]) > 0 {
+                    notify.notify_one();
                 }
             }
-        },
-        move |err| {
-            error!("Audio input stream error: {err:?}");
-        },
-        None,
-    )
+        }
+    }
 }
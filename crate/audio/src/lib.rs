@@ -1,20 +1,21 @@
-use std::sync::Arc;
+use std::sync::{Arc, atomic::AtomicUsize};
 
+use cpal::SupportedStreamConfig;
 pub use cpal::{ChannelCount, SampleFormat, SampleRate};
-use cpal::{
-    SupportedStreamConfig,
-    traits::{DeviceTrait as _, HostTrait as _},
-};
 use ringbuf::{StaticRb, traits::Observer as _, wrap::caching::Caching};
+use tracing::warn;
 
 pub use asyncs_sync::Notify;
+pub use resample::Resampling;
 
 use crate::{
+    backend::{InputBackend, OutputBackend},
     input::InputRxEvent,
-    output::OutputRxEvent,
-    stream::{Master, open_master},
+    output::{ChannelControl, OutputRxEvent},
+    stream::Master,
 };
 
+pub mod backend;
 pub mod effects;
 pub mod input;
 pub mod output;
@@ -58,37 +59,49 @@ impl StreamFormat {
     }
 }
 
+/// A ring a [`SampleConsumer`] pops from, its wakeup, and how many samples
+/// in it a swapped-out device left behind.
+type CaptureChannel = (SampleRb<CHANNEL_BUFFER_SIZE>, Arc<Notify>, Arc<AtomicUsize>);
+
 pub struct AudioMixer {
     output: Option<Master<OutputRxEvent>>,
     input: Option<Master<InputRxEvent>>,
-    /// Rings of all live channels. Holding an `Arc` here guarantees the audio
-    /// thread never drops the last reference to a ring; deallocation happens
-    /// on the control thread when these lists are pruned.
-    output_channels: Vec<SampleRb<CHANNEL_BUFFER_SIZE>>,
-    input_channels: Vec<(SampleRb<CHANNEL_BUFFER_SIZE>, Arc<Notify>)>,
+    /// Rings (and output controls) of all live channels. Holding an `Arc`
+    /// here guarantees the audio thread never drops the last reference to
+    /// one; deallocation happens on the control thread when these lists are
+    /// pruned.
+    output_channels: Vec<(SampleRb<CHANNEL_BUFFER_SIZE>, ChannelControl)>,
+    input_channels: Vec<CaptureChannel>,
+    /// Rings the playback callback copies its mix into.
+    monitor_channels: Vec<CaptureChannel>,
 }
 
+/// A mixer over the host's default devices.
 impl Default for AudioMixer {
     fn default() -> Self {
-        let host = cpal::default_host();
+        Self::new(OutputBackend::System, InputBackend::System)
+    }
+}
+
+impl AudioMixer {
+    /// A mixer over the given devices. A direction whose device can't be
+    /// opened is logged and left without one, as if the host had none.
+    pub fn new(output: OutputBackend, input: InputBackend) -> Self {
         AudioMixer {
-            output: open_master(
-                host.default_output_device(),
-                |device| device.default_output_config(),
-                "Output",
-            ),
-            input: open_master(
-                host.default_input_device(),
-                |device| device.default_input_config(),
-                "Input",
-            ),
+            output: output
+                .open()
+                .inspect_err(|err| warn!("Output device unavailable: {err}"))
+                .ok(),
+            input: input
+                .open()
+                .inspect_err(|err| warn!("Input device unavailable: {err}"))
+                .ok(),
             output_channels: Vec::new(),
             input_channels: Vec::new(),
+            monitor_channels: Vec::new(),
         }
     }
-}
 
-impl AudioMixer {
     pub fn is_streaming_output(&self) -> bool {
         self.output
             .as_ref()
@@ -106,11 +119,13 @@ impl AudioMixer {
     /// one, so ring memory is never freed on the audio thread.
     pub(crate) fn prune_output_channels(&mut self) {
         self.output_channels
-            .retain(|rb| rb.write_is_held() || rb.read_is_held());
+            .retain(|(rb, _)| rb.write_is_held() || rb.read_is_held());
+        self.monitor_channels
+            .retain(|(rb, ..)| rb.write_is_held() || rb.read_is_held());
     }
 
     pub(crate) fn prune_input_channels(&mut self) {
         self.input_channels
-            .retain(|(rb, _)| rb.write_is_held() || rb.read_is_held());
+            .retain(|(rb, ..)| rb.write_is_held() || rb.read_is_held());
     }
 }
@@ -1,4 +1,12 @@
-use std::{error::Error, fmt::Debug, io, sync::Arc};
+use std::{
+    error::Error,
+    fmt::Debug,
+    io,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering},
+    },
+};
 
 use cpal::{FromSample, Sample, SizedSample, traits::DeviceTrait as _};
 use ringbuf::{
@@ -10,9 +18,13 @@ use tracing::error;
 use crate::{
     AudioMixer, AudioSampleType, CHANNEL_BUFFER_SIZE, CHANNEL_HEADROOM, CONVERT_SCRATCH_LEN,
     MIX_SCRATCH_LEN, Notify, SampleConsum, SampleProd, SampleRb, StreamFormat,
+    backend::OutputBackend,
     effects::EffectChain,
-    resample::Resampler,
-    stream::{for_each_sample_format, update_presence},
+    input::SampleConsumer,
+    resample::{Resampler, Resampling},
+    stream::{
+        Device, DeviceFormat, RunningStream, SharedFormat, for_each_sample_format, update_presence,
+    },
 };
 
 /// Application handle for pushing audio into an output channel.
@@ -24,13 +36,24 @@ use crate::{
 pub struct SampleProducer {
     producer: SampleProd<CHANNEL_BUFFER_SIZE>,
     resampler: Resampler,
+    resampling: Resampling,
     effects: EffectChain,
     /// Device-format staging between conversion/effects and the ring.
     scratch: Box<[AudioSampleType]>,
     format: StreamFormat,
+    /// The device format `resampler` and `effects` are set up for, and the
+    /// mixer's current one, which moves when the device is swapped.
+    device_format: DeviceFormat,
+    shared_format: SharedFormat,
+    control: ChannelControl,
 }
 
 impl SampleProducer {
+    /// The channel's mix controls, to hand to whatever adjusts them.
+    pub fn control(&self) -> ChannelControl {
+        self.control.clone()
+    }
+
     /// Push interleaved samples in the channel's declared format.
     ///
     /// Returns how many samples were consumed (always whole frames). A short
@@ -45,6 +68,12 @@ impl SampleProducer {
             self.format.sample_format,
             "pushed sample type does not match the channel's declared format",
         );
+        let shared_format = self.shared_format.clone();
+        // Held for the whole push, so a device swap can't land halfway through.
+        let device_format = shared_format.read().unwrap();
+        if *device_format != self.device_format {
+            self.follow_device(*device_format);
+        }
         let src_ch = self.format.channels as usize;
         let dst_ch = self.resampler.dst_channels();
         let usable = samples.len() - samples.len() % src_ch;
@@ -88,15 +117,193 @@ impl SampleProducer {
         }
         consumed
     }
+
+    /// Convert for a newly swapped-in device from here on. Whatever was
+    /// buffered for the old device was dropped with the swap.
+    fn follow_device(&mut self, device_format: DeviceFormat) {
+        self.resampler = Resampler::new(
+            self.resampling,
+            self.format.channels as usize,
+            self.format.sample_rate,
+            device_format.format.channels as usize,
+            device_format.format.sample_rate,
+        );
+        for effect in self.effects.iter_mut() {
+            effect.prepare(device_format.format);
+        }
+        self.device_format = device_format;
+    }
+}
+
+/// Volume, mute and pan for one output channel, applied by the audio thread
+/// as it mixes — so unlike a [`Gain`](crate::effects::Gain) effect they can
+/// change at any time. Clones share the same controls. Setting them never
+/// blocks, and changes are ramped over a callback so they don't click.
+#[derive(Clone, Debug, Default)]
+pub struct ChannelControl(Arc<Controls>);
+
+#[derive(Debug)]
+struct Controls {
+    /// `f32` bit patterns.
+    volume: AtomicU32,
+    pan: AtomicU32,
+    muted: AtomicBool,
+}
+
+impl Default for Controls {
+    fn default() -> Self {
+        Controls {
+            volume: AtomicU32::new(1.0f32.to_bits()),
+            pan: AtomicU32::new(0.0f32.to_bits()),
+            muted: AtomicBool::new(false),
+        }
+    }
+}
+
+impl ChannelControl {
+    /// Linear gain; 1 plays the channel as pushed. Negative volumes are
+    /// taken as 0.
+    pub fn set_volume(&self, volume: f32) {
+        let volume = if volume > 0.0 { volume } else { 0.0 };
+        self.0.volume.store(volume.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn volume(&self) -> f32 {
+        f32::from_bits(self.0.volume.load(Ordering::Relaxed))
+    }
+
+    /// Silence the channel without losing its volume. It keeps draining
+    /// while muted, so unmuting resumes in step.
+    pub fn set_muted(&self, muted: bool) {
+        self.0.muted.store(muted, Ordering::Relaxed);
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.0.muted.load(Ordering::Relaxed)
+    }
+
+    /// Balance between the first two device channels, from -1 (left only)
+    /// through 0 (both as pushed) to 1 (right only). Mono devices ignore it.
+    pub fn set_pan(&self, pan: f32) {
+        let pan = if pan.is_nan() {
+            0.0
+        } else {
+            pan.clamp(-1.0, 1.0)
+        };
+        self.0.pan.store(pan.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn pan(&self) -> f32 {
+        f32::from_bits(self.0.pan.load(Ordering::Relaxed))
+    }
+
+    fn gains(&self) -> Gains {
+        if self.is_muted() {
+            return Gains::SILENT;
+        }
+        let volume = self.volume() as AudioSampleType;
+        let pan = self.pan() as AudioSampleType;
+        Gains {
+            left: volume * (1.0 - pan).min(1.0),
+            right: volume * (1.0 + pan).min(1.0),
+            rest: volume,
+        }
+    }
+}
+
+/// Per-device-channel gains: the front pair, and every other channel (all of
+/// a mono device).
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Gains {
+    left: AudioSampleType,
+    right: AudioSampleType,
+    rest: AudioSampleType,
+}
+
+impl Gains {
+    const UNITY: Gains = Gains {
+        left: 1.0,
+        right: 1.0,
+        rest: 1.0,
+    };
+    const SILENT: Gains = Gains {
+        left: 0.0,
+        right: 0.0,
+        rest: 0.0,
+    };
+
+    fn for_channel(&self, channel: usize, device_channels: usize) -> AudioSampleType {
+        match channel {
+            0 if device_channels > 1 => self.left,
+            1 => self.right,
+            _ => self.rest,
+        }
+    }
+}
+
+/// An output channel as the playback callback sees it.
+pub(crate) struct MixChannel {
+    consumer: SampleConsum<CHANNEL_BUFFER_SIZE>,
+    control: ChannelControl,
+    /// What the last chunk ended on; the next one ramps from here.
+    gains: Gains,
+}
+
+impl MixChannel {
+    pub(crate) fn new(
+        consumer: SampleConsum<CHANNEL_BUFFER_SIZE>,
+        control: ChannelControl,
+    ) -> Self {
+        MixChannel {
+            gains: control.gains(),
+            consumer,
+            control,
+        }
+    }
+}
+
+/// Add `samples` (whole frames) into `mix`, scaled by gains ramping linearly
+/// from `from` to `to` across them.
+fn mix_scaled(
+    mix: &mut [AudioSampleType],
+    samples: &[AudioSampleType],
+    device_channels: usize,
+    from: Gains,
+    to: Gains,
+) {
+    if from == to && to == Gains::SILENT {
+        return;
+    }
+    if from == to && to == Gains::UNITY {
+        for (mixed, sample) in mix.iter_mut().zip(samples) {
+            *mixed += sample;
+        }
+        return;
+    }
+    let frames = (samples.len() / device_channels) as AudioSampleType;
+    for (index, (mixed, frame)) in mix
+        .chunks_exact_mut(device_channels)
+        .zip(samples.chunks_exact(device_channels))
+        .enumerate()
+    {
+        let t = (index + 1) as AudioSampleType / frames;
+        for (channel, (mixed, sample)) in mixed.iter_mut().zip(frame).enumerate() {
+            let from = from.for_channel(channel, device_channels);
+            let to = to.for_channel(channel, device_channels);
+            *mixed += sample * (from + (to - from) * t);
+        }
+    }
 }
 
 pub(crate) enum OutputRxEvent {
-    AddOutputChannel(SampleConsum<CHANNEL_BUFFER_SIZE>),
+    AddOutputChannel(MixChannel),
+    AddMonitorChannel(SampleProd<CHANNEL_BUFFER_SIZE>, Arc<Notify>),
 }
 impl Debug for OutputRxEvent {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::AddOutputChannel(_) => f.debug_tuple("AddOutputChannel").finish(),
+            Self::AddMonitorChannel(_, _) => f.debug_tuple("AddMonitorChannel").finish(),
         }
     }
 }
@@ -104,10 +311,12 @@ impl Debug for OutputRxEvent {
 impl AudioMixer {
     /// Create an output (playback) channel. `format` declares what the
     /// application will push; audio is converted to the device's preferred
-    /// format, with `effects` applied (in device format) at push time.
+    /// format with `resampling`, and `effects` applied (in device format) at
+    /// push time.
     pub fn create_output_channel(
         &mut self,
         format: StreamFormat,
+        resampling: Resampling,
         mut effects: EffectChain,
     ) -> Result<SampleProducer, Box<dyn Error>> {
         if format.channels == 0 || format.sample_rate == 0 {
@@ -117,7 +326,8 @@ impl AudioMixer {
         let Some(master) = &mut self.output else {
             return Err("no output device available".into());
         };
-        let device_format = StreamFormat::of_device(&master.config);
+        let device = master.format();
+        let device_format = device.format;
         if device_format.channels as usize > CONVERT_SCRATCH_LEN {
             return Err(format!(
                 "unsupported device channel count: {}",
@@ -132,32 +342,87 @@ impl AudioMixer {
         }
 
         let rb = SampleRb::<CHANNEL_BUFFER_SIZE>::default();
+        let control = ChannelControl::default();
         let producer = SampleProducer {
             producer: CachingProd::new(rb.clone()),
             resampler: Resampler::new(
+                resampling,
                 format.channels as usize,
                 format.sample_rate,
                 device_format.channels as usize,
                 device_format.sample_rate,
             ),
+            resampling,
             effects,
             scratch: vec![0.0; CONVERT_SCRATCH_LEN].into_boxed_slice(),
             format,
+            device_format: device,
+            shared_format: master.shared_format(),
+            control: control.clone(),
         };
 
         if let Some(event_tx) = master.event_tx() {
-            let sample_consumer = CachingCons::new(rb.clone());
+            let channel = MixChannel::new(CachingCons::new(rb.clone()), control.clone());
             event_tx
-                .try_push(OutputRxEvent::AddOutputChannel(sample_consumer))
+                .try_push(OutputRxEvent::AddOutputChannel(channel))
                 .map_err(|err| {
                     io::Error::other(format!("failed to push output channel event: {err:?}"))
                 })?;
         }
-        self.output_channels.push(rb);
+        self.output_channels.push((rb, control));
 
         Ok(producer)
     }
 
+    /// Create a monitor channel: a capture of the mix the output device plays,
+    /// every channel's volume and pan applied, e.g. as an
+    /// [`EchoCanceller`](crate::effects::EchoCanceller)'s reference. It pops
+    /// like an input channel, converted into `format` with `resampling` and
+    /// `effects` applied, and only receives audio while the output streams.
+    pub fn create_monitor_channel(
+        &mut self,
+        format: StreamFormat,
+        resampling: Resampling,
+        effects: EffectChain,
+    ) -> Result<SampleConsumer, Box<dyn Error>> {
+        if format.channels == 0 || format.sample_rate == 0 {
+            return Err("invalid stream format: zero channels or sample rate".into());
+        }
+        if format.channels as usize > CONVERT_SCRATCH_LEN {
+            return Err(format!("unsupported channel count: {}", format.channels).into());
+        }
+        self.prune_output_channels();
+        let Some(master) = &mut self.output else {
+            return Err("no output device available".into());
+        };
+        let rb = SampleRb::<CHANNEL_BUFFER_SIZE>::default();
+        let notify = Arc::new(Notify::new());
+        let stale = Arc::new(AtomicUsize::new(0));
+        let consumer = SampleConsumer::new(
+            &rb,
+            notify.clone(),
+            stale.clone(),
+            format,
+            resampling,
+            effects,
+            master,
+        );
+
+        if let Some(event_tx) = master.event_tx() {
+            event_tx
+                .try_push(OutputRxEvent::AddMonitorChannel(
+                    CachingProd::new(rb.clone()),
+                    notify.clone(),
+                ))
+                .map_err(|err| {
+                    io::Error::other(format!("failed to push monitor channel event: {err:?}"))
+                })?;
+        }
+        self.monitor_channels.push((rb, notify, stale));
+
+        Ok(consumer)
+    }
+
     /// Start the playback stream using the device's preferred configuration.
     ///
     /// Returns a notification that fires when the last channel closes, or
@@ -165,108 +430,263 @@ impl AudioMixer {
     /// is already running returns the running stream's notification.
     pub fn start_stream_output(&mut self) -> Result<Option<Arc<Notify>>, Box<dyn Error>> {
         self.prune_output_channels();
-        let Some(master) = &mut self.output else {
+        let Some(master) = &self.output else {
             return Ok(None);
         };
         if let Some(notify) = master.running_notify() {
             return Ok(Some(notify));
         }
+        let close_notify = Arc::new(Notify::new());
+        self.run_output(close_notify.clone())?;
+        Ok(Some(close_notify))
+    }
+
+    /// Stop the playback stream. Channels survive; a later
+    /// `start_stream_output` picks them up again.
+    pub fn stop_stream_output(&mut self) {
+        if let Some(output) = &mut self.output {
+            output.stop();
+        }
+        self.prune_output_channels();
+    }
+
+    /// Move playback to another device. Channels carry over: their producers
+    /// keep working, converting to the new device's format from their next
+    /// push, and a running stream restarts on the new device with the same
+    /// close notification. If the device can't be opened, the current one
+    /// stays.
+    pub fn set_output_device(&mut self, backend: OutputBackend) -> Result<(), Box<dyn Error>> {
+        let new = backend.open()?;
+        self.prune_output_channels();
+        let Some(master) = &mut self.output else {
+            self.output = Some(new);
+            return Ok(());
+        };
+        let running = master.running_notify();
+        let channels = &self.output_channels;
+        let monitors = &self.monitor_channels;
+        master.replace_device(new, || {
+            // The old stream's consumers are gone with it; drain each ring
+            // through a temporary one.
+            for (rb, _) in channels.iter().filter(|(rb, _)| !rb.read_is_held()) {
+                CachingCons::new(rb.clone()).clear();
+            }
+            // A monitor's consumer skips the old mix itself, as input
+            // channels do.
+            for (rb, _, stale) in monitors {
+                stale.store(rb.occupied_len(), Ordering::Relaxed);
+            }
+        });
+        if let Some(close_notify) = running {
+            self.run_output(close_notify.clone()).inspect_err(|_| {
+                // Nothing will close the stream now; don't leave its waiter hanging.
+                close_notify.notify_one();
+            })?;
+        }
+        Ok(())
+    }
 
+    /// Run the playback stream over every live channel.
+    fn run_output(&mut self, close_notify: Arc<Notify>) -> Result<(), Box<dyn Error>> {
+        let Some(master) = &mut self.output else {
+            return Ok(());
+        };
         let consumers = self
             .output_channels
             .iter()
-            .map(|rb| CachingCons::new(rb.clone()))
+            .map(|(rb, control)| MixChannel::new(CachingCons::new(rb.clone()), control.clone()))
+            .collect::<Vec<_>>();
+        let monitors = self
+            .monitor_channels
+            .iter()
+            .map(|(rb, notify, _)| (CachingProd::new(rb.clone()), notify.clone()))
             .collect::<Vec<_>>();
 
         master.start(
             "output",
-            consumers,
-            |device, config, sample_format, consumers, events, close_notify| {
-                for_each_sample_format!(
-                    sample_format,
-                    build_output_stream_typed,
-                    device,
-                    config,
-                    consumers,
-                    events,
-                    close_notify
-                )
+            close_notify,
+            (consumers, monitors),
+            |device, config, sample_format, (consumers, monitors), events, close_notify| {
+                match device {
+                    Device::System(device) => {
+                        let stream: Result<cpal::Stream, Box<dyn Error>> = for_each_sample_format!(
+                            sample_format,
+                            build_output_stream_typed,
+                            device,
+                            config,
+                            consumers,
+                            monitors,
+                            events,
+                            close_notify
+                        );
+                        stream.map(RunningStream::System)
+                    }
+                    Device::Virtual(device) => device
+                        .run_output(
+                            config.channels as usize,
+                            config.sample_rate,
+                            output_callback(
+                                config.channels as usize,
+                                consumers,
+                                monitors,
+                                events,
+                                close_notify,
+                            ),
+                        )
+                        .map(|stream| RunningStream::Virtual { _stream: stream }),
+                }
             },
         )
     }
-
-    /// Stop the playback stream. Channels survive; a later
-    /// `start_stream_output` picks them up again.
-    pub fn stop_stream_output(&mut self) {
-        if let Some(output) = &mut self.output {
-            output.stop();
-        }
-        self.prune_output_channels();
-    }
 }
 
-/// Build the real-time playback callback. It must stay free of locks,
-/// allocation and blocking: it drains the channel-event ring, mixes channel
-/// rings into the device buffer in frame-aligned chunks, and signals
-/// `close_notify` once when the last channel disappears (`notify_one` is
-/// lock-free unless a task is parked on it; firing it only on that transition
-/// keeps the cost off the steady-state path).
 fn build_output_stream_typed<T>(
     device: &cpal::Device,
     config: &cpal::StreamConfig,
-    mut sample_consumers: Vec<SampleConsum<CHANNEL_BUFFER_SIZE>>,
-    mut events: CachingCons<Arc<StaticRb<OutputRxEvent, 8>>>,
+    sample_consumers: Vec<MixChannel>,
+    monitors: Vec<(SampleProd<CHANNEL_BUFFER_SIZE>, Arc<Notify>)>,
+    events: CachingCons<Arc<StaticRb<OutputRxEvent, 8>>>,
     close_notify: Arc<Notify>,
 ) -> Result<cpal::Stream, cpal::BuildStreamError>
 where
     T: SizedSample + FromSample<AudioSampleType>,
 {
-    let device_channels = config.channels as usize;
+    let mut callback = output_callback(
+        config.channels as usize,
+        sample_consumers,
+        monitors,
+        events,
+        close_notify,
+    );
+    device.build_output_stream(
+        config,
+        move |data: &mut [T], _| callback(data),
+        move |err| {
+            error!("Audio output stream error: {err:?}");
+        },
+        None,
+    )
+}
+
+/// The real-time playback callback. It must stay free of locks, allocation
+/// and blocking: it drains the channel-event ring, mixes channel rings into
+/// the device buffer in frame-aligned chunks at each channel's volume and
+/// pan (read from atomics), copies the mix into every monitor ring, and
+/// signals `close_notify` once when the last channel disappears
+/// (`notify_one` is lock-free unless a task is parked on it; firing it only
+/// on that transition keeps the cost off the steady-state path). Monitors
+/// don't count as channels: they only listen.
+pub(crate) fn output_callback<T>(
+    device_channels: usize,
+    mut sample_consumers: Vec<MixChannel>,
+    mut monitors: Vec<(SampleProd<CHANNEL_BUFFER_SIZE>, Arc<Notify>)>,
+    mut events: CachingCons<Arc<StaticRb<OutputRxEvent, 8>>>,
+    close_notify: Arc<Notify>,
+) -> impl FnMut(&mut [T]) + Send + 'static
+where
+    T: Sample + FromSample<AudioSampleType>,
+{
     // Frame-aligned chunking so a partial pop can never shift interleaving.
     let chunk_len = (MIX_SCRATCH_LEN / device_channels) * device_channels;
     sample_consumers.reserve(CHANNEL_HEADROOM);
+    monitors.reserve(CHANNEL_HEADROOM);
     let mut had_channels = !sample_consumers.is_empty();
     let mut mix_buf = [0.0 as AudioSampleType; MIX_SCRATCH_LEN];
     let mut chan_buf = [0.0 as AudioSampleType; MIX_SCRATCH_LEN];
 
-    device.build_output_stream(
-        config,
-        move |data: &mut [T], _| {
-            for event in events.pop_iter() {
-                match event {
-                    OutputRxEvent::AddOutputChannel(cons) => sample_consumers.push(cons),
-                }
+    move |data: &mut [T]| {
+        for event in events.pop_iter() {
+            match event {
+                OutputRxEvent::AddOutputChannel(channel) => sample_consumers.push(channel),
+                OutputRxEvent::AddMonitorChannel(prod, notify) => monitors.push((prod, notify)),
             }
-            // Channels whose producer is gone are dropped here, but the mixer
-            // still holds each ring's `Arc`, so the deallocating drop happens
-            // on the control thread (`AudioMixer::prune_output_channels`).
-            sample_consumers.retain(|consumer| consumer.write_is_held());
-            update_presence(
-                !sample_consumers.is_empty(),
-                &mut had_channels,
-                &close_notify,
-            );
+        }
+        // Channels whose producer is gone are dropped here, but the mixer
+        // still holds each ring's and control's `Arc`, so the deallocating
+        // drop happens on the control thread (`AudioMixer::prune_output_channels`).
+        sample_consumers.retain(|channel| channel.consumer.write_is_held());
+        monitors.retain(|(prod, _)| prod.read_is_held());
+        update_presence(
+            !sample_consumers.is_empty(),
+            &mut had_channels,
+            &close_notify,
+        );
 
-            for chunk in data.chunks_mut(chunk_len) {
-                let len = chunk.len();
-                mix_buf[..len].fill(0.0);
-                for consumer in sample_consumers.iter_mut() {
-                    let available = consumer.occupied_len();
-                    // Whole frames only: an underrun must not flip parity.
-                    let take = (available - available % device_channels).min(len);
-                    let popped = consumer.pop_slice(&mut chan_buf[..take]);
-                    for (mixed, sample) in mix_buf[..popped].iter_mut().zip(&chan_buf[..popped]) {
-                        *mixed += sample;
-                    }
+        for chunk in data.chunks_mut(chunk_len) {
+            let len = chunk.len();
+            mix_buf[..len].fill(0.0);
+            for channel in sample_consumers.iter_mut() {
+                let available = channel.consumer.occupied_len();
+                // Whole frames only: an underrun must not flip parity.
+                let take = (available - available % device_channels).min(len);
+                let popped = channel.consumer.pop_slice(&mut chan_buf[..take]);
+                if popped == 0 {
+                    continue;
                 }
-                for (out, mixed) in chunk.iter_mut().zip(&mix_buf[..len]) {
-                    *out = T::from_sample(mixed.clamp(-1.0, 1.0));
+                let gains = channel.control.gains();
+                mix_scaled(
+                    &mut mix_buf[..popped],
+                    &chan_buf[..popped],
+                    device_channels,
+                    channel.gains,
+                    gains,
+                );
+                channel.gains = gains;
+            }
+            for (out, mixed) in chunk.iter_mut().zip(mix_buf[..len].iter_mut()) {
+                *mixed = mixed.clamp(-1.0, 1.0);
+                *out = T::from_sample(*mixed);
+            }
+            for (prod, notify) in monitors.iter_mut() {
+                let vacant = prod.vacant_len();
+                // Whole frames only: an overrun must not flip parity.
+                let take = (vacant - vacant % device_channels).min(len);
+                if prod.push_slice(&mix_buf[..take]) > 0 {
+                    notify.notify_one();
                 }
             }
-        },
-        move |err| {
-            error!("Audio output stream error: {err:?}");
-        },
-        None,
-    )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pan_balances_the_front_pair() {
+        let control = ChannelControl::default();
+        control.set_volume(0.5);
+        control.set_pan(0.5);
+        let gains = control.gains();
+        assert_eq!((gains.left, gains.right, gains.rest), (0.25, 0.5, 0.5));
+        control.set_pan(-2.0);
+        assert_eq!(control.pan(), -1.0);
+        let gains = control.gains();
+        assert_eq!((gains.left, gains.right), (0.5, 0.0));
+        // A mono device plays the volume, whatever the pan.
+        assert_eq!(gains.for_channel(0, 1), 0.5);
+    }
+
+    #[test]
+    fn mute_keeps_the_volume() {
+        let control = ChannelControl::default();
+        control.set_volume(0.5);
+        control.set_muted(true);
+        assert_eq!(control.gains(), Gains::SILENT);
+        control.set_muted(false);
+        assert_eq!(control.volume(), 0.5);
+        assert_eq!(control.gains().rest, 0.5);
+    }
+
+    #[test]
+    fn gain_changes_ramp_across_the_chunk() {
+        let mut mix = [0.0; 8];
+        mix_scaled(&mut mix, &[1.0; 8], 2, Gains::UNITY, Gains::SILENT);
+        assert_eq!(mix, [0.75, 0.75, 0.5, 0.5, 0.25, 0.25, 0.0, 0.0]);
+
+        let mut mix = [0.5; 4];
+        mix_scaled(&mut mix, &[1.0; 4], 2, Gains::UNITY, Gains::UNITY);
+        assert_eq!(mix, [1.5; 4]);
+    }
 }
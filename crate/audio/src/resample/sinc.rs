@@ -0,0 +1,133 @@
+//! Band-limited interpolation with a Blackman-windowed sinc kernel. The kernel
+//! is tabulated at [`PHASES`] fractional positions between two source frames;
+//! positions in between blend the neighbouring rows.
+
+use std::f64::consts::PI;
+
+use crate::AudioSampleType;
+
+/// Sinc zero crossings kept either side of the centre tap. Sets the filter's
+/// steepness, and its lookahead: this many source frames (more when
+/// downsampling) must arrive before a frame can be interpolated.
+pub(super) const ZERO_CROSSINGS: usize = 16;
+/// Kernel rows tabulated per source frame.
+const PHASES: usize = 128;
+/// Cutoff as a fraction of the lower Nyquist frequency, leaving the window's
+/// transition band room to fall off before it.
+const ROLLOFF: f64 = 0.92;
+
+pub(super) struct Sinc {
+    channels: usize,
+    /// Kernel taps, half of them past the centre.
+    taps: usize,
+    /// `PHASES + 1` rows of `taps` weights; each row sums to one.
+    table: Box<[AudioSampleType]>,
+    /// The last `taps` source frames, oldest first. Output is interpolated
+    /// between frame `taps / 2 - 1` and the next.
+    history: Box<[AudioSampleType]>,
+    /// Frames loaded into `history` so far, leading silence included.
+    filled: usize,
+    incoming: Box<[AudioSampleType]>,
+    weights: Box<[AudioSampleType]>,
+    step: f64,
+    phase: f64,
+}
+
+impl Sinc {
+    pub(super) fn new(channels: usize, src_rate: u32, dst_rate: u32) -> Self {
+        // Downsampling must also cut everything above the new Nyquist, which
+        // widens the kernel by the same factor.
+        let cutoff = (dst_rate as f64 / src_rate as f64).min(1.0) * ROLLOFF;
+        let half = (ZERO_CROSSINGS as f64 / cutoff).ceil() as usize;
+        let taps = 2 * half;
+
+        let mut table = vec![0.0; (PHASES + 1) * taps];
+        for (phase, row) in table.chunks_exact_mut(taps).enumerate() {
+            let frac = phase as f64 / PHASES as f64;
+            let weights: Vec<f64> = (0..taps)
+                .map(|tap| kernel(tap as f64 - (half - 1) as f64 - frac, cutoff, half as f64))
+                .collect();
+            // Unity gain at DC, so steady levels pass through exactly.
+            let sum: f64 = weights.iter().sum();
+            for (slot, weight) in row.iter_mut().zip(weights) {
+                *slot = (weight / sum) as AudioSampleType;
+            }
+        }
+
+        Sinc {
+            channels,
+            taps,
+            table: table.into_boxed_slice(),
+            history: vec![0.0; taps * channels].into_boxed_slice(),
+            // Silence before the stream starts puts its first frame at the
+            // centre, so output starts in step with the input.
+            filled: half - 1,
+            incoming: vec![0.0; channels].into_boxed_slice(),
+            weights: vec![0.0; taps].into_boxed_slice(),
+            step: src_rate as f64 / dst_rate as f64,
+            phase: 0.0,
+        }
+    }
+
+    /// Interpolate the next output frame into `out`, pulling source frames
+    /// through `next` as the window needs them. Returns `false`, leaving the
+    /// window intact, once `next` runs dry.
+    pub(super) fn next_frame(
+        &mut self,
+        next: &mut impl FnMut(&mut [AudioSampleType]) -> bool,
+        out: &mut [AudioSampleType],
+    ) -> bool {
+        while self.filled < self.taps || self.phase >= 1.0 {
+            if !next(&mut self.incoming) {
+                return false;
+            }
+            self.history.copy_within(self.channels.., 0);
+            let last = self.history.len() - self.channels;
+            self.history[last..].copy_from_slice(&self.incoming);
+            if self.filled < self.taps {
+                self.filled += 1;
+            } else {
+                self.phase -= 1.0;
+            }
+        }
+
+        let position = self.phase * PHASES as f64;
+        let row = (position as usize).min(PHASES - 1);
+        let blend = (position - row as f64) as AudioSampleType;
+        let lower = &self.table[row * self.taps..(row + 1) * self.taps];
+        let upper = &self.table[(row + 1) * self.taps..(row + 2) * self.taps];
+        for ((weight, lower), upper) in self.weights.iter_mut().zip(lower).zip(upper) {
+            *weight = lower + (upper - lower) * blend;
+        }
+
+        out.fill(0.0);
+        for (frame, weight) in self.history.chunks_exact(self.channels).zip(&self.weights) {
+            for (sample, source) in out.iter_mut().zip(frame) {
+                *sample += source * weight;
+            }
+        }
+        self.phase += self.step;
+        true
+    }
+
+    /// Source frames that must be queued past the one being interpolated.
+    #[cfg(test)]
+    pub(super) fn lookahead(&self) -> usize {
+        self.taps / 2
+    }
+}
+
+/// The windowed sinc at `x` source frames from the centre.
+fn kernel(x: f64, cutoff: f64, half: f64) -> f64 {
+    if x.abs() >= half {
+        return 0.0;
+    }
+    let sinc = if x == 0.0 {
+        1.0
+    } else {
+        (PI * cutoff * x).sin() / (PI * cutoff * x)
+    };
+    let t = x / half;
+    let window = 0.42 + 0.5 * (PI * t).cos() + 0.08 * (2.0 * PI * t).cos();
+    cutoff * sinc * window
+}
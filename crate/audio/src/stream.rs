@@ -1,17 +1,23 @@
 //! Shared real-time stream lifecycle. The capture and playback sides are
 //! near-mirror images; everything that does not depend on the direction lives
-//! here: the per-direction [`Master`] (device + config + the running stream),
+//! here: the per-direction [`Master`] (device + format + the running stream),
 //! the start/stop scaffolding, sample-format dispatch, and channel-presence
 //! tracking. The direction-specific handles and callbacks live in
 //! [`crate::input`] and [`crate::output`].
 
-use std::{error::Error, sync::Arc};
+use std::{
+    error::Error,
+    sync::{Arc, RwLock},
+};
 
-use cpal::{SupportedStreamConfig, traits::StreamTrait as _};
+use cpal::{DefaultStreamConfigError, SupportedStreamConfig, traits::StreamTrait as _};
 use ringbuf::{CachingCons, CachingProd, StaticRb, traits::Split as _};
-use tracing::{debug, warn};
+use tracing::debug;
 
-use crate::{MIX_SCRATCH_LEN, Notify};
+use crate::{
+    MIX_SCRATCH_LEN, Notify, StreamFormat,
+    backend::{VirtualDevice, VirtualStream},
+};
 
 /// Producer/consumer ends of the channel-event ring: control-thread → audio-thread
 /// messages that hand a newly created channel to an already-running stream. Eight
@@ -19,24 +25,83 @@ use crate::{MIX_SCRATCH_LEN, Notify};
 pub(crate) type EventProd<E> = CachingProd<Arc<StaticRb<E, 8>>>;
 pub(crate) type EventCons<E> = CachingCons<Arc<StaticRb<E, 8>>>;
 
-/// A running stream and the live wiring to it: the kept-alive `cpal::Stream`,
-/// the producer end of the channel-event ring, and the close notification.
+/// A direction's device format, shared with every channel handle so that a
+/// device swap can change it under them. Handles hold the read lock while they
+/// convert, which keeps a swap from landing halfway through a push or pop.
+pub(crate) type SharedFormat = Arc<RwLock<DeviceFormat>>;
+
+/// A device's format, stamped with how often it has changed so that a handle
+/// notices even a swap away and back to the format it knows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct DeviceFormat {
+    pub(crate) format: StreamFormat,
+    generation: u64,
+}
+
+/// What a direction streams through: a host device, or one of the
+/// [`backend`](crate::backend)'s stand-ins.
+pub(crate) enum Device {
+    System(cpal::Device),
+    Virtual(VirtualDevice),
+}
+
+/// A stream kept alive for as long as it should run.
+pub(crate) enum RunningStream {
+    System(cpal::Stream),
+    /// Never read: only its `Drop`, which stops the stream, matters.
+    Virtual {
+        _stream: VirtualStream,
+    },
+}
+
+impl RunningStream {
+    fn play(&self) -> Result<(), Box<dyn Error>> {
+        match self {
+            Self::System(stream) => Ok(stream.play()?),
+            // Virtual streams run from the moment they are built.
+            Self::Virtual { .. } => Ok(()),
+        }
+    }
+}
+
+/// A running stream and the live wiring to it: the kept-alive stream, the
+/// producer end of the channel-event ring, and the close notification.
 struct StreamHandle<E> {
-    _stream: cpal::Stream,
+    _stream: RunningStream,
     channel_event_tx: EventProd<E>,
     close_notify: Arc<Notify>,
 }
 
-/// One audio direction's device, its preferred config, and its
+/// One audio direction's device, its preferred format, and its
 /// optionally-running stream. `E` is the channel-event type
 /// ([`crate::input::InputRxEvent`] or [`crate::output::OutputRxEvent`]).
 pub(crate) struct Master<E> {
-    device: cpal::Device,
-    pub(crate) config: SupportedStreamConfig,
+    device: Device,
+    format: SharedFormat,
     stream: Option<StreamHandle<E>>,
 }
 
 impl<E> Master<E> {
+    pub(crate) fn new(device: Device, format: StreamFormat) -> Self {
+        Master {
+            device,
+            format: Arc::new(RwLock::new(DeviceFormat {
+                format,
+                generation: 0,
+            })),
+            stream: None,
+        }
+    }
+
+    pub(crate) fn format(&self) -> DeviceFormat {
+        *self.format.read().unwrap()
+    }
+
+    /// The format cell channel handles watch for device swaps.
+    pub(crate) fn shared_format(&self) -> SharedFormat {
+        self.format.clone()
+    }
+
     pub(crate) fn is_streaming(&self) -> bool {
         self.stream.is_some()
     }
@@ -58,26 +123,49 @@ impl<E> Master<E> {
         self.stream = None;
     }
 
-    /// Start a stream over this device's preferred config. `build` turns the
-    /// resolved config plus the channel snapshot into the typed `cpal::Stream`
-    /// — the one piece that differs by direction. Everything around it (channel
-    /// validation, the event ring, the close notification, playback, and
-    /// installing the handle) is shared.
+    /// Move onto `other`'s device, stopping the running stream. Channel
+    /// handles are held off while the format changes, and `clear` runs then,
+    /// with the old stream gone, if it did change — so nothing buffered in the
+    /// old format reaches the new device.
+    pub(crate) fn replace_device(&mut self, other: Master<E>, clear: impl FnOnce()) {
+        let new_format = other.format().format;
+        let mut format = self.format.write().unwrap();
+        self.stream = None;
+        self.device = other.device;
+        if format.format != new_format {
+            format.format = new_format;
+            format.generation += 1;
+            clear();
+        }
+    }
+
+    /// Start a stream over this device's preferred format, signalling
+    /// `close_notify` when its last channel closes. `build` turns the resolved
+    /// config plus the channel snapshot into the running stream — the one
+    /// piece that differs by direction. Everything around it (channel
+    /// validation, the event ring, playback, and installing the handle) is
+    /// shared.
     pub(crate) fn start<C>(
         &mut self,
         direction: &str,
+        close_notify: Arc<Notify>,
         channels: C,
         build: impl FnOnce(
-            &cpal::Device,
+            &Device,
             &cpal::StreamConfig,
             cpal::SampleFormat,
             C,
             EventCons<E>,
             Arc<Notify>,
-        ) -> Result<cpal::Stream, Box<dyn Error>>,
-    ) -> Result<Option<Arc<Notify>>, Box<dyn Error>> {
-        let stream_config = self.config.config();
-        let sample_format = self.config.sample_format();
+        ) -> Result<RunningStream, Box<dyn Error>>,
+    ) -> Result<(), Box<dyn Error>> {
+        let format = self.format().format;
+        let stream_config = cpal::StreamConfig {
+            channels: format.channels,
+            sample_rate: format.sample_rate,
+            buffer_size: cpal::BufferSize::Default,
+        };
+        let sample_format = format.sample_format;
         let device_channels = stream_config.channels as usize;
         if device_channels == 0 || device_channels > MIX_SCRATCH_LEN {
             return Err(format!("unsupported {direction} channel count: {device_channels}").into());
@@ -85,7 +173,6 @@ impl<E> Master<E> {
         debug!("Starting {direction} stream with config {stream_config:?}, format {sample_format:?}");
 
         let (event_prod, event_cons) = StaticRb::<E, 8>::default().split();
-        let close_notify = Arc::new(Notify::new());
 
         let stream = build(
             &self.device,
@@ -99,33 +186,23 @@ impl<E> Master<E> {
         self.stream = Some(StreamHandle {
             _stream: stream,
             channel_event_tx: event_prod,
-            close_notify: close_notify.clone(),
+            close_notify,
         });
-        Ok(Some(close_notify))
+        Ok(())
     }
 }
 
-/// Open a direction's default device and resolve its default config into a
-/// [`Master`], logging and yielding `None` when either is unavailable.
+/// Resolve a host device's default config into a [`Master`].
 pub(crate) fn open_master<E>(
-    device: Option<cpal::Device>,
-    config_of: impl FnOnce(
-        &cpal::Device,
-    ) -> Result<SupportedStreamConfig, cpal::DefaultStreamConfigError>,
-    label: &str,
-) -> Option<Master<E>> {
-    let device = device?;
-    match config_of(&device) {
-        Ok(config) => Some(Master {
-            device,
-            config,
-            stream: None,
-        }),
-        Err(err) => {
-            warn!("{label} device has no usable default config: {err}");
-            None
-        }
-    }
+    device: cpal::Device,
+    config_of: impl FnOnce(&cpal::Device) -> Result<SupportedStreamConfig, DefaultStreamConfigError>,
+) -> Result<Master<E>, Box<dyn Error>> {
+    let config =
+        config_of(&device).map_err(|err| format!("device has no usable default config: {err}"))?;
+    Ok(Master::new(
+        Device::System(device),
+        StreamFormat::of_device(&config),
+    ))
 }
 
 /// Fire `close_notify` exactly once, on the transition to zero live channels —
@@ -10,10 +10,12 @@
 //! - Errors are intentionally trait-object based (`Box<dyn Error + Send + Sync>`) to
 //!   allow each backend to return its own error types without exposing them here.
 use std::fmt::Debug;
-use std::{error::Error, sync::Arc};
+use std::{collections::HashMap, error::Error, path::PathBuf, sync::Arc, time::Duration};
 
 // QueryPlace is kept for reference in the commented-out legacy code below
-use crate::types::{House, ID, Identifier, Message, Place, Room, User};
+use crate::types::{
+    House, ID, Identifier, Message, Place, ReadState, RichText, Room, Status, User, UserPresence,
+};
 
 pub use crate::stream::{ArcStream, WeakSocketStream};
 
@@ -99,6 +101,28 @@ impl<T: Messenger + Voice + 'static> MessengerCasterVoice for T {
     }
 }
 
+pub trait MessengerCasterPresence {
+    /// Capability: online status of contacts and of the client.
+    fn presence(&self) -> Result<&dyn Presence, MessengerError>;
+    fn arc_presence(self: Arc<Self>) -> Result<Arc<dyn Presence>, MessengerError>;
+}
+impl<T: Messenger> MessengerCasterPresence for T {
+    default fn presence(&self) -> Result<&dyn Presence, MessengerError> {
+        Err(MessengerError::NotImplemented)
+    }
+    default fn arc_presence(self: Arc<Self>) -> Result<Arc<dyn Presence>, MessengerError> {
+        Err(MessengerError::NotImplemented)
+    }
+}
+impl<T: Messenger + Presence + 'static> MessengerCasterPresence for T {
+    fn presence(&self) -> Result<&dyn Presence, MessengerError> {
+        Ok(self)
+    }
+    fn arc_presence(self: Arc<Self>) -> Result<Arc<dyn Presence>, MessengerError> {
+        Ok(self)
+    }
+}
+
 /// A concrete messenger backend.
 ///
 /// Implement this for each integration (e.g. Discord, Matrix, etc).
@@ -108,7 +132,8 @@ impl<T: Messenger + Voice + 'static> MessengerCasterVoice for T {
 /// or return [`MessengerError::NotImplemented`] if they don't support it.
 pub trait Messenger: Send + Sync
 where
-    Self: MessengerCasterQuery + MessengerCasterText + MessengerCasterVoice,
+    Self:
+        MessengerCasterQuery + MessengerCasterText + MessengerCasterVoice + MessengerCasterPresence,
 {
     // TODO: Replace auth_obj with a better representation then str
     fn create_messenger(auth_obj: &str) -> Arc<dyn Messenger>
@@ -159,6 +184,15 @@ pub trait Query: Send + Sync {
     ) -> Result<Room, Box<dyn Error + Sync + Send>> {
         Err(Box::new(MessengerError::NotImplemented))
     }
+    /// Fetch the open threads of `room`: for a forum, its posts. Each comes
+    /// back with [`Room::parent`] set to `room`. Threads created, updated or
+    /// closed later arrive as `Channel*` events with the parent set.
+    async fn threads(
+        &self,
+        _room: &Identifier<Place<Room>>,
+    ) -> Result<Vec<Identifier<Place<Room>>>, Box<dyn Error + Sync + Send>> {
+        Err(Box::new(MessengerError::NotImplemented))
+    }
     /// Fetch detailed information about a specific house/server/guild.
     async fn house_details(
         &self,
@@ -190,6 +224,11 @@ pub enum Ordering {
     Time,
 }
 
+/// How long a [`TextEvent::TypingStarted`] stays valid without a repeat.
+/// Discord shows an indicator for 10 seconds and Steam clients for about as
+/// long, so one value serves every backend.
+pub const TYPING_INDICATOR_TIMEOUT: Duration = Duration::from_secs(10);
+
 /// Text chat API for reading/sending messages in a room/channel.
 #[async_trait]
 pub trait Text: Send + Sync {
@@ -224,12 +263,111 @@ pub trait Text: Send + Sync {
 
     /// Send a new message into `location`.
     ///
+    /// `contents.reply` sends it as a reply to that message; backends without
+    /// replies send it as a plain message and return it with `reply: None`.
+    /// `contents.draft`, when set, goes out verbatim, so markup the backend
+    /// doesn't parse survives; otherwise `contents.content` is written in the
+    /// platform's markup.
+    ///
     /// Returns the confirmed message (with server-assigned ID) on success.
     async fn send_message(
         &self,
         location: &Identifier<Place<Room>>,
         contents: Message,
     ) -> Result<Identifier<Message>, Box<dyn Error + Sync + Send>>;
+
+    /// Read what the user typed into the composer as rich text, in the
+    /// platform's own markup (Discord markdown, Steam BBCode, ...), so the
+    /// local echo shows the way the sent draft will. Backends with no markup
+    /// take the draft as plain text.
+    fn parse_draft(&self, draft: &str) -> RichText {
+        RichText::plain(draft)
+    }
+
+    /// The composer text that [`parse_draft`](Text::parse_draft)s back into
+    /// `text`, for editing a message.
+    fn write_draft(&self, text: &RichText) -> String {
+        text.to_plain()
+    }
+
+    /// Send the files at `files` into `location`, as one message with
+    /// `contents` as its (possibly empty) text.
+    ///
+    /// Returns the confirmed message with its `attachments` on success.
+    async fn send_files(
+        &self,
+        _location: &Identifier<Place<Room>>,
+        _contents: Message,
+        _files: Vec<PathBuf>,
+    ) -> Result<Identifier<Message>, Box<dyn Error + Sync + Send>> {
+        Err(Box::new(MessengerError::NotImplemented))
+    }
+
+    /// Whether the platform lets the client edit its messages, i.e. whether
+    /// [`edit_message`](Text::edit_message) is implemented. The UI only offers
+    /// editing where it is.
+    fn can_edit(&self) -> bool {
+        false
+    }
+
+    /// Replace the text of one of the client's own messages with `draft`,
+    /// the composer text in the platform's markup. It goes out verbatim, like
+    /// a sent message's `draft`.
+    ///
+    /// Returns the message as it is after the edit, with the previous text
+    /// pushed onto its history where the platform reports it.
+    async fn edit_message(
+        &self,
+        _location: &Identifier<Place<Room>>,
+        _message: &Identifier<Message>,
+        _draft: &str,
+    ) -> Result<Identifier<Message>, Box<dyn Error + Sync + Send>> {
+        Err(Box::new(MessengerError::NotImplemented))
+    }
+
+    /// Delete a message. Platforms decide whose messages the client may
+    /// delete; a refusal comes back as an error.
+    async fn delete_message(
+        &self,
+        _location: &Identifier<Place<Room>>,
+        _message: &Identifier<Message>,
+    ) -> Result<(), Box<dyn Error + Sync + Send>> {
+        Err(Box::new(MessengerError::NotImplemented))
+    }
+
+    /// Read state of every room the platform tracks one for, keyed by room
+    /// ID. Rooms missing from the map have nothing unread.
+    async fn read_states(&self) -> Result<HashMap<ID, ReadState>, Box<dyn Error + Sync + Send>> {
+        Err(Box::new(MessengerError::NotImplemented))
+    }
+
+    /// Mark `location` read up to and including `message`.
+    async fn mark_read(
+        &self,
+        _location: &Identifier<Place<Room>>,
+        _message: &Identifier<Message>,
+    ) -> Result<(), Box<dyn Error + Sync + Send>> {
+        Err(Box::new(MessengerError::NotImplemented))
+    }
+
+    /// Show the client as typing in `location`. The indicator lapses on its
+    /// own, so call this again every few seconds while the user keeps typing.
+    async fn send_typing(
+        &self,
+        _location: &Identifier<Place<Room>>,
+    ) -> Result<(), Box<dyn Error + Sync + Send>> {
+        Err(Box::new(MessengerError::NotImplemented))
+    }
+
+    /// The direct-message room with the user `user`, opened if the client
+    /// has none with them yet.
+    async fn direct_room(
+        &self,
+        _user: ID,
+    ) -> Result<Identifier<Place<Room>>, Box<dyn Error + Sync + Send>> {
+        Err(Box::new(MessengerError::NotImplemented))
+    }
+
     async fn listen(
         self: Arc<Self>,
     ) -> Result<WeakSocketStream<TextEvent>, Box<dyn Error + Sync + Send>> {
@@ -237,6 +375,27 @@ pub trait Text: Send + Sync {
     }
 }
 
+/// Online status of the client's contacts, and of the client itself.
+#[async_trait]
+pub trait Presence: Send + Sync {
+    /// Presence of every user the platform has reported on, keyed by user
+    /// ID. Users missing from the map are [`Status::Offline`].
+    async fn presences(&self) -> Result<HashMap<ID, UserPresence>, Box<dyn Error + Sync + Send>>;
+
+    /// Change the status the client shows to others. Platforms that can't
+    /// show a connected client as offline take [`Status::Offline`] as
+    /// [`Status::Invisible`].
+    async fn set_status(&self, _status: Status) -> Result<(), Box<dyn Error + Sync + Send>> {
+        Err(Box::new(MessengerError::NotImplemented))
+    }
+
+    async fn listen(
+        self: Arc<Self>,
+    ) -> Result<WeakSocketStream<PresenceEvent>, Box<dyn Error + Sync + Send>> {
+        Err(Box::new(MessengerError::NotImplemented))
+    }
+}
+
 /// Non-connected sub-states of a voice call. The connected state is represented
 /// by the *absence* of a `CallStatus` — see [`CallState`].
 #[derive(Debug, Clone, Copy)]
@@ -265,6 +424,83 @@ pub enum CallState {
     Pending(CallStatus),
 }
 
+/// Whether a call participant can speak and hear. The `self_` flags are the
+/// participant's own toggles; the others were imposed on them by the server,
+/// e.g. by a moderator.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MuteState {
+    pub self_mute: bool,
+    pub self_deaf: bool,
+    pub mute: bool,
+    pub deaf: bool,
+}
+
+impl MuteState {
+    /// Nothing the participant says reaches the call.
+    pub fn is_muted(&self) -> bool {
+        self.self_mute || self.mute || self.is_deafened()
+    }
+    /// The participant hears nothing of the call.
+    pub fn is_deafened(&self) -> bool {
+        self.self_deaf || self.deaf
+    }
+}
+
+/// The container and codec of a call recording.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RecordingFormat {
+    /// Opus in an Ogg container (`.ogg`): small, and what the call carried.
+    #[default]
+    OggOpus,
+    /// Uncompressed 16-bit PCM (`.wav`).
+    Wav,
+}
+
+/// How a call recording is split into files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RecordingLayout {
+    /// Everyone, the client included, mixed into one file.
+    #[default]
+    Mixed,
+    /// One file per participant, all starting when the recording did, so they
+    /// line up when played together.
+    PerParticipant,
+}
+
+impl RecordingFormat {
+    pub const ALL: [RecordingFormat; 2] = [RecordingFormat::OggOpus, RecordingFormat::Wav];
+}
+impl std::fmt::Display for RecordingFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::OggOpus => "Ogg/Opus",
+            Self::Wav => "WAV",
+        })
+    }
+}
+
+impl RecordingLayout {
+    pub const ALL: [RecordingLayout; 2] = [RecordingLayout::Mixed, RecordingLayout::PerParticipant];
+}
+impl std::fmt::Display for RecordingLayout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Mixed => "One mixed track",
+            Self::PerParticipant => "A track per participant",
+        })
+    }
+}
+
+/// Where and how [`Voice::start_recording`] writes. File names start with the
+/// local time the recording started.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordingSettings {
+    /// Created if missing.
+    pub directory: PathBuf,
+    pub format: RecordingFormat,
+    pub layout: RecordingLayout,
+}
+
 impl CallState {
     pub fn as_str(&self) -> &'static str {
         match self {
@@ -286,6 +522,28 @@ pub trait Voice: Send + Sync {
     ) -> Result<CallStatus, Box<dyn Error + Sync + Send>>;
     /// Disconnect from voice in `location`.
     async fn disconnect(&self, location: &Identifier<Place<Room>>);
+    /// Stop (or resume) sending the client's microphone. Sticks across calls;
+    /// the platform reports the result as [`VoiceEvent::MuteStateChanged`].
+    async fn set_self_mute(&self, _muted: bool) -> Result<(), Box<dyn Error + Sync + Send>> {
+        Err(Box::new(MessengerError::NotImplemented))
+    }
+    /// Stop (or resume) hearing calls. A deafened client is muted as well.
+    async fn set_self_deaf(&self, _deafened: bool) -> Result<(), Box<dyn Error + Sync + Send>> {
+        Err(Box::new(MessengerError::NotImplemented))
+    }
+    /// Start recording what the client hears and says in calls, replacing
+    /// (and finishing) any recording already running.
+    async fn start_recording(
+        &self,
+        _settings: RecordingSettings,
+    ) -> Result<(), Box<dyn Error + Sync + Send>> {
+        Err(Box::new(MessengerError::NotImplemented))
+    }
+    /// Stop recording and finish its files, returning their paths. Leaving the
+    /// call stops it too.
+    async fn stop_recording(&self) -> Result<Vec<PathBuf>, Box<dyn Error + Sync + Send>> {
+        Err(Box::new(MessengerError::NotImplemented))
+    }
 
     async fn listen(
         self: Arc<Self>,
@@ -337,6 +595,11 @@ pub enum SocketEvent {
     CallStreamReady(WeakSocketStream<AudioEvent>),
     /// Request to attach an audio source into the audio graph.
     AddAudioSource(oneshot::Sender<SampleProducer>),
+    /// Request to attach one call participant's voice into the audio graph.
+    AddParticipantSource {
+        user_id: ID,
+        sender: oneshot::Sender<SampleProducer>,
+    },
     /// Request to attach a local audio input (microphone) for sending to voice.
     AddAudioInput(oneshot::Sender<SampleConsumer>),
     /// Socket disconnected (cleanly or due to error).
@@ -351,6 +614,13 @@ impl From<QueryEvent> for SocketEvent {
             QueryEvent::ChannelCreated { r#where, room } => {
                 SocketEvent::ChannelCreated { r#where, room }
             }
+            QueryEvent::ChannelUpdated { .. }
+            | QueryEvent::ChannelDeleted { .. }
+            | QueryEvent::HouseJoined { .. }
+            | QueryEvent::HouseUpdated { .. }
+            | QueryEvent::HouseLeft { .. }
+            | QueryEvent::ContactAdded { .. }
+            | QueryEvent::ContactRemoved { .. } => SocketEvent::Skip,
         }
     }
 }
@@ -388,6 +658,9 @@ impl From<TextEvent> for SocketEvent {
                 user_id,
                 emoji,
             },
+            TextEvent::TypingStarted { .. } | TextEvent::ReadStateUpdated { .. } => {
+                SocketEvent::Skip
+            }
         }
     }
 }
@@ -396,9 +669,10 @@ impl From<VoiceEvent> for SocketEvent {
         match value {
             VoiceEvent::CallStatusUpdate(call_status) => SocketEvent::CallStatusUpdate(call_status),
             VoiceEvent::CallStreamReady(stream) => SocketEvent::CallStreamReady(stream),
-            VoiceEvent::ParticipantJoined { .. } | VoiceEvent::ParticipantLeft { .. } => {
-                SocketEvent::Skip
-            }
+            VoiceEvent::ParticipantJoined { .. }
+            | VoiceEvent::ParticipantLeft { .. }
+            | VoiceEvent::MuteStateChanged { .. }
+            | VoiceEvent::SpeakingChanged { .. } => SocketEvent::Skip,
         }
     }
 }
@@ -406,18 +680,47 @@ impl From<AudioEvent> for SocketEvent {
     fn from(value: AudioEvent) -> Self {
         match value {
             AudioEvent::AddAudioSource(sender) => SocketEvent::AddAudioSource(sender),
+            AudioEvent::AddParticipantSource { user_id, sender } => {
+                SocketEvent::AddParticipantSource { user_id, sender }
+            }
             AudioEvent::AddAudioInput(sender) => SocketEvent::AddAudioInput(sender),
         }
     }
 }
 
 pub enum QueryEvent {
-    /// A channel/room was created (optionally within a server/place).
+    /// A channel/room was created (optionally within a server/place). A
+    /// room with a [`Room::parent`] is a thread that was opened or came
+    /// into view.
     ChannelCreated {
         r#where: Option<Identifier<()>>,
         // r#where: Option<Identifier<Place<House>>>,
         room: Identifier<Place<Room>>,
     },
+    /// A channel/room was renamed or otherwise changed. Only the name, icon
+    /// and capabilities are meaningful; keep the messages and participants
+    /// already loaded.
+    ChannelUpdated {
+        r#where: Option<Identifier<()>>,
+        room: Identifier<Place<Room>>,
+    },
+    /// A channel/room was deleted, or a thread was closed.
+    ChannelDeleted {
+        r#where: Option<Identifier<()>>,
+        room_id: ID,
+    },
+    /// The client joined a server/place, or one came back after an outage.
+    /// Its rooms are not loaded; fetch them with `Query::house_details`.
+    HouseJoined { house: Identifier<Place<House>> },
+    /// A server/place was renamed or got a new icon. Rooms are not loaded;
+    /// keep the ones already fetched.
+    HouseUpdated { house: Identifier<Place<House>> },
+    /// The client left, or was removed from, a server/place.
+    HouseLeft { house_id: ID },
+    /// A user was added to the client's contacts.
+    ContactAdded { user: Identifier<User> },
+    /// A user was removed from the client's contacts.
+    ContactRemoved { user_id: ID },
 }
 pub enum TextEvent {
     /// A message was created in a channel.
@@ -449,6 +752,26 @@ pub enum TextEvent {
         user_id: ID,
         emoji: String,
     },
+    /// `user` started typing in a room. Platforms repeat this while the user
+    /// keeps typing and send nothing when they stop, so consumers should
+    /// drop the indicator after [`TYPING_INDICATOR_TIMEOUT`] or once a
+    /// message from `user` arrives.
+    TypingStarted {
+        room: Identifier<()>,
+        user: Identifier<User>,
+    },
+    /// The client's read state in a room changed, e.g. the room was read on
+    /// another device. `state` replaces what was known.
+    ReadStateUpdated {
+        room: Identifier<()>,
+        state: ReadState,
+    },
+}
+
+pub enum PresenceEvent {
+    /// A user's status or activity changed; `presence` replaces what was
+    /// known for them. Also sent for the client after [`Presence::set_status`].
+    PresenceUpdated { user_id: ID, presence: UserPresence },
 }
 
 /// Updates in the status of the voice call
@@ -466,6 +789,20 @@ pub enum VoiceEvent {
     ParticipantLeft {
         user_id: ID,
     },
+    /// A call participant's mute or deafen state changed, possibly the
+    /// client's own (see [`Voice::set_self_mute`]).
+    MuteStateChanged {
+        user_id: ID,
+        state: MuteState,
+    },
+    /// A call participant, possibly the client, started or stopped speaking,
+    /// or their loudness changed noticeably while speaking. `level` is in dBov
+    /// (0 is the loudest, -127 silence) when the platform reports it.
+    SpeakingChanged {
+        user_id: ID,
+        speaking: bool,
+        level: Option<f32>,
+    },
 }
 /// Audio events that are meant to be processed with the mixer
 pub enum AudioEvent {
@@ -473,6 +810,15 @@ pub enum AudioEvent {
     ///
     /// The receiver receives a `SampleProducer` used to push samples into the system.
     AddAudioSource(oneshot::Sender<SampleProducer>),
+    /// Like [`AudioEvent::AddAudioSource`], for the voice of one call
+    /// participant, so the app can tell who is speaking and adjust each person
+    /// on their own.
+    ///
+    /// Requested lazily, once the platform knows which user a stream belongs to.
+    AddParticipantSource {
+        user_id: ID,
+        sender: oneshot::Sender<SampleProducer>,
+    },
     /// Request to attach a local audio input (microphone) for sending to voice.
     ///
     /// The receiver receives a `SampleConsumer` used to pull samples from the input stream.
@@ -4,19 +4,25 @@
 //! so consumers keep using `messenger_interface::types::*` regardless of which
 //! file a given item lives in.
 
+mod attachment;
 mod cache;
 mod identifier;
 mod message;
 mod place;
+mod presence;
+mod read_state;
 mod rich_text;
 mod user;
 
+pub use attachment::Attachment;
 pub use cache::{
     CACHE_IMGS_DIR, CacheCategory, TEMP_FILE_SUFFIX, cache_dir, cache_img_dir,
     sweep_stale_temp_files,
 };
 pub use identifier::{ID, Identifier};
-pub use message::{Message, Reaction, Revision};
+pub use message::{Message, Reaction, Reply, ReplyPreview, Revision};
 pub use place::{House, Place, Room, RoomCapabilities};
-pub use rich_text::{Emoji, RichText, Span, TextStyle};
+pub use presence::{Status, UserPresence};
+pub use read_state::ReadState;
+pub use rich_text::{Emoji, Mention, RichText, Span, TextStyle, TimestampStyle};
 pub use user::User;
@@ -0,0 +1,32 @@
+//! The [`Attachment`] entity: a file sent along with a message.
+
+use std::path::PathBuf;
+
+/// A file attached to a message.
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    /// The file's name as the sender uploaded it.
+    pub filename: String,
+    /// Size of the file in bytes.
+    pub size: u64,
+    /// MIME type of the file (e.g. `image/png`), if the platform reports one.
+    pub mime: Option<String>,
+    /// Path to a local copy of the file, if it has been downloaded.
+    ///
+    /// Interfaces download into the [`CacheCategory::Attachments`] cache, but
+    /// only what the app shows inline (small images); other files keep just
+    /// their metadata.
+    ///
+    /// [`CacheCategory::Attachments`]: super::CacheCategory::Attachments
+    pub path: Option<PathBuf>,
+    /// Width and height in pixels, for images and videos.
+    pub dimensions: Option<(u32, u32)>,
+}
+
+impl Attachment {
+    pub fn is_image(&self) -> bool {
+        self.mime
+            .as_deref()
+            .is_some_and(|mime| mime.starts_with("image/"))
+    }
+}
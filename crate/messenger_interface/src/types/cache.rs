@@ -21,6 +21,8 @@ pub enum CacheCategory {
     Channels,
     Emoji,
     Stickers,
+    /// Files attached to messages.
+    Attachments,
     /// Images whose owner/type is unknown or does not map to a standard
     /// messenger entity.
     Misc,
@@ -34,6 +36,7 @@ impl CacheCategory {
             Self::Channels => "channels",
             Self::Emoji => "emoji",
             Self::Stickers => "stickers",
+            Self::Attachments => "attachments",
             Self::Misc => "misc",
             Self::Custom(s) => s,
         }
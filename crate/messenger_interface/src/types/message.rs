@@ -1,10 +1,12 @@
-//! Messages and their parts: [`Reaction`]s and the [`Revision`] history.
+//! Messages and their parts: [`Reaction`]s, the [`Revision`] history and the
+//! [`Reply`] reference.
 
 use std::mem;
 
 use chrono::{DateTime, Utc};
 
-use super::identifier::Identifier;
+use super::attachment::Attachment;
+use super::identifier::{ID, Identifier};
 use super::rich_text::{Emoji, RichText};
 use super::user::User;
 
@@ -31,6 +33,28 @@ pub struct Revision {
     pub text: RichText,
 }
 
+/// The message a message replies to.
+///
+/// Carries a [`ReplyPreview`] so the quoted header renders without fetching
+/// the target, which may lie outside the loaded history.
+#[derive(Debug, Clone)]
+pub struct Reply {
+    /// ID of the replied-to message, in the same room.
+    pub message_id: ID,
+    /// `None` when the platform no longer has the target (it was deleted)
+    /// or didn't send it along.
+    pub preview: Option<ReplyPreview>,
+}
+
+/// A lightweight quote of a replied-to message.
+#[derive(Debug, Clone)]
+pub struct ReplyPreview {
+    /// Display name of the replied-to message's author.
+    pub author: String,
+    /// The replied-to message's text, flattened to plain text.
+    pub text: String,
+}
+
 /// Represents a message in a chat room/channel.
 ///
 /// `content` is the live revision; `history` holds the *previous*
@@ -45,8 +69,20 @@ pub struct Message {
     pub history: Vec<Revision>,
     /// List of reactions on this message.
     pub reactions: Vec<Reaction>,
+    /// Files attached to this message, in the order they were sent.
+    pub attachments: Vec<Attachment>,
     /// The author of this message, if known.
     pub author: Option<Identifier<User>>,
+    /// The message this one replies to, if any. Set it on the message passed
+    /// to `Text::send_message` to send a reply.
+    pub reply: Option<Reply>,
+    /// What the user typed into the composer, in the platform's markup. Set
+    /// it on the message passed to `Text::send_message` to send the draft
+    /// as typed; `content` then only serves as the local echo.
+    pub draft: Option<String>,
+    /// Whether the message mentions the client user, by name or through a
+    /// mention of everyone.
+    pub mentions_me: bool,
 }
 
 impl Message {
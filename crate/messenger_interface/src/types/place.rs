@@ -6,20 +6,24 @@ use std::path::PathBuf;
 
 use bitflags::bitflags;
 
-use super::identifier::Identifier;
+use super::identifier::{ID, Identifier};
 use super::message::Message;
 use super::user::User;
 
 bitflags! {
     /// Bitflags representing the capabilities supported by a room/channel.
     ///
-    /// Rooms can support text chat, voice chat, or both.
+    /// Rooms can support text chat, voice chat, or both, and may hold
+    /// threads.
     #[derive(Debug, Clone, Copy)]
     pub struct RoomCapabilities: u8 {
         /// Room supports text messaging.
         const Text = 0b0000_0001;
         /// Room supports voice chat.
         const Voice = 0b0000_0010;
+        /// Room holds threads (or, for a forum, posts), listed by
+        /// `Query::threads`.
+        const Threads = 0b0000_0100;
     }
 }
 
@@ -32,7 +36,8 @@ type Fetched<T> = Option<T>;
 /// Represents a room/channel in the messenger system.
 ///
 /// Rooms contain capabilities (text/voice) and optionally loaded data
-/// about participants and messages.
+/// about participants and messages. A room with a `parent` is a thread (or
+/// forum post) branching off that room.
 #[derive(Debug, Clone)]
 pub struct Room {
     /// Capabilities supported by this room (text, voice, etc.).
@@ -41,9 +46,12 @@ pub struct Room {
     pub messages: Fetched<Vec<Identifier<Message>>>,
     /// List of participants, if fetched.
     pub participants: Fetched<Vec<Identifier<User>>>,
+    /// ID of the room this one is a thread of, if it is one.
+    pub parent: Option<ID>,
 }
 impl Room {
-    /// Create a new Room with the given capabilities and optional data.
+    /// Create a new top-level Room with the given capabilities and optional
+    /// data.
     pub fn new(
         room_capabilities: RoomCapabilities,
         participants: Fetched<Vec<Identifier<User>>>,
@@ -53,8 +61,15 @@ impl Room {
             room_capabilities,
             participants,
             messages,
+            parent: None,
         }
     }
+
+    /// Make this room a thread of the room `parent`.
+    pub fn with_parent(mut self, parent: ID) -> Self {
+        self.parent = Some(parent);
+        self
+    }
 }
 
 /// Represents a house/server/guild that contains multiple rooms.
@@ -0,0 +1,25 @@
+//! The [`UserPresence`] entity: whether a user is around and what they are doing.
+
+/// How available a user shows as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Status {
+    Online,
+    /// Away, either set by the user or after a while without input.
+    Idle,
+    /// Busy; notifications are muted on the user's side.
+    DoNotDisturb,
+    /// Connected but shown to everyone else as [`Status::Offline`], so only
+    /// ever reported for the client itself.
+    Invisible,
+    #[default]
+    Offline,
+}
+
+/// A user's status and activity as last reported by the platform.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct UserPresence {
+    pub status: Status,
+    /// What the user is doing, ready to display: the game being played, or
+    /// a custom status line.
+    pub activity: Option<String>,
+}
@@ -0,0 +1,21 @@
+//! The [`ReadState`] entity: how far the client has read in a room.
+
+use super::ID;
+
+/// The client's read marker in one room and what arrived after it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ReadState {
+    /// The newest message the client has read, if it ever read the room.
+    pub last_read: Option<ID>,
+    /// At least how many messages came after `last_read`. Platforms that only
+    /// know *whether* something new came report `1`.
+    pub unread: u32,
+    /// How many of those mention the client.
+    pub mentions: u32,
+}
+
+impl ReadState {
+    pub fn is_unread(&self) -> bool {
+        self.unread > 0 || self.mentions > 0
+    }
+}
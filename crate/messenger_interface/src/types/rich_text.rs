@@ -5,40 +5,131 @@
 
 use std::path::PathBuf;
 
+use chrono::{DateTime, Local, Utc};
+
+use super::identifier::ID;
+
 /// A custom emoji / emoticon: a small image identified by a shortcode.
 ///
 /// `shortcode` is the platform name (e.g. `steamhappy`, a Discord emoji name,
 /// or a Unicode emoji itself) and doubles as the textual fallback rendered as
 /// `:shortcode:` when there is no image. `image` is a cached local path, or
 /// `None` when the emoji has no image (Unicode) or it could not be resolved.
-#[derive(Debug, Clone, Default)]
+/// `id` is the platform's handle for a custom emoji that is sent by id
+/// rather than by name (Discord), so it can go back out as it came in.
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct Emoji {
     pub shortcode: String,
     pub image: Option<PathBuf>,
+    pub id: Option<ID>,
+    pub animated: bool,
 }
 impl Emoji {
     /// A shortcode-only emoji with no resolved image (Unicode, or pending).
     pub fn shortcode(shortcode: impl Into<String>) -> Self {
         Self {
             shortcode: shortcode.into(),
-            image: None,
+            ..Default::default()
         }
     }
 }
 
 /// Inline text styling for a run of text. Extend as backends/UI grow
-/// (strikethrough, code, color, ...).
+/// (color, ...).
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub struct TextStyle {
     pub bold: bool,
     pub italic: bool,
+    pub underline: bool,
+    pub strikethrough: bool,
+    /// Hidden until the reader chooses to see it.
+    pub spoiler: bool,
+    /// Inline code: monospaced, shown verbatim.
+    pub code: bool,
+}
+
+/// How a [`Span::Timestamp`] is shown. Rendered in the reader's local time,
+/// so everyone sees the moment in their own zone.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimestampStyle {
+    /// `16:20`
+    ShortTime,
+    /// `16:20:30`
+    LongTime,
+    /// `20/04/2021`
+    ShortDate,
+    /// `20 April 2021`
+    LongDate,
+    /// `20 April 2021 16:20`
+    #[default]
+    ShortDateTime,
+    /// `Tuesday, 20 April 2021 16:20`
+    LongDateTime,
+    /// `2 months ago`, `in 5 minutes`
+    Relative,
+}
+impl TimestampStyle {
+    /// `at` as this style shows it, in local time or relative to now.
+    pub fn format(self, at: DateTime<Utc>) -> String {
+        let local = at.with_timezone(&Local);
+        let pattern = match self {
+            TimestampStyle::ShortTime => "%H:%M",
+            TimestampStyle::LongTime => "%H:%M:%S",
+            TimestampStyle::ShortDate => "%d/%m/%Y",
+            TimestampStyle::LongDate => "%-d %B %Y",
+            TimestampStyle::ShortDateTime => "%-d %B %Y %H:%M",
+            TimestampStyle::LongDateTime => "%A, %-d %B %Y %H:%M",
+            TimestampStyle::Relative => return relative(at, Utc::now()),
+        };
+        local.format(pattern).to_string()
+    }
 }
 
-/// One inline piece of a message's content. A message's text is a flat
-/// sequence of these (see [`RichText`]); backends parse their own wire format
-/// (Discord markdown, Steam BBCode, ...) into spans so the UI renders one
-/// uniform model and never has to know a platform's syntax.
-#[derive(Debug, Clone)]
+/// The distance from `now` to `at` in its largest whole unit.
+fn relative(at: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    const UNITS: [(i64, &str); 6] = [
+        (365 * 24 * 60 * 60, "year"),
+        (30 * 24 * 60 * 60, "month"),
+        (24 * 60 * 60, "day"),
+        (60 * 60, "hour"),
+        (60, "minute"),
+        (1, "second"),
+    ];
+    let seconds = (at - now).num_seconds();
+    let (size, unit) = UNITS
+        .into_iter()
+        .find(|(size, _)| seconds.abs() >= *size)
+        .unwrap_or(UNITS[UNITS.len() - 1]);
+    let count = seconds.abs() / size;
+    let plural = if count == 1 { "" } else { "s" };
+    if seconds < 0 {
+        format!("{count} {unit}{plural} ago")
+    } else {
+        format!("in {count} {unit}{plural}")
+    }
+}
+
+/// What a [`Span::Mention`] points at, by the IDs the backend hands out for
+/// its users and rooms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mention {
+    User(ID),
+    Role(ID),
+    /// A room, by its ID.
+    Channel(ID),
+    /// Everyone in the room.
+    Everyone,
+}
+
+/// One piece of a message's content. A message's text is a flat sequence of
+/// these (see [`RichText`]); backends parse their own wire format (Discord
+/// markdown, Steam BBCode, ...) into spans so the UI renders one uniform
+/// model and never has to know a platform's syntax.
+///
+/// Most spans flow inline. [`CodeBlock`](Span::CodeBlock),
+/// [`Quote`](Span::Quote) and [`Heading`](Span::Heading) are blocks: each
+/// sits on lines of its own, between the inline runs around it.
+#[derive(Debug, Clone, PartialEq)]
 pub enum Span {
     /// A run of plain text with optional styling.
     Text { text: String, style: TextStyle },
@@ -48,31 +139,79 @@ pub enum Span {
     Sticker { alt: String, image: Option<PathBuf> },
     /// A hyperlink: display text plus its target URL.
     Link { text: String, href: String },
+    /// A mention of a user, role or room, or of everyone. `name` is the
+    /// display name the backend resolved, without the `@`/`#` in front.
+    Mention { target: Mention, name: String },
+    /// A moment in time, shown in the reader's zone.
+    Timestamp {
+        at: DateTime<Utc>,
+        style: TimestampStyle,
+    },
+    /// Preformatted code, with the language it is written in if given.
+    CodeBlock {
+        language: Option<String>,
+        code: String,
+    },
+    /// Quoted content.
+    Quote(Vec<Span>),
+    /// A heading; level 1 is the largest.
+    Heading { level: u8, spans: Vec<Span> },
 }
 impl Span {
     /// Plain-text projection, for flattening / search / logging. Emoji become
-    /// `:shortcode:`, stickers their `alt`, links their display text.
+    /// `:shortcode:`, stickers their `alt`, links their display text,
+    /// mentions `@name` (`#name` for rooms), timestamps their local date and
+    /// time. Blocks end in a line break.
     pub fn to_plain(&self) -> String {
         match self {
             Span::Text { text, .. } => text.clone(),
             Span::Emoji(emoji) => format!(":{}:", emoji.shortcode),
             Span::Sticker { alt, .. } => alt.clone(),
             Span::Link { text, .. } => text.clone(),
+            Span::Mention {
+                target: Mention::Channel(_),
+                name,
+            } => format!("#{name}"),
+            Span::Mention { name, .. } => format!("@{name}"),
+            Span::Timestamp { at, .. } => TimestampStyle::default().format(*at),
+            Span::CodeBlock { code, .. } => format!("{code}\n"),
+            Span::Quote(spans) => {
+                let quoted: String = spans.iter().map(Span::to_plain).collect();
+                let mut plain: String = quoted.lines().map(|line| format!("> {line}\n")).collect();
+                if plain.is_empty() {
+                    plain.push_str(">\n");
+                }
+                plain
+            }
+            Span::Heading { spans, .. } => {
+                let mut plain: String = spans.iter().map(Span::to_plain).collect();
+                plain.push('\n');
+                plain
+            }
         }
     }
+
+    /// Whether this span is a block, on lines of its own.
+    pub fn is_block(&self) -> bool {
+        matches!(
+            self,
+            Span::CodeBlock { .. } | Span::Quote(_) | Span::Heading { .. }
+        )
+    }
 }
 
-/// A message's rendered content: a flat sequence of inline [`Span`]s.
+/// A message's rendered content: a flat sequence of [`Span`]s.
 ///
-/// Backends build this from their wire format; the compose path uses
-/// [`RichText::plain`] since the user types plain text.
-#[derive(Debug, Clone, Default)]
+/// Backends build this from their wire format, and serialize it back to that
+/// format when sending.
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct RichText {
     pub spans: Vec<Span>,
 }
 impl RichText {
-    /// A single unstyled text span (empty input yields no spans). Used by the
-    /// compose path and by backends with no rich formatting.
+    /// A single unstyled text span (empty input yields no spans). Used by
+    /// backends with no rich formatting, and for drafts on platforms with no
+    /// markup (see [`Text::parse_draft`](crate::interface::Text::parse_draft)).
     pub fn plain(text: impl Into<String>) -> Self {
         let text = text.into();
         if text.is_empty() {
@@ -1,10 +1,16 @@
 use chrono::{DateTime, Utc};
 use facet::Facet;
 use futures::future::join_all;
-use messenger_interface::types::{Identifier, Place, Revision, RichText, Room, RoomCapabilities};
+use messenger_interface::types::{
+    House, Identifier, Mention, Place, Reply, ReplyPreview, Revision, RichText, Room,
+    RoomCapabilities, Status, UserPresence,
+};
 use tracing::error;
 
-use crate::downloaders::CdnImage;
+use crate::downloaders::{CdnAttachment, CdnImage};
+
+/// Image attachments up to this size are downloaded to show inline.
+const INLINE_ATTACHMENT_MAX_BYTES: u64 = 8 * 1024 * 1024;
 
 pub type SNOWFLAKE = u64;
 
@@ -45,7 +51,7 @@ pub struct User {
     pub username: String,
 }
 
-#[derive(Facet)]
+#[derive(Facet, Clone)]
 pub struct Friend {
     pub id: SNOWFLAKE,
     // is_spam_request: bool,
@@ -54,8 +60,21 @@ pub struct Friend {
     // type: i32,
     pub user: User,
 }
+impl Friend {
+    /// The befriended user with their avatar downloaded.
+    pub async fn to_user_data(&self) -> messenger_interface::types::User {
+        let icon = match &self.user.avatar {
+            Some(hash) => CdnImage::avatar(self.id, hash).fetch().await.ok(),
+            None => None,
+        };
+        messenger_interface::types::User {
+            name: self.user.username.clone(),
+            icon,
+        }
+    }
+}
 
-#[derive(Facet)]
+#[derive(Facet, Clone)]
 pub struct Recipient {
     pub(crate) avatar: Option<String>,
     // avatar_decoration_data: Option<String>,
@@ -69,7 +88,7 @@ pub struct Recipient {
 
 // === Chennels ===
 
-#[derive(Facet)]
+#[derive(Facet, Clone)]
 pub(crate) struct OverwriteObject {
     // pub(crate) id: String,
     // pub(crate) allow: String,
@@ -101,7 +120,14 @@ impl From<ChannelTypes> for RoomCapabilities {
             ChannelTypes::DM | ChannelTypes::GroupDM => {
                 RoomCapabilities::Text | RoomCapabilities::Voice
             }
-            ChannelTypes::GuildText | ChannelTypes::GuildAnnouncement => RoomCapabilities::Text,
+            ChannelTypes::GuildText | ChannelTypes::GuildAnnouncement => {
+                RoomCapabilities::Text | RoomCapabilities::Threads
+            }
+            ChannelTypes::AnnouncementThread
+            | ChannelTypes::PublicThread
+            | ChannelTypes::PrivateThread => RoomCapabilities::Text,
+            // Forums hold only posts, each one a thread.
+            ChannelTypes::GuildForum | ChannelTypes::GuildMedia => RoomCapabilities::Threads,
             ChannelTypes::GuildVoice | ChannelTypes::GuildStageVoice => RoomCapabilities::Voice,
             ChannelTypes::GuildCategory => RoomCapabilities::empty(),
             _ => RoomCapabilities::empty(),
@@ -109,7 +135,7 @@ impl From<ChannelTypes> for RoomCapabilities {
     }
 }
 
-#[derive(Facet)]
+#[derive(Facet, Clone)]
 pub struct Channel {
     pub id: SNOWFLAKE,
     pub guild_id: Option<SNOWFLAKE>,
@@ -123,8 +149,50 @@ pub struct Channel {
     pub name: Option<String>,
     pub recipients: Option<Vec<Recipient>>,
     pub permission_overwrites: Option<Vec<OverwriteObject>>,
+    /// Set on threads only.
+    pub thread_metadata: Option<ThreadMetadata>,
+}
+
+// https://discord.com/developers/docs/resources/channel#thread-metadata-object
+#[derive(Facet, Clone)]
+pub struct ThreadMetadata {
+    pub archived: bool,
+    // auto_archive_duration: u32,
+    // archive_timestamp: String,
+    // locked: bool,
 }
+
+// https://docs.discord.food/resources/channel#search-threads
+#[derive(Facet)]
+pub struct ThreadSearch {
+    pub threads: Vec<Channel>,
+    // members: Vec<ThreadMember>,
+    // has_more: bool,
+}
+
 impl Channel {
+    pub fn is_thread(&self) -> bool {
+        matches!(
+            self.channel_type,
+            ChannelTypes::AnnouncementThread
+                | ChannelTypes::PublicThread
+                | ChannelTypes::PrivateThread
+        )
+    }
+
+    /// The category of a channel, or the channel a thread was opened in.
+    pub fn parent(&self) -> Option<SNOWFLAKE> {
+        self.parent_id.as_ref()?.parse().ok()
+    }
+
+    /// An archived thread is closed: it no longer lists among the active
+    /// threads of its channel.
+    pub fn is_archived(&self) -> bool {
+        self.thread_metadata
+            .as_ref()
+            .is_some_and(|metadata| metadata.archived)
+    }
+
     /// Extract room data, name, and icon from a channel.
     /// Returns (name, icon, room_data).
     pub async fn to_room_data(&self) -> Place<Room> {
@@ -182,12 +250,17 @@ impl Channel {
             };
         }
 
-        let room = Room::new(
+        let mut room = Room::new(
             // NOTE: DMs can have voice calls; treat as both for now.
             RoomCapabilities::from(self.channel_type),
             Some(recipients),
             None,
         );
+        if self.is_thread()
+            && let Some(parent) = self.parent()
+        {
+            room = room.with_parent(parent);
+        }
 
         Place {
             name,
@@ -230,9 +303,21 @@ pub struct StickerItem {
     pub format_type: u8,
 }
 
+// https://discord.com/developers/docs/resources/message#attachment-object
+#[derive(Facet, Clone)]
+pub struct Attachment {
+    pub id: SNOWFLAKE,
+    pub filename: String,
+    pub content_type: Option<String>, // MIME type
+    pub size: u64,                    // Bytes
+    pub url: String,
+    pub height: Option<u32>, // Images and videos only
+    pub width: Option<u32>,  // Images and videos only
+}
+
 #[derive(Facet, Clone)]
 pub struct Message {
-    // attachments: Vec<String>,
+    pub attachments: Option<Vec<Attachment>>, // Absent from partial MESSAGE_UPDATEs
     pub author: User,
     pub channel_id: SNOWFLAKE,
     // components: Vec<String>,
@@ -241,18 +326,120 @@ pub struct Message {
     // embeds: Vec<u32>,
     // flags: u32,
     pub id: SNOWFLAKE,
-    // mention_everyone: bool,
-    // mention_roles: Vec<String>,
-    // mentions: Vec<String>,
+    pub mention_everyone: Option<bool>, // Also set by `@here`
+    pub mention_roles: Option<Vec<SNOWFLAKE>>,
+    pub mentions: Option<Vec<User>>, // The users mentioned by name
+    pub message_reference: Option<MessageReference>,
     // pinned: bool,
     pub reactions: Option<Vec<Reaction>>,
+    pub referenced_message: Option<ReferencedMessage>, // null when the reply target was deleted
     pub sticker_items: Option<Vec<StickerItem>>,
     pub timestamp: String,
     // tts: bool,
     // type: u32,
 }
 
+// https://discord.com/developers/docs/resources/message#message-reference-structure
+#[derive(Facet, Clone)]
+pub struct MessageReference {
+    #[facet(rename = "type")]
+    pub reference_type: Option<u8>, // 0 = DEFAULT (reply), 1 = FORWARD; absent means DEFAULT
+    pub message_id: Option<SNOWFLAKE>,
+    pub channel_id: Option<SNOWFLAKE>,
+    pub guild_id: Option<SNOWFLAKE>,
+    pub fail_if_not_exists: Option<bool>, // Send only: error instead of sending a plain message if the target is gone
+}
+
+/// The replied-to message Discord embeds in a reply; only what the quoted
+/// preview needs.
+#[derive(Facet, Clone)]
+pub struct ReferencedMessage {
+    pub author: User,
+    pub content: String,
+    pub id: SNOWFLAKE,
+}
+
 impl Message {
+    /// The interface reply reference, if this message is a reply. The
+    /// preview quotes the replied-to message as plain text, mentions named by
+    /// `known`.
+    ///
+    /// Forwards and crossposts also carry a `message_reference`; only a
+    /// DEFAULT-type reference into the same channel is a reply.
+    pub fn reply(&self, known: impl Fn(Mention) -> Option<String>) -> Option<Reply> {
+        let reference = self.message_reference.as_ref()?;
+        if reference.reference_type.unwrap_or(0) != 0
+            || reference
+                .channel_id
+                .is_some_and(|channel_id| channel_id != self.channel_id)
+        {
+            return None;
+        }
+        Some(Reply {
+            message_id: reference.message_id?,
+            preview: self
+                .referenced_message
+                .as_ref()
+                .map(|referenced| ReplyPreview {
+                    author: referenced.author.username.clone(),
+                    text: crate::rich::to_plain(&referenced.content, &known),
+                }),
+        })
+    }
+
+    /// Whether the message mentions `user_id` by name, one of the roles
+    /// `has_role` accepts, or everyone.
+    pub fn mentions_user(&self, user_id: SNOWFLAKE, has_role: impl Fn(SNOWFLAKE) -> bool) -> bool {
+        self.mention_everyone == Some(true)
+            || self
+                .mentions
+                .as_deref()
+                .unwrap_or(&[])
+                .iter()
+                .any(|user| user.id == user_id)
+            || self
+                .mention_roles
+                .as_deref()
+                .unwrap_or(&[])
+                .iter()
+                .any(|&role| has_role(role))
+    }
+
+    /// Map the Discord attachment objects onto interface attachments,
+    /// downloading the small images the chat shows inline into the attachment
+    /// cache. A failed download leaves `path` empty rather than dropping the
+    /// attachment.
+    pub async fn interface_attachments(&self) -> Vec<messenger_interface::types::Attachment> {
+        join_all(
+            self.attachments
+                .as_deref()
+                .unwrap_or(&[])
+                .iter()
+                .map(async |attachment| {
+                    let mut interface_attachment = messenger_interface::types::Attachment {
+                        filename: attachment.filename.clone(),
+                        size: attachment.size,
+                        mime: attachment.content_type.clone(),
+                        path: None,
+                        dimensions: attachment.width.zip(attachment.height),
+                    };
+                    if interface_attachment.is_image()
+                        && attachment.size <= INLINE_ATTACHMENT_MAX_BYTES
+                    {
+                        interface_attachment.path = CdnAttachment::new(attachment)
+                            .fetch()
+                            .await
+                            .inspect_err(|err| {
+                                error!("Failed to download attachment {}: {err}", attachment.id)
+                            })
+                            .ok();
+                    }
+                    interface_attachment
+                }),
+        )
+        .await
+    }
+
     /// Map the Discord reaction objects onto interface reactions. An absent
     /// `reactions` field maps to an empty list.
     pub async fn interface_reactions(&self) -> Vec<messenger_interface::types::Reaction> {
@@ -268,6 +455,8 @@ impl Message {
                 emoji: messenger_interface::types::Emoji {
                     shortcode: reaction.emoji.name.clone(),
                     image,
+                    id: reaction.emoji.id,
+                    animated: false,
                 },
                 count: reaction.count,
                 reacted: reaction.me,
@@ -287,7 +476,13 @@ impl Message {
     /// whose `text` is empty — enough to drive the UI's "edited"
     /// indicator while being honest that we don't know what the message
     /// used to say.
-    pub async fn revisions(&self) -> (Revision, Vec<Revision>) {
+    ///
+    /// Mentioned users are named from the message's own `mentions`; `known`
+    /// names the roles and channels, and any user missing there.
+    pub async fn revisions(
+        &self,
+        known: impl Fn(Mention) -> Option<String>,
+    ) -> (Revision, Vec<Revision>) {
         let parse = |s: &str| {
             DateTime::parse_from_rfc3339(s)
                 .ok()
@@ -295,7 +490,16 @@ impl Message {
         };
         let created_at = parse(&self.timestamp);
         let stickers = self.sticker_items.as_deref().unwrap_or(&[]);
-        let content = crate::rich::build_content(&self.content, stickers).await;
+        let mentioned = self.mentions.as_deref().unwrap_or(&[]);
+        let names = |mention| {
+            let user = match mention {
+                Mention::User(id) => mentioned.iter().find(|user| user.id == id),
+                _ => None,
+            };
+            user.map(|user| user.username.clone())
+                .or_else(|| known(mention))
+        };
+        let content = crate::rich::build_content(&self.content, stickers, names).await;
         match self.edited_timestamp.as_deref().and_then(parse) {
             Some(edit_at) => (
                 Revision {
@@ -328,17 +532,92 @@ pub struct CreateMessage {
     //
     // embeds: Option<Vec<Embed>>,                // Up to 10 rich embeds (max 6000 chars total)
     // allowed_mentions: Option<AllowedMentions>, // Who can be mentioned
-    // message_reference: Option<MessageReference>, // Reply or forward
+    pub message_reference: Option<MessageReference>, // Reply or forward
     // components: Option<Vec<Component>>,        // Components to include with the message
     // sticker_ids: Option<Vec<Snowflake>>,        // IDs of up to 3 stickers
     // files: Option<Vec<FileContent>>,            // Files being sent
     // payload_json: Option<String>,               // JSON-encoded body for multipart/form-data
-    // attachments: Option<Vec<Attachment>>,       // Attachments (filename, description)
+    pub attachments: Option<Vec<CreateAttachment>>, // Describes the `files[n]` parts of a multipart send
     //
     pub flags: Option<u32>, // Bitfield (only certain flags allowed)
                             // poll: Option<Poll>, // Poll object
 }
 
+// https://discord.com/developers/docs/reference#uploading-files
+#[derive(Facet)]
+pub struct CreateAttachment {
+    pub id: u32, // Index `n` of the matching `files[n]` part
+    pub filename: String,
+}
+
+// https://discord.com/developers/docs/resources/message#edit-message-jsonform-params
+#[derive(Facet)]
+pub struct EditMessage {
+    pub content: Option<String>, // Up to 2000 characters
+}
+
+// === Presence ===
+
+/// <https://docs.discord.food/resources/presence#activity-object>
+#[derive(Facet, Clone)]
+pub struct Activity {
+    pub name: String,
+    // https://docs.discord.food/resources/presence#activity-type
+    #[facet(rename = "type")]
+    pub activity_type: u8,
+    /// The status text, for custom statuses.
+    pub state: Option<String>,
+}
+impl Activity {
+    const CUSTOM: u8 = 4;
+
+    fn describe(&self) -> String {
+        let name = &self.name;
+        match self.activity_type {
+            0 => format!("Playing {name}"),
+            1 => format!("Streaming {name}"),
+            2 => format!("Listening to {name}"),
+            3 => format!("Watching {name}"),
+            5 => format!("Competing in {name}"),
+            _ => name.clone(),
+        }
+    }
+}
+
+/// Build the interface presence from a presence object's `status` and
+/// `activities`. A custom status wins over games, as in the official client.
+pub fn presence(status: &str, activities: &[Activity]) -> UserPresence {
+    let status = match status {
+        "online" => Status::Online,
+        "idle" => Status::Idle,
+        "dnd" => Status::DoNotDisturb,
+        "invisible" => Status::Invisible,
+        _ => Status::Offline,
+    };
+    let custom = activities
+        .iter()
+        .find(|activity| activity.activity_type == Activity::CUSTOM)
+        .and_then(|activity| activity.state.clone());
+    let activity = custom.or_else(|| {
+        activities
+            .iter()
+            .find(|activity| activity.activity_type != Activity::CUSTOM)
+            .map(Activity::describe)
+    });
+    UserPresence { status, activity }
+}
+
+/// The `status` string for a Presence Update (opcode 3). A connected client
+/// can't be offline, only invisible.
+pub fn status_name(status: Status) -> &'static str {
+    match status {
+        Status::Online => "online",
+        Status::Idle => "idle",
+        Status::DoNotDisturb => "dnd",
+        Status::Invisible | Status::Offline => "invisible",
+    }
+}
+
 // === Auth / Login ===
 // Username + password login, as used by the official client (not the bot API).
 // Field/endpoint shapes verified against: https://docs.discord.food/authentication
@@ -413,8 +692,19 @@ pub struct MessageReactionChange {
     pub emoji: Emoji,
 }
 
+// https://discord.com/developers/docs/topics/permissions#role-object
+#[derive(Facet, Clone)]
+pub struct Role {
+    pub id: SNOWFLAKE,
+    pub name: String,
+    // color: u32,
+    // hoist: bool,
+    // position: i32,
+    // permissions: String,
+}
+
 // https://discord.com/developers/docs/resources/guild#guild-object
-#[derive(Facet)]
+#[derive(Facet, Clone)]
 pub struct Guild {
     pub id: SNOWFLAKE,
     pub name: String,
@@ -461,3 +751,20 @@ pub struct Guild {
     // pub safety_alerts_channel_id: Option<String>,  // Snowflake
     // pub incidents_data: Option<IncidentsData>,
 }
+impl Guild {
+    /// The guild as a house with its icon downloaded. Rooms are left unloaded;
+    /// `house_details` fills them in.
+    pub async fn to_house_data(&self) -> Place<House> {
+        let icon = match &self.icon {
+            Some(hash) => match CdnImage::guild_icon(self.id, hash).fetch().await {
+                Ok(path) => Some(path),
+                Err(e) => {
+                    error!("Failed to download icon for guild: {}\n{}", self.name, e);
+                    None
+                }
+            },
+            None => None,
+        };
+        Place::new(self.name.clone(), icon, House::new(None))
+    }
+}
@@ -1,21 +1,28 @@
 use std::{
     error::Error,
     path::{Path, PathBuf},
+    sync::LazyLock,
     time::Duration,
 };
 
-use futures_timer::Delay;
-
 use facet::Facet;
+use futures_timer::Delay;
 use messenger_interface::types::{CacheCategory, cache_dir};
 use surf::{RequestBuilder, StatusCode};
 use tracing::{error, warn};
 
-use crate::{INTERFACE_NAME, api_types::SNOWFLAKE};
+use crate::{
+    INTERFACE_NAME,
+    api_types::{self, SNOWFLAKE},
+    rate_limits::{RateLimitHeaders, RateLimits, Route},
+};
 
 const IMG_EXT: &str = "webp";
 const DISCORD_CDN: &str = "https://cdn.discordapp.com";
 
+/// Shared by every [`Fetch`]; Discord limits per account, not per connection.
+static RATE_LIMITS: LazyLock<RateLimits> = LazyLock::new(RateLimits::default);
+
 /// A Discord CDN image, identified typesafely instead of by a stringly path
 /// segment. Each variant owns enough to derive its CDN URL, cache filename, and
 /// [`CacheCategory`], so all of that construction lives here rather than being
@@ -121,6 +128,50 @@ impl CdnImage {
     }
 }
 
+/// A message attachment on Discord's CDN. Unlike a [`CdnImage`], its URL can't
+/// be derived — attachment URLs are signed and expire — so it's carried over
+/// from the API object.
+pub(crate) struct CdnAttachment {
+    id: SNOWFLAKE,
+    filename: String,
+    url: String,
+}
+
+impl CdnAttachment {
+    pub(crate) fn new(attachment: &api_types::Attachment) -> Self {
+        Self {
+            id: attachment.id,
+            filename: attachment.filename.clone(),
+            url: attachment.url.clone(),
+        }
+    }
+
+    /// `{id}/{filename}`: the id keeps attachments apart, the filename keeps
+    /// the extension for whatever opens the file. Only the final component of
+    /// the uploader-chosen name is used, so it can't escape the cache.
+    fn file_name(&self) -> String {
+        let name = Path::new(&self.filename)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("attachment");
+        format!("{}/{name}", self.id)
+    }
+
+    /// Fetch the attachment (or load it from the on-disk cache) and return its
+    /// local path, like [`CdnImage::fetch`]. The cache is keyed by id, so an
+    /// expired signature on a cached attachment doesn't matter.
+    pub(crate) async fn fetch(self) -> Result<PathBuf, Box<dyn Error + Sync + Send>> {
+        Fetch::<Cached>::cached_fetch(
+            || surf::get(&self.url),
+            Vec::new(),
+            CacheCategory::Attachments,
+            &self.file_name(),
+        )
+        .await
+        .map(|fetched| fetched.into_path())
+    }
+}
+
 /// Typestate marker: a live, uncached response, read straight from the network.
 pub(crate) struct Fresh(surf::Response);
 /// Typestate marker: a body that lives on disk at this path.
@@ -135,11 +186,14 @@ pub(crate) struct Cached(PathBuf);
 pub(crate) struct Fetch<S>(S);
 
 impl Fetch<Fresh> {
-    /// Perform a one-shot request, accepting any 2xx response. Retries on 429
-    /// honoring `Retry-After` (reactions hit Discord's rate limits first), and
-    /// surfaces the response body of failed requests in the returned error
-    /// instead of discarding it — Discord explains failures (e.g. an outdated
-    /// token) there. The body is otherwise left unread for the terminal.
+    /// Perform a one-shot request, accepting any 2xx response. An
+    /// authenticated request waits its turn in its rate limit bucket first
+    /// (see [`RateLimits`]), and should a 429 get through anyway it is retried
+    /// once the bucket (or, unauthenticated, the `Retry-After`) allows.
+    /// The response body of a failed request is surfaced in the returned
+    /// error instead of discarded — Discord explains failures (e.g. an
+    /// outdated token) there. The body is otherwise left unread for the
+    /// terminal.
     pub(crate) async fn fetch(
         req: impl Fn() -> RequestBuilder,
         headers: Vec<(&str, String)>,
@@ -153,22 +207,37 @@ impl Fetch<Fresh> {
             let request = request.build();
             let method = request.method().to_string();
             let url = request.url().to_string();
-
+            let authorization = headers
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case("Authorization"))
+                .map(|(_, value)| value.as_str());
+            let permit = match authorization {
+                Some(authorization) => {
+                    let route = Route::new(authorization, &method, request.url().path());
+                    Some(RATE_LIMITS.acquire(route).await)
+                }
+                None => None,
+            };
             let mut res = surf::client().send(request).await.map_err(|err| {
                 error!("HTTP request failed for {method} {url}: {err}");
                 err
             })?;
             let status = res.status();
+            let limited = status == StatusCode::TooManyRequests;
+            let rate_limit = RateLimitHeaders::of(&res);
+            if let Some(permit) = permit {
+                permit.finish(&rate_limit, limited);
+            }
 
-            if status == StatusCode::TooManyRequests && attempt < MAX_ATTEMPTS {
-                let retry_after = res
-                    .header("Retry-After")
-                    .and_then(|values| values.last().as_str().parse::<f64>().ok())
-                    .unwrap_or(1.0);
+            if limited && attempt < MAX_ATTEMPTS {
                 warn!(
-                    "Rate limited {method} {url} (attempt {attempt}/{MAX_ATTEMPTS}); retrying in {retry_after}s"
+                    "Rate limited {method} {url} (attempt {attempt}/{MAX_ATTEMPTS}); retrying after {:?}",
+                    rate_limit.retry_after
                 );
-                Delay::new(Duration::from_secs_f64(retry_after)).await;
+                if authorization.is_none() {
+                    // Nothing else holds the retry back.
+                    Delay::new(rate_limit.retry_after.unwrap_or(Duration::from_secs(1))).await;
+                }
                 continue;
             }
             if !status.is_success() {
@@ -2,7 +2,10 @@ use std::{
     iter,
     ops::Deref,
     pin::Pin,
-    sync::{OnceLock, atomic::AtomicUsize},
+    sync::{
+        OnceLock,
+        atomic::{AtomicUsize, Ordering},
+    },
     time::Duration,
 };
 
@@ -12,7 +15,7 @@ use async_tungstenite::{
     tungstenite::{Bytes, Message as WebsocketMessage},
 };
 use facet::Facet;
-use futures::{StreamExt, lock::Mutex as AsyncMutex, stream::FusedStream};
+use futures::{StreamExt, future::ready, lock::Mutex as AsyncMutex, stream::FusedStream};
 use futures_timer::Delay;
 use tracing::warn;
 
@@ -25,6 +28,9 @@ pub mod voice;
 struct Websocket {
     sender: AsyncMutex<WebSocketSender<ConnectStream>>,
     receiver: AsyncMutex<WebSocketReceiver<ConnectStream>>,
+    /// Code of the close frame the server ended the connection with, once
+    /// [`GatewayStreamReciver::filter_payload`] has seen one.
+    close_code: OnceLock<u16>,
 }
 impl Websocket {
     fn new(websocket: WebSocketStream<ConnectStream>) -> Self {
@@ -32,6 +38,7 @@ impl Websocket {
         Self {
             sender: sender.into(),
             receiver: receiver.into(),
+            close_code: OnceLock::new(),
         }
     }
     async fn send(
@@ -55,16 +62,23 @@ impl Websocket {
     }
 }
 trait GatewayStreamReciver: FusedStream {
-    fn filter_payload<Op: Facet<'static> + TryFrom<u8>>(
-        &mut self,
-    ) -> impl FusedStream<Item = GatewayPayload<Op>> + '_;
+    /// The gateway payloads among the frames received. The code of a close
+    /// frame, should one arrive, is stored in `close_code`.
+    fn filter_payload<'a, Op: Facet<'static> + TryFrom<u8>>(
+        &'a mut self,
+        close_code: &'a OnceLock<u16>,
+    ) -> impl FusedStream<Item = GatewayPayload<Op>> + 'a;
 }
 impl GatewayStreamReciver for WebSocketReceiver<ConnectStream> {
-    fn filter_payload<Op: Facet<'static> + TryFrom<u8>>(
-        &mut self,
-    ) -> impl FusedStream<Item = GatewayPayload<Op>> + '_ {
-        self.filter_map(async |msg| -> Option<GatewayPayload<Op>> {
-            match msg {
+    fn filter_payload<'a, Op: Facet<'static> + TryFrom<u8>>(
+        &'a mut self,
+        close_code: &'a OnceLock<u16>,
+    ) -> impl FusedStream<Item = GatewayPayload<Op>> + 'a {
+        self.filter_map(move |msg| {
+            if let Ok(WebsocketMessage::Close(Some(frame))) = &msg {
+                let _ = close_code.set(frame.code.into());
+            }
+            ready(match msg {
                 Ok(msg) => match parse_gateway_event::<Op>(&msg) {
                     Some(payload) => Some(payload),
                     None => {
@@ -76,7 +90,7 @@ impl GatewayStreamReciver for WebSocketReceiver<ConnectStream> {
                     warn!("Gateway websocket error while waiting for payload: {err:#?}");
                     None
                 }
-            }
+            })
         })
     }
 }
@@ -128,6 +142,19 @@ impl<T> Gateway<T> {
     ) -> Result<(), async_tungstenite::tungstenite::Error> {
         self.websocket.send(msg).await
     }
+
+    /// Sequence number of the last dispatch received, if any.
+    pub(crate) fn sequence(&self) -> Option<usize> {
+        self.last_sequence_number
+            .get()
+            .map(|sequence| sequence.load(Ordering::Relaxed))
+    }
+
+    /// Code of the close frame the server ended this connection with, if it
+    /// sent one.
+    pub(crate) fn close_code(&self) -> Option<u16> {
+        self.websocket.close_code.get().copied()
+    }
 }
 impl<T> Deref for Gateway<T> {
     type Target = T;
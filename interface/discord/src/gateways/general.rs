@@ -21,6 +21,7 @@ use crate::{
     },
 };
 
+mod caches;
 mod events;
 pub(crate) mod payloads;
 pub(crate) mod recording;
@@ -41,6 +42,7 @@ pub enum Opcode {
     Identify = 2,
     PresenceUpdate = 3,
     VoiceStateUpdate = 4,
+    Resume = 6,
     Reconnect = 7,
     InvalidSession = 9,
     Hello = 10,
@@ -56,6 +58,7 @@ pub enum Opcode {
 pub enum GatewayEvent {
     Hello,
     Ready,
+    ReadySupplemental,
     Resumed,
     Reconnect,
     RateLimited,
@@ -101,6 +104,11 @@ pub enum GatewayEvent {
     MessageDeleteBulk,
     MessageReactionAdd,
     MessageReactionRemove,
+    MessageAck,
+    TypingStart,
+    PresenceUpdate,
+    RelationshipAdd,
+    RelationshipRemove,
 }
 
 pub struct General {
@@ -116,19 +124,48 @@ pub struct General {
     pub recorded: Mutex<recording::RecordingState>,
 }
 
+/// What a dropped connection needs to pick up where it left off, taken from
+/// the last `Ready` and kept on `InnerDiscord` across connections.
+/// <https://docs.discord.food/topics/gateway#resuming>
+#[derive(Clone)]
+pub(crate) struct ResumeSession {
+    pub(crate) session_id: String,
+    /// Host-only URL (no query string) to dial for the resume.
+    pub(crate) resume_gateway_url: Option<String>,
+    /// Last dispatch sequence number seen; the server replays everything after.
+    pub(crate) sequence: usize,
+}
+
 impl Gateway<General> {
-    const GATEWAY_URL: &str = "wss://gateway.discord.gg/?encoding=json&v=9";
+    /// Production gateway; the URL actually dialed is `InnerDiscord::endpoints`.
+    pub(crate) const GATEWAY_URL: &str = "wss://gateway.discord.gg/?encoding=json&v=9";
+    const GATEWAY_QUERY: &str = "?encoding=json&v=9";
+
+    /// Connect and either resume the session stored on `discord` (opcode 6)
+    /// or, without one, identify afresh (opcode 2). A resume is answered by
+    /// the missed dispatches followed by `RESUMED`, or by an `InvalidSession`
+    /// that makes the next connection identify.
     pub async fn new<T: UnitStruct>(
         discord: &InnerDiscord<T>,
     ) -> Result<Self, Box<dyn Error + Sync + Send>> {
-        let (gateway_websocket, _) = connect_async(Self::GATEWAY_URL).await?;
+        let resume = discord.resume_session.lock().unwrap().clone();
+        let url = match resume
+            .as_ref()
+            .and_then(|session| session.resume_gateway_url.as_deref())
+        {
+            Some(host) => format!("{}/{}", host.trim_end_matches('/'), Self::GATEWAY_QUERY),
+            None => discord.endpoints.gateway.clone(),
+        };
+        let (gateway_websocket, _) = connect_async(url.as_str()).await?;
         let websocket = Websocket::new(gateway_websocket);
 
         // First event send by discord has to be Hello event according to
         // https://docs.discord.food/topics/gateway#connections
         let hello_event = {
             let mut receiver = websocket.receiver.lock().await;
-            pin!(receiver.filter_payload()).next().await
+            pin!(receiver.filter_payload(&websocket.close_code))
+                .next()
+                .await
         }
         .ok_or_else(|| {
             io::Error::new(
@@ -154,8 +191,20 @@ impl Gateway<General> {
         // login returns the cached token immediately.
         let token = discord.ensure_token().await?;
         let token = token.unsecure().trim();
-        websocket
-            .send(WebsocketMessage::Text(
+        let resuming = resume.is_some();
+        let (handshake, last_sequence_number) = match resume {
+            Some(session) => (
+                json!({
+                    "op": Opcode::Resume as u8,
+                    "d": {
+                        "token": token,
+                        "session_id": session.session_id,
+                        "seq": session.sequence,
+                    }
+                }),
+                OnceLock::from(AtomicUsize::new(session.sequence)),
+            ),
+            None => (
                 json!({
                     "op": Opcode::Identify as u8,
                     "d": {
@@ -168,17 +217,23 @@ impl Gateway<General> {
                             "$device": ""
                         }
                     }
-                })
-                .to_string()
-                .into(),
-            ))
+                }),
+                OnceLock::new(),
+            ),
+        };
+        websocket
+            .send(WebsocketMessage::Text(handshake.to_string().into()))
             .await?;
-        debug!("Identify payload sent");
+        if resuming {
+            debug!("Resume payload sent");
+        } else {
+            debug!("Identify payload sent");
+        }
 
         Ok(Self {
             websocket,
             heart_beating: HeartBeatingData::new(heart_beating_duration).into(),
-            last_sequence_number: OnceLock::new(),
+            last_sequence_number,
             type_specific_data: General {
                 voice: VoiceGateway::default(),
                 recording_refs: AtomicUsize::new(0),
@@ -0,0 +1,484 @@
+//! Incremental upkeep of the gateway-owned caches after `Ready`.
+//!
+//! `Ready` seeds `guilds`, `guild_channels`, `guild_threads`, `guild_roles`,
+//! `own_roles`, `dm_channels` and `relationships` wholesale; the `CHANNEL_*`, `THREAD_*`,
+//! `GUILD_*` and `RELATIONSHIP_*`
+//! arms in [`super::events`] call these helpers to keep them current for the
+//! rest of the session, and emit the matching `QueryEvent` after the cache is
+//! updated so a query made in response already sees the change.
+//!
+//! `presences` has no snapshot of its own: `Ready`, `READY_SUPPLEMENTAL` and
+//! `PRESENCE_UPDATE` all go through [`InnerDiscord::cache_presence`], which
+//! reports each change as a `PresenceEvent`.
+//!
+//! `MESSAGE_CREATE` moves its channel's `last_message_id` on and counts the
+//! message in `read_states` ([`InnerDiscord::note_message`]).
+//!
+//! The list caches are `ArcSwapOption`s handed out to readers as snapshots,
+//! so edits copy the list and swap it in. A cache still `None` (no `Ready`
+//! yet) is left alone: the REST cold-start path serves it until `Ready`
+//! seeds it whole.
+
+use std::{collections::HashMap, sync::Arc};
+
+use arc_swap::ArcSwapOption;
+use messenger_interface::{
+    interface::PresenceEvent,
+    types::{Mention, ReadState, UserPresence},
+};
+use tracing::warn;
+
+use super::payloads::{MergedMemberPayload, ReadyGuildPayload};
+use crate::{ChannelLocation, InnerDiscord, UnitStruct, api_types};
+
+/// Copy-on-write edit of a list cache; a no-op while the cache is `None`.
+fn edit_cache<V: Clone>(cache: &ArcSwapOption<Vec<V>>, edit: impl Fn(&mut Vec<V>)) {
+    cache.rcu(|current| {
+        current.as_ref().map(|current| {
+            let mut next = Vec::clone(current);
+            edit(&mut next);
+            Arc::new(next)
+        })
+    });
+}
+
+/// Replace the entry `id_of` matches in `list`, or append `value`.
+fn upsert<V: Clone>(list: &mut Vec<V>, value: &V, id_of: impl Fn(&V) -> api_types::SNOWFLAKE) {
+    let id = id_of(value);
+    match list.iter_mut().find(|entry| id_of(entry) == id) {
+        Some(entry) => *entry = value.clone(),
+        None => list.push(value.clone()),
+    }
+}
+
+impl<T: UnitStruct> InnerDiscord<T> {
+    /// Cache one gateway guild object, from `Ready` or `GuildCreate`: its
+    /// channels go into `guild_channels`, its roles into `guild_roles`, the
+    /// client's own roles into `own_roles` and its voice states into the
+    /// voice rosters. `merged_members` are the client's own members that
+    /// `Ready` sends separately. Returns the guild's properties for the
+    /// caller to cache, and one roster future per voice state for the caller
+    /// to await — `Ready` awaits those of every guild together.
+    pub(super) fn ingest_guild(
+        &self,
+        guild: ReadyGuildPayload,
+        merged_members: Vec<MergedMemberPayload>,
+    ) -> (Option<api_types::Guild>, Vec<impl Future<Output = ()> + '_>) {
+        if let (Some(guild_id), Some(channels)) = (guild.id, guild.channels) {
+            self.guild_channels.insert(guild_id, channels);
+        }
+        if let (Some(guild_id), Some(mut threads)) = (guild.id, guild.threads) {
+            for thread in &mut threads {
+                thread.guild_id = Some(guild_id);
+            }
+            self.guild_threads.insert(guild_id, threads);
+        }
+        if let (Some(guild_id), Some(roles)) = (guild.id, guild.roles) {
+            self.guild_roles.insert(guild_id, roles);
+        }
+
+        let members = guild.members.unwrap_or_default();
+        // The client's own member: apart in `merged_members` from `Ready`,
+        // among `members` from `GuildCreate`.
+        let own_id = self.profile.load().as_ref().map(|profile| profile.id);
+        let own_members = merged_members
+            .iter()
+            .map(|member| &member.roles)
+            .chain(
+                members
+                    .iter()
+                    .filter(|member| Some(member.user.id) == own_id)
+                    .map(|member| &member.roles),
+            )
+            .collect::<Vec<_>>();
+        if let Some(guild_id) = guild.id.filter(|_| !own_members.is_empty()) {
+            let roles = own_members
+                .into_iter()
+                .flatten()
+                .flatten()
+                .copied()
+                .collect();
+            self.own_roles.insert(guild_id, roles);
+        }
+
+        let mut members = members
+            .into_iter()
+            .map(|member| member.user)
+            .chain(merged_members.into_iter().filter_map(|member| member.user))
+            .map(|user| (user.id, user))
+            .collect::<HashMap<_, _>>();
+
+        let voice_participant_futures = guild
+            .voice_states
+            .unwrap_or_default()
+            .into_iter()
+            .map(|voice_state| {
+                let member_user = members.remove(&voice_state.user_id);
+                self.emit_voice_state_participant(voice_state.user_id, voice_state, member_user)
+            })
+            .collect();
+
+        (guild.properties, voice_participant_futures)
+    }
+
+    /// Insert or replace a guild's properties (name, icon).
+    pub(super) fn cache_guild(&self, guild: api_types::Guild) {
+        self.guild_id_mappings.insert(guild.id, guild.id);
+        edit_cache(&self.guilds, |guilds| upsert(guilds, &guild, |g| g.id));
+    }
+
+    /// Drop everything cached for a guild the client is no longer in: the
+    /// guild, its channels and threads with their ID mappings, the voice
+    /// rosters, its roles, and the voice states of its members.
+    pub(super) fn forget_guild(&self, guild_id: api_types::SNOWFLAKE) {
+        edit_cache(&self.guilds, |guilds| guilds.retain(|g| g.id != guild_id));
+        self.guild_id_mappings.remove(&guild_id);
+        if let Some((_, channels)) = self.guild_channels.remove(&guild_id) {
+            for channel in channels {
+                self.channel_id_mappings.remove(&channel.id);
+                self.voice_participants.remove(&channel.id);
+            }
+        }
+        if let Some((_, threads)) = self.guild_threads.remove(&guild_id) {
+            for thread in threads {
+                self.channel_id_mappings.remove(&thread.id);
+            }
+        }
+        self.guild_roles.remove(&guild_id);
+        self.own_roles.remove(&guild_id);
+        self.voice_states
+            .retain(|_, voice_state| voice_state.guild_id != Some(guild_id));
+    }
+
+    /// Insert or replace a channel in the guild channel list or, for DMs and
+    /// group DMs, the DM list, and (re)map its ID.
+    pub(super) fn cache_channel(&self, channel: api_types::Channel) {
+        // Top-level channel events carry guild_id directly, so no parent
+        // context is needed.
+        match ChannelLocation::from_api(&channel, None) {
+            Some(location) => {
+                self.channel_id_mappings.insert(channel.id, location);
+            }
+            None => warn!("Channel {} produced no ChannelLocation", channel.id),
+        }
+
+        match channel.guild_id {
+            Some(guild_id) => upsert(
+                &mut *self.guild_channels.entry(guild_id).or_default(),
+                &channel,
+                |c| c.id,
+            ),
+            None => edit_cache(&self.dm_channels, |channels| {
+                upsert(channels, &channel, |c| c.id)
+            }),
+        }
+    }
+
+    /// Drop a channel along with the threads opened in it.
+    pub(super) fn forget_channel(&self, channel: &api_types::Channel) {
+        match channel.guild_id {
+            Some(guild_id) => {
+                if let Some(mut channels) = self.guild_channels.get_mut(&guild_id) {
+                    channels.retain(|c| c.id != channel.id);
+                }
+                if let Some(mut threads) = self.guild_threads.get_mut(&guild_id) {
+                    threads.retain(|thread| {
+                        let child = thread.parent() == Some(channel.id);
+                        if child {
+                            self.channel_id_mappings.remove(&thread.id);
+                        }
+                        !child
+                    });
+                }
+            }
+            None => edit_cache(&self.dm_channels, |channels| {
+                channels.retain(|c| c.id != channel.id)
+            }),
+        }
+        self.channel_id_mappings.remove(&channel.id);
+        self.voice_participants.remove(&channel.id);
+    }
+
+    /// Insert or replace an active thread and (re)map its ID. Returns whether
+    /// the thread was already cached.
+    pub(super) fn cache_thread(&self, thread: api_types::Channel) -> bool {
+        let Some(guild_id) = thread.guild_id else {
+            warn!("Thread {} came without a guild", thread.id);
+            return false;
+        };
+        if let Some(location) = ChannelLocation::from_api(&thread, None) {
+            self.channel_id_mappings.insert(thread.id, location);
+        }
+        let mut threads = self.guild_threads.entry(guild_id).or_default();
+        let known = threads.iter().any(|t| t.id == thread.id);
+        upsert(&mut threads, &thread, |t| t.id);
+        known
+    }
+
+    /// Drop a thread that was deleted or archived.
+    pub(super) fn forget_thread(
+        &self,
+        guild_id: api_types::SNOWFLAKE,
+        thread_id: api_types::SNOWFLAKE,
+    ) {
+        self.channel_id_mappings.remove(&thread_id);
+        if let Some(mut threads) = self.guild_threads.get_mut(&guild_id) {
+            threads.retain(|t| t.id != thread_id);
+        }
+    }
+
+    /// The newest message the caches know of in `channel_id`.
+    pub(crate) fn last_message_id(
+        &self,
+        channel_id: api_types::SNOWFLAKE,
+    ) -> Option<api_types::SNOWFLAKE> {
+        let find = |channels: &[api_types::Channel]| {
+            channels
+                .iter()
+                .find(|channel| channel.id == channel_id)
+                .map(|channel| channel.last_message_id)
+        };
+        self.dm_channels
+            .load()
+            .as_deref()
+            .and_then(|channels| find(channels))
+            .or_else(|| {
+                self.guild_channels
+                    .iter()
+                    .find_map(|channels| find(&channels))
+            })
+            .or_else(|| self.guild_threads.iter().find_map(|threads| find(&threads)))
+            .flatten()
+    }
+
+    /// Take a new message into its channel's `last_message_id` and read
+    /// state: one more unread (and mention, if `mentions_me`), or, when the
+    /// client sent it, the channel read up to it.
+    pub(super) fn note_message(&self, message: &api_types::Message, mentions_me: bool) {
+        let channel_id = message.channel_id;
+        let previous = self.last_message_id(channel_id);
+        let advance = |channels: &mut Vec<api_types::Channel>| {
+            for channel in channels.iter_mut().filter(|c| c.id == channel_id) {
+                channel.last_message_id = Some(message.id);
+            }
+        };
+        let in_dms = self
+            .dm_channels
+            .load()
+            .as_deref()
+            .is_some_and(|channels| channels.iter().any(|c| c.id == channel_id));
+        if in_dms {
+            edit_cache(&self.dm_channels, advance);
+        }
+        for mut channels in self.guild_channels.iter_mut() {
+            advance(&mut channels);
+        }
+        for mut threads in self.guild_threads.iter_mut() {
+            advance(&mut threads);
+        }
+
+        let own = self
+            .profile
+            .load()
+            .as_ref()
+            .is_some_and(|profile| profile.id == message.author.id);
+        let mut state = self.read_states.entry(channel_id).or_default();
+        if own {
+            *state = ReadState {
+                last_read: Some(message.id),
+                ..Default::default()
+            };
+        } else {
+            // What came before `Ready` is only known to be there, so it
+            // counts as one.
+            if state.unread == 0 && previous > state.last_read {
+                state.unread = 1;
+            }
+            state.unread += 1;
+            if mentions_me {
+                state.mentions += 1;
+            }
+        }
+    }
+
+    /// Replace the active threads of the channels `channel_ids` (every
+    /// channel of the guild when `None`) with `threads`, as
+    /// `THREAD_LIST_SYNC` asks. Returns the threads that were not cached
+    /// before and the IDs of those no longer active.
+    pub(super) fn sync_threads(
+        &self,
+        guild_id: api_types::SNOWFLAKE,
+        channel_ids: Option<Vec<api_types::SNOWFLAKE>>,
+        threads: Vec<api_types::Channel>,
+    ) -> (Vec<api_types::Channel>, Vec<api_types::SNOWFLAKE>) {
+        let synced = |thread: &api_types::Channel| {
+            channel_ids.as_ref().is_none_or(|channel_ids| {
+                thread
+                    .parent()
+                    .is_some_and(|parent| channel_ids.contains(&parent))
+            })
+        };
+
+        let mut cached = self.guild_threads.entry(guild_id).or_default();
+        let mut closed = Vec::new();
+        cached.retain(|thread| {
+            let gone = synced(thread) && !threads.iter().any(|t| t.id == thread.id);
+            if gone {
+                self.channel_id_mappings.remove(&thread.id);
+                closed.push(thread.id);
+            }
+            !gone
+        });
+
+        let mut opened = Vec::new();
+        for mut thread in threads {
+            thread.guild_id = Some(guild_id);
+            if let Some(location) = ChannelLocation::from_api(&thread, None) {
+                self.channel_id_mappings.insert(thread.id, location);
+            }
+            if !cached.iter().any(|t| t.id == thread.id) {
+                opened.push(thread.clone());
+            }
+            upsert(&mut cached, &thread, |t| t.id);
+        }
+        (opened, closed)
+    }
+
+    /// Insert or replace a relationship; `RELATIONSHIP_ADD` is also sent when
+    /// a pending request turns into a friendship.
+    pub(super) fn cache_relationship(&self, friend: api_types::Friend) {
+        edit_cache(&self.relationships, |relationships| {
+            upsert(relationships, &friend, |f| f.id)
+        });
+    }
+
+    pub(super) fn forget_relationship(&self, user_id: api_types::SNOWFLAKE) {
+        edit_cache(&self.relationships, |relationships| {
+            relationships.retain(|f| f.id != user_id)
+        });
+    }
+
+    /// Record a user's presence and report it, unless it is what was
+    /// already known.
+    pub(crate) fn cache_presence(&self, user_id: api_types::SNOWFLAKE, presence: UserPresence) {
+        if self
+            .presences
+            .get(&user_id)
+            .is_some_and(|known| *known == presence)
+        {
+            return;
+        }
+        self.presences.insert(user_id, presence.clone());
+        self.presence_events
+            .force_push(PresenceEvent::PresenceUpdated { user_id, presence });
+    }
+
+    /// Look a user up among the recipients of `channel_id` (a DM or group
+    /// DM) and then among the relationships, for events that carry only a
+    /// user ID.
+    pub(super) fn known_user(
+        &self,
+        channel_id: api_types::SNOWFLAKE,
+        user_id: api_types::SNOWFLAKE,
+    ) -> Option<api_types::User> {
+        let recipient = self.dm_channels.load().as_ref().and_then(|channels| {
+            channels
+                .iter()
+                .find(|channel| channel.id == channel_id)
+                .and_then(|channel| channel.recipients.as_ref())
+                .and_then(|recipients| recipients.iter().find(|r| r.id == user_id))
+                .map(|recipient| api_types::User {
+                    avatar: recipient.avatar.clone(),
+                    id: recipient.id,
+                    username: recipient.username.clone(),
+                })
+        });
+        recipient.or_else(|| {
+            self.relationships
+                .load()
+                .as_ref()
+                .and_then(|relationships| {
+                    relationships
+                        .iter()
+                        .find(|friend| friend.id == user_id)
+                        .map(|friend| friend.user.clone())
+                })
+        })
+    }
+
+    /// The name a mention shows, looked up in the caches: users among the
+    /// client, its relationships and DM recipients, roles across the
+    /// guilds, channels and threads by their name or DM recipients.
+    pub(crate) fn mention_name(&self, mention: Mention) -> Option<String> {
+        match mention {
+            Mention::User(user_id) => {
+                if let Some(profile) = self.profile.load().as_ref()
+                    && profile.id == user_id
+                {
+                    return Some(profile.username.clone());
+                }
+                let friend = self.relationships.load().as_ref().and_then(|friends| {
+                    friends
+                        .iter()
+                        .find(|friend| friend.id == user_id)
+                        .map(|friend| friend.user.username.clone())
+                });
+                friend.or_else(|| {
+                    self.dm_channels.load().as_ref().and_then(|channels| {
+                        channels
+                            .iter()
+                            .flat_map(|channel| channel.recipients.as_deref().unwrap_or(&[]))
+                            .find(|recipient| recipient.id == user_id)
+                            .map(|recipient| recipient.username.clone())
+                    })
+                })
+            }
+            Mention::Role(role_id) => self.guild_roles.iter().find_map(|roles| {
+                roles
+                    .iter()
+                    .find(|role| role.id == role_id)
+                    .map(|role| role.name.clone())
+            }),
+            Mention::Channel(channel_id) => {
+                let guild_channel = self
+                    .guild_channels
+                    .iter()
+                    .chain(self.guild_threads.iter())
+                    .find_map(|channels| {
+                        channels
+                            .iter()
+                            .find(|channel| channel.id == channel_id)
+                            .and_then(|channel| channel.name.clone())
+                    });
+                guild_channel.or_else(|| {
+                    self.dm_channels.load().as_ref().and_then(|channels| {
+                        let channel = channels.iter().find(|channel| channel.id == channel_id)?;
+                        channel.name.clone().or_else(|| {
+                            let recipients = channel.recipients.as_deref()?;
+                            Some(
+                                recipients
+                                    .iter()
+                                    .map(|recipient| recipient.username.as_str())
+                                    .collect::<Vec<_>>()
+                                    .join(", "),
+                            )
+                        })
+                    })
+                })
+            }
+            Mention::Everyone => Some("everyone".to_owned()),
+        }
+    }
+
+    /// Whether `message` mentions the client user, by name, by one of its
+    /// roles or as everyone. The client's own messages never do.
+    pub(crate) fn mentions_me(&self, message: &api_types::Message) -> bool {
+        self.profile.load().as_ref().is_some_and(|profile| {
+            message.author.id != profile.id
+                && message.mentions_user(profile.id, |role| {
+                    // Role IDs are unique across guilds.
+                    self.own_roles.iter().any(|roles| roles.contains(&role))
+                })
+        })
+    }
+}
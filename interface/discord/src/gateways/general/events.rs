@@ -1,5 +1,4 @@
 use std::{
-    collections::HashMap,
     io,
     sync::{Arc, atomic::Ordering},
 };
@@ -8,17 +7,22 @@ use facet_pretty::FacetPretty;
 use futures::future::join_all;
 use messenger_interface::{
     interface::{QueryEvent, TextEvent},
-    types::{Identifier, Message as GlobalMessage, User as GlobalUser},
+    types::{Identifier, Message as GlobalMessage, ReadState, User as GlobalUser},
 };
 use tracing::{debug, error, trace, warn};
 
 use super::{
-    GatewayEvent, Opcode,
-    payloads::{ReadyPayload, SessionObjectPayload, VoiceServerUpdatePayload, VoiceStatePayload},
+    GatewayEvent, Opcode, ResumeSession,
+    payloads::{
+        GuildDeletePayload, MessageAckPayload, PresenceUpdatePayload, ReadStateEntryPayload,
+        ReadyGuildPayload, ReadyPayload, ReadySupplementalPayload, RelationshipRemovePayload,
+        SessionObjectPayload, ThreadDeletePayload, ThreadListSyncPayload, TypingStartPayload,
+        VersionedReadStatePayload, VoiceServerUpdatePayload, VoiceStatePayload,
+    },
     recording::RecordedEvent,
 };
 use crate::{
-    ChannelLocation, Discord, InnerDiscord, UnitStruct,
+    Discord, InnerDiscord, UnitStruct,
     api_types::{self, Message},
     downloaders::CdnImage,
     gateways::{GatewayPayload, voice::Endpoint},
@@ -62,6 +66,15 @@ impl GatewayPayload<Opcode> {
 
                         let ready = facet_value::from_value::<ReadyPayload>(self.d)?;
 
+                        discord.gateway_attempts.store(0, Ordering::Release);
+                        // A fresh session: later drops resume this one.
+                        *discord.resume_session.lock().unwrap() =
+                            ready.session_id.map(|session_id| ResumeSession {
+                                session_id,
+                                resume_gateway_url: ready.resume_gateway_url,
+                                sequence: self.s.unwrap_or_default(),
+                            });
+
                         if let Some(user) = ready.user {
                             discord.profile.store(Some(Arc::new(api_types::Profile {
                                 id: user.id,
@@ -87,6 +100,49 @@ impl GatewayPayload<Opcode> {
                                 .store(Some(Arc::new(cached_relationships)));
                         }
 
+                        for presence in ready.presences.unwrap_or_default() {
+                            match facet_value::from_value::<PresenceUpdatePayload>(presence) {
+                                Ok(presence) => discord.cache_presence(
+                                    presence.user.id,
+                                    api_types::presence(
+                                        &presence.status,
+                                        &presence.activities.unwrap_or_default(),
+                                    ),
+                                ),
+                                Err(err) => warn!("Skipping unparseable presence: {err}"),
+                            }
+                        }
+
+                        if let Some(read_state) = ready.read_state {
+                            let entries =
+                                facet_value::from_value::<VersionedReadStatePayload>(read_state)
+                                    .map(|versioned| versioned.entries)
+                                    .unwrap_or_else(|err| {
+                                        warn!("Skipping unparseable read state: {err}");
+                                        Vec::new()
+                                    });
+                            discord.read_states.clear();
+                            for entry in entries {
+                                match facet_value::from_value::<ReadStateEntryPayload>(entry) {
+                                    // Only channel read states (type 0) mark messages.
+                                    Ok(entry) if entry.read_state_type.unwrap_or(0) == 0 => {
+                                        discord.read_states.insert(
+                                            entry.id,
+                                            ReadState {
+                                                last_read: entry
+                                                    .last_message_id
+                                                    .filter(|id| *id != 0),
+                                                unread: 0,
+                                                mentions: entry.mention_count.unwrap_or(0),
+                                            },
+                                        );
+                                    }
+                                    Ok(_) => {}
+                                    Err(err) => warn!("Skipping unparseable read state: {err}"),
+                                }
+                            }
+                        }
+
                         let mut merged_members =
                             ready.merged_members.unwrap_or_default().into_iter();
                         let mut cached_guilds = Vec::new();
@@ -103,36 +159,12 @@ impl GatewayPayload<Opcode> {
                         let mut voice_participant_futures = Vec::new();
 
                         for guild_payload in ready.guilds.unwrap_or_default() {
-                            if let (Some(guild_id), Some(channels)) =
-                                (guild_payload.id, guild_payload.channels)
-                            {
-                                discord.guild_channels.insert(guild_id, channels);
-                            }
-
-                            if let Some(properties) = guild_payload.properties {
-                                cached_guilds.push(properties);
-                            }
-
-                            let mut guild_members = guild_payload.members.unwrap_or_default();
-                            if let Some(members) = merged_members.next() {
-                                guild_members.extend(members);
-                            }
-
-                            let mut members = guild_members
-                                .into_iter()
-                                .map(|member| (member.user.id, member.user))
-                                .collect::<HashMap<_, _>>();
-
-                            for voice_state in guild_payload.voice_states.unwrap_or_default() {
-                                let member_user = members.remove(&voice_state.user_id);
-                                voice_participant_futures.push(
-                                    discord.emit_voice_state_participant(
-                                        voice_state.user_id,
-                                        voice_state,
-                                        member_user,
-                                    ),
-                                );
-                            }
+                            let (properties, voice_futures) = discord.ingest_guild(
+                                guild_payload,
+                                merged_members.next().unwrap_or_default(),
+                            );
+                            cached_guilds.extend(properties);
+                            voice_participant_futures.extend(voice_futures);
                         }
 
                         join_all(voice_participant_futures).await;
@@ -141,6 +173,29 @@ impl GatewayPayload<Opcode> {
                             discord.guilds.store(Some(Arc::new(cached_guilds)));
                         }
                     }
+                    GatewayEvent::ReadySupplemental => {
+                        let supplemental =
+                            facet_value::from_value::<ReadySupplementalPayload>(self.d)?;
+                        let friends = supplemental
+                            .merged_presences
+                            .and_then(|merged| merged.friends)
+                            .unwrap_or_default();
+                        for presence in friends {
+                            discord.cache_presence(
+                                presence.user_id,
+                                api_types::presence(
+                                    &presence.status,
+                                    &presence.activities.unwrap_or_default(),
+                                ),
+                            );
+                        }
+                    }
+                    GatewayEvent::Resumed => {
+                        // The missed dispatches were replayed ahead of this
+                        // through the normal handlers above.
+                        discord.gateway_attempts.store(0, Ordering::Release);
+                        debug!("Gateway session resumed");
+                    }
                     GatewayEvent::SessionsReplace => {
                         debug!("Session replace");
                         let session = facet_value::from_value::<Vec<SessionObjectPayload>>(self.d)?;
@@ -227,7 +282,13 @@ impl GatewayPayload<Opcode> {
                         let msg_id_hash = message.id;
 
                         trace!("{}", message.pretty());
-                        let (content, history) = message.revisions().await;
+                        let (content, history) = message
+                            .revisions(|mention| discord.mention_name(mention))
+                            .await;
+                        let mentions_me = discord.mentions_me(&message);
+                        discord.note_message(&message, mentions_me);
+                        let reply = message.reply(|mention| discord.mention_name(mention));
+                        let attachments = message.interface_attachments().await;
                         let icon = match &message.author.avatar {
                             Some(hash) => {
                                 CdnImage::avatar(message.author.id, hash).fetch().await.ok()
@@ -247,7 +308,11 @@ impl GatewayPayload<Opcode> {
                                 content,
                                 history,
                                 reactions: Vec::new(),
+                                attachments,
                                 author: Some(author),
+                                reply,
+                                draft: None,
+                                mentions_me,
                             },
                         );
 
@@ -287,10 +352,15 @@ impl GatewayPayload<Opcode> {
                             message: message.clone(),
                         });
 
-                        let (content, history) = message.revisions().await;
+                        let (content, history) = message
+                            .revisions(|mention| discord.mention_name(mention))
+                            .await;
+                        let mentions_me = discord.mentions_me(&message);
                         // Edit payloads often omit `reactions`; map what we
                         // got so reactions survive when they are included.
                         let reactions = message.interface_reactions().await;
+                        let reply = message.reply(|mention| discord.mention_name(mention));
+                        let attachments = message.interface_attachments().await;
                         let icon = match &message.author.avatar {
                             Some(hash) => {
                                 CdnImage::avatar(message.author.id, hash).fetch().await.ok()
@@ -310,7 +380,11 @@ impl GatewayPayload<Opcode> {
                                 content,
                                 history,
                                 reactions,
+                                attachments,
                                 author: Some(author),
+                                reply,
+                                draft: None,
+                                mentions_me,
                             },
                         );
 
@@ -417,42 +491,250 @@ impl GatewayPayload<Opcode> {
                             emoji: emoji_name,
                         });
                     }
-                    GatewayEvent::ChannelCreate => {
-                        let channel = facet_value::from_value::<api_types::Channel>(self.d)?;
+                    GatewayEvent::TypingStart => {
+                        let payload = facet_value::from_value::<TypingStartPayload>(self.d)?;
 
-                        let place_room = channel.to_room_data().await;
-                        let room = Discord::identifier_generator(channel.id, place_room);
-                        let guild_id = channel.guild_id;
+                        let is_self = discord
+                            .profile
+                            .load()
+                            .as_ref()
+                            .is_some_and(|p| p.id == payload.user_id);
+                        if is_self {
+                            return Ok(());
+                        }
+                        // Guild channels carry the member; DMs only the ID.
+                        let Some(user) = payload
+                            .member
+                            .map(|member| member.user)
+                            .or_else(|| discord.known_user(payload.channel_id, payload.user_id))
+                        else {
+                            trace!("TypingStart from unknown user {}", payload.user_id);
+                            return Ok(());
+                        };
+                        let icon = match &user.avatar {
+                            Some(hash) => CdnImage::avatar(user.id, hash).fetch().await.ok(),
+                            None => None,
+                        };
 
-                        // Top-level ChannelCreate carries guild_id directly,
-                        // so no parent context is needed.
-                        if let Some(location) = ChannelLocation::from_api(&channel, None) {
-                            discord.channel_id_mappings.insert(*room.id(), location);
+                        discord.text_events.force_push(TextEvent::TypingStarted {
+                            room: Identifier::new(payload.channel_id, ()),
+                            user: Identifier::new(
+                                user.id,
+                                GlobalUser {
+                                    name: user.username,
+                                    icon,
+                                },
+                            ),
+                        });
+                    }
+                    GatewayEvent::MessageAck => {
+                        let ack = facet_value::from_value::<MessageAckPayload>(self.d)?;
+                        let state = ReadState {
+                            last_read: Some(ack.message_id),
+                            unread: 0,
+                            mentions: ack.mention_count.unwrap_or(0),
+                        };
+                        discord.read_states.insert(ack.channel_id, state);
+                        discord.text_events.force_push(TextEvent::ReadStateUpdated {
+                            room: Identifier::new(ack.channel_id, ()),
+                            state,
+                        });
+                    }
+                    GatewayEvent::PresenceUpdate => {
+                        let presence = facet_value::from_value::<PresenceUpdatePayload>(self.d)?;
+                        // Contacts are relationships, whose presences come
+                        // without a guild; guild-scoped copies would only
+                        // repeat them or describe strangers.
+                        if presence.guild_id.is_none() {
+                            discord.cache_presence(
+                                presence.user.id,
+                                api_types::presence(
+                                    &presence.status,
+                                    &presence.activities.unwrap_or_default(),
+                                ),
+                            );
+                        }
+                    }
+                    GatewayEvent::ChannelCreate => {
+                        let channel = facet_value::from_value::<api_types::Channel>(self.d)?;
+                        let room =
+                            Discord::identifier_generator(channel.id, channel.to_room_data().await);
+                        let r#where = channel
+                            .guild_id
+                            .map(|guild_id| Discord::identifier_generator(guild_id, ()));
+                        // Cached first so a `house_details`/`rooms` call made
+                        // in response to the event already lists it.
+                        discord.cache_channel(channel);
+                        discord
+                            .query_events
+                            .force_push(QueryEvent::ChannelCreated { r#where, room });
+                    }
+                    GatewayEvent::ChannelUpdate => {
+                        let channel = facet_value::from_value::<api_types::Channel>(self.d)?;
+                        let room =
+                            Discord::identifier_generator(channel.id, channel.to_room_data().await);
+                        let r#where = channel
+                            .guild_id
+                            .map(|guild_id| Discord::identifier_generator(guild_id, ()));
+                        discord.cache_channel(channel);
+                        discord
+                            .query_events
+                            .force_push(QueryEvent::ChannelUpdated { r#where, room });
+                    }
+                    GatewayEvent::ChannelDelete => {
+                        let channel = facet_value::from_value::<api_types::Channel>(self.d)?;
+                        discord.forget_channel(&channel);
+                        discord.query_events.force_push(QueryEvent::ChannelDeleted {
+                            r#where: channel
+                                .guild_id
+                                .map(|guild_id| Discord::identifier_generator(guild_id, ())),
+                            room_id: channel.id,
+                        });
+                    }
+                    GatewayEvent::ThreadCreate => {
+                        let thread = facet_value::from_value::<api_types::Channel>(self.d)?;
+                        let room =
+                            Discord::identifier_generator(thread.id, thread.to_room_data().await);
+                        let r#where = thread
+                            .guild_id
+                            .map(|guild_id| Discord::identifier_generator(guild_id, ()));
+                        discord.cache_thread(thread);
+                        discord
+                            .query_events
+                            .force_push(QueryEvent::ChannelCreated { r#where, room });
+                    }
+                    GatewayEvent::ThreadUpdate => {
+                        let thread = facet_value::from_value::<api_types::Channel>(self.d)?;
+                        let r#where = thread
+                            .guild_id
+                            .map(|guild_id| Discord::identifier_generator(guild_id, ()));
+                        if thread.is_archived() {
+                            // Archived threads are no longer listed; to the
+                            // client they are closed. Reported even when not
+                            // cached: forum posts fetched over REST never are.
+                            if let Some(guild_id) = thread.guild_id {
+                                discord.forget_thread(guild_id, thread.id);
+                            }
+                            discord.query_events.force_push(QueryEvent::ChannelDeleted {
+                                r#where,
+                                room_id: thread.id,
+                            });
                         } else {
-                            warn!(
-                                "ChannelCreate for {} produced no ChannelLocation",
-                                channel.id
+                            let room = Discord::identifier_generator(
+                                thread.id,
+                                thread.to_room_data().await,
                             );
+                            // An unarchived thread shows up again.
+                            let event = if discord.cache_thread(thread) {
+                                QueryEvent::ChannelUpdated { r#where, room }
+                            } else {
+                                QueryEvent::ChannelCreated { r#where, room }
+                            };
+                            discord.query_events.force_push(event);
                         }
-
-                        // Append to the per-guild channel cache so a later
-                        // `house_details` fetch for this guild sees the new
-                        // channel — without this, the cache reflects only
-                        // the Ready snapshot and freshly-created channels
-                        // vanish until the gateway reconnects. DM/group-DM
-                        // creates (no guild_id) aren't cached here yet.
-                        if let Some(guild_id) = guild_id {
+                    }
+                    GatewayEvent::ThreadDelete => {
+                        let thread = facet_value::from_value::<ThreadDeletePayload>(self.d)?;
+                        discord.forget_thread(thread.guild_id, thread.id);
+                        discord.query_events.force_push(QueryEvent::ChannelDeleted {
+                            r#where: Some(Discord::identifier_generator(thread.guild_id, ())),
+                            room_id: thread.id,
+                        });
+                    }
+                    GatewayEvent::ThreadListSync => {
+                        // Sent when the client gains access to channels, with
+                        // every active thread in them.
+                        let sync = facet_value::from_value::<ThreadListSyncPayload>(self.d)?;
+                        let (opened, closed) =
+                            discord.sync_threads(sync.guild_id, sync.channel_ids, sync.threads);
+                        for thread in opened {
+                            let room = Discord::identifier_generator(
+                                thread.id,
+                                thread.to_room_data().await,
+                            );
+                            discord.query_events.force_push(QueryEvent::ChannelCreated {
+                                r#where: Some(Discord::identifier_generator(sync.guild_id, ())),
+                                room,
+                            });
+                        }
+                        for room_id in closed {
+                            discord.query_events.force_push(QueryEvent::ChannelDeleted {
+                                r#where: Some(Discord::identifier_generator(sync.guild_id, ())),
+                                room_id,
+                            });
+                        }
+                    }
+                    GatewayEvent::ThreadMemberUpdate | GatewayEvent::ThreadMembersUpdate => {
+                        // Thread member lists are not cached.
+                        trace!("Thread member event: {}", event_name.pretty());
+                    }
+                    GatewayEvent::GuildCreate => {
+                        // Joining a guild, or one coming back after an outage.
+                        let guild = facet_value::from_value::<ReadyGuildPayload>(self.d)?;
+                        if guild.unavailable == Some(true) {
+                            debug!("GuildCreate for a guild that is still unavailable");
+                        } else {
+                            let (properties, voice_futures) =
+                                discord.ingest_guild(guild, Vec::new());
+                            join_all(voice_futures).await;
+                            match properties {
+                                Some(properties) => {
+                                    let house = Discord::identifier_generator(
+                                        properties.id,
+                                        properties.to_house_data().await,
+                                    );
+                                    discord.cache_guild(properties);
+                                    discord
+                                        .query_events
+                                        .force_push(QueryEvent::HouseJoined { house });
+                                }
+                                None => warn!("GuildCreate without guild properties"),
+                            }
+                        }
+                    }
+                    GatewayEvent::GuildUpdate => {
+                        let guild = facet_value::from_value::<api_types::Guild>(self.d)?;
+                        let house =
+                            Discord::identifier_generator(guild.id, guild.to_house_data().await);
+                        discord.cache_guild(guild);
+                        discord
+                            .query_events
+                            .force_push(QueryEvent::HouseUpdated { house });
+                    }
+                    GatewayEvent::GuildDelete => {
+                        let guild = facet_value::from_value::<GuildDeletePayload>(self.d)?;
+                        if guild.unavailable == Some(true) {
+                            // An outage, not a leave: keep the cache, the
+                            // guild returns with a GuildCreate.
+                            debug!("Guild {} became unavailable", guild.id);
+                        } else {
+                            discord.forget_guild(guild.id);
                             discord
-                                .guild_channels
-                                .entry(guild_id)
-                                .or_default()
-                                .push(channel);
+                                .query_events
+                                .force_push(QueryEvent::HouseLeft { house_id: guild.id });
                         }
-
-                        discord.query_events.force_push(QueryEvent::ChannelCreated {
-                            r#where: guild_id
-                                .map(|guild_id| Discord::identifier_generator(guild_id, ())),
-                            room,
+                    }
+                    GatewayEvent::GuildMemberAdd | GatewayEvent::GuildMemberRemove => {
+                        // No member lists are cached (voice rosters follow
+                        // VoiceStateUpdate), and the client itself leaving
+                        // arrives as GuildDelete.
+                        trace!("Guild member event: {}", event_name.pretty());
+                    }
+                    GatewayEvent::RelationshipAdd => {
+                        let friend = facet_value::from_value::<api_types::Friend>(self.d)?;
+                        let user =
+                            Discord::identifier_generator(friend.id, friend.to_user_data().await);
+                        discord.cache_relationship(friend);
+                        discord
+                            .query_events
+                            .force_push(QueryEvent::ContactAdded { user });
+                    }
+                    GatewayEvent::RelationshipRemove => {
+                        let relationship =
+                            facet_value::from_value::<RelationshipRemovePayload>(self.d)?;
+                        discord.forget_relationship(relationship.id);
+                        discord.query_events.force_push(QueryEvent::ContactRemoved {
+                            user_id: relationship.id,
                         });
                     }
                     GatewayEvent::CallCreate
@@ -465,15 +747,20 @@ impl GatewayPayload<Opcode> {
                     _ => warn!("Unknown event_name received: {}", event_name.pretty()),
                 }
             }
-            Opcode::Reconnect | Opcode::InvalidSession => {
-                // The server wants this connection gone (and we don't
-                // implement RESUME). Tear the gateway down: the event
-                // streams end, and a later `listen()` establishes a
-                // fresh connection with a fresh `Ready` snapshot.
-                warn!(
-                    "Server requested reconnect / invalidated the session; tearing down the gateway"
-                );
-                discord.gateway.store(None);
+            Opcode::Reconnect => {
+                // The server wants this connection gone; the next poll
+                // reconnects and resumes the session.
+                debug!("Server requested a reconnect; resuming on a new connection");
+                discord.retire_gateway(true);
+            }
+            Opcode::InvalidSession => {
+                // `d` says whether the session can still be resumed. If
+                // not, the next connection identifies and gets a fresh
+                // `Ready` snapshot instead of a replay.
+                // https://docs.discord.food/topics/gateway-events#invalid-session
+                let resumable = facet_value::from_value::<bool>(self.d).unwrap_or(false);
+                warn!("Gateway session invalidated (resumable: {resumable}); reconnecting");
+                discord.retire_gateway(resumable);
             }
             Opcode::HeartbeatAck => {
                 trace!("HeartbeatAck");
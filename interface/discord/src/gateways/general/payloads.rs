@@ -28,7 +28,7 @@ pub(super) struct ReadyPayload {
     pub(super) notes: Option<facet_value::Value>,
     pub(super) presences: Option<Vec<facet_value::Value>>,
     pub(super) merged_presences: Option<facet_value::Value>,
-    pub(super) merged_members: Option<Vec<Vec<VoiceStateMemberPayload>>>,
+    pub(super) merged_members: Option<Vec<Vec<MergedMemberPayload>>>,
     pub(super) users: Option<Vec<facet_value::Value>>,
     pub(super) linked_users: Option<Vec<facet_value::Value>>,
     pub(super) application: Option<facet_value::Value>,
@@ -75,7 +75,9 @@ pub(super) struct ReadyGuildPayload {
     pub(super) member_count: Option<u64>,
     pub(super) members: Option<Vec<VoiceStateMemberPayload>>,
     pub(super) channels: Option<Vec<api_types::Channel>>,
-    pub(super) threads: Option<Vec<facet_value::Value>>,
+    /// The active threads the client can see; like `channels`, without
+    /// `guild_id`.
+    pub(super) threads: Option<Vec<api_types::Channel>>,
     pub(super) presences: Option<Vec<facet_value::Value>>,
     pub(super) voice_states: Option<Vec<VoiceStatePayload>>,
     pub(super) activity_instances: Option<Vec<facet_value::Value>>,
@@ -84,12 +86,114 @@ pub(super) struct ReadyGuildPayload {
     pub(super) data_mode: Option<String>,
     pub(super) properties: Option<api_types::Guild>,
     pub(super) stickers: Option<Vec<facet_value::Value>>,
-    pub(super) roles: Option<Vec<facet_value::Value>>,
+    pub(super) roles: Option<Vec<api_types::Role>>,
     pub(super) emojis: Option<Vec<facet_value::Value>>,
     pub(super) soundboard_sounds: Option<Vec<facet_value::Value>>,
     pub(super) premium_subscription_count: Option<u64>,
 }
 
+/// <https://docs.discord.food/topics/gateway-events#guild-delete>
+#[derive(Facet)]
+pub(super) struct GuildDeletePayload {
+    pub(super) id: SNOWFLAKE,
+    /// Set during an outage; the guild comes back with a `GuildCreate`.
+    pub(super) unavailable: Option<bool>,
+}
+
+/// <https://docs.discord.food/topics/gateway-events#thread-delete>
+#[derive(Facet)]
+pub(super) struct ThreadDeletePayload {
+    pub(super) id: SNOWFLAKE,
+    pub(super) guild_id: SNOWFLAKE,
+    // parent_id: SNOWFLAKE,
+}
+
+/// <https://docs.discord.food/topics/gateway-events#thread-list-sync>
+#[derive(Facet)]
+pub(super) struct ThreadListSyncPayload {
+    pub(super) guild_id: SNOWFLAKE,
+    /// The channels whose threads are synced; the whole guild when absent.
+    pub(super) channel_ids: Option<Vec<SNOWFLAKE>>,
+    /// Every active thread in those channels.
+    pub(super) threads: Vec<api_types::Channel>,
+}
+
+/// <https://docs.discord.food/topics/gateway-events#relationship-remove>
+#[derive(Facet)]
+pub(super) struct RelationshipRemovePayload {
+    pub(super) id: SNOWFLAKE,
+}
+
+/// <https://docs.discord.food/topics/gateway-events#presence-update>
+#[derive(Facet)]
+pub(super) struct PresenceUpdatePayload {
+    pub(super) user: PresenceUserPayload,
+    /// Set when the presence was seen through a guild rather than a
+    /// relationship.
+    pub(super) guild_id: Option<SNOWFLAKE>,
+    pub(super) status: String,
+    pub(super) activities: Option<Vec<api_types::Activity>>,
+}
+
+/// Presence updates carry a partial user; only the ID is guaranteed.
+#[derive(Facet)]
+pub(super) struct PresenceUserPayload {
+    pub(super) id: SNOWFLAKE,
+}
+
+/// <https://docs.discord.food/topics/gateway-events#ready-supplemental>
+#[derive(Facet)]
+pub(super) struct ReadySupplementalPayload {
+    pub(super) merged_presences: Option<MergedPresencesPayload>,
+}
+
+#[derive(Facet)]
+pub(super) struct MergedPresencesPayload {
+    pub(super) friends: Option<Vec<MergedPresencePayload>>,
+}
+
+/// <https://docs.discord.food/resources/presence#merged-presence-object>
+#[derive(Facet)]
+pub(super) struct MergedPresencePayload {
+    pub(super) user_id: SNOWFLAKE,
+    pub(super) status: String,
+    pub(super) activities: Option<Vec<api_types::Activity>>,
+}
+
+/// <https://docs.discord.food/topics/gateway-events#typing-start>
+#[derive(Facet)]
+pub(super) struct TypingStartPayload {
+    pub(super) channel_id: SNOWFLAKE,
+    pub(super) user_id: SNOWFLAKE,
+    /// Only sent in guild channels.
+    pub(super) member: Option<VoiceStateMemberPayload>,
+}
+
+/// `read_state` in `Ready`, in the shape `VERSIONED_READ_STATES` asks for.
+#[derive(Facet)]
+pub(super) struct VersionedReadStatePayload {
+    pub(super) entries: Vec<facet_value::Value>,
+}
+
+/// <https://docs.discord.food/resources/read-state#read-state-object>
+#[derive(Facet)]
+pub(super) struct ReadStateEntryPayload {
+    /// The channel, for channel read states.
+    pub(super) id: SNOWFLAKE,
+    /// Absent for channel read states; other types track other things.
+    pub(super) read_state_type: Option<u8>,
+    pub(super) last_message_id: Option<SNOWFLAKE>,
+    pub(super) mention_count: Option<u32>,
+}
+
+/// <https://docs.discord.food/topics/gateway-events#message-ack>
+#[derive(Facet)]
+pub(super) struct MessageAckPayload {
+    pub(super) channel_id: SNOWFLAKE,
+    pub(super) message_id: SNOWFLAKE,
+    pub(super) mention_count: Option<u32>,
+}
+
 /// <https://docs.discord.com/developers/events/gateway-events#voice-server-update>
 #[derive(Debug, Facet)]
 pub struct VoiceServerUpdatePayload {
@@ -122,6 +226,15 @@ pub(crate) struct VoiceStatePayload {
 #[derive(Facet)]
 pub(crate) struct VoiceStateMemberPayload {
     pub(crate) user: User,
+    pub(crate) roles: Option<Vec<SNOWFLAKE>>,
+}
+
+/// The client's own member object in one guild, from `Ready`'s
+/// `merged_members`. These carry `user_id` in place of `user`.
+#[derive(Facet)]
+pub(super) struct MergedMemberPayload {
+    pub(super) user: Option<User>,
+    pub(super) roles: Option<Vec<SNOWFLAKE>>,
 }
 
 /// https://docs.discord.food/resources/presence#session-object
@@ -242,6 +242,7 @@ mod tests {
 
     fn message(id: SNOWFLAKE, content: &str, reactions: Option<Vec<Reaction>>) -> Message {
         Message {
+            attachments: None,
             author: User {
                 avatar: None,
                 id: 1,
@@ -251,7 +252,12 @@ mod tests {
             content: content.to_string(),
             edited_timestamp: None,
             id,
+            mention_everyone: None,
+            mention_roles: None,
+            mentions: None,
+            message_reference: None,
             reactions,
+            referenced_message: None,
             sticker_items: None,
             timestamp: "2026-01-01T00:00:00+00:00".to_string(),
         }
@@ -7,7 +7,7 @@
 //! [`super::events`] calls them from the relevant event arms.
 
 use messenger_interface::{
-    interface::VoiceEvent,
+    interface::{MuteState, VoiceEvent},
     types::{Identifier, User as GlobalUser},
 };
 use tracing::warn;
@@ -54,6 +54,12 @@ impl<T: UnitStruct> InnerDiscord<T> {
         };
 
         let member = voice_state.member.take();
+        let mute_state = MuteState {
+            self_mute: voice_state.self_mute,
+            self_deaf: voice_state.self_deaf,
+            mute: voice_state.mute,
+            deaf: voice_state.deaf,
+        };
         // Capture the previous channel (if any) so we can evict the user from
         // its roster after the insert overwrites the state.
         let prev_channel_id = self
@@ -65,6 +71,11 @@ impl<T: UnitStruct> InnerDiscord<T> {
         {
             self.evict_voice_participant(prev, user_id);
         }
+        // Every update restates it; toggling mute or deafen sends nothing else.
+        let _ = self.voice_events.force_push(VoiceEvent::MuteStateChanged {
+            user_id,
+            state: mute_state,
+        });
 
         let Some(user) = member.map(|m| m.user).or(member_user) else {
             warn!("Voice state for user {user_id} is missing member data");
@@ -8,7 +8,11 @@ use futures::{FutureExt as _, StreamExt, future::Either, select, stream};
 use smol::future::yield_now;
 use tracing::{debug, error, trace, warn};
 
-use super::{general::Opcode, voice::VoiceOpcode};
+use super::{
+    Gateway,
+    general::{General, Opcode},
+    voice::VoiceOpcode,
+};
 use crate::{InnerDiscord, UnitStruct, gateways::GatewayStreamReciver};
 
 /// Wakes everyone parked on `pulled_notification` when dropped. The holder
@@ -24,20 +28,56 @@ impl Drop for NotifyOnDrop<'_> {
 }
 
 impl<T: UnitStruct> InnerDiscord<T> {
-    /// Pump one round of gateway events. Returns `None` when the gateway is
-    /// gone for good (never started, killed, or torn down after the
-    /// connection closed) — the calling stream should then end. Re-calling
-    /// `listen()` re-establishes a fresh gateway connection.
-    // TODO: Depricate in favore of just using poll_for_events
-    pub async fn poll_gateway_cache_event(&self) -> Option<()> {
+    /// The live gateway, reconnecting (resuming when possible) if the last
+    /// connection was retired. `None` once the messenger is killed or the
+    /// reconnect attempts ran out.
+    async fn live_gateway(&self) -> Option<Arc<Gateway<General>>> {
         if self.killed.load(Ordering::Acquire) {
             return None;
         }
-        let gateway = self.gateway.load();
-        let Some(ref_gateway) = gateway.as_ref() else {
-            debug!("Gateway not connected (never started, disconnected, or killed)");
-            return None;
-        };
+        match self.gateway.load_full() {
+            Some(gateway) => Some(gateway),
+            None => {
+                debug!("Gateway not connected; reconnecting");
+                self.reconnect_gateway().await
+            }
+        }
+    }
+
+    /// Retire a gateway connection that ended, going by the code Discord
+    /// closed it with. Session timeouts and invalid sequences (4007, 4009)
+    /// identify afresh; authentication failures, bad intents and the like
+    /// (4004, 4010–4014) can't be fixed by reconnecting and end the event
+    /// streams; anything else, including a drop without a close frame,
+    /// resumes.
+    ///
+    /// https://docs.discord.food/topics/opcodes-and-status-codes#gateway-close-event-codes
+    fn gateway_closed(&self, code: Option<u16>) {
+        match code {
+            Some(code @ (4007 | 4009)) => {
+                warn!("Gateway closed with {code}; identifying again");
+                self.retire_gateway(false);
+            }
+            Some(code @ (4004 | 4010..=4014)) => {
+                error!("Gateway closed with {code}; not reconnecting");
+                *self.gateway_refused.lock().unwrap() =
+                    Some(format!("gateway closed with code {code}"));
+                self.retire_gateway(false);
+            }
+            code => {
+                error!("Gateway connection closed ({code:?}); reconnecting");
+                self.retire_gateway(true);
+            }
+        }
+    }
+
+    /// Pump one round of gateway events. A dropped connection is retired and
+    /// re-established on the next call; returns `None` only when the gateway
+    /// is gone for good (killed, refused or unreachable) — the calling stream
+    /// should then end. Re-calling `listen()` starts over.
+    // TODO: Depricate in favore of just using poll_for_events
+    pub async fn poll_gateway_cache_event(&self) -> Option<()> {
+        let ref_gateway = self.live_gateway().await?;
 
         // If someone else is already pulling we just wait until they finish by looking at
         // the lock state. We also need to yield here, as try_lock isn't a future which means
@@ -48,14 +88,14 @@ impl<T: UnitStruct> InnerDiscord<T> {
             return Some(());
         };
         let _notify_guard = NotifyOnDrop(&self.pulled_notification);
-        let mut gateway_receiver = pin!(gateway_receiver.filter_payload::<Opcode>());
+        let mut gateway_receiver =
+            pin!(gateway_receiver.filter_payload::<Opcode>(&ref_gateway.websocket.close_code));
 
         select! {
         event = gateway_receiver.next() => {
             let Some(event) = event else {
-                error!("Gateway connection closed; tearing it down");
-                self.gateway.store(None);
-                return None;
+                self.gateway_closed(ref_gateway.close_code());
+                return Some(());
             };
             if let Err(err) = event.exec(self).await {
                 warn!("Failed to execute gateway event: {err}");
@@ -64,9 +104,9 @@ impl<T: UnitStruct> InnerDiscord<T> {
         }
         result = ref_gateway.heartbeat().fuse() => {
             if let Err(err) = result {
-                error!("Gateway heartbeat failed: {err}; tearing down the connection");
-                self.gateway.store(None);
-                return None;
+                warn!("Gateway heartbeat failed: {err}");
+                self.gateway_closed(ref_gateway.close_code());
+                return Some(());
             }
         }
         };
@@ -74,15 +114,8 @@ impl<T: UnitStruct> InnerDiscord<T> {
     }
 
     pub async fn poll_for_events(self: &Arc<Self>) -> Option<()> {
-        if self.killed.load(Ordering::Acquire) {
-            return None;
-        }
         // === Main Gateway ===
-        let gateway = self.gateway.load();
-        let Some(ref_gateway) = gateway.as_ref() else {
-            debug!("Gateway not connected (never started, disconnected, or killed)");
-            return None;
-        };
+        let ref_gateway = self.live_gateway().await?;
         // If someone else is already pulling we just wait until they finish by looking at
         // the lock state. We also need to yield here, as try_lock isn't a future which means
         // that a stream polling at the moment might relock before ever yielding to us.
@@ -95,7 +128,8 @@ impl<T: UnitStruct> InnerDiscord<T> {
         // early returns and cancellation (the consumer stream can be
         // dropped at any await point while we hold the receiver lock).
         let _notify_guard = NotifyOnDrop(&self.pulled_notification);
-        let mut gateway_receiver = pin!(gateway_receiver.filter_payload::<Opcode>());
+        let mut gateway_receiver =
+            pin!(gateway_receiver.filter_payload::<Opcode>(&ref_gateway.websocket.close_code));
 
         // === Voice Gateway ===
         let voice_gateway = ref_gateway.voice.full_load_gateway();
@@ -109,7 +143,10 @@ impl<T: UnitStruct> InnerDiscord<T> {
                 trace!("poll_for_events: waiting for voice receiver lock");
                 websocket_reciver_guard = voice_gateway.websocket.receiver.lock().await;
                 trace!("poll_for_events: voice receiver lock acquired");
-                Either::Right(websocket_reciver_guard.filter_payload::<VoiceOpcode>())
+                Either::Right(
+                    websocket_reciver_guard
+                        .filter_payload::<VoiceOpcode>(&voice_gateway.websocket.close_code),
+                )
             }
             // Eternally hang this
             None => Either::Left(stream::empty()),
@@ -120,9 +157,8 @@ impl<T: UnitStruct> InnerDiscord<T> {
         // Main gateway
         event = gateway_receiver.next() => {
             let Some(event) = event else {
-                error!("Gateway connection closed; tearing it down");
-                self.gateway.store(None);
-                return None;
+                self.gateway_closed(ref_gateway.close_code());
+                return Some(());
             };
             if let Err(err) = event.exec(self).await {
                 warn!("Failed to execute gateway event: {err}");
@@ -150,11 +186,13 @@ impl<T: UnitStruct> InnerDiscord<T> {
         // heartbeat over main gateway
         result = ref_gateway.heartbeat().fuse() => {
             if let Err(err) = result {
-                error!("Gateway heartbeat failed: {err}; tearing down the connection");
-                self.gateway.store(None);
-                return None;
+                warn!("Gateway heartbeat failed: {err}");
+                self.gateway_closed(ref_gateway.close_code());
+                return Some(());
             }
         }
+        // voice events queued by the audio loop; the caller pops them
+        _ = self.voice_events_pushed.notified().fuse() => {}
         // voice heartbeat over main gateway
         result = async {
                 match voice_gateway_clone {
@@ -9,14 +9,17 @@ use simple_audio_channels::output::SampleProducer;
 use tracing::trace;
 
 pub(super) use self::gateway::Voice;
+pub(crate) use self::recorder::RecordingThread;
 use crate::gateways::Gateway;
 use crate::{ChannelLocation, api_types::SNOWFLAKE};
 
+mod activity;
 mod audio;
 pub(super) mod connection;
 mod events;
 mod gateway;
 mod payloads;
+mod recorder;
 
 /// Voice gateway protocol version.
 pub(crate) const VOICE_GATEWAY_VERSION: usize = 9;
@@ -218,6 +221,16 @@ impl VoiceGateway {
         let mut status = self.status.lock().await;
         status.insert_session_id(session_id);
     }
+    /// The channel being joined or talked in, if any.
+    pub async fn channel(&self) -> Option<ChannelLocation> {
+        match &*self.status.lock().await {
+            VoiceGatewayStatus::Closed => None,
+            VoiceGatewayStatus::AwaitingData { channel }
+            | VoiceGatewayStatus::AwaitingEndpoint { channel, .. }
+            | VoiceGatewayStatus::AwaitingSession { channel, .. }
+            | VoiceGatewayStatus::Ready { channel, .. } => Some(*channel),
+        }
+    }
     pub fn full_load_gateway(&self) -> Option<Arc<Gateway<Voice>>> {
         self.voice_gateway.load_full()
     }
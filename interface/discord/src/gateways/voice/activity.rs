@@ -0,0 +1,308 @@
+//! Who in a call is speaking, for [`VoiceEvent::SpeakingChanged`].
+//!
+//! Discord stops sending a speaker's packets a few silence frames after they
+//! go quiet, and the Speaking payload only announces the start of a stream, so
+//! speech is derived from the packets themselves: someone speaks while voiced
+//! packets keep arriving and stops once none came for [`SPEAKING_HOLD`]. A
+//! packet counts as voiced unless its RFC 6464 audio level says otherwise.
+//! Our own frames are fed in the same way; they only exist while the
+//! microphone's gate is open.
+//!
+//! [`VoiceEvent::SpeakingChanged`]: messenger_interface::interface::VoiceEvent::SpeakingChanged
+
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    time::{Duration, Instant},
+};
+
+use simple_audio_channels::AudioSampleType;
+
+use crate::api_types::SNOWFLAKE;
+
+/// How long after their last voiced packet a speaker counts as stopped. Spans
+/// a few lost or late 20 ms frames without flickering.
+const SPEAKING_HOLD: Duration = Duration::from_millis(300);
+/// Packets quieter than this, in dBov, are silence unless flagged as voice.
+const SILENCE_DBOV: f32 = -70.0;
+/// Level updates while someone speaks are sent at most this often...
+const LEVEL_INTERVAL: Duration = Duration::from_millis(200);
+/// ...and only once the level moved by this many dB.
+const LEVEL_STEP_DB: f32 = 3.0;
+/// RFC 6464 levels bottom out at -127 dBov.
+const MIN_DBOV: f32 = -127.0;
+
+/// The RFC 6464 audio level of one packet: its loudness in dBov (0 is the
+/// loudest, -127 silence) and whether the sender's VAD flagged it as voice.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudioLevel {
+    pub voice: bool,
+    pub dbov: f32,
+}
+
+impl AudioLevel {
+    /// Decode the extension's data byte, `V:1 | level:7` with the level in
+    /// -dBov.
+    pub fn from_byte(byte: u8) -> Self {
+        Self {
+            voice: byte & 0x80 != 0,
+            dbov: -f32::from(byte & 0x7F),
+        }
+    }
+
+    /// The level of a frame we are about to send. The gate already decided it
+    /// is voice.
+    pub fn of_samples(samples: &[AudioSampleType]) -> Self {
+        let power =
+            samples.iter().map(|sample| sample * sample).sum::<f32>() / samples.len().max(1) as f32;
+        Self {
+            voice: true,
+            dbov: (10.0 * power.log10()).clamp(MIN_DBOV, 0.0),
+        }
+    }
+
+    fn is_voiced(self) -> bool {
+        self.voice || self.dbov > SILENCE_DBOV
+    }
+}
+
+/// A change worth reporting as [`VoiceEvent::SpeakingChanged`].
+///
+/// [`VoiceEvent::SpeakingChanged`]: messenger_interface::interface::VoiceEvent::SpeakingChanged
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpeakingChange {
+    pub user_id: SNOWFLAKE,
+    pub speaking: bool,
+    pub level: Option<f32>,
+}
+
+struct Speaker {
+    last_voiced: Instant,
+    reported_level: Option<f32>,
+    reported_at: Instant,
+}
+
+/// Everyone currently speaking in one call.
+#[derive(Default)]
+pub struct VoiceActivity {
+    speakers: HashMap<SNOWFLAKE, Speaker>,
+}
+
+impl VoiceActivity {
+    /// Note a packet from `user_id`, with its audio level if it carried one.
+    /// Returns the start of their speech, or a level that moved noticeably
+    /// since the last report.
+    pub fn heard(
+        &mut self,
+        user_id: SNOWFLAKE,
+        level: Option<AudioLevel>,
+        now: Instant,
+    ) -> Option<SpeakingChange> {
+        // Silence frames don't end speech by themselves; the hold runs out.
+        if level.is_some_and(|level| !level.is_voiced()) {
+            return None;
+        }
+        let level = level.map(|level| level.dbov);
+        let speaker = match self.speakers.entry(user_id) {
+            Entry::Vacant(vacant) => {
+                vacant.insert(Speaker {
+                    last_voiced: now,
+                    reported_level: level,
+                    reported_at: now,
+                });
+                return Some(SpeakingChange {
+                    user_id,
+                    speaking: true,
+                    level,
+                });
+            }
+            Entry::Occupied(occupied) => occupied.into_mut(),
+        };
+        speaker.last_voiced = now;
+
+        let moved = match (level, speaker.reported_level) {
+            (Some(level), Some(reported)) => (level - reported).abs() >= LEVEL_STEP_DB,
+            (Some(_), None) => true,
+            (None, _) => false,
+        };
+        if !moved || now.duration_since(speaker.reported_at) < LEVEL_INTERVAL {
+            return None;
+        }
+        speaker.reported_level = level;
+        speaker.reported_at = now;
+        Some(SpeakingChange {
+            user_id,
+            speaking: true,
+            level,
+        })
+    }
+
+    /// `user_id` announced that they stopped speaking.
+    pub fn stopped(&mut self, user_id: SNOWFLAKE) -> Option<SpeakingChange> {
+        self.speakers.remove(&user_id).map(|_| SpeakingChange {
+            user_id,
+            speaking: false,
+            level: None,
+        })
+    }
+
+    /// End the speech of everyone silent for longer than [`SPEAKING_HOLD`].
+    pub fn expire(&mut self, now: Instant) -> Vec<SpeakingChange> {
+        let mut changes = Vec::new();
+        self.speakers.retain(|&user_id, speaker| {
+            let speaking = now.duration_since(speaker.last_voiced) < SPEAKING_HOLD;
+            if !speaking {
+                changes.push(SpeakingChange {
+                    user_id,
+                    speaking,
+                    level: None,
+                });
+            }
+            speaking
+        });
+        changes
+    }
+
+    /// When the next [`Self::expire`] can end someone's speech, if anyone is
+    /// speaking.
+    pub fn next_expiry(&self) -> Option<Instant> {
+        self.speakers
+            .values()
+            .map(|speaker| speaker.last_voiced + SPEAKING_HOLD)
+            .min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE: SNOWFLAKE = 1;
+    const BOB: SNOWFLAKE = 2;
+
+    fn voiced(dbov: f32) -> Option<AudioLevel> {
+        Some(AudioLevel { voice: true, dbov })
+    }
+
+    #[test]
+    fn audio_level_byte_splits_flag_and_level() {
+        assert_eq!(
+            AudioLevel::from_byte(0x80 | 30),
+            AudioLevel {
+                voice: true,
+                dbov: -30.0
+            }
+        );
+        assert_eq!(
+            AudioLevel::from_byte(127),
+            AudioLevel {
+                voice: false,
+                dbov: -127.0
+            }
+        );
+    }
+
+    #[test]
+    fn sample_level_is_rms_in_dbov() {
+        let full_scale = [1.0, -1.0, 1.0, -1.0];
+        assert_eq!(AudioLevel::of_samples(&full_scale).dbov, 0.0);
+        let half = [0.5; 8];
+        assert!((AudioLevel::of_samples(&half).dbov + 6.02).abs() < 0.01);
+        assert_eq!(AudioLevel::of_samples(&[0.0; 8]).dbov, MIN_DBOV);
+    }
+
+    #[test]
+    fn speech_starts_on_the_first_voiced_packet() {
+        let mut activity = VoiceActivity::default();
+        let now = Instant::now();
+
+        assert_eq!(
+            activity.heard(ALICE, voiced(-20.0), now),
+            Some(SpeakingChange {
+                user_id: ALICE,
+                speaking: true,
+                level: Some(-20.0)
+            })
+        );
+        // Already speaking at about that level: nothing new to say.
+        assert_eq!(activity.heard(ALICE, voiced(-21.0), now), None);
+        // Without a level (no extension, or a Speaking payload) too.
+        assert_eq!(
+            activity.heard(BOB, None, now).map(|change| change.level),
+            Some(None)
+        );
+    }
+
+    #[test]
+    fn silence_frames_never_start_speech() {
+        let mut activity = VoiceActivity::default();
+        let silence = Some(AudioLevel::from_byte(127));
+        assert_eq!(activity.heard(ALICE, silence, Instant::now()), None);
+        assert_eq!(activity.next_expiry(), None);
+    }
+
+    #[test]
+    fn speech_ends_after_the_hold() {
+        let mut activity = VoiceActivity::default();
+        let start = Instant::now();
+        activity.heard(ALICE, voiced(-20.0), start);
+        activity.heard(BOB, voiced(-20.0), start + Duration::from_millis(200));
+        // Silence frames keep flowing but don't extend the hold.
+        activity.heard(
+            ALICE,
+            Some(AudioLevel::from_byte(127)),
+            start + Duration::from_millis(100),
+        );
+
+        assert_eq!(activity.next_expiry(), Some(start + SPEAKING_HOLD));
+        assert!(activity.expire(start + SPEAKING_HOLD / 2).is_empty());
+        assert_eq!(
+            activity.expire(start + SPEAKING_HOLD),
+            vec![SpeakingChange {
+                user_id: ALICE,
+                speaking: false,
+                level: None
+            }]
+        );
+        assert_eq!(
+            activity.next_expiry(),
+            Some(start + Duration::from_millis(200) + SPEAKING_HOLD)
+        );
+    }
+
+    #[test]
+    fn level_updates_are_throttled() {
+        let mut activity = VoiceActivity::default();
+        let start = Instant::now();
+        activity.heard(ALICE, voiced(-40.0), start);
+
+        // A big jump right away waits for the interval...
+        assert_eq!(
+            activity.heard(ALICE, voiced(-10.0), start + LEVEL_INTERVAL / 2),
+            None
+        );
+        // ...then goes out.
+        assert_eq!(
+            activity
+                .heard(ALICE, voiced(-10.0), start + LEVEL_INTERVAL)
+                .and_then(|change| change.level),
+            Some(-10.0)
+        );
+        // Small moves never do.
+        assert_eq!(
+            activity.heard(ALICE, voiced(-11.0), start + LEVEL_INTERVAL * 3),
+            None
+        );
+    }
+
+    #[test]
+    fn announced_stop_ends_speech_once() {
+        let mut activity = VoiceActivity::default();
+        activity.heard(ALICE, None, Instant::now());
+        assert!(
+            activity
+                .stopped(ALICE)
+                .is_some_and(|change| !change.speaking)
+        );
+        assert_eq!(activity.stopped(ALICE), None);
+    }
+}
@@ -3,7 +3,8 @@
 //! [`InnerDiscord::poll_audio`] drives one call's media for the lifetime of its
 //! [`ArcStream`]: it acquires the mic and builds the Opus codecs once, then
 //! loops `pull` → `process`, transmitting one outgoing frame per turn and
-//! dispatching incoming packets to per-speaker playback channels. The live call
+//! dispatching incoming packets to per-speaker playback channels (and both
+//! directions to the call [`RecordingThread`], while one runs). The live call
 //! is re-loaded every round ([`InnerDiscord::load_live_call`]) so a disconnect
 //! or reconnect ends the session instead of transmitting into a dead
 //! connection — no cross-task teardown signal is needed.
@@ -11,24 +12,29 @@ use std::{
     ops::ControlFlow,
     pin::pin,
     sync::{Arc, atomic::Ordering},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use async_trait::async_trait;
 use async_tungstenite::tungstenite::Message as WebsocketMessage;
 use futures::{FutureExt as _, channel::oneshot, future, select};
 use futures_timer::Delay;
-use messenger_interface::{interface::AudioEvent, stream::ArcStream};
+use messenger_interface::{
+    interface::{AudioEvent, VoiceEvent},
+    stream::ArcStream,
+};
 use simple_audio_channels::{AudioSampleType, input::SampleConsumer};
 use surf::http::convert::json;
 use tracing::{debug, error, trace, warn};
 
 use super::{
-    Voice, VoiceOpcode,
+    RecordingThread, Voice, VoiceOpcode,
+    activity::{AudioLevel, SpeakingChange},
     connection::{Connection, RecvCodec, SendCodec, UdpPacket, VOICE_FRAME_SAMPLES},
 };
 use crate::{
-    AudioDiscord, AudioManager, InnerDiscord, StreamPollGuard, UnitStruct, gateways::Gateway,
+    AudioDiscord, AudioManager, InnerDiscord, StreamPollGuard, UnitStruct, api_types::SNOWFLAKE,
+    gateways::Gateway,
 };
 
 fn speaking_payload(ssrc: u32, speaking: bool) -> WebsocketMessage {
@@ -67,7 +73,7 @@ enum Pulled {
     /// Outgoing work to hand to [`InnerDiscord::process`].
     Work(AudioWork),
     /// A new playback channel is initializing: `poll_audio` must return to the
-    /// stream so the queued [`AudioEvent::AddAudioSource`] reaches the UI — the
+    /// stream so the queued [`AudioEvent::AddParticipantSource`] reaches the UI — the
     /// only consumer that can supply the channel's producer — then re-enter.
     Yield,
     /// The call is gone, or we were killed — end the audio stream.
@@ -94,6 +100,8 @@ struct AudioSession<'mic> {
     frame: [AudioSampleType; VOICE_FRAME_SAMPLES],
     /// Partial fill carried across select cancellations.
     frame_filled: usize,
+    /// The microphone was halted by a self-mute or deafen last round.
+    mic_halted: bool,
     stop_speaking_delay: Option<Delay>,
     call_identity: Arc<Connection>,
 }
@@ -161,7 +169,7 @@ impl<T: UnitStruct> InnerDiscord<T> {
                     }
                 }
                 // Return so `ArcStream::next` can drain the queued audio events
-                // (e.g. AddAudioSource) to the UI, then re-enter `poll_audio`.
+                // (e.g. AddParticipantSource) to the UI, then re-enter `poll_audio`.
                 Pulled::Yield => return Some(()),
                 Pulled::End => return None,
             }
@@ -222,6 +230,7 @@ impl<T: UnitStruct> InnerDiscord<T> {
             microphone: manager.microphone.as_mut(),
             frame: [0.0; VOICE_FRAME_SAMPLES],
             frame_filled: 0,
+            mic_halted: false,
             stop_speaking_delay: None,
             call_identity: call.connection,
         }))
@@ -244,6 +253,16 @@ impl<T: UnitStruct> InnerDiscord<T> {
                 .connection
                 .recv_transport(&live.gateway.dave_session, &live.gateway.ssrc_to_user_id);
 
+            let mic_halted = self.mic_halted();
+            if session.mic_halted && !mic_halted {
+                // Drop what was captured while halted rather than send it late.
+                if let Some(mic) = session.microphone.as_deref_mut() {
+                    mic.clear();
+                }
+                session.frame_filled = 0;
+            }
+            session.mic_halted = mic_halted;
+
             let AudioSession {
                 recv,
                 microphone,
@@ -258,23 +277,37 @@ impl<T: UnitStruct> InnerDiscord<T> {
             let mut outgoing = pin!(
                 async move {
                     match mic {
-                        Some(mic) => fill_frame(mic, frame, frame_filled).await,
-                        None => future::pending::<()>().await,
+                        Some(mic) if !mic_halted => fill_frame(mic, frame, frame_filled).await,
+                        _ => future::pending::<()>().await,
                     }
                 }
                 .fuse()
             );
             let mut silence = pin!(stop_speaking_or_pending(stop_speaking_delay).fuse());
+            let mut speech_lapse = pin!(self.expire_speakers(&live).fuse());
+            let mut toggled = pin!(self.self_voice_toggled.notified().fuse());
             let mut heartbeat = pin!(Delay::new(HEARTBEAT).fuse());
             let mut killed = pin!(self.killed_signal().fuse());
 
             select! {
                 packet = incoming => {
                     match packet {
-                        Ok(UdpPacket::Voice { ssrc, samples }) => {
+                        Ok(UdpPacket::Voice { ssrc, samples, level }) => {
+                            self.report_speaking(live.gateway.hear(ssrc, level));
+                            // Recorded even while deafened, like it is decoded.
+                            let user_id = live.gateway.ssrc_to_user_id.get(&ssrc).map(|id| *id);
+                            if let Some(user_id) = user_id {
+                                self.record(|recorder| {
+                                    recorder.record_pcm16(user_id, samples, Instant::now())
+                                });
+                            }
+                            // Deafened: the packet is still decoded, so each
+                            // speaker's Opus state keeps up, but not played.
                             // `false` ⇒ a new channel is initializing; yield so
-                            // its queued AddAudioSource can be delivered.
-                            if !live.gateway.dispatch_incoming_audio(&self.audio_events, ssrc, samples) {
+                            // its queued AddParticipantSource can be delivered.
+                            if !self.self_deaf.load(Ordering::Relaxed)
+                                && !live.gateway.dispatch_incoming_audio(&self.audio_events, ssrc, samples)
+                            {
                                 return Pulled::Yield;
                             }
                         }
@@ -288,6 +321,8 @@ impl<T: UnitStruct> InnerDiscord<T> {
                 }
                 _ = outgoing => return Pulled::Work(AudioWork::SendMic),
                 _ = silence => return Pulled::Work(AudioWork::StopSpeaking),
+                _ = speech_lapse => continue,
+                _ = toggled => continue,
                 _ = heartbeat => continue,
                 _ = killed => return Pulled::End,
             }
@@ -303,6 +338,18 @@ impl<T: UnitStruct> InnerDiscord<T> {
         match work {
             AudioWork::SendMic => {
                 self.update_speaking(&live, true).await;
+                if let Some(user_id) = self.own_user_id() {
+                    let level = AudioLevel::of_samples(&session.frame);
+                    let change = live.gateway.activity.lock().unwrap().heard(
+                        user_id,
+                        Some(level),
+                        Instant::now(),
+                    );
+                    self.report_speaking(change);
+                    self.record(|recorder| {
+                        recorder.record(user_id, &session.frame, Instant::now())
+                    });
+                }
                 let send_t = live.connection.send_transport(&live.gateway.dave_session);
                 if let Some(codec) = session.send.as_mut()
                     && let Err(err) = send_t.send_frame(codec, &session.frame).await
@@ -314,6 +361,10 @@ impl<T: UnitStruct> InnerDiscord<T> {
             }
             AudioWork::StopSpeaking => {
                 self.update_speaking(&live, false).await;
+                if let Some(user_id) = self.own_user_id() {
+                    let change = live.gateway.activity.lock().unwrap().stopped(user_id);
+                    self.report_speaking(change);
+                }
                 session.stop_speaking_delay = None;
             }
         }
@@ -331,6 +382,55 @@ impl<T: UnitStruct> InnerDiscord<T> {
         }
     }
 
+    /// End the speech of whoever went quiet, then wait until the next speaker
+    /// may lapse (forever when nobody is speaking).
+    async fn expire_speakers(&self, live: &LiveCall) {
+        let now = Instant::now();
+        let next_expiry = {
+            let mut activity = live.gateway.activity.lock().unwrap();
+            self.report_speaking(activity.expire(now));
+            activity.next_expiry()
+        };
+        match next_expiry {
+            Some(at) => Delay::new(at.saturating_duration_since(now)).await,
+            None => future::pending::<()>().await,
+        }
+    }
+
+    /// Queue [`VoiceEvent::SpeakingChanged`] for each change and wake the
+    /// event poller to deliver them.
+    pub(super) fn report_speaking(&self, changes: impl IntoIterator<Item = SpeakingChange>) {
+        let mut reported = false;
+        for change in changes {
+            let _ = self.voice_events.force_push(VoiceEvent::SpeakingChanged {
+                user_id: change.user_id,
+                speaking: change.speaking,
+                level: change.level,
+            });
+            reported = true;
+        }
+        if reported {
+            self.voice_events_pushed.notify_one();
+        }
+    }
+
+    /// Feed the call recording, if one runs. The lock is only held to queue
+    /// a copy of the frame; the recording thread does the writing.
+    fn record(&self, feed: impl FnOnce(&RecordingThread)) {
+        if let Some(recorder) = self.recorder.lock().unwrap().as_ref() {
+            feed(recorder);
+        }
+    }
+
+    /// Self-mute and deafen both stop the microphone from being sent.
+    fn mic_halted(&self) -> bool {
+        self.self_mute.load(Ordering::Relaxed) || self.self_deaf.load(Ordering::Relaxed)
+    }
+
+    fn own_user_id(&self) -> Option<SNOWFLAKE> {
+        self.profile.load().as_ref().map(|profile| profile.id)
+    }
+
     /// Load the current voice gateway and media connection, if any.
     fn load_call(&self) -> Option<LiveCall> {
         let gateway = self.gateway.load();
@@ -27,6 +27,7 @@ use simple_audio_channels::AudioSampleType;
 use smol::net::UdpSocket;
 use tracing::{debug, trace, warn};
 
+use super::activity::AudioLevel;
 use crate::api_types::SNOWFLAKE;
 use rtp::{OPUS_PAYLOAD_TYPE, PacketClass, RtpPacket, WrapU16, WrapU32};
 
@@ -38,8 +39,12 @@ pub use rtp::{RtcpType, Ssrc};
 
 /// A classified and (for voice) decoded UDP packet.
 pub enum UdpPacket<'a> {
-    /// Decoded Opus audio frame.
-    Voice { ssrc: Ssrc, samples: &'a [i16] },
+    /// Decoded Opus audio frame, with the sender's audio level if attached.
+    Voice {
+        ssrc: Ssrc,
+        samples: &'a [i16],
+        level: Option<AudioLevel>,
+    },
     /// RTCP control packet.
     Rtcp(RtcpType),
     /// RTP packet with an unhandled payload type (e.g., video).
@@ -231,7 +236,7 @@ impl RecvTransport<'_> {
             .into());
         }
         let (rtp_extensions, voice_data) = decrypted_payload.split_at(ext_size_bytes);
-        trace_rtp_extensions(rtp_extensions);
+        let level = parse_rtp_extensions(rtp_extensions);
 
         // RTP padding per RFC 3550 §5.1: when the P bit is set, the last
         // octet of the payload counts padding bytes (including itself).
@@ -287,15 +292,16 @@ impl RecvTransport<'_> {
         Ok(UdpPacket::Voice {
             ssrc,
             samples: &codec.decoded_audio_buf[..n_decoded_samples * VOICE_CHANNELS],
+            level,
         })
     }
 }
 
-/// Parse and log the RFC 5285 one-byte RTP header extensions Discord attaches
-/// to voice packets. Purely diagnostic — none of the parsed values
-/// (timecode / audio level / channels) are used downstream — so it exists only
-/// to keep that nesting out of [`RecvTransport::recv`]'s hot path.
-fn trace_rtp_extensions(rtp_extensions: &[u8]) {
+/// Parse the RFC 5285 one-byte RTP header extensions Discord attaches to voice
+/// packets, returning the RFC 6464 audio level. The timecode and channels are
+/// only logged. Kept out of [`RecvTransport::recv`] so that nesting stays off
+/// its hot path.
+fn parse_rtp_extensions(rtp_extensions: &[u8]) -> Option<AudioLevel> {
     let mut timecode = None; // ID=3, Discord-specific (purpose unconfirmed)
     let mut audio_level = None; // ID=1, RFC 6464: V:1 | level:7
     let mut channels = None; // ID=9, Discord-specific (purpose unconfirmed)
@@ -341,6 +347,7 @@ fn trace_rtp_extensions(rtp_extensions: &[u8]) {
     trace!(
         "RTP extensions parsed: timecode={timecode:02x?} audio_level={audio_level:?} channels={channels:?}"
     );
+    audio_level.map(AudioLevel::from_byte)
 }
 
 /// RTP/AEAD send state. Lives on [`Connection`] (shared by every per-round
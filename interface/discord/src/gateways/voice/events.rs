@@ -2,6 +2,7 @@ use std::{
     io, mem,
     num::NonZeroU16,
     sync::{Arc, atomic::Ordering},
+    time::Instant,
 };
 
 use dashmap::DashMap;
@@ -147,6 +148,18 @@ impl GatewayPayload<VoiceOpcode> {
                 voice_gateway
                     .ssrc_to_user_id
                     .insert(speaking.ssrc, speaking.user_id);
+
+                // Usually only the start of a stream is announced; the audio
+                // loop notices when the packets stop.
+                let change = {
+                    let mut activity = voice_gateway.activity.lock().unwrap();
+                    if speaking.speaking == 0 {
+                        activity.stopped(speaking.user_id)
+                    } else {
+                        activity.heard(speaking.user_id, None, Instant::now())
+                    }
+                };
+                discord.report_speaking(change);
             }
             VoiceOpcode::Ready => {
                 let ready = facet_value::from_value::<ReadyPayload>(self.d)?;
@@ -3,7 +3,7 @@ use std::{
     num::NonZeroU16,
     pin::pin,
     sync::{OnceLock, atomic::AtomicBool},
-    time::{Duration, SystemTime, UNIX_EPOCH},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 use arc_swap::ArcSwapOption;
@@ -23,6 +23,7 @@ use tracing::{debug, error, trace, warn};
 
 use super::{
     AudioChannel, Endpoint, SessionId, VoiceOpcode,
+    activity::{AudioLevel, SpeakingChange, VoiceActivity},
     connection::{Connection, Ssrc},
     payloads::HelloPayload,
 };
@@ -38,6 +39,8 @@ pub struct Voice {
     pub ssrc_to_audio_channel: DashMap<Ssrc, std::sync::Mutex<super::AudioChannel>>,
     pub ssrc_to_user_id: DashMap<Ssrc, SNOWFLAKE>,
     pub is_speaking: AtomicBool,
+    /// Who in the call is speaking, ourselves included.
+    pub activity: std::sync::Mutex<VoiceActivity>,
 }
 
 pub(crate) struct DaveSessionGuard<'a>(MutexGuard<'a, Option<DaveSession>>);
@@ -71,7 +74,21 @@ impl Voice {
         Ok(DaveSessionGuard(guard))
     }
 
-    /// Dispatch a decoded audio frame to the appropriate playback channel.
+    /// Note a voice packet from `ssrc` for speaking detection. Packets from an
+    /// SSRC no Speaking payload tied to a user yet are not attributed.
+    pub(crate) fn hear(&self, ssrc: Ssrc, level: Option<AudioLevel>) -> Option<SpeakingChange> {
+        let user_id = *self.ssrc_to_user_id.get(&ssrc)?;
+        self.activity
+            .lock()
+            .unwrap()
+            .heard(user_id, level, Instant::now())
+    }
+
+    /// Dispatch a decoded audio frame to the speaker's playback channel.
+    ///
+    /// Each user gets their own channel, requested the first time their SSRC
+    /// is heard after a Speaking payload tied it to them; until then the
+    /// frames are dropped.
     ///
     /// Returns `false` if a new channel is being initialized and the caller should yield.
     pub(crate) fn dispatch_incoming_audio(
@@ -83,9 +100,14 @@ impl Voice {
         let mut channel_entry = match self.ssrc_to_audio_channel.entry(ssrc) {
             dashmap::Entry::Occupied(channel) => channel,
             dashmap::Entry::Vacant(vacant_entry) => {
+                let Some(user_id) = self.ssrc_to_user_id.get(&ssrc).map(|user_id| *user_id) else {
+                    trace!("Dropping audio from SSRC {ssrc} until it maps to a user");
+                    return true;
+                };
                 let (sender, receiver) = oneshot::channel();
                 vacant_entry.insert(AudioChannel::Initializing(receiver).into());
-                let _ = audio_events.force_push(AudioEvent::AddAudioSource(sender));
+                let _ =
+                    audio_events.force_push(AudioEvent::AddParticipantSource { user_id, sender });
                 // TODO: Save samples for when channel is ready
                 return false;
             }
@@ -170,7 +192,9 @@ impl Gateway<Voice> {
         trace!("voice gateway: identify sent, waiting for hello");
         let hello_event = {
             let mut receiver = websocket.receiver.lock().await;
-            pin!(receiver.filter_payload()).next().await
+            pin!(receiver.filter_payload(&websocket.close_code))
+                .next()
+                .await
         }
         .ok_or_else(|| {
             io::Error::new(
@@ -203,6 +227,7 @@ impl Gateway<Voice> {
                 connection: Default::default(),
                 ssrc_to_audio_channel: DashMap::new(),
                 ssrc_to_user_id: Default::default(),
+                activity: Default::default(),
                 is_speaking: false.into(),
             },
         })
@@ -0,0 +1,664 @@
+//! Call recording, for [`Voice::start_recording`].
+//!
+//! The recorder is fed the PCM the call carries: every speaker's decoded
+//! packets from the receive path, and our own frames just before they are
+//! encoded. Each source is placed on one timeline that starts with the
+//! recording, by arrival time. Discord sends nothing while someone is silent,
+//! so a source that comes back after more than [`JITTER`] resumes where the
+//! clock says, with silence in between; this keeps per-participant files
+//! lined up and everyone in sync in the mix. Arrival jitter below that is
+//! absorbed by writing packets back to back.
+//!
+//! Encoding and file writes run on a [`RecordingThread`] of their own; the
+//! audio loop only copies its frames over.
+//!
+//! [`Voice::start_recording`]: messenger_interface::interface::Voice::start_recording
+
+use std::{
+    collections::{HashMap, VecDeque, hash_map::Entry},
+    fs::{self, File},
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, SyncSender, TrySendError},
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use chrono::Local;
+use messenger_interface::interface::{RecordingFormat, RecordingLayout, RecordingSettings};
+use ogg::writing::{PacketWriteEndInfo, PacketWriter};
+use simple_audio_channels::AudioSampleType;
+use tracing::{error, warn};
+
+use super::connection::{VOICE_CHANNELS, VOICE_FREQUENCY};
+use crate::api_types::SNOWFLAKE;
+
+/// How late a source's audio may arrive and still be written straight after
+/// its previous packet.
+const JITTER: Duration = Duration::from_millis(60);
+/// How far behind the clock the mix is written, so packets arriving late
+/// still land in it.
+const MIX_DELAY: Duration = Duration::from_millis(250);
+/// Samples per channel in one Opus packet: 20 ms.
+const OPUS_FRAME: usize = 960;
+/// Opus packets per Ogg page: a page a second, so an interrupted recording
+/// loses at most that much.
+const PACKETS_PER_PAGE: u64 = 50;
+/// Each file holds a single logical stream, so any serial number does.
+const OGG_SERIAL: u32 = 1;
+/// Frames queued for the [`RecordingThread`] before further ones are
+/// dropped: about five seconds of two people talking.
+const QUEUED_FRAMES: usize = 512;
+
+fn frames(duration: Duration) -> u64 {
+    (duration.as_secs_f64() * VOICE_FREQUENCY as f64) as u64
+}
+
+/// A running recording. Dropping it without [`Recorder::finish`] leaves
+/// WAV headers unfinalized and the Ogg streams unterminated.
+pub(crate) struct Recorder {
+    settings: RecordingSettings,
+    started: Instant,
+    /// The local start time, as written into Ogg tags.
+    started_at: String,
+    /// File names start with this.
+    stem: String,
+    /// Where each source's next frame goes on the timeline.
+    cursors: HashMap<SNOWFLAKE, u64>,
+    sink: Sink,
+    scratch: Vec<AudioSampleType>,
+}
+
+enum Sink {
+    Mixed {
+        track: Track,
+        /// Interleaved frames from `base` on, still being mixed into.
+        pending: VecDeque<AudioSampleType>,
+        base: u64,
+    },
+    PerParticipant(HashMap<SNOWFLAKE, Track>),
+}
+
+impl Recorder {
+    pub(crate) fn start(settings: RecordingSettings) -> io::Result<Self> {
+        fs::create_dir_all(&settings.directory)?;
+        let now = Local::now();
+        let stem = now.format("call-%Y-%m-%d_%H-%M-%S").to_string();
+        let started_at = now.to_rfc3339();
+        let sink = match settings.layout {
+            RecordingLayout::Mixed => Sink::Mixed {
+                track: Track::create(
+                    settings
+                        .directory
+                        .join(format!("{stem}.{}", extension(settings.format))),
+                    settings.format,
+                    &started_at,
+                )?,
+                pending: VecDeque::new(),
+                base: 0,
+            },
+            RecordingLayout::PerParticipant => Sink::PerParticipant(HashMap::new()),
+        };
+        Ok(Self {
+            settings,
+            started: Instant::now(),
+            started_at,
+            stem,
+            cursors: HashMap::new(),
+            sink,
+            scratch: Vec::new(),
+        })
+    }
+
+    /// Add what `user_id` said or sent: interleaved stereo at the call's rate,
+    /// arrived `at`.
+    pub(crate) fn record(
+        &mut self,
+        user_id: SNOWFLAKE,
+        samples: &[AudioSampleType],
+        at: Instant,
+    ) -> io::Result<()> {
+        let now = frames(at.saturating_duration_since(self.started));
+        let len = (samples.len() / VOICE_CHANNELS) as u64;
+        let cursor = self.cursors.entry(user_id).or_insert(now);
+        if now > *cursor + frames(JITTER) {
+            *cursor = now;
+        }
+        let start = *cursor;
+        *cursor += len;
+
+        match &mut self.sink {
+            Sink::Mixed {
+                track,
+                pending,
+                base,
+            } => {
+                // Whatever falls before `base` came too late for the mix.
+                let skip = base.saturating_sub(start).min(len);
+                let offset = (start + skip - *base) as usize * VOICE_CHANNELS;
+                let samples = &samples[skip as usize * VOICE_CHANNELS..];
+                if pending.len() < offset + samples.len() {
+                    pending.resize(offset + samples.len(), 0.0);
+                }
+                for (mixed, sample) in pending.range_mut(offset..).zip(samples) {
+                    *mixed += sample;
+                }
+                flush_mix(track, pending, base, now.saturating_sub(frames(MIX_DELAY)))
+            }
+            Sink::PerParticipant(tracks) => {
+                let track = match tracks.entry(user_id) {
+                    Entry::Occupied(track) => track.into_mut(),
+                    Entry::Vacant(slot) => slot.insert(Track::create(
+                        self.settings.directory.join(format!(
+                            "{}-{user_id}.{}",
+                            self.stem,
+                            extension(self.settings.format)
+                        )),
+                        self.settings.format,
+                        &self.started_at,
+                    )?),
+                };
+                track.write_silence(start.saturating_sub(track.written))?;
+                track.write(samples)
+            }
+        }
+    }
+
+    /// [`Self::record`] for 16-bit samples, as Opus decodes them.
+    pub(crate) fn record_pcm16(
+        &mut self,
+        user_id: SNOWFLAKE,
+        samples: &[i16],
+        at: Instant,
+    ) -> io::Result<()> {
+        let mut scratch = std::mem::take(&mut self.scratch);
+        scratch.clear();
+        scratch.extend(
+            samples
+                .iter()
+                .map(|&sample| sample as AudioSampleType / 32_768.0),
+        );
+        let result = self.record(user_id, &scratch, at);
+        self.scratch = scratch;
+        result
+    }
+
+    /// Write out what is still buffered and close every file, returning the
+    /// files written.
+    pub(crate) fn finish(self) -> io::Result<Vec<PathBuf>> {
+        match self.sink {
+            Sink::Mixed {
+                mut track,
+                mut pending,
+                mut base,
+            } => {
+                let end = base + (pending.len() / VOICE_CHANNELS) as u64;
+                flush_mix(&mut track, &mut pending, &mut base, end)?;
+                Ok(vec![track.finish()?])
+            }
+            Sink::PerParticipant(tracks) => tracks.into_values().map(Track::finish).collect(),
+        }
+    }
+}
+
+/// A [`Recorder`] on a thread of its own, fed over a bounded channel. When
+/// the disk can't keep up, frames are dropped rather than queued without end.
+pub(crate) struct RecordingThread {
+    frames: SyncSender<Frame>,
+    dropped: AtomicU64,
+    thread: JoinHandle<io::Result<Vec<PathBuf>>>,
+}
+
+/// One frame for [`Recorder::record`] or [`Recorder::record_pcm16`].
+struct Frame {
+    user_id: SNOWFLAKE,
+    samples: Samples,
+    at: Instant,
+}
+
+enum Samples {
+    Float(Vec<AudioSampleType>),
+    Pcm16(Vec<i16>),
+}
+
+impl RecordingThread {
+    pub(crate) fn start(settings: RecordingSettings) -> io::Result<Self> {
+        let mut recorder = Recorder::start(settings)?;
+        let (frames, received) = mpsc::sync_channel::<Frame>(QUEUED_FRAMES);
+        let thread = thread::Builder::new()
+            .name("call-recorder".to_owned())
+            .spawn(move || {
+                for frame in received {
+                    let written = match &frame.samples {
+                        Samples::Float(samples) => {
+                            recorder.record(frame.user_id, samples, frame.at)
+                        }
+                        Samples::Pcm16(samples) => {
+                            recorder.record_pcm16(frame.user_id, samples, frame.at)
+                        }
+                    };
+                    // A failed write ends the recording, keeping what was
+                    // written so far; later frames go nowhere.
+                    if let Err(err) = written {
+                        error!("Call recording failed, stopping it: {err}");
+                        break;
+                    }
+                }
+                recorder.finish()
+            })?;
+        Ok(Self {
+            frames,
+            dropped: AtomicU64::new(0),
+            thread,
+        })
+    }
+
+    /// Queue a copy of `samples` for [`Recorder::record`].
+    pub(crate) fn record(&self, user_id: SNOWFLAKE, samples: &[AudioSampleType], at: Instant) {
+        self.send(user_id, Samples::Float(samples.to_vec()), at);
+    }
+
+    /// Queue a copy of `samples` for [`Recorder::record_pcm16`].
+    pub(crate) fn record_pcm16(&self, user_id: SNOWFLAKE, samples: &[i16], at: Instant) {
+        self.send(user_id, Samples::Pcm16(samples.to_vec()), at);
+    }
+
+    fn send(&self, user_id: SNOWFLAKE, samples: Samples, at: Instant) {
+        let frame = Frame {
+            user_id,
+            samples,
+            at,
+        };
+        match self.frames.try_send(frame) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+            // The thread gave up on a failed write.
+            Err(TrySendError::Disconnected(_)) => {}
+        }
+    }
+
+    /// Wait, off the executor, for the thread to write out every queued
+    /// frame and [`Recorder::finish`], returning the files written.
+    pub(crate) async fn finish(self) -> io::Result<Vec<PathBuf>> {
+        drop(self.frames);
+        let dropped = self.dropped.into_inner();
+        if dropped > 0 {
+            warn!("Call recording dropped {dropped} frames the disk couldn't keep up with");
+        }
+        let thread = self.thread;
+        smol::unblock(move || thread.join())
+            .await
+            .unwrap_or_else(|_| Err(io::Error::other("call recorder thread panicked")))
+    }
+}
+
+/// Write the mix up to frame `until`, silence where nobody spoke.
+fn flush_mix(
+    track: &mut Track,
+    pending: &mut VecDeque<AudioSampleType>,
+    base: &mut u64,
+    until: u64,
+) -> io::Result<()> {
+    if until <= *base {
+        return Ok(());
+    }
+    let ready = ((until - *base) as usize * VOICE_CHANNELS).min(pending.len());
+    for sample in pending.range_mut(..ready) {
+        *sample = sample.clamp(-1.0, 1.0);
+    }
+    let (head, tail) = pending.as_slices();
+    let split = ready.min(head.len());
+    track.write(&head[..split])?;
+    track.write(&tail[..ready - split])?;
+    pending.drain(..ready);
+    track.write_silence(until - *base - (ready / VOICE_CHANNELS) as u64)?;
+    *base = until;
+    Ok(())
+}
+
+fn extension(format: RecordingFormat) -> &'static str {
+    match format {
+        RecordingFormat::OggOpus => "ogg",
+        RecordingFormat::Wav => "wav",
+    }
+}
+
+/// One file being written.
+struct Track {
+    path: PathBuf,
+    /// Frames written so far.
+    written: u64,
+    file: TrackFile,
+}
+
+enum TrackFile {
+    Wav(hound::WavWriter<BufWriter<File>>),
+    Opus(Box<OggOpus>),
+}
+
+impl Track {
+    fn create(path: PathBuf, format: RecordingFormat, started_at: &str) -> io::Result<Self> {
+        let file = match format {
+            RecordingFormat::Wav => TrackFile::Wav(
+                hound::WavWriter::create(
+                    &path,
+                    hound::WavSpec {
+                        channels: VOICE_CHANNELS as u16,
+                        sample_rate: VOICE_FREQUENCY as u32,
+                        bits_per_sample: 16,
+                        sample_format: hound::SampleFormat::Int,
+                    },
+                )
+                .map_err(wav_error)?,
+            ),
+            RecordingFormat::OggOpus => {
+                TrackFile::Opus(Box::new(OggOpus::create(&path, started_at)?))
+            }
+        };
+        Ok(Self {
+            path,
+            written: 0,
+            file,
+        })
+    }
+
+    fn write(&mut self, samples: &[AudioSampleType]) -> io::Result<()> {
+        match &mut self.file {
+            TrackFile::Wav(writer) => {
+                for &sample in samples {
+                    writer
+                        .write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)
+                        .map_err(wav_error)?;
+                }
+            }
+            TrackFile::Opus(opus) => opus.write(samples)?,
+        }
+        self.written += (samples.len() / VOICE_CHANNELS) as u64;
+        Ok(())
+    }
+
+    fn write_silence(&mut self, len: u64) -> io::Result<()> {
+        let silence = [0.0; OPUS_FRAME * VOICE_CHANNELS];
+        let mut left = len;
+        while left > 0 {
+            let chunk = left.min(OPUS_FRAME as u64);
+            self.write(&silence[..chunk as usize * VOICE_CHANNELS])?;
+            left -= chunk;
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> io::Result<PathBuf> {
+        match self.file {
+            TrackFile::Wav(writer) => writer.finalize().map_err(wav_error)?,
+            TrackFile::Opus(opus) => opus.finish()?,
+        }
+        Ok(self.path)
+    }
+}
+
+fn wav_error(err: hound::Error) -> io::Error {
+    match err {
+        hound::Error::IoError(err) => err,
+        err => io::Error::other(err),
+    }
+}
+
+/// An Ogg/Opus stream (RFC 7845) being encoded into a file.
+struct OggOpus {
+    packets: PacketWriter<BufWriter<File>>,
+    encoder: opus::Encoder,
+    /// Samples per channel the decoder must discard at the start.
+    pre_skip: u64,
+    /// Interleaved samples short of a whole packet.
+    pending: Vec<AudioSampleType>,
+    /// Packets written, and samples per channel handed to the encoder.
+    encoded_packets: u64,
+    received: u64,
+    encoded: [u8; 4000],
+}
+
+impl OggOpus {
+    fn create(path: &Path, started_at: &str) -> io::Result<Self> {
+        let mut encoder = opus::Encoder::new(
+            VOICE_FREQUENCY as u32,
+            opus::Channels::Stereo,
+            opus::Application::Audio,
+        )
+        .map_err(opus_error)?;
+        let pre_skip = encoder.get_lookahead().map_err(opus_error)? as u64;
+        let mut packets = PacketWriter::new(BufWriter::new(File::create(path)?));
+
+        let mut head = b"OpusHead".to_vec();
+        head.push(1); // version
+        head.push(VOICE_CHANNELS as u8);
+        head.extend((pre_skip as u16).to_le_bytes());
+        head.extend((VOICE_FREQUENCY as u32).to_le_bytes());
+        head.extend(0i16.to_le_bytes()); // output gain
+        head.push(0); // channel mapping family: mono or stereo
+        packets.write_packet(head.into(), OGG_SERIAL, PacketWriteEndInfo::EndPage, 0)?;
+
+        let vendor = opus::version();
+        let date = format!("DATE={started_at}");
+        let mut tags = b"OpusTags".to_vec();
+        tags.extend((vendor.len() as u32).to_le_bytes());
+        tags.extend(vendor.as_bytes());
+        tags.extend(1u32.to_le_bytes());
+        tags.extend((date.len() as u32).to_le_bytes());
+        tags.extend(date.as_bytes());
+        packets.write_packet(tags.into(), OGG_SERIAL, PacketWriteEndInfo::EndPage, 0)?;
+
+        Ok(Self {
+            packets,
+            encoder,
+            pre_skip,
+            pending: Vec::with_capacity(OPUS_FRAME * VOICE_CHANNELS),
+            encoded_packets: 0,
+            received: 0,
+            encoded: [0; 4000],
+        })
+    }
+
+    fn write(&mut self, mut samples: &[AudioSampleType]) -> io::Result<()> {
+        self.received += (samples.len() / VOICE_CHANNELS) as u64;
+        while !samples.is_empty() {
+            let take = (OPUS_FRAME * VOICE_CHANNELS - self.pending.len()).min(samples.len());
+            self.pending.extend(&samples[..take]);
+            samples = &samples[take..];
+            if self.pending.len() == OPUS_FRAME * VOICE_CHANNELS {
+                self.encode(PacketWriteEndInfo::NormalPacket)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Encode `pending`, padded with silence to a whole packet.
+    fn encode(&mut self, end: PacketWriteEndInfo) -> io::Result<()> {
+        self.pending.resize(OPUS_FRAME * VOICE_CHANNELS, 0.0);
+        let len = self
+            .encoder
+            .encode_float(&self.pending, &mut self.encoded)
+            .map_err(opus_error)?;
+        self.pending.clear();
+        self.encoded_packets += 1;
+        let end = match end {
+            PacketWriteEndInfo::NormalPacket if self.encoded_packets.is_multiple_of(PACKETS_PER_PAGE) => {
+                PacketWriteEndInfo::EndPage
+            }
+            end => end,
+        };
+        // The last page's granule position trims the padding off the end.
+        let granule = (self.encoded_packets * OPUS_FRAME as u64).min(self.received + self.pre_skip);
+        self.packets
+            .write_packet(self.encoded[..len].into(), OGG_SERIAL, end, granule)
+    }
+
+    /// Encode the rest, with enough silence behind it to flush the encoder's
+    /// lookahead, and end the stream.
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        let end = self.received + self.pre_skip;
+        while (self.encoded_packets + 1) * (OPUS_FRAME as u64) < end {
+            self.encode(PacketWriteEndInfo::NormalPacket)?;
+        }
+        self.encode(PacketWriteEndInfo::EndStream)?;
+        io::Write::flush(self.packets.inner_mut())
+    }
+}
+
+fn opus_error(err: opus::Error) -> io::Error {
+    io::Error::other(format!("opus: {err}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE: SNOWFLAKE = 1;
+    const BOB: SNOWFLAKE = 2;
+
+    fn recorder(
+        dir: &tempfile::TempDir,
+        format: RecordingFormat,
+        layout: RecordingLayout,
+    ) -> Recorder {
+        Recorder::start(RecordingSettings {
+            directory: dir.path().to_owned(),
+            format,
+            layout,
+        })
+        .unwrap()
+    }
+
+    fn wav_samples(path: &Path) -> Vec<i16> {
+        hound::WavReader::open(path)
+            .unwrap()
+            .into_samples::<i16>()
+            .map(Result::unwrap)
+            .collect()
+    }
+
+    /// 20 ms of stereo at `value`.
+    fn frame(value: AudioSampleType) -> Vec<AudioSampleType> {
+        vec![value; OPUS_FRAME * VOICE_CHANNELS]
+    }
+
+    #[test]
+    fn participant_tracks_line_up_on_the_clock() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut recorder = recorder(&dir, RecordingFormat::Wav, RecordingLayout::PerParticipant);
+        let start = recorder.started;
+        recorder.record(ALICE, &frame(0.5), start).unwrap();
+        // Within the jitter: straight after the first frame.
+        recorder
+            .record(ALICE, &frame(0.5), start + Duration::from_millis(50))
+            .unwrap();
+        // Back after a pause: on the clock.
+        recorder
+            .record(ALICE, &frame(0.5), start + Duration::from_millis(500))
+            .unwrap();
+        recorder
+            .record_pcm16(
+                BOB,
+                &[16_384; OPUS_FRAME * VOICE_CHANNELS],
+                start + Duration::from_millis(100),
+            )
+            .unwrap();
+        let mut paths = recorder.finish().unwrap();
+        paths.sort();
+        assert_eq!(paths.len(), 2);
+        assert!(paths[0].to_str().unwrap().ends_with("-1.wav"));
+
+        let alice = wav_samples(&paths[0]);
+        let loud = |samples: &[i16]| samples.iter().all(|&s| s > 16_000);
+        assert_eq!(alice.len(), (24_000 + OPUS_FRAME) * VOICE_CHANNELS);
+        assert!(loud(&alice[..2 * OPUS_FRAME * VOICE_CHANNELS]));
+        assert!(
+            alice[2 * OPUS_FRAME * VOICE_CHANNELS..24_000 * VOICE_CHANNELS]
+                .iter()
+                .all(|&s| s == 0)
+        );
+        assert!(loud(&alice[24_000 * VOICE_CHANNELS..]));
+
+        let bob = wav_samples(&paths[1]);
+        assert_eq!(bob.len(), (4_800 + OPUS_FRAME) * VOICE_CHANNELS);
+        assert!(bob[..4_800 * VOICE_CHANNELS].iter().all(|&s| s == 0));
+        assert!(bob[4_800 * VOICE_CHANNELS..].iter().all(|&s| s > 16_000));
+    }
+
+    #[test]
+    fn the_mix_adds_up_whoever_spoke_at_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut recorder = recorder(&dir, RecordingFormat::Wav, RecordingLayout::Mixed);
+        let start = recorder.started;
+        recorder.record(ALICE, &frame(0.25), start).unwrap();
+        recorder
+            .record(BOB, &frame(0.25), start + Duration::from_millis(10))
+            .unwrap();
+        recorder
+            .record(BOB, &frame(0.25), start + Duration::from_millis(30))
+            .unwrap();
+        let paths = recorder.finish().unwrap();
+        assert_eq!(paths.len(), 1);
+
+        let mix = wav_samples(&paths[0]);
+        assert_eq!(mix.len(), 2_400 * VOICE_CHANNELS);
+        let at = |frame: usize| mix[frame * VOICE_CHANNELS] as f32 / i16::MAX as f32;
+        assert!((at(0) - 0.25).abs() < 1e-3);
+        assert!((at(480) - 0.5).abs() < 1e-3);
+        assert!((at(2_000) - 0.25).abs() < 1e-3);
+    }
+
+    #[test]
+    fn the_thread_writes_out_every_queued_frame() {
+        let dir = tempfile::tempdir().unwrap();
+        let recorder = RecordingThread::start(RecordingSettings {
+            directory: dir.path().to_owned(),
+            format: RecordingFormat::Wav,
+            layout: RecordingLayout::Mixed,
+        })
+        .unwrap();
+        let start = Instant::now();
+        recorder.record(ALICE, &frame(0.5), start);
+        recorder.record_pcm16(BOB, &[16_384; OPUS_FRAME * VOICE_CHANNELS], start);
+        let paths = smol::block_on(recorder.finish()).unwrap();
+
+        // Both arrived together, so they mix, after however long starting
+        // the recording took.
+        let mix = wav_samples(&paths[0]);
+        let (lead, frame) = mix.split_at(mix.len() - OPUS_FRAME * VOICE_CHANNELS);
+        assert!(lead.iter().all(|&s| s == 0));
+        assert!(frame.iter().all(|&s| s > 32_000));
+    }
+
+    #[test]
+    fn ogg_opus_stream_is_complete() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut recorder = recorder(&dir, RecordingFormat::OggOpus, RecordingLayout::Mixed);
+        let start = recorder.started;
+        for n in 0..10 {
+            recorder
+                .record(ALICE, &frame(0.1), start + Duration::from_millis(20 * n))
+                .unwrap();
+        }
+        let paths = recorder.finish().unwrap();
+
+        let mut reader = ogg::PacketReader::new(File::open(&paths[0]).unwrap());
+        let head = reader.read_packet_expected().unwrap();
+        assert!(head.data.starts_with(b"OpusHead"));
+        let pre_skip = u16::from_le_bytes([head.data[10], head.data[11]]) as u64;
+        let tags = reader.read_packet_expected().unwrap();
+        assert!(tags.data.starts_with(b"OpusTags"));
+        let mut last = None;
+        while let Some(packet) = reader.read_packet().unwrap() {
+            last = Some(packet);
+        }
+        let last = last.unwrap();
+        assert!(last.last_in_stream());
+        assert_eq!(last.absgp_page(), 10 * OPUS_FRAME as u64 + pre_skip);
+    }
+}
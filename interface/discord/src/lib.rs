@@ -2,10 +2,11 @@ use std::{
     marker::PhantomData,
     pin::pin,
     sync::{
-        Arc, Weak,
-        atomic::{AtomicBool, AtomicUsize, Ordering, fence},
+        Arc, Mutex, Weak,
+        atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering, fence},
     },
     task::Poll,
+    time::Duration,
 };
 
 use arc_swap::ArcSwapOption;
@@ -13,18 +14,28 @@ use asyncs_sync::Notify;
 use bitflags::bitflags;
 use crossbeam::queue::ArrayQueue;
 use dashmap::DashMap;
-use futures::{channel::oneshot, future::poll_fn, lock::Mutex as AsyncMutex};
+use futures::{
+    channel::oneshot,
+    future::{poll_fn, select},
+    lock::Mutex as AsyncMutex,
+};
+use futures_timer::Delay;
 use messenger_interface::{
-    interface::{AudioEvent, Messenger, QueryEvent, TextEvent, VoiceEvent},
+    interface::{AudioEvent, Messenger, PresenceEvent, QueryEvent, TextEvent, VoiceEvent},
     stream::{ArcStream, WeakSocketStream},
-    types::{ID, Identifier, User as GlobalUser},
+    types::{ID, Identifier, ReadState, User as GlobalUser, UserPresence},
 };
 use secure_string::SecureString;
 use simple_audio_channels::input::SampleConsumer;
+use tracing::{debug, error, warn};
 
 use crate::{
     api_types::SNOWFLAKE,
-    gateways::{Gateway, general::General, general::payloads::VoiceStatePayload},
+    gateways::{
+        Gateway,
+        general::{General, ResumeSession, payloads::VoiceStatePayload},
+        voice::RecordingThread,
+    },
     lazy_arc::LazyArc,
 };
 
@@ -32,7 +43,9 @@ mod api_types;
 mod downloaders;
 mod gateways;
 mod lazy_arc;
+mod presence;
 mod query;
+mod rate_limits;
 mod rest_api;
 mod rich;
 mod text;
@@ -41,6 +54,26 @@ mod voice;
 pub(crate) const INTERFACE_NAME: &str = "Discord";
 pub(crate) const DISCORD_API: &str = "https://discord.com/api/v10";
 
+/// Where the adapter connects. [`Endpoints::default`] is production Discord;
+/// pointing these at a local server lets the adapter run against a fake
+/// Discord (see `tests/support`) without touching the network.
+#[derive(Clone, Debug)]
+pub struct Endpoints {
+    /// REST base URL without a trailing slash, e.g. `https://discord.com/api/v10`.
+    pub api: String,
+    /// Gateway websocket URL, including the `encoding`/`v` query string.
+    pub gateway: String,
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Self {
+            api: DISCORD_API.to_owned(),
+            gateway: Gateway::<General>::GATEWAY_URL.to_owned(),
+        }
+    }
+}
+
 bitflags! {
     /// <https://discord.com/developers/docs/events/gateway#list-of-intents>
     struct Intents: u32 {
@@ -54,6 +87,7 @@ bitflags! {
         const GUILD_PRESENCES           = 1 << 8;
         const GUILD_MESSAGES            = 1 << 9;
         const GUILD_MESSAGE_REACTIONS   = 1 << 10;
+        const GUILD_MESSAGE_TYPING      = 1 << 11;
         const DIRECT_MESSAGES           = 1 << 12;
         const DIRECT_MESSAGE_REACTIONS  = 1 << 13;
         const DIRECT_MESSAGE_TYPING     = 1 << 14;
@@ -67,6 +101,9 @@ const DEFAULT_INTENTS: Intents = Intents::all();
 bitflags! {
     /// <https://docs.discord.food/topics/gateway#gateway-capabilities>
     struct Capabilities: u32 {
+        /// Sends `read_state` in Ready as `{ entries, partial, version }`
+        /// instead of a bare entry list.
+        const VERSIONED_READ_STATES = 1 << 2;
         /// Splits each guild's static metadata into a `properties` sub-object
         /// in Ready/GuildCreate events. Without this, those fields are merged
         /// flat into the guild object.
@@ -74,7 +111,8 @@ bitflags! {
     }
 }
 
-const DEFAULT_CAPABILITIES: Capabilities = Capabilities::CLIENT_STATE_V2;
+const DEFAULT_CAPABILITIES: Capabilities =
+    Capabilities::VERSIONED_READ_STATES.union(Capabilities::CLIENT_STATE_V2);
 
 /// Where a Discord channel lives. Splits guild vs. private so the opcode 4
 /// payload and join flow can be picked statically instead of inferring from
@@ -135,6 +173,16 @@ impl Discord {
         InnerDiscord::create_messenger(token)
     }
 
+    /// Like [`Discord::new_messenger`], but talking to `endpoints` instead of
+    /// discord.com.
+    pub fn with_endpoints(token: &str, endpoints: Endpoints) -> Arc<dyn Messenger> {
+        InnerDiscord::build(
+            ArcSwapOption::new(Some(Arc::new(SecureString::from(token)))),
+            None,
+            endpoints,
+        )
+    }
+
     /// Build a Discord messenger that authenticates with a username (email or
     /// phone) and password instead of a token. The token is fetched lazily on
     /// first use; if the account has two-factor enabled, supply the TOTP code
@@ -148,6 +196,7 @@ impl Discord {
                 password: password.into(),
                 mfa_code: mfa_code.filter(|code| !code.trim().is_empty()),
             }),
+            Endpoints::default(),
         )
     }
     fn identifier_generator<D>(id: SNOWFLAKE, data: D) -> Identifier<D> {
@@ -162,6 +211,21 @@ impl Discord {
 /// `force_push` to drop the oldest entry when full.
 const EVENT_QUEUE_CAP: usize = 4096;
 
+/// Consecutive gateway connections that may fail to reach `Ready`/`Resumed`
+/// before the event streams give up and end. Spaced by
+/// [`gateway_reconnect_backoff`].
+const GATEWAY_RECONNECT_ATTEMPTS: u32 = 5;
+
+/// Delay before reconnecting after `failed` fruitless connections: none after
+/// a healthy connection dropped (Discord wants resumes to be prompt), then
+/// 1s, 2s, 4s, 8s.
+fn gateway_reconnect_backoff(failed: u32) -> Duration {
+    match failed {
+        0 => Duration::ZERO,
+        n => Duration::from_secs(1 << (n - 1).min(3)),
+    }
+}
+
 trait UnitStruct {}
 
 struct Owned;
@@ -174,6 +238,8 @@ struct VoiceDiscord;
 impl UnitStruct for VoiceDiscord {}
 struct AudioDiscord;
 impl UnitStruct for AudioDiscord {}
+struct PresenceDiscord;
+impl UnitStruct for PresenceDiscord {}
 
 #[derive(Default)]
 struct AudioManager {
@@ -211,10 +277,36 @@ struct InnerDiscord<T: UnitStruct> {
     token_lock: AsyncMutex<Option<String>>,
     intents: Intents,
     capabilities: Capabilities,
+    /// REST and gateway base URLs; production Discord unless built through
+    /// [`Discord::with_endpoints`].
+    endpoints: Endpoints,
     // Microphone
     audio_manager: AsyncMutex<AudioManager>,
+    /// The client's own mute and deafen toggles. Sent with every opcode-4
+    /// voice state update and honoured by the audio loop; kept across calls.
+    self_mute: AtomicBool,
+    self_deaf: AtomicBool,
+    /// Wakes the audio loop when either toggle flips, so the microphone
+    /// resumes right away instead of on the loop's next heartbeat.
+    self_voice_toggled: Notify,
+    /// The call recording in progress, fed by the audio loop. Outlives
+    /// individual audio sessions; leaving the call finishes it.
+    recorder: Mutex<Option<RecordingThread>>,
     // === socket related ===
     gateway: LazyArc<Gateway<General>>,
+    /// Resume data of the current gateway session, set by `Ready`. Outlives
+    /// individual connections so [`InnerDiscord::retire_gateway`] followed
+    /// by a reconnect resumes (and replays missed dispatches) instead of
+    /// identifying afresh. `None` before the first `Ready` and after a
+    /// non-resumable `InvalidSession`.
+    resume_session: Mutex<Option<ResumeSession>>,
+    /// Gateway connections opened since one last reached `Ready`/`Resumed`
+    /// (which resets it). Bounds [`InnerDiscord::reconnect_gateway`].
+    gateway_attempts: AtomicU32,
+    /// Why Discord refused this session (close codes 4004 and 4010–4014).
+    /// While set no further connection is attempted; the next `listen()`
+    /// clears it and tries again.
+    gateway_refused: Mutex<Option<String>>,
     pulled_notification: Notify,
     /// Set by [`InnerDiscord::kill`] once a stream detects the app dropped
     /// its last handle (see [`InnerDiscord::owner_dropped`]). In-flight
@@ -232,7 +324,11 @@ struct InnerDiscord<T: UnitStruct> {
     query_events: ArrayQueue<QueryEvent>,
     text_events: ArrayQueue<TextEvent>,
     voice_events: ArrayQueue<VoiceEvent>,
+    /// Wakes the event poller when the audio loop queues a voice event, which
+    /// would otherwise wait for the next gateway message to be delivered.
+    voice_events_pushed: Notify,
     audio_events: ArrayQueue<AudioEvent>,
+    presence_events: ArrayQueue<PresenceEvent>,
     // === Cached data ===
     profile: ArcSwapOption<api_types::Profile>,
     voice_states: DashMap<SNOWFLAKE, VoiceStatePayload>,
@@ -242,14 +338,12 @@ struct InnerDiscord<T: UnitStruct> {
     /// fetch — see `process_guild_channels`. Mutated alongside `voice_states`
     /// in `emit_voice_state_participant`.
     voice_participants: DashMap<SNOWFLAKE, Vec<Identifier<GlobalUser>>>,
-    // Gateway-owned caches. The `Ready` dispatch handler in
-    // `gateways::general::events` is currently the *sole* writer; the REST
-    // fallback in `query.rs` reads through these for the cold-start path but
-    // never writes back. Once `Ready` lands, REST is bypassed entirely.
-    // Caveat: `*_UPDATE` / `*_DELETE` / `*_CREATE` handlers that would keep
-    // these caches fresh over the connection's lifetime are not yet wired
-    // up, so the cache reflects the `Ready` snapshot frozen in time. That's
-    // a separate correctness gap, not a race-policy concern.
+    // Gateway-owned caches. The gateway dispatch handlers are the *only*
+    // writers: `Ready` seeds them and the `CHANNEL_*` / `THREAD_*` /
+    // `GUILD_*` / `RELATIONSHIP_*` handlers keep them fresh (see
+    // `gateways::general::caches`); the REST fallback in `query.rs` reads
+    // through these for the cold-start path but never writes back. Once
+    // `Ready` lands, REST is bypassed entirely.
     // This is what keeps the gateway/REST race surface narrow for non-message
     // entities; see `crate/messenger_interface/docs/races.md` ("Cache-backed
     // queries: gateway is the only writer").
@@ -257,6 +351,20 @@ struct InnerDiscord<T: UnitStruct> {
     dm_channels: ArcSwapOption<Vec<api_types::Channel>>,
     guilds: ArcSwapOption<Vec<api_types::Guild>>,
     guild_channels: DashMap<SNOWFLAKE, Vec<api_types::Channel>>,
+    /// Active (unarchived) threads per guild, with `guild_id` filled in.
+    /// Forums list more posts than these; `Query::threads` asks REST for them.
+    guild_threads: DashMap<SNOWFLAKE, Vec<api_types::Channel>>,
+    /// Roles per guild, for the names of role mentions.
+    guild_roles: DashMap<SNOWFLAKE, Vec<api_types::Role>>,
+    /// The client's own role IDs per guild, from `Ready` and `GuildCreate`,
+    /// so role mentions flag it.
+    own_roles: DashMap<SNOWFLAKE, Vec<SNOWFLAKE>>,
+    /// Last known presence per user, for the client's relationships.
+    presences: DashMap<SNOWFLAKE, UserPresence>,
+    /// Read marker, unread and mention counts per channel, from `Ready`,
+    /// `MESSAGE_CREATE` and `MESSAGE_ACK`. `unread` only counts messages
+    /// since `Ready`; `Text::read_states` accounts for older ones.
+    read_states: DashMap<SNOWFLAKE, ReadState>,
     // External to internal ID mappings (TODO: Remove we can store discord IDs diractly in external
     // IDs)
     channel_id_mappings: DashMap<ID, ChannelLocation>,
@@ -274,10 +382,12 @@ const _: () = {
     assert!(size_of::<InnerDiscord<Owned>>() == size_of::<InnerDiscord<TextDiscord>>());
     assert!(size_of::<InnerDiscord<Owned>>() == size_of::<InnerDiscord<VoiceDiscord>>());
     assert!(size_of::<InnerDiscord<Owned>>() == size_of::<InnerDiscord<AudioDiscord>>());
+    assert!(size_of::<InnerDiscord<Owned>>() == size_of::<InnerDiscord<PresenceDiscord>>());
     assert!(align_of::<InnerDiscord<Owned>>() == align_of::<InnerDiscord<QueryDiscord>>());
     assert!(align_of::<InnerDiscord<Owned>>() == align_of::<InnerDiscord<TextDiscord>>());
     assert!(align_of::<InnerDiscord<Owned>>() == align_of::<InnerDiscord<VoiceDiscord>>());
     assert!(align_of::<InnerDiscord<Owned>>() == align_of::<InnerDiscord<AudioDiscord>>());
+    assert!(align_of::<InnerDiscord<Owned>>() == align_of::<InnerDiscord<PresenceDiscord>>());
 };
 
 /// RAII registration of one in-flight `ArcStream::next` future in
@@ -329,6 +439,7 @@ impl<T: UnitStruct> InnerDiscord<T> {
             .as_ref()
             .ok_or("Discord: no token and no credentials to log in with")?;
         let token = match rest_api::login_with_credentials(
+            &self.endpoints.api,
             &credentials.login,
             credentials.password.unsecure(),
             credentials.mfa_code.as_deref(),
@@ -349,14 +460,71 @@ impl<T: UnitStruct> InnerDiscord<T> {
         Ok(token)
     }
 
-    async fn ensure_gateway(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    async fn ensure_gateway(
+        &self,
+    ) -> Result<Arc<Gateway<General>>, Box<dyn std::error::Error + Send + Sync>> {
         if self.killed.load(Ordering::Acquire) {
             return Err("messenger was dropped".into());
         }
+        if let Some(reason) = self.gateway_refused.lock().unwrap().clone() {
+            return Err(reason.into());
+        }
         self.gateway
-            .get_or_try_init(async || Gateway::<General>::new(self).await)
-            .await?;
-        Ok(())
+            .get_or_try_init(async || {
+                self.gateway_attempts.fetch_add(1, Ordering::AcqRel);
+                Gateway::<General>::new(self).await
+            })
+            .await
+    }
+
+    /// Drop the current gateway connection after it failed or the server
+    /// asked us to leave. With `resumable` the session (and the sequence
+    /// number reached on this connection) is kept so the next connection
+    /// resumes; otherwise it is forgotten and the next one identifies.
+    fn retire_gateway(&self, resumable: bool) {
+        let retired = self.gateway.swap(None);
+        let mut session = self.resume_session.lock().unwrap();
+        if !resumable {
+            *session = None;
+        } else if let (Some(session), Some(sequence)) = (
+            session.as_mut(),
+            retired.as_ref().and_then(|gateway| gateway.sequence()),
+        ) {
+            session.sequence = sequence;
+        }
+    }
+
+    /// Re-establish a retired gateway, resuming when possible. Gives up
+    /// (returning `None`, which ends the calling stream) once
+    /// [`GATEWAY_RECONNECT_ATTEMPTS`] connections in a row failed to reach
+    /// `Ready`/`Resumed`, once Discord refused the session for good (see
+    /// [`InnerDiscord::gateway_closed`]) or once the messenger is killed; a
+    /// later `listen()` starts over.
+    async fn reconnect_gateway(&self) -> Option<Arc<Gateway<General>>> {
+        loop {
+            if let Some(reason) = self.gateway_refused.lock().unwrap().clone() {
+                error!("Gateway refused the session ({reason}); ending event streams");
+                return None;
+            }
+            let failed = self.gateway_attempts.load(Ordering::Acquire);
+            if failed >= GATEWAY_RECONNECT_ATTEMPTS {
+                error!("Gateway unreachable after {failed} attempts; ending event streams");
+                return None;
+            }
+            let backoff = pin!(Delay::new(gateway_reconnect_backoff(failed)));
+            select(backoff, pin!(self.killed_signal())).await;
+            match self.ensure_gateway().await {
+                Ok(gateway) => return Some(gateway),
+                Err(err) if self.killed.load(Ordering::Acquire) => {
+                    debug!("Gateway reconnect abandoned: {err}");
+                    return None;
+                }
+                Err(err) => warn!(
+                    "Gateway reconnect failed (attempt {}/{GATEWAY_RECONNECT_ATTEMPTS}): {err}",
+                    failed + 1
+                ),
+            }
+        }
     }
 
     /// True when every remaining strong reference is held by in-flight
@@ -429,6 +597,7 @@ impl<T: UnitStruct> InnerDiscord<T> {
         InnerDiscord<C>: ArcStream<Item = E> + Send + Sync,
         E: Send + 'static,
     {
+        self.gateway_refused.lock().unwrap().take();
         self.ensure_gateway().await?;
         let weak = Arc::downgrade(&self);
         let ptr = Weak::into_raw(weak) as *const InnerDiscord<C>;
@@ -442,6 +611,7 @@ impl InnerDiscord<Owned> {
     fn build(
         token: ArcSwapOption<SecureString>,
         credentials: Option<Credentials>,
+        endpoints: Endpoints,
     ) -> Arc<dyn Messenger> {
         Arc::new(InnerDiscord {
             token,
@@ -449,8 +619,16 @@ impl InnerDiscord<Owned> {
             token_lock: AsyncMutex::new(None),
             intents: DEFAULT_INTENTS,
             capabilities: DEFAULT_CAPABILITIES,
+            endpoints,
             audio_manager: Default::default(),
+            self_mute: AtomicBool::new(false),
+            self_deaf: AtomicBool::new(false),
+            self_voice_toggled: Default::default(),
+            recorder: Mutex::new(None),
             gateway: Default::default(),
+            resume_session: Mutex::new(None),
+            gateway_attempts: AtomicU32::new(0),
+            gateway_refused: Mutex::new(None),
             pulled_notification: Default::default(),
             killed: AtomicBool::new(false),
             kill_notify: Default::default(),
@@ -458,7 +636,9 @@ impl InnerDiscord<Owned> {
             query_events: ArrayQueue::new(EVENT_QUEUE_CAP),
             text_events: ArrayQueue::new(EVENT_QUEUE_CAP),
             voice_events: ArrayQueue::new(EVENT_QUEUE_CAP),
+            voice_events_pushed: Default::default(),
             audio_events: ArrayQueue::new(EVENT_QUEUE_CAP),
+            presence_events: ArrayQueue::new(EVENT_QUEUE_CAP),
             profile: ArcSwapOption::empty(),
             voice_states: DashMap::new(),
             voice_participants: DashMap::new(),
@@ -466,6 +646,11 @@ impl InnerDiscord<Owned> {
             dm_channels: ArcSwapOption::empty(),
             guilds: ArcSwapOption::empty(),
             guild_channels: DashMap::new(),
+            guild_threads: DashMap::new(),
+            guild_roles: DashMap::new(),
+            own_roles: DashMap::new(),
+            presences: DashMap::new(),
+            read_states: DashMap::new(),
             guild_id_mappings: DashMap::new(),
             channel_id_mappings: DashMap::new(),
             message_id_mappings: DashMap::new(),
@@ -484,6 +669,7 @@ impl Messenger for InnerDiscord<Owned> {
         Self::build(
             ArcSwapOption::new(Some(Arc::new(SecureString::from(auth_obj)))),
             None,
+            Endpoints::default(),
         )
     }
     /// NOTE: the id is meant to be used only on the client, for
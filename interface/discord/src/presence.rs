@@ -0,0 +1,94 @@
+//! High-level presence (online status) layer.
+//!
+//! Implements the `Presence` trait from `messenger_interface`. Presences only
+//! arrive over the main gateway (`Ready`, `READY_SUPPLEMENTAL` and
+//! `PRESENCE_UPDATE`, kept in the `presences` cache), and the client's own
+//! status goes out as an opcode-3 Presence Update. The companion `ArcStream`
+//! impl drains the buffered `PresenceEvent` queue.
+use std::{collections::HashMap, error::Error, sync::Arc};
+
+use async_trait::async_trait;
+use messenger_interface::{
+    interface::{Presence as PresenceTrait, PresenceEvent},
+    stream::{ArcStream, WeakSocketStream},
+    types::{ID, Status, UserPresence},
+};
+use surf::http::convert::json;
+use tracing::debug;
+
+use crate::{
+    InnerDiscord, Owned, PresenceDiscord, StreamPollGuard, api_types, gateways::general::Opcode,
+};
+
+#[async_trait]
+impl PresenceTrait for InnerDiscord<Owned> {
+    async fn presences(&self) -> Result<HashMap<ID, UserPresence>, Box<dyn Error + Sync + Send>> {
+        self.ensure_gateway().await?;
+        Ok(self
+            .presences
+            .iter()
+            .map(|entry| (*entry.key(), entry.value().clone()))
+            .collect())
+    }
+
+    /// Replaces the client's activities with none, since only the status is
+    /// modelled.
+    async fn set_status(&self, status: Status) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let gateway = self.ensure_gateway().await?;
+        let payload = json!({
+            "op": Opcode::PresenceUpdate as u8,
+            "d": {
+                "status": api_types::status_name(status),
+                "since": 0,
+                "activities": [],
+                "afk": false
+            }
+        });
+        debug!("Sending opcode 3 (PresenceUpdate): {}", payload);
+        gateway.send(payload.to_string().into()).await?;
+
+        // Discord doesn't echo the client's own presence back.
+        if let Some(profile) = self.profile.load().as_ref() {
+            let status = match status {
+                Status::Offline => Status::Invisible,
+                status => status,
+            };
+            self.cache_presence(
+                profile.id,
+                UserPresence {
+                    status,
+                    activity: None,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    async fn listen(
+        self: Arc<Self>,
+    ) -> Result<WeakSocketStream<PresenceEvent>, Box<dyn Error + Sync + Send>> {
+        self.listen_as::<PresenceDiscord, _>().await
+    }
+}
+
+#[async_trait]
+impl ArcStream for InnerDiscord<PresenceDiscord> {
+    type Item = PresenceEvent;
+    /// Await the next item. Works with shared ownership via `Arc`.
+    async fn next(self: Arc<Self>) -> Option<<Self as ArcStream>::Item> {
+        let _guard = StreamPollGuard::new(&self.active_streams);
+        loop {
+            if self.killed.load(std::sync::atomic::Ordering::Acquire) {
+                return None;
+            }
+            if self.owner_dropped() {
+                self.kill();
+                return None;
+            }
+            if let Some(event) = self.presence_events.pop() {
+                return Some(event);
+            }
+            self.poll_for_events().await?;
+        }
+    }
+}
@@ -14,21 +14,19 @@
 //!
 //! ## Cache-backed (cache-first, REST cold-start fallback)
 //!
-//! `houses`, `rooms`, `house_details`, `client_user`: read the relevant
-//! `InnerDiscord` cache field (`guilds`, `dm_channels`, `guild_channels`,
-//! `profile`) and only fall back to REST if the cache is empty. The cache
-//! is seeded by the `Ready` gateway dispatch (see `gateways::general::events`),
-//! which is currently the **sole** writer for these fields. Subsequent
-//! `*_UPDATE` / `*_DELETE` / `*_CREATE` handlers that would mutate these
-//! caches in place are not yet wired up, so the cache today reflects the
-//! `Ready` snapshot frozen in time and goes progressively stale over the
-//! connection's lifetime. Adding those handlers is a tracked correctness
-//! task — see `crate/messenger_interface/docs/races.md` ("Audit").
+//! `houses`, `rooms`, `house_details`, `contacts`, `client_user`: read the
+//! relevant `InnerDiscord` cache field (`guilds`, `dm_channels`,
+//! `guild_channels`, `relationships`, `profile`) and only fall back to REST if the cache is empty. The cache
+//! is seeded by the `Ready` gateway dispatch (see `gateways::general::events`)
+//! and kept current by the `CHANNEL_*`, `THREAD_*`, `GUILD_*` and `RELATIONSHIP_*`
+//! dispatches (see `gateways::general::caches`); the gateway is the **sole**
+//! writer for these fields.
 //!
 //! **The REST fallback returns data directly to the caller without writing
 //! it back to the cache.** This is load-bearing: once `Ready` lands, the
-//! cache is populated forever (or until the gateway reconnects, at which
-//! point a fresh `Ready` overwrites it), and REST is never consulted again
+//! cache is populated forever (a resumed connection replays the missed
+//! dispatches into it; one that has to re-identify gets a fresh `Ready` that
+//! overwrites it), and REST is never consulted again
 //! for these queries. The race window for cache-backed queries is therefore
 //! confined to the gateway-up-but-pre-`Ready` cold-start window — typically
 //! 1–3 seconds — and even within that window there is no cache-clobber,
@@ -36,13 +34,13 @@
 //! the in-flight REST future resolves.
 //!
 //! Practical consequence: handlers for `GUILD_UPDATE`, `CHANNEL_UPDATE`,
-//! etc. need to mutate the cache for correctness (otherwise it goes stale
-//! over the connection's lifetime), but they do **not** need tombstone
-//! rings or per-ID merge policies.
+//! etc. mutate the cache for correctness (otherwise it goes stale over the
+//! connection's lifetime), but they do **not** need tombstone rings or
+//! per-ID merge policies.
 //!
 //! ## Every-call HTTP (no cache, REST on every invocation)
 //!
-//! `get_messages`, `contacts`: have no cache-first path. Every call hits
+//! `get_messages`: has no cache-first path. Every call hits
 //! REST, and the result is returned to the UI without ever being stored.
 //! These are the queries where the gateway/REST reconciliation policy
 //! actually applies: a gateway event landing during the in-flight HTTP
@@ -50,6 +48,10 @@
 //! per-field merge (for updates) is required to keep the UI consistent.
 //! See `crate/messenger_interface/docs/races.md` for the full reconciliation
 //! policy and the per-fetch audit table.
+//!
+//! `threads` sits in between: the active threads come from `guild_threads`,
+//! but for a forum it also searches REST on every call for the posts the
+//! gateway never sent, again without storing them.
 use crate::{
     Discord, InnerDiscord, Owned, QueryDiscord, StreamPollGuard,
     api_types::{self, SNOWFLAKE},
@@ -63,7 +65,7 @@ use messenger_interface::{
     stream::{ArcStream, WeakSocketStream},
     types::{House, Identifier, Place, Room, RoomCapabilities, User},
 };
-use tracing::{error, warn};
+use tracing::warn;
 
 use std::{error::Error, sync::Arc, time::Duration};
 
@@ -135,18 +137,7 @@ impl InnerDiscord<Owned> {
         let contact_producer = friends
             .iter()
             .map(async move |friend| {
-                let hash = match &friend.user.avatar {
-                    Some(hash) => CdnImage::avatar(friend.id, hash).fetch().await.ok(),
-                    None => None,
-                };
-
-                Discord::identifier_generator(
-                    friend.id,
-                    User {
-                        name: friend.user.username.clone(),
-                        icon: hash,
-                    },
-                )
+                Discord::identifier_generator(friend.id, friend.to_user_data().await)
             })
             .collect::<Vec<_>>();
 
@@ -155,27 +146,7 @@ impl InnerDiscord<Owned> {
 
     async fn process_guilds(&self, guilds: &[api_types::Guild]) -> Vec<Identifier<Place<House>>> {
         let house_producer = guilds.iter().map(async move |guild| {
-            let icon = guild.icon.as_ref().map(async move |hash| {
-                match CdnImage::guild_icon(guild.id, hash).fetch().await {
-                    Ok(path) => Some(path),
-                    Err(e) => {
-                        error!("Failed to download icon for guild: {}\n{}", guild.name, e);
-                        None
-                    }
-                }
-            });
-
-            Discord::identifier_generator(
-                guild.id,
-                Place::new(
-                    guild.name.clone(),
-                    match icon {
-                        Some(icon) => icon.await,
-                        None => None,
-                    },
-                    House::new(None),
-                ),
-            )
+            Discord::identifier_generator(guild.id, guild.to_house_data().await)
         });
 
         let places = join_all(house_producer).await;
@@ -279,6 +250,30 @@ impl InnerDiscord<Owned> {
             .collect::<Vec<_>>()
     }
 
+    /// Map `threads` of the guild `guild_id` to rooms, most recently active
+    /// first.
+    async fn process_threads(
+        &self,
+        guild_id: SNOWFLAKE,
+        threads: &[api_types::Channel],
+    ) -> Vec<Identifier<Place<Room>>> {
+        let mut sorted: Vec<&api_types::Channel> = threads.iter().collect();
+        sorted.sort_by_key(|thread| std::cmp::Reverse(thread.last_message_id.unwrap_or(thread.id)));
+
+        let rooms_producer = sorted.iter().map(async move |thread| {
+            Discord::identifier_generator(thread.id, thread.to_room_data().await)
+        });
+        let places = join_all(rooms_producer).await;
+
+        for thread in sorted {
+            if let Some(location) = crate::ChannelLocation::from_api(thread, Some(guild_id)) {
+                self.channel_id_mappings.insert(thread.id, location);
+            }
+        }
+
+        places
+    }
+
     async fn process_profile(&self, profile: &api_types::Profile) -> Identifier<User> {
         let icon = match &profile.avatar {
             Some(hash) => CdnImage::avatar(profile.id, hash).fetch().await.ok(),
@@ -404,6 +399,57 @@ impl Query for InnerDiscord<Owned> {
 
         Ok(House::new(Some(rooms)))
     }
+
+    async fn threads(
+        &self,
+        room: &Identifier<Place<Room>>,
+    ) -> Result<Vec<Identifier<Place<Room>>>, Box<dyn Error + Sync + Send>> {
+        let location = *self
+            .channel_id_mappings
+            .get(room.id())
+            .ok_or("No discord channel mapping for this room")?;
+        // Private channels have no threads.
+        let Some(guild_id) = location.guild_id() else {
+            return Ok(Vec::new());
+        };
+        let channel_id = location.channel_id();
+
+        let mut threads: Vec<api_types::Channel> = self
+            .guild_threads
+            .get(&guild_id)
+            .map(|threads| {
+                threads
+                    .iter()
+                    .filter(|thread| thread.parent() == Some(channel_id))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let is_forum = self.guild_channels.get(&guild_id).is_some_and(|channels| {
+            channels.iter().any(|channel| {
+                channel.id == channel_id
+                    && matches!(
+                        channel.channel_type,
+                        api_types::ChannelTypes::GuildForum | api_types::ChannelTypes::GuildMedia
+                    )
+            })
+        });
+        if is_forum {
+            match self.rest_get_forum_posts(channel_id).await {
+                Ok(posts) => {
+                    for post in posts {
+                        if !threads.iter().any(|thread| thread.id == post.id) {
+                            threads.push(post);
+                        }
+                    }
+                }
+                Err(err) => warn!("Discord: forum post search failed for {channel_id}: {err}"),
+            }
+        }
+
+        Ok(self.process_threads(guild_id, &threads).await)
+    }
     async fn listen(
         self: Arc<Self>,
     ) -> Result<WeakSocketStream<QueryEvent>, Box<dyn Error + Sync + Send>> {
@@ -0,0 +1,519 @@
+//! Proactive handling of Discord's REST rate limits.
+//!
+//! Discord groups routes into buckets and reports each bucket's budget on every
+//! response (`X-RateLimit-Bucket`, `-Remaining`, `-Reset-After`), on top of a
+//! global limit per account. [`RateLimits::acquire`] queues a request behind
+//! the others in its bucket and holds it back until both the bucket and the
+//! global limit have room, so a burst (a page of reactions, a run of message
+//! fetches) goes out at the pace Discord allows instead of coming back as
+//! 429s. A bucket lets as many requests run at once as its budget has left.
+//!
+//! Only authenticated requests are limited: the CDN and login have neither
+//! buckets nor a global limit to draw on.
+//!
+//! See <https://docs.discord.food/topics/rate-limits>.
+use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use asyncs_sync::Notify;
+use futures_locks::Mutex as AsyncMutex;
+use futures_timer::Delay;
+use tracing::{debug, warn};
+
+/// Requests an account may make per second across all routes. Discord doesn't
+/// announce this one ahead of time, only in the 429 that enforces it.
+const GLOBAL_PER_SECOND: u32 = 50;
+
+/// Path segments whose following ID is a route's major parameter: Discord
+/// keeps a separate bucket per channel, guild or webhook.
+const MAJOR_PARAMETERS: [&str; 3] = ["channels", "guilds", "webhooks"];
+
+/// Which requests share a queue: one account's calls to one endpoint, with the
+/// IDs in the path generalised except for the major parameter.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct Route {
+    /// Hash of the `Authorization` header.
+    account: u64,
+    method: String,
+    path: String,
+    major: Option<String>,
+}
+
+impl Route {
+    pub(crate) fn new(authorization: &str, method: &str, path: &str) -> Self {
+        // Keyed by a hash so the limiter doesn't hold yet another copy of the
+        // token.
+        let mut hasher = DefaultHasher::new();
+        authorization.hash(&mut hasher);
+        let account = hasher.finish();
+        let segments = path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .skip_while(|&segment| segment == "api")
+            .skip_while(|segment| {
+                segment
+                    .strip_prefix('v')
+                    .is_some_and(|version| version.parse::<u8>().is_ok())
+            });
+
+        let mut template = String::new();
+        let mut major = None;
+        let mut previous = "";
+        for segment in segments {
+            template.push('/');
+            if previous == "reactions" {
+                // Every emoji shares the channel's reaction bucket.
+                template.push_str(":emoji");
+            } else if segment.parse::<u64>().is_err() {
+                template.push_str(segment);
+            } else if major.is_none() && MAJOR_PARAMETERS.contains(&previous) {
+                template.push_str(segment);
+                major = Some(segment.to_owned());
+            } else {
+                template.push_str(":id");
+            }
+            previous = segment;
+        }
+        Route {
+            account,
+            method: method.to_owned(),
+            path: template,
+            major,
+        }
+    }
+}
+
+/// The rate limit headers of one response.
+#[derive(Debug, Default)]
+pub(crate) struct RateLimitHeaders {
+    /// Discord's name for the bucket, shared by every route drawing on it.
+    pub bucket: Option<String>,
+    pub remaining: Option<u32>,
+    pub reset_after: Option<Duration>,
+    /// Only on a 429: how long until the request may be retried.
+    pub retry_after: Option<Duration>,
+    /// The 429 came from the account's global limit, not the bucket.
+    pub global: bool,
+}
+
+impl RateLimitHeaders {
+    pub(crate) fn of(response: &surf::Response) -> Self {
+        let header = |name: &str| {
+            response
+                .header(name)
+                .map(|values| values.last().as_str().to_owned())
+        };
+        let seconds = |name: &str| {
+            header(name)
+                .and_then(|value| value.parse::<f64>().ok())
+                .filter(|seconds| seconds.is_finite() && *seconds >= 0.0)
+                .map(Duration::from_secs_f64)
+        };
+        RateLimitHeaders {
+            bucket: header("X-RateLimit-Bucket"),
+            remaining: header("X-RateLimit-Remaining").and_then(|value| value.parse().ok()),
+            reset_after: seconds("X-RateLimit-Reset-After"),
+            retry_after: seconds("Retry-After"),
+            global: header("X-RateLimit-Global").is_some_and(|value| value == "true")
+                || header("X-RateLimit-Scope").is_some_and(|value| value == "global"),
+        }
+    }
+}
+
+/// What is known of a bucket's budget, and how much of it is out.
+#[derive(Debug, Default)]
+struct BucketState {
+    /// Requests the budget has left, less those admitted since it was
+    /// reported. `None` until a response reports it, and again once the
+    /// window resets.
+    remaining: Option<u32>,
+    reset_at: Option<Instant>,
+    /// Requests admitted and not yet [`finish`](Permit::finish)ed.
+    in_flight: u32,
+}
+
+/// Why a request can't go out yet.
+#[derive(Debug, PartialEq)]
+enum Hold {
+    /// The budget is spent until the window resets.
+    Reset(Duration),
+    /// The budget is unknown (or spent without a known reset) and a request
+    /// is out that will tell.
+    InFlight,
+}
+
+impl BucketState {
+    /// How long the next request has to wait, if the budget is spent.
+    fn wait(&self, now: Instant) -> Option<Duration> {
+        match (self.remaining, self.reset_at) {
+            (Some(0), Some(reset_at)) if reset_at > now => Some(reset_at - now),
+            _ => None,
+        }
+    }
+
+    /// Let a request out if the budget has room, counting it against the
+    /// budget. With the budget unknown only one request goes out at a time,
+    /// to learn it.
+    fn admit(&mut self, now: Instant) -> Result<(), Hold> {
+        if let Some(wait) = self.wait(now) {
+            return Err(Hold::Reset(wait));
+        }
+        if self.reset_at.is_some_and(|reset_at| reset_at <= now) {
+            // A new window, whose budget the next response reports.
+            self.remaining = None;
+            self.reset_at = None;
+        }
+        match self.remaining {
+            Some(remaining) if remaining > 0 => self.remaining = Some(remaining - 1),
+            _ if self.in_flight > 0 => return Err(Hold::InFlight),
+            _ => {}
+        }
+        self.in_flight += 1;
+        Ok(())
+    }
+
+    fn update(&mut self, headers: &RateLimitHeaders, limited: bool, now: Instant) {
+        if let Some(remaining) = headers.remaining {
+            // Requests admitted while this one was out already drew on the
+            // budget it reports.
+            self.remaining = Some(self.remaining.map_or(remaining, |own| own.min(remaining)));
+        }
+        if let Some(reset_after) = headers.reset_after {
+            self.reset_at = Some(now + reset_after);
+        }
+        if limited && !headers.global {
+            // Whatever the headers said, nothing more goes out until Discord
+            // is ready for the retry.
+            let retry_after = headers
+                .retry_after
+                .or(headers.reset_after)
+                .unwrap_or(Duration::from_secs(1));
+            self.remaining = Some(0);
+            self.reset_at = Some(now + retry_after);
+        }
+    }
+}
+
+/// One account's global budget: a fixed one-second window, plus whatever
+/// block a global 429 imposed.
+#[derive(Debug)]
+struct Global {
+    window_start: Instant,
+    sent: u32,
+    blocked_until: Option<Instant>,
+}
+
+impl Global {
+    fn new(now: Instant) -> Self {
+        Global {
+            window_start: now,
+            sent: 0,
+            blocked_until: None,
+        }
+    }
+
+    /// Count a request against the budget, or say how long until it may try
+    /// again.
+    fn reserve(&mut self, now: Instant) -> Option<Duration> {
+        if let Some(blocked_until) = self.blocked_until {
+            if blocked_until > now {
+                return Some(blocked_until - now);
+            }
+            self.blocked_until = None;
+        }
+        if now.duration_since(self.window_start) >= Duration::from_secs(1) {
+            self.window_start = now;
+            self.sent = 0;
+        }
+        if self.sent >= GLOBAL_PER_SECOND {
+            return Some(self.window_start + Duration::from_secs(1) - now);
+        }
+        self.sent += 1;
+        None
+    }
+}
+
+/// One bucket: a semaphore sized from the headers, which lets up to
+/// `remaining` requests run at once.
+#[derive(Default)]
+struct Bucket {
+    /// Admits requests one at a time, in the order they asked.
+    queue: AsyncMutex<()>,
+    state: Mutex<BucketState>,
+    /// Wakes the request at the head of `queue` when one in flight finishes.
+    released: Notify,
+}
+
+/// A bucket Discord named: the account, the bucket's name and the route's
+/// major parameter.
+type BucketKey = (u64, String, Option<String>);
+
+/// The rate limit bookkeeping for every account this process talks to
+/// Discord as.
+#[derive(Default)]
+pub(crate) struct RateLimits {
+    /// Each route's queue. A route starts on a bucket of its own and moves
+    /// onto the shared one once Discord names it.
+    routes: Mutex<HashMap<Route, Arc<Bucket>>>,
+    /// Discord's buckets, shared by the routes drawing on them.
+    buckets: Mutex<HashMap<BucketKey, Arc<Bucket>>>,
+    global: Mutex<HashMap<u64, Global>>,
+}
+
+impl RateLimits {
+    /// Wait for `route`'s turn. Requests in a bucket are admitted in the
+    /// order they asked, as many at once as the budget the last response
+    /// reported has left (one while it is unknown). The permit counts as
+    /// in flight until it is [`finish`](Permit::finish)ed (or dropped).
+    pub(crate) async fn acquire(&self, route: Route) -> Permit<'_> {
+        let bucket = self
+            .routes
+            .lock()
+            .unwrap()
+            .entry(route.clone())
+            .or_default()
+            .clone();
+        {
+            let _turn = bucket.queue.lock().await;
+            loop {
+                let admitted = bucket.state.lock().unwrap().admit(Instant::now());
+                match admitted {
+                    Ok(()) => break,
+                    Err(Hold::Reset(wait)) => {
+                        debug!("Rate limit bucket for {route:?} is spent; waiting {wait:?}");
+                        Delay::new(wait).await;
+                    }
+                    Err(Hold::InFlight) => bucket.released.notified().await,
+                }
+            }
+        }
+        loop {
+            let now = Instant::now();
+            let wait = self
+                .global
+                .lock()
+                .unwrap()
+                .entry(route.account)
+                .or_insert_with(|| Global::new(now))
+                .reserve(now);
+            match wait {
+                Some(wait) => Delay::new(wait).await,
+                None => break,
+            }
+        }
+        Permit {
+            limits: self,
+            route,
+            bucket,
+        }
+    }
+}
+
+/// A request's turn in its bucket; see [`RateLimits::acquire`].
+pub(crate) struct Permit<'a> {
+    limits: &'a RateLimits,
+    route: Route,
+    bucket: Arc<Bucket>,
+}
+
+impl Permit<'_> {
+    /// Record the budget the response reported, releasing the request's
+    /// place in the bucket. `limited` is whether it was a 429.
+    pub(crate) fn finish(self, headers: &RateLimitHeaders, limited: bool) {
+        let now = Instant::now();
+        // The budget reported is the named bucket's, so it goes to the
+        // bucket every route drawing on it shares.
+        let bucket = match &headers.bucket {
+            Some(name) => self.share_bucket(name),
+            None => self.bucket.clone(),
+        };
+        bucket.state.lock().unwrap().update(headers, limited, now);
+
+        if limited && headers.global {
+            let retry_after = headers.retry_after.unwrap_or(Duration::from_secs(1));
+            warn!("Hit Discord's global rate limit; pausing all requests for {retry_after:?}");
+            self.limits
+                .global
+                .lock()
+                .unwrap()
+                .entry(self.route.account)
+                .or_insert_with(|| Global::new(now))
+                .blocked_until = Some(now + retry_after);
+        }
+    }
+
+    /// Point the route at Discord's bucket `name`, which the route's own
+    /// bucket becomes if no other route named it first, and return it.
+    fn share_bucket(&self, name: &str) -> Arc<Bucket> {
+        let key: BucketKey = (
+            self.route.account,
+            name.to_owned(),
+            self.route.major.clone(),
+        );
+        let mut routes = self.limits.routes.lock().unwrap();
+        let own = routes
+            .get(&self.route)
+            .cloned()
+            .unwrap_or_else(|| self.bucket.clone());
+        let shared = self
+            .limits
+            .buckets
+            .lock()
+            .unwrap()
+            .entry(key)
+            .or_insert(own.clone())
+            .clone();
+        if !Arc::ptr_eq(&own, &shared) {
+            routes.insert(self.route.clone(), shared.clone());
+        }
+        shared
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        let mut state = self.bucket.state.lock().unwrap();
+        state.in_flight = state.in_flight.saturating_sub(1);
+        drop(state);
+        self.bucket.released.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(path: &str) -> Route {
+        Route::new("token", "GET", path)
+    }
+
+    #[test]
+    fn ids_are_generalised_except_the_major_parameter() {
+        let reaction = Route::new(
+            "token",
+            "PUT",
+            "/api/v9/channels/100/messages/1003/reactions/%F0%9F%91%8D/@me",
+        );
+        assert_eq!(
+            reaction.path,
+            "/channels/100/messages/:id/reactions/:emoji/@me"
+        );
+        assert_eq!(reaction.major.as_deref(), Some("100"));
+        assert_eq!(
+            route("/channels/100/messages/1001"),
+            route("/channels/100/messages/1002")
+        );
+        assert_ne!(
+            route("/channels/100/messages"),
+            route("/channels/101/messages")
+        );
+        assert_eq!(route("/users/@me/relationships").major, None);
+    }
+
+    #[test]
+    fn accounts_queue_apart() {
+        assert_ne!(
+            Route::new("one", "GET", "/users/@me"),
+            Route::new("two", "GET", "/users/@me")
+        );
+    }
+
+    #[test]
+    fn spent_buckets_wait_for_the_reset() {
+        let now = Instant::now();
+        let mut bucket = BucketState::default();
+        assert_eq!(bucket.wait(now), None);
+
+        let headers = RateLimitHeaders {
+            remaining: Some(0),
+            reset_after: Some(Duration::from_millis(300)),
+            ..Default::default()
+        };
+        bucket.update(&headers, false, now);
+        assert_eq!(bucket.wait(now), Some(Duration::from_millis(300)));
+        assert_eq!(bucket.wait(now + Duration::from_millis(300)), None);
+
+        // A 429 blocks the bucket for the retry even without budget headers.
+        let mut bucket = BucketState::default();
+        let headers = RateLimitHeaders {
+            retry_after: Some(Duration::from_secs(2)),
+            ..Default::default()
+        };
+        bucket.update(&headers, true, now);
+        assert_eq!(bucket.wait(now), Some(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn buckets_admit_as_many_as_remain() {
+        let now = Instant::now();
+        let mut bucket = BucketState::default();
+        // Unknown budget: one request goes out to learn it.
+        assert_eq!(bucket.admit(now), Ok(()));
+        assert_eq!(bucket.admit(now), Err(Hold::InFlight));
+
+        let headers = RateLimitHeaders {
+            remaining: Some(2),
+            reset_after: Some(Duration::from_secs(1)),
+            ..Default::default()
+        };
+        bucket.update(&headers, false, now);
+        bucket.in_flight -= 1;
+        assert_eq!(bucket.admit(now), Ok(()));
+        assert_eq!(bucket.admit(now), Ok(()));
+        assert_eq!(bucket.in_flight, 2);
+        assert_eq!(bucket.admit(now), Err(Hold::Reset(Duration::from_secs(1))));
+
+        // A response sent before the others were admitted doesn't hand
+        // their share of the budget back.
+        bucket.update(&headers, false, now);
+        assert_eq!(bucket.remaining, Some(0));
+
+        // Past the reset the budget is unknown again.
+        let later = now + Duration::from_secs(1);
+        bucket.in_flight = 0;
+        assert_eq!(bucket.admit(later), Ok(()));
+        assert_eq!(bucket.admit(later), Err(Hold::InFlight));
+    }
+
+    #[test]
+    fn named_buckets_take_the_budget_of_every_route_sharing_them() {
+        let limits = RateLimits::default();
+        let shared = |remaining| RateLimitHeaders {
+            bucket: Some("shared".to_owned()),
+            remaining: Some(remaining),
+            reset_after: Some(Duration::from_secs(10)),
+            ..Default::default()
+        };
+        smol::block_on(async {
+            let messages = limits.acquire(route("/channels/100/messages")).await;
+            messages.finish(&shared(5), false);
+            // A second route learns it draws on the same bucket, and spends it.
+            let pins = limits.acquire(route("/channels/100/pins")).await;
+            pins.finish(&shared(0), false);
+        });
+
+        let routes = limits.routes.lock().unwrap();
+        let bucket = &routes[&route("/channels/100/messages")];
+        assert!(Arc::ptr_eq(bucket, &routes[&route("/channels/100/pins")]));
+        assert!(bucket.state.lock().unwrap().wait(Instant::now()).is_some());
+    }
+
+    #[test]
+    fn global_budget_refills_every_second() {
+        let now = Instant::now();
+        let mut global = Global::new(now);
+        for _ in 0..GLOBAL_PER_SECOND {
+            assert_eq!(global.reserve(now), None);
+        }
+        let later = now + Duration::from_millis(400);
+        assert_eq!(global.reserve(later), Some(Duration::from_millis(600)));
+        assert_eq!(global.reserve(now + Duration::from_secs(1)), None);
+
+        global.blocked_until = Some(later + Duration::from_secs(5));
+        assert_eq!(global.reserve(later), Some(Duration::from_secs(5)));
+    }
+}
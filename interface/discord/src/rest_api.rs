@@ -5,13 +5,16 @@
 //! body). Anything stateful — cache lookups, mapping inserts, conversion to
 //! `messenger_interface` types — belongs in `query.rs`.
 
-use std::error::Error;
+use std::{
+    error::Error,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use percent_encoding::{NON_ALPHANUMERIC, utf8_percent_encode};
 use tracing::info;
 
 use crate::{
-    DISCORD_API, InnerDiscord, Owned,
+    InnerDiscord, Owned,
     api_types::{self, SNOWFLAKE},
     downloaders::{Body as _, Fetch, Fresh},
 };
@@ -66,10 +69,12 @@ fn login_headers() -> Vec<(&'static str, String)> {
 /// Discord's `POST /auth/login` either returns a token directly or, when
 /// two-factor is enabled, an MFA `ticket` that must be redeemed with a TOTP
 /// code via `POST /auth/mfa/totp`. CAPTCHA-gated logins cannot be solved here
-/// and surface as an error. No token is required for these endpoints.
+/// and surface as an error. No token is required for these endpoints. `api` is
+/// the REST base URL the messenger was built with.
 ///
 /// Flow and field shapes per <https://docs.discord.food/authentication>.
 pub(crate) async fn login_with_credentials(
+    api: &str,
     login: &str,
     password: &str,
     mfa_code: Option<&str>,
@@ -87,7 +92,7 @@ pub(crate) async fn login_with_credentials(
     let response = async {
         Fetch::<Fresh>::fetch(
             || {
-                surf::post(format!("{DISCORD_API}/auth/login"))
+                surf::post(format!("{api}/auth/login"))
                     .body(body.clone())
                     .content_type("application/json")
             },
@@ -126,7 +131,7 @@ pub(crate) async fn login_with_credentials(
     })?;
     let response = Fetch::<Fresh>::fetch(
         || {
-            surf::post(format!("{DISCORD_API}/auth/mfa/totp"))
+            surf::post(format!("{api}/auth/mfa/totp"))
                 .body(body.clone())
                 .content_type("application/json")
         },
@@ -172,6 +177,42 @@ fn explain_login_error(
     err
 }
 
+/// A boundary for [`multipart_body`], unique per call so that it's vanishingly
+/// unlikely to occur inside an uploaded file.
+fn multipart_boundary() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos())
+        .unwrap_or_default();
+    format!("generic-messenger-{nanos:x}")
+}
+
+/// Encode a create-message `multipart/form-data` body: the JSON params as the
+/// `payload_json` part, then each file as a `files[n]` part.
+fn multipart_body(boundary: &str, payload_json: &[u8], files: &[(String, Vec<u8>)]) -> Vec<u8> {
+    let mut body = Vec::new();
+    body.extend_from_slice(
+        format!(
+            "--{boundary}\r\nContent-Disposition: form-data; name=\"payload_json\"\r\nContent-Type: application/json\r\n\r\n"
+        )
+        .as_bytes(),
+    );
+    body.extend_from_slice(payload_json);
+    for (n, (filename, contents)) in files.iter().enumerate() {
+        // The filename sits in a quoted header value.
+        let filename = filename.replace(['"', '\r', '\n'], "_");
+        body.extend_from_slice(
+            format!(
+                "\r\n--{boundary}\r\nContent-Disposition: form-data; name=\"files[{n}]\"; filename=\"{filename}\"\r\nContent-Type: application/octet-stream\r\n\r\n"
+            )
+            .as_bytes(),
+        );
+        body.extend_from_slice(contents);
+    }
+    body.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+    body
+}
+
 impl InnerDiscord<Owned> {
     pub(crate) async fn get_auth_header(
         &self,
@@ -183,8 +224,9 @@ impl InnerDiscord<Owned> {
     pub(crate) async fn rest_get_profile(
         &self,
     ) -> Result<api_types::Profile, Box<dyn Error + Sync + Send>> {
+        let api = &self.endpoints.api;
         Fetch::<Fresh>::fetch(
-            || surf::get(format!("{DISCORD_API}/users/@me")),
+            || surf::get(format!("{api}/users/@me")),
             self.get_auth_header().await?,
         )
         .await?
@@ -195,8 +237,9 @@ impl InnerDiscord<Owned> {
     pub(crate) async fn rest_get_contacts(
         &self,
     ) -> Result<Vec<api_types::Friend>, Box<dyn Error + Sync + Send>> {
+        let api = &self.endpoints.api;
         Fetch::<Fresh>::fetch(
-            || surf::get(format!("{DISCORD_API}/users/@me/relationships")),
+            || surf::get(format!("{api}/users/@me/relationships")),
             self.get_auth_header().await?,
         )
         .await?
@@ -207,8 +250,9 @@ impl InnerDiscord<Owned> {
     pub(crate) async fn rest_get_dms(
         &self,
     ) -> Result<Vec<api_types::Channel>, Box<dyn Error + Sync + Send>> {
+        let api = &self.endpoints.api;
         Fetch::<Fresh>::fetch(
-            || surf::get(format!("{DISCORD_API}/users/@me/channels")),
+            || surf::get(format!("{api}/users/@me/channels")),
             self.get_auth_header().await?,
         )
         .await?
@@ -219,8 +263,9 @@ impl InnerDiscord<Owned> {
     pub(crate) async fn rest_get_guilds(
         &self,
     ) -> Result<Vec<api_types::Guild>, Box<dyn Error + Sync + Send>> {
+        let api = &self.endpoints.api;
         Fetch::<Fresh>::fetch(
-            || surf::get(format!("{DISCORD_API}/users/@me/guilds")),
+            || surf::get(format!("{api}/users/@me/guilds")),
             self.get_auth_header().await?,
         )
         .await?
@@ -232,8 +277,9 @@ impl InnerDiscord<Owned> {
         &self,
         guild_id: SNOWFLAKE,
     ) -> Result<Vec<api_types::Channel>, Box<dyn Error + Sync + Send>> {
+        let api = &self.endpoints.api;
         Fetch::<Fresh>::fetch(
-            || surf::get(format!("{DISCORD_API}/guilds/{guild_id}/channels")),
+            || surf::get(format!("{api}/guilds/{guild_id}/channels")),
             self.get_auth_header().await?,
         )
         .await?
@@ -241,16 +287,37 @@ impl InnerDiscord<Owned> {
         .await
     }
 
+    /// The open posts of the forum `channel_id`, most recently active first.
+    pub(crate) async fn rest_get_forum_posts(
+        &self,
+        channel_id: SNOWFLAKE,
+    ) -> Result<Vec<api_types::Channel>, Box<dyn Error + Sync + Send>> {
+        let api = &self.endpoints.api;
+        let search = Fetch::<Fresh>::fetch(
+            || {
+                surf::get(format!(
+                    "{api}/channels/{channel_id}/threads/search?archived=false&sort_by=last_message_time&sort_order=desc&limit=25"
+                ))
+            },
+            self.get_auth_header().await?,
+        )
+        .await?
+        .json::<api_types::ThreadSearch>()
+        .await?;
+        Ok(search.threads)
+    }
+
     pub(crate) async fn rest_get_messages(
         &self,
         channel_id: SNOWFLAKE,
         before: Option<SNOWFLAKE>,
     ) -> Result<Vec<api_types::Message>, Box<dyn Error + Sync + Send>> {
+        let api = &self.endpoints.api;
         let before_param = match before {
             Some(msg_id) => format!("?before={msg_id}"),
             None => String::new(),
         };
-        let url = format!("{DISCORD_API}/channels/{channel_id}/messages{before_param}");
+        let url = format!("{api}/channels/{channel_id}/messages{before_param}");
         Fetch::<Fresh>::fetch(|| surf::get(&url), self.get_auth_header().await?)
             .await?
             .json::<Vec<api_types::Message>>()
@@ -264,9 +331,10 @@ impl InnerDiscord<Owned> {
         message_id: SNOWFLAKE,
         emoji: &str,
     ) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let api = &self.endpoints.api;
         let encoded_emoji = utf8_percent_encode(emoji, NON_ALPHANUMERIC);
         let url = format!(
-            "{DISCORD_API}/channels/{channel_id}/messages/{message_id}/reactions/{encoded_emoji}/@me"
+            "{api}/channels/{channel_id}/messages/{message_id}/reactions/{encoded_emoji}/@me"
         );
         Fetch::<Fresh>::fetch(|| surf::put(&url), self.get_auth_header().await?).await?;
         Ok(())
@@ -279,32 +347,91 @@ impl InnerDiscord<Owned> {
         message_id: SNOWFLAKE,
         emoji: &str,
     ) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let api = &self.endpoints.api;
         let encoded_emoji = utf8_percent_encode(emoji, NON_ALPHANUMERIC);
         let url = format!(
-            "{DISCORD_API}/channels/{channel_id}/messages/{message_id}/reactions/{encoded_emoji}/@me"
+            "{api}/channels/{channel_id}/messages/{message_id}/reactions/{encoded_emoji}/@me"
         );
         Fetch::<Fresh>::fetch(|| surf::delete(&url), self.get_auth_header().await?).await?;
         Ok(())
     }
 
     // Docs: https://discord.com/developers/docs/resources/message#create-message
+    // Uploads: https://discord.com/developers/docs/reference#uploading-files
+    /// `files` are `(filename, contents)` pairs; with any present the message
+    /// goes out as `multipart/form-data` instead of JSON.
     pub(crate) async fn rest_send_message(
         &self,
         channel_id: SNOWFLAKE,
         content: String,
+        reply_to: Option<SNOWFLAKE>,
+        files: Vec<(String, Vec<u8>)>,
     ) -> Result<api_types::Message, Box<dyn Error + Sync + Send>> {
+        let api = &self.endpoints.api;
         let message = api_types::CreateMessage {
             content: Some(content),
             nonce: None,
             enforce_nonce: None,
             tts: Some(false),
+            message_reference: reply_to.map(|message_id| api_types::MessageReference {
+                reference_type: Some(0),
+                message_id: Some(message_id),
+                channel_id: Some(channel_id),
+                guild_id: None,
+                // A reply whose target was deleted meanwhile still sends.
+                fail_if_not_exists: Some(false),
+            }),
+            attachments: (!files.is_empty()).then(|| {
+                files
+                    .iter()
+                    .zip(0..)
+                    .map(|((filename, _), id)| api_types::CreateAttachment {
+                        id,
+                        filename: filename.clone(),
+                    })
+                    .collect()
+            }),
             flags: Some(0),
         };
         let msg_string = facet_json::to_vec(&message)?;
+        let (body, content_type) = if files.is_empty() {
+            (msg_string, "application/json".to_owned())
+        } else {
+            let boundary = multipart_boundary();
+            (
+                multipart_body(&boundary, &msg_string, &files),
+                format!("multipart/form-data; boundary={boundary}"),
+            )
+        };
         Fetch::<Fresh>::fetch(
             || {
-                surf::post(format!("{DISCORD_API}/channels/{channel_id}/messages"))
-                    .body(msg_string.clone())
+                surf::post(format!("{api}/channels/{channel_id}/messages"))
+                    .body(body.clone())
+                    .content_type(content_type.as_str())
+            },
+            self.get_auth_header().await?,
+        )
+        .await?
+        .json::<api_types::Message>()
+        .await
+    }
+
+    // Docs: https://discord.com/developers/docs/resources/message#edit-message
+    pub(crate) async fn rest_edit_message(
+        &self,
+        channel_id: SNOWFLAKE,
+        message_id: SNOWFLAKE,
+        content: String,
+    ) -> Result<api_types::Message, Box<dyn Error + Sync + Send>> {
+        let api = &self.endpoints.api;
+        let edit = api_types::EditMessage {
+            content: Some(content),
+        };
+        let edit_string = facet_json::to_vec(&edit)?;
+        Fetch::<Fresh>::fetch(
+            || {
+                surf::patch(format!("{api}/channels/{channel_id}/messages/{message_id}"))
+                    .body(edit_string.clone())
                     .content_type("application/json")
             },
             self.get_auth_header().await?,
@@ -313,4 +440,68 @@ impl InnerDiscord<Owned> {
         .json::<api_types::Message>()
         .await
     }
+
+    // Docs: https://discord.com/developers/docs/resources/message#delete-message
+    pub(crate) async fn rest_delete_message(
+        &self,
+        channel_id: SNOWFLAKE,
+        message_id: SNOWFLAKE,
+    ) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let api = &self.endpoints.api;
+        let url = format!("{api}/channels/{channel_id}/messages/{message_id}");
+        Fetch::<Fresh>::fetch(|| surf::delete(&url), self.get_auth_header().await?).await?;
+        Ok(())
+    }
+
+    // Docs: https://docs.discord.food/resources/message#acknowledge-message
+    pub(crate) async fn rest_ack_message(
+        &self,
+        channel_id: SNOWFLAKE,
+        message_id: SNOWFLAKE,
+    ) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let api = &self.endpoints.api;
+        let url = format!("{api}/channels/{channel_id}/messages/{message_id}/ack");
+        Fetch::<Fresh>::fetch(
+            || {
+                surf::post(&url)
+                    .body(r#"{"token":null}"#)
+                    .content_type("application/json")
+            },
+            self.get_auth_header().await?,
+        )
+        .await?;
+        Ok(())
+    }
+
+    // Docs: https://docs.discord.food/resources/channel#create-private-channel
+    /// Open the DM with `recipient`, or get the one already open.
+    pub(crate) async fn rest_open_dm(
+        &self,
+        recipient: SNOWFLAKE,
+    ) -> Result<api_types::Channel, Box<dyn Error + Sync + Send>> {
+        let api = &self.endpoints.api;
+        let body = format!(r#"{{"recipients":["{recipient}"]}}"#);
+        Fetch::<Fresh>::fetch(
+            || {
+                surf::post(format!("{api}/users/@me/channels"))
+                    .body(body.clone())
+                    .content_type("application/json")
+            },
+            self.get_auth_header().await?,
+        )
+        .await?
+        .json::<api_types::Channel>()
+        .await
+    }
+
+    // Docs: https://docs.discord.food/resources/channel#trigger-typing-indicator
+    pub(crate) async fn rest_send_typing(
+        &self,
+        channel_id: SNOWFLAKE,
+    ) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let api = &self.endpoints.api;
+        let url = format!("{api}/channels/{channel_id}/typing");
+        Fetch::<Fresh>::fetch(|| surf::post(&url), self.get_auth_header().await?).await?;
+        Ok(())
+    }
 }
@@ -1,24 +1,59 @@
 //! Parse Discord message content into the interface [`RichText`] model.
 //!
-//! Discord sends content as a flat string with markdown (`**bold**`,
-//! `*italic*`/`_italic_`), bare URLs, and custom-emoji tokens
-//! (`<:name:id>` / animated `<a:name:id>`); stickers ride alongside as a
-//! separate `sticker_items` array. We tokenize all of that here — *in the
-//! adapter* — so the UI renders one uniform span model and never has to know
-//! Discord's syntax. Image resolution (emoji/sticker → cached file) happens in
-//! the async pass; the tokenizer itself is pure and unit-tested.
+//! Discord sends content as a flat string of markdown: inline emphasis
+//! (`**bold**`, `*italic*`/`_italic_`, `__underline__`, `~~strike~~`,
+//! `||spoiler||`, `` `code` ``), masked links (`[text](url)`) and bare URLs,
+//! custom-emoji tokens (`<:name:id>` / animated `<a:name:id>`), mentions
+//! (`<@user>`, `<@&role>`, `<#channel>`, `@everyone`) and timestamps
+//! (`<t:unix:style>`), plus the blocks: fenced code with an optional language,
+//! `> `/`>>> ` quotes and `#` headings. Stickers ride alongside as a separate
+//! `sticker_items` array. We tokenize all of that here — *in the adapter* — so
+//! the UI renders one uniform span model and never has to know Discord's
+//! syntax. Image resolution (emoji/sticker → cached file) happens in the async
+//! pass and mention names come from the caller; the tokenizer itself is pure
+//! and unit-tested. [`to_markdown`] goes the other way for sending rich text
+//! that wasn't typed (drafts go out as typed), and round-trips with the
+//! tokenizer.
 
-use std::path::PathBuf;
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    path::PathBuf,
+};
 
-use messenger_interface::types::{Emoji, RichText, Span, TextStyle};
+use chrono::{DateTime, Utc};
+use messenger_interface::types::{Emoji, Mention, RichText, Span, TextStyle, TimestampStyle};
 
 use crate::{
     api_types::{self, SNOWFLAKE},
     downloaders::CdnImage,
 };
 
-/// A token produced by [`tokenize`], before image resolution. Custom emoji
-/// carry their id so the CDN image can be fetched in the async pass.
+/// Emphasis delimiters, longest first so `***` is tried before `**` before
+/// `*`.
+const DELIMITERS: [&str; 7] = ["***", "**", "__", "~~", "||", "*", "_"];
+
+/// The emphasis [`to_markdown`] writes, outermost first: the order
+/// [`match_emphasis`] reads nested runs back in (`**bold *both***`).
+const EMPHASIS: [&str; 5] = ["||", "__", "~~", "**", "*"];
+
+/// Characters [`inline`] reads as markdown, escaped in text that should
+/// arrive as typed. `@` is only escaped in front of `everyone`/`here`.
+const ESCAPED: &str = "\\*_~|`<>#[";
+
+/// The `<t:…:style>` letters.
+const TIMESTAMP_STYLES: [(&str, TimestampStyle); 7] = [
+    ("t", TimestampStyle::ShortTime),
+    ("T", TimestampStyle::LongTime),
+    ("d", TimestampStyle::ShortDate),
+    ("D", TimestampStyle::LongDate),
+    ("f", TimestampStyle::ShortDateTime),
+    ("F", TimestampStyle::LongDateTime),
+    ("R", TimestampStyle::Relative),
+];
+
+/// A token produced by [`tokenize`], before image and name resolution.
+/// Custom emoji carry their id so the CDN image can be fetched in the async
+/// pass; mentions carry a name only when the token spells it out.
 #[derive(Debug, PartialEq)]
 enum Piece {
     Text {
@@ -34,6 +69,23 @@ enum Piece {
         text: String,
         href: String,
     },
+    Mention {
+        target: Mention,
+        name: Option<String>,
+    },
+    Timestamp {
+        at: DateTime<Utc>,
+        style: TimestampStyle,
+    },
+    CodeBlock {
+        language: Option<String>,
+        code: String,
+    },
+    Quote(Vec<Piece>),
+    Heading {
+        level: u8,
+        pieces: Vec<Piece>,
+    },
 }
 
 fn bytes_ci_prefix(s: &str, prefix: &str) -> bool {
@@ -45,6 +97,12 @@ fn is_url_char(c: char) -> bool {
     c.is_ascii_alphanumeric() || "-._~:/?#[]@!$&'()*+,;=%".contains(c)
 }
 
+fn is_url(text: &str) -> bool {
+    (bytes_ci_prefix(text, "http://") || bytes_ci_prefix(text, "https://"))
+        && text.len() > "http://".len()
+        && text.chars().all(is_url_char)
+}
+
 /// `<:name:id>` or `<a:name:id>`. Returns (bytes consumed, piece).
 fn match_emoji(rest: &str) -> Option<(usize, Piece)> {
     let (animated, after_prefix) = if let Some(r) = rest.strip_prefix("<a:") {
@@ -77,45 +135,91 @@ fn match_emoji(rest: &str) -> Option<(usize, Piece)> {
     ))
 }
 
-/// `**bold**`. Empty `****` is treated as literal text (returns `None`).
-fn match_bold(rest: &str) -> Option<(usize, Piece)> {
-    let inner = rest.strip_prefix("**")?;
-    let end = inner.find("**")?;
-    if end == 0 {
+/// `<@user>` (or the older `<@!user>`), `<@&role>`, `<#channel>`, and
+/// `@everyone` / `@here`.
+fn match_mention(rest: &str) -> Option<(usize, Piece)> {
+    for everyone in ["@everyone", "@here"] {
+        if rest.starts_with(everyone) {
+            return Some((
+                everyone.len(),
+                Piece::Mention {
+                    target: Mention::Everyone,
+                    name: Some(everyone[1..].to_owned()),
+                },
+            ));
+        }
+    }
+    let inner = rest.strip_prefix('<')?;
+    let end = inner.find('>')?;
+    let token = &inner[..end];
+    let snowflake = |id: &str| {
+        if id.is_empty() || !id.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        id.parse().ok()
+    };
+    let target = if let Some(id) = token.strip_prefix("@&") {
+        Mention::Role(snowflake(id)?)
+    } else if let Some(id) = token.strip_prefix('@') {
+        Mention::User(snowflake(id.strip_prefix('!').unwrap_or(id))?)
+    } else {
+        Mention::Channel(snowflake(token.strip_prefix('#')?)?)
+    };
+    Some((1 + end + 1, Piece::Mention { target, name: None }))
+}
+
+/// `<t:unix>` or `<t:unix:style>`, the style a single letter.
+fn match_timestamp(rest: &str) -> Option<(usize, Piece)> {
+    let inner = rest.strip_prefix("<t:")?;
+    let end = inner.find('>')?;
+    let (seconds, style) = match inner[..end].split_once(':') {
+        Some((seconds, style)) => (seconds, style),
+        None => (&inner[..end], "f"),
+    };
+    let (_, style) = TIMESTAMP_STYLES
+        .into_iter()
+        .find(|(letter, _)| *letter == style)?;
+    let at = DateTime::from_timestamp(seconds.parse().ok()?, 0)?;
+    Some(("<t:".len() + end + 1, Piece::Timestamp { at, style }))
+}
+
+/// `<https://…>`: a URL with its embed suppressed, which is still a link.
+fn match_bracketed_url(rest: &str) -> Option<(usize, Piece)> {
+    let inner = rest.strip_prefix('<')?;
+    let end = inner.find('>')?;
+    let url = &inner[..end];
+    if !is_url(url) {
         return None;
     }
     Some((
-        2 + end + 2,
-        Piece::Text {
-            text: inner[..end].to_owned(),
-            style: TextStyle {
-                bold: true,
-                ..Default::default()
-            },
+        1 + end + 1,
+        Piece::Link {
+            text: url.to_owned(),
+            href: url.to_owned(),
         },
     ))
 }
 
-/// `*italic*` or `_italic_`. A leading `**` is left for [`match_bold`].
-fn match_italic(rest: &str) -> Option<(usize, Piece)> {
-    let delim = match rest.as_bytes().first()? {
-        b'*' if !rest.starts_with("**") => '*',
-        b'_' => '_',
-        _ => return None,
-    };
-    let inner = &rest[1..];
-    let end = inner.find(delim)?;
-    if end == 0 {
+/// `[text](https://…)`, the URL optionally in angle brackets.
+fn match_masked_link(rest: &str) -> Option<(usize, Piece)> {
+    let inner = rest.strip_prefix('[')?;
+    let text_end = inner.find(']')?;
+    let text = &inner[..text_end];
+    let target = inner[text_end + 1..].strip_prefix('(')?;
+    let target_end = target.find(')')?;
+    let href = &target[..target_end];
+    let href = href
+        .strip_prefix('<')
+        .and_then(|href| href.strip_suffix('>'))
+        .unwrap_or(href);
+    if text.trim().is_empty() || !is_url(href) {
         return None;
     }
     Some((
-        1 + end + 1,
-        Piece::Text {
-            text: inner[..end].to_owned(),
-            style: TextStyle {
-                italic: true,
-                ..Default::default()
-            },
+        1 + text_end + 2 + target_end + 1,
+        Piece::Link {
+            text: text.to_owned(),
+            href: href.to_owned(),
         },
     ))
 }
@@ -140,43 +244,283 @@ fn match_url(rest: &str) -> Option<(usize, Piece)> {
     ))
 }
 
-fn match_special(rest: &str) -> Option<(usize, Piece)> {
-    match_emoji(rest)
-        .or_else(|| match_bold(rest))
-        .or_else(|| match_italic(rest))
-        .or_else(|| match_url(rest))
+/// `` `code` `` or ``` ``code`` ```: shown verbatim, nothing inside is
+/// markdown.
+fn match_inline_code(rest: &str, style: TextStyle) -> Option<(usize, Piece)> {
+    let ticks = rest.bytes().take_while(|&b| b == b'`').count();
+    if !(1..=2).contains(&ticks) {
+        return None;
+    }
+    let fence = &rest[..ticks];
+    let inner = &rest[ticks..];
+    let end = inner.find(fence)?;
+    let code = &inner[..end];
+    // Double ticks let code start or end with a tick, padded by a space.
+    let code = if ticks == 2 {
+        let code = code.strip_prefix(' ').unwrap_or(code);
+        code.strip_suffix(' ').unwrap_or(code)
+    } else {
+        code
+    };
+    if code.is_empty() {
+        return None;
+    }
+    Some((
+        ticks + end + ticks,
+        Piece::Text {
+            text: code.to_owned(),
+            style: TextStyle {
+                code: true,
+                ..style
+            },
+        },
+    ))
 }
 
-/// Split `content` into [`Piece`]s. Plain runs (including Unicode emoji, which
-/// need no special handling) accumulate into `Text` pieces; everything between
-/// is a recognized markdown/emoji/link token.
-fn tokenize(content: &str) -> Vec<Piece> {
+/// `style` with the emphasis `delimiter` stands for added.
+fn emphasis(delimiter: &str, mut style: TextStyle) -> TextStyle {
+    match delimiter {
+        "***" => {
+            style.bold = true;
+            style.italic = true;
+        }
+        "**" => style.bold = true,
+        "__" => style.underline = true,
+        "~~" => style.strikethrough = true,
+        "||" => style.spoiler = true,
+        _ => style.italic = true,
+    }
+    style
+}
+
+/// Where `delimiter` next appears in `inner`, from `from` on. Escaped
+/// delimiters don't count, nor do ones inside inline code.
+fn find_delimiter(inner: &str, from: usize, delimiter: &str) -> Option<usize> {
+    let mut at = from;
+    while at < inner.len() {
+        let rest = &inner[at..];
+        if rest.starts_with(delimiter) {
+            return Some(at);
+        }
+        at += match match_inline_code(rest, TextStyle::default()) {
+            Some((consumed, _)) => consumed,
+            None if rest.starts_with('\\') => {
+                1 + rest[1..].chars().next().map_or(0, char::len_utf8)
+            }
+            None => rest.chars().next()?.len_utf8(),
+        };
+    }
+    None
+}
+
+/// Emphasis: `inner` between a pair of [`DELIMITERS`], itself parsed with
+/// the style added. Empty pairs (`****`) are literal text (`None`), and an
+/// escaped delimiter or one in inline code doesn't close. A single `_` only
+/// counts at word edges, so `snake_case_names` stay as they are.
+fn match_emphasis(
+    rest: &str,
+    style: TextStyle,
+    previous: Option<char>,
+) -> Option<(usize, Vec<Piece>)> {
+    DELIMITERS
+        .into_iter()
+        .filter(|delimiter| rest.starts_with(delimiter))
+        .find_map(|delimiter| {
+            let word_edge = delimiter == "_";
+            if word_edge && previous.is_some_and(char::is_alphanumeric) {
+                return None;
+            }
+            let inner = &rest[delimiter.len()..];
+            if delimiter == "*" && inner.starts_with(char::is_whitespace) {
+                return None;
+            }
+            let repeat = delimiter.as_bytes()[0];
+            let mut from = 0;
+            let end = loop {
+                let mut end = find_delimiter(inner, from, delimiter)?;
+                if end == 0 {
+                    return None;
+                }
+                // A closing run longer than the delimiter ends on its last
+                // characters: `**bold *italic***`.
+                while inner.as_bytes().get(end + delimiter.len()) == Some(&repeat) {
+                    end += 1;
+                }
+                let after = inner[end + delimiter.len()..].chars().next();
+                if word_edge && after.is_some_and(char::is_alphanumeric) {
+                    from = end + delimiter.len();
+                    continue;
+                }
+                break end;
+            };
+            Some((
+                delimiter.len() + end + delimiter.len(),
+                inline(&inner[..end], emphasis(delimiter, style)),
+            ))
+        })
+}
+
+fn match_special(
+    rest: &str,
+    style: TextStyle,
+    previous: Option<char>,
+) -> Option<(usize, Vec<Piece>)> {
+    let single =
+        |matched: Option<(usize, Piece)>| matched.map(|(consumed, piece)| (consumed, vec![piece]));
+    single(match_inline_code(rest, style))
+        .or_else(|| single(match_emoji(rest)))
+        .or_else(|| single(match_mention(rest)))
+        .or_else(|| single(match_timestamp(rest)))
+        .or_else(|| single(match_bracketed_url(rest)))
+        .or_else(|| single(match_masked_link(rest)))
+        .or_else(|| match_emphasis(rest, style, previous))
+        .or_else(|| single(match_url(rest)))
+}
+
+/// Split a run of inline `content` into [`Piece`]s, styled on top of `style`.
+/// Plain runs (including Unicode emoji, which need no special handling)
+/// accumulate into `Text` pieces; everything between is a recognized
+/// markdown/emoji/mention/link token. A backslash escapes the punctuation after it.
+fn inline(content: &str, style: TextStyle) -> Vec<Piece> {
     let mut pieces = Vec::new();
     let mut text = String::new();
     let mut rest = content;
+    let mut previous = None;
 
     while !rest.is_empty() {
-        if let Some((consumed, piece)) = match_special(rest) {
+        let mut chars = rest.chars();
+        let ch = chars.next().expect("rest is non-empty");
+        if ch == '\\'
+            && let Some(escaped) = chars.next().filter(char::is_ascii_punctuation)
+        {
+            text.push(escaped);
+            rest = &rest[1 + escaped.len_utf8()..];
+            previous = Some(escaped);
+        } else if let Some((consumed, special)) = match_special(rest, style, previous) {
             if !text.is_empty() {
                 pieces.push(Piece::Text {
                     text: std::mem::take(&mut text),
-                    style: TextStyle::default(),
+                    style,
                 });
             }
-            pieces.push(piece);
+            pieces.extend(special);
+            previous = rest[..consumed].chars().next_back();
             rest = &rest[consumed..];
         } else {
-            let ch = rest.chars().next().expect("rest is non-empty");
             text.push(ch);
             rest = &rest[ch.len_utf8()..];
+            previous = Some(ch);
         }
     }
     if !text.is_empty() {
-        pieces.push(Piece::Text {
-            text,
-            style: TextStyle::default(),
-        });
+        pieces.push(Piece::Text { text, style });
+    }
+    pieces
+}
+
+/// A fenced ```` ```code``` ```` block. A lone word on the opening line names
+/// the language.
+fn match_code_block(rest: &str) -> Option<(usize, Piece)> {
+    let inner = rest.strip_prefix("```")?;
+    let end = inner.find("```")?;
+    let body = &inner[..end];
+    let (language, code) = match body.split_once('\n') {
+        Some((language, code))
+            if !language.is_empty()
+                && language
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "+-#_.".contains(c)) =>
+        {
+            (Some(language.to_owned()), code)
+        }
+        _ => (None, body.strip_prefix('\n').unwrap_or(body)),
+    };
+    let code = code.strip_suffix('\n').unwrap_or(code);
+    if code.trim().is_empty() {
+        return None;
+    }
+    Some((
+        3 + end + 3,
+        Piece::CodeBlock {
+            language,
+            code: code.to_owned(),
+        },
+    ))
+}
+
+/// The blocks that only start a line: `>>> ` quotes the rest of the
+/// message, a run of `> ` lines quotes those lines, and `# `, `## `, `### `
+/// make a heading of theirs. The line break ending the block isn't consumed.
+fn match_line_block(rest: &str) -> Option<(usize, Piece)> {
+    if let Some(quoted) = rest.strip_prefix(">>> ") {
+        return Some((rest.len(), Piece::Quote(tokenize(quoted))));
+    }
+    if rest.starts_with("> ") {
+        let mut consumed = 0;
+        let mut quoted = Vec::new();
+        for line in rest.split('\n') {
+            let Some(line) = line.strip_prefix("> ") else {
+                break;
+            };
+            consumed += "> ".len() + line.len() + 1;
+            quoted.push(line);
+        }
+        // No line break after the last line.
+        consumed = consumed.min(rest.len());
+        if rest[..consumed].ends_with('\n') {
+            consumed -= 1;
+        }
+        return Some((consumed, Piece::Quote(tokenize(&quoted.join("\n")))));
+    }
+
+    let level = rest.bytes().take_while(|&b| b == b'#').count();
+    let line = rest[level..].strip_prefix(' ')?;
+    let line = &line[..line.find('\n').unwrap_or(line.len())];
+    if !(1..=3).contains(&level) || line.trim().is_empty() {
+        return None;
+    }
+    Some((
+        level + 1 + line.len(),
+        Piece::Heading {
+            level: level as u8,
+            pieces: inline(line.trim(), TextStyle::default()),
+        },
+    ))
+}
+
+/// Split `content` into [`Piece`]s: its code blocks, quotes and headings, and
+/// the [`inline`] runs between them.
+fn tokenize(content: &str) -> Vec<Piece> {
+    let mut pieces = Vec::new();
+    // Start of the inline run not yet parsed.
+    let mut run = 0;
+    let mut at = 0;
+
+    while at < content.len() {
+        let rest = &content[at..];
+        let line_start = at == 0 || content.as_bytes()[at - 1] == b'\n';
+        let block =
+            match_code_block(rest).or_else(|| line_start.then(|| match_line_block(rest)).flatten());
+        match block {
+            Some((consumed, block)) => {
+                // The line break before the block goes with it.
+                let before = &content[run..at];
+                pieces.extend(inline(
+                    before.strip_suffix('\n').unwrap_or(before),
+                    TextStyle::default(),
+                ));
+                pieces.push(block);
+                at += consumed;
+                // And so does the one after.
+                if content[at..].starts_with('\n') {
+                    at += 1;
+                }
+                run = at;
+            }
+            None => at += rest.chars().next().expect("rest is non-empty").len_utf8(),
+        }
     }
+    pieces.extend(inline(&content[run..], TextStyle::default()));
     pieces
 }
 
@@ -194,23 +538,85 @@ async fn resolve_sticker(sticker: &api_types::StickerItem) -> Option<PathBuf> {
         .ok()
 }
 
-/// Build the [`RichText`] for a message body plus its stickers, resolving
-/// emoji/sticker images into the cache as it goes.
-pub async fn build_content(content: &str, stickers: &[api_types::StickerItem]) -> RichText {
-    let mut spans = Vec::new();
-    for piece in tokenize(content) {
+/// The ids of the custom emoji in `pieces`, blocks included.
+fn custom_emoji(pieces: &[Piece], emoji: &mut Vec<(SNOWFLAKE, bool)>) {
+    for piece in pieces {
         match piece {
-            Piece::Text { text, style } => spans.push(Span::Text { text, style }),
-            Piece::Link { text, href } => spans.push(Span::Link { text, href }),
-            Piece::Emoji { name, id, animated } => {
-                let image = resolve_emoji(id, animated).await;
-                spans.push(Span::Emoji(Emoji {
-                    shortcode: name,
-                    image,
-                }));
-            }
+            Piece::Emoji { id, animated, .. } => emoji.push((*id, *animated)),
+            Piece::Quote(pieces) | Piece::Heading { pieces, .. } => custom_emoji(pieces, emoji),
+            _ => {}
         }
     }
+}
+
+/// What Discord shows for a mention it has no name for.
+fn unknown_name(target: Mention) -> &'static str {
+    match target {
+        Mention::User(_) => "unknown-user",
+        Mention::Role(_) => "deleted-role",
+        Mention::Channel(_) => "unknown",
+        Mention::Everyone => "everyone",
+    }
+}
+
+fn into_span(
+    piece: Piece,
+    images: &HashMap<SNOWFLAKE, Option<PathBuf>>,
+    names: &impl Fn(Mention) -> Option<String>,
+) -> Span {
+    let spans = |pieces: Vec<Piece>| {
+        pieces
+            .into_iter()
+            .map(|piece| into_span(piece, images, names))
+            .collect()
+    };
+    match piece {
+        Piece::Text { text, style } => Span::Text { text, style },
+        Piece::Link { text, href } => Span::Link { text, href },
+        Piece::Mention { target, name } => Span::Mention {
+            target,
+            name: name
+                .or_else(|| names(target))
+                .unwrap_or_else(|| unknown_name(target).to_owned()),
+        },
+        Piece::Emoji { name, id, animated } => Span::Emoji(Emoji {
+            shortcode: name,
+            image: images.get(&id).cloned().flatten(),
+            id: Some(id),
+            animated,
+        }),
+        Piece::Timestamp { at, style } => Span::Timestamp { at, style },
+        Piece::CodeBlock { language, code } => Span::CodeBlock { language, code },
+        Piece::Quote(pieces) => Span::Quote(spans(pieces)),
+        Piece::Heading { level, pieces } => Span::Heading {
+            level,
+            spans: spans(pieces),
+        },
+    }
+}
+
+/// Build the [`RichText`] for a message body plus its stickers, resolving
+/// emoji/sticker images into the cache as it goes and mentions to the display
+/// name `names` knows them by.
+pub async fn build_content(
+    content: &str,
+    stickers: &[api_types::StickerItem],
+    names: impl Fn(Mention) -> Option<String>,
+) -> RichText {
+    let pieces = tokenize(content);
+    let mut emoji = Vec::new();
+    custom_emoji(&pieces, &mut emoji);
+    let mut images = HashMap::new();
+    for (id, animated) in emoji {
+        if let Entry::Vacant(entry) = images.entry(id) {
+            entry.insert(resolve_emoji(id, animated).await);
+        }
+    }
+
+    let mut spans: Vec<Span> = pieces
+        .into_iter()
+        .map(|piece| into_span(piece, &images, &names))
+        .collect();
     for sticker in stickers {
         let image = resolve_sticker(sticker).await;
         spans.push(Span::Sticker {
@@ -221,6 +627,197 @@ pub async fn build_content(content: &str, stickers: &[api_types::StickerItem]) -
     RichText { spans }
 }
 
+/// Parse markdown the user typed, for the local echo of the draft (which is
+/// itself sent as typed). Nothing is fetched: emoji keep their ids but no
+/// image until the sent message comes back.
+pub fn parse_draft(content: &str, names: impl Fn(Mention) -> Option<String>) -> RichText {
+    let spans = tokenize(content)
+        .into_iter()
+        .map(|piece| into_span(piece, &HashMap::new(), &names))
+        .collect();
+    RichText { spans }
+}
+
+/// What markdown `content` reads as with the markup stripped, mentions
+/// named by `names`; for quoting a message, like a reply's preview does.
+pub fn to_plain(content: &str, names: impl Fn(Mention) -> Option<String>) -> String {
+    parse_draft(content, names).to_plain()
+}
+
+fn has_emphasis(style: TextStyle, delimiter: &str) -> bool {
+    match delimiter {
+        "||" => style.spoiler,
+        "__" => style.underline,
+        "~~" => style.strikethrough,
+        "**" => style.bold,
+        _ => style.italic,
+    }
+}
+
+/// Append `text` so [`inline`] reads it back as exactly that text. URLs go
+/// out as they are, so Discord still links them.
+fn push_escaped(out: &mut String, text: &str) {
+    let mut rest = text;
+    while let Some(ch) = rest.chars().next() {
+        if let Some((end, _)) = match_url(rest) {
+            out.push_str(&rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+        let after = &rest[ch.len_utf8()..];
+        if ESCAPED.contains(ch)
+            || ch == '@' && (after.starts_with("everyone") || after.starts_with("here"))
+        {
+            out.push('\\');
+        }
+        out.push(ch);
+        rest = after;
+    }
+}
+
+/// `` `code` ``, fenced with two ticks when the code holds one. Code with
+/// a double tick in it can't be fenced, so it goes out as text.
+fn push_code(out: &mut String, code: &str) {
+    if !code.contains('`') {
+        out.push_str(&format!("`{code}`"));
+    } else if !code.contains("``") {
+        out.push_str(&format!("`` {code} ``"));
+    } else {
+        push_escaped(out, code);
+    }
+}
+
+/// Append a run of text spans, wrapping each stretch that shares an
+/// emphasis the enclosing delimiters (`open`) don't already give it.
+fn push_emphasized(out: &mut String, runs: &[(&str, TextStyle)], open: TextStyle) {
+    let mut at = 0;
+    while at < runs.len() {
+        let (text, style) = runs[at];
+        let delimiter = EMPHASIS
+            .into_iter()
+            .find(|delimiter| has_emphasis(style, delimiter) && !has_emphasis(open, delimiter));
+        let Some(delimiter) = delimiter else {
+            if style.code {
+                push_code(out, text);
+            } else {
+                push_escaped(out, text);
+            }
+            at += 1;
+            continue;
+        };
+        let end = at
+            + runs[at..]
+                .iter()
+                .take_while(|(_, style)| has_emphasis(*style, delimiter))
+                .count();
+        out.push_str(delimiter);
+        push_emphasized(out, &runs[at..end], emphasis(delimiter, open));
+        out.push_str(delimiter);
+        at = end;
+    }
+}
+
+/// Append one span. [`push_spans`] hands runs of text to
+/// [`push_emphasized`] together, so their emphasis can nest.
+fn push_span(out: &mut String, span: &Span) {
+    match span {
+        Span::Text { text, style } => {
+            push_emphasized(out, &[(text.as_str(), *style)], TextStyle::default())
+        }
+        Span::Emoji(emoji) => match emoji.id {
+            Some(id) => {
+                let prefix = if emoji.animated { "<a:" } else { "<:" };
+                out.push_str(&format!("{prefix}{}:{id}>", emoji.shortcode));
+            }
+            // Unicode emoji are text; anything else can only go by name.
+            None if !emoji.shortcode.is_ascii() => out.push_str(&emoji.shortcode),
+            None => push_escaped(out, &format!(":{}:", emoji.shortcode)),
+        },
+        // Stickers are sent by id alongside the content, not in it.
+        Span::Sticker { .. } => {}
+        Span::Link { text, href } if text == href && is_url(href) => {
+            out.push_str(&format!("<{href}>"));
+        }
+        Span::Link { text, href }
+            if is_url(href)
+                && !href.contains(')')
+                && !text.trim().is_empty()
+                && !text.contains(']') =>
+        {
+            out.push_str(&format!("[{text}]({href})"));
+        }
+        Span::Link { text, .. } => push_escaped(out, text),
+        Span::Mention { target, name } => match target {
+            Mention::User(id) => out.push_str(&format!("<@{id}>")),
+            Mention::Role(id) => out.push_str(&format!("<@&{id}>")),
+            Mention::Channel(id) => out.push_str(&format!("<#{id}>")),
+            Mention::Everyone if name == "here" => out.push_str("@here"),
+            Mention::Everyone => out.push_str("@everyone"),
+        },
+        Span::Timestamp { at, style } => {
+            let (letter, _) = TIMESTAMP_STYLES
+                .into_iter()
+                .find(|(_, known)| known == style)
+                .expect("every style has a letter");
+            out.push_str(&format!("<t:{}:{letter}>", at.timestamp()));
+        }
+        Span::CodeBlock { language, code } => {
+            let language = language.as_deref().unwrap_or_default();
+            out.push_str(&format!("```{language}\n{code}\n```"));
+        }
+        Span::Quote(spans) => {
+            let mut quoted = String::new();
+            push_spans(&mut quoted, spans);
+            let lines: Vec<String> = quoted.split('\n').map(|line| format!("> {line}")).collect();
+            out.push_str(&lines.join("\n"));
+        }
+        Span::Heading { level, spans } => {
+            out.push_str(&"#".repeat((*level).clamp(1, 3).into()));
+            out.push(' ');
+            push_spans(out, spans);
+        }
+    }
+}
+
+/// Append `spans`: runs of text with their emphasis nested, and blocks on
+/// lines of their own.
+fn push_spans(out: &mut String, spans: &[Span]) {
+    let mut at = 0;
+    while at < spans.len() {
+        let runs: Vec<(&str, TextStyle)> = spans[at..]
+            .iter()
+            .map_while(|span| match span {
+                Span::Text { text, style } => Some((text.as_str(), *style)),
+                _ => None,
+            })
+            .collect();
+        if !runs.is_empty() {
+            push_emphasized(out, &runs, TextStyle::default());
+            at += runs.len();
+            continue;
+        }
+        let span = &spans[at];
+        // The line breaks around a block go with it when read back.
+        if span.is_block() && at > 0 && !spans[at - 1].is_block() {
+            out.push('\n');
+        }
+        push_span(out, span);
+        if span.is_block() && at + 1 < spans.len() {
+            out.push('\n');
+        }
+        at += 1;
+    }
+}
+
+/// Serialize `text` to the markdown Discord sends it as. It reads back
+/// through [`tokenize`] as the same spans, so long as markdown can tell
+/// them apart: `**a***b*` can't be, nor two quotes in a row.
+pub fn to_markdown(text: &RichText) -> String {
+    let mut out = String::new();
+    push_spans(&mut out, &text.spans);
+    out
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -291,4 +888,322 @@ mod tests {
             }]
         );
     }
+
+    fn text(text: &str, style: TextStyle) -> Piece {
+        Piece::Text {
+            text: text.to_owned(),
+            style,
+        }
+    }
+
+    #[test]
+    fn emphasis_nests() {
+        let bold = TextStyle {
+            bold: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            tokenize("**bold *both***"),
+            vec![
+                text("bold ", bold),
+                text(
+                    "both",
+                    TextStyle {
+                        italic: true,
+                        ..bold
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn underline_strikethrough_spoiler_and_code() {
+        let pieces = tokenize("__u__ ~~s~~ ||x|| `c **d**`");
+        assert!(matches!(&pieces[0], Piece::Text { style, .. } if style.underline));
+        assert!(matches!(&pieces[2], Piece::Text { style, .. } if style.strikethrough));
+        assert!(matches!(&pieces[4], Piece::Text { style, .. } if style.spoiler));
+        // Nothing inside inline code is markdown.
+        assert_eq!(
+            pieces[6],
+            text(
+                "c **d**",
+                TextStyle {
+                    code: true,
+                    ..Default::default()
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn snake_case_and_escapes_stay_literal() {
+        assert_eq!(
+            tokenize(r"snake_case_name \*not italic\*"),
+            vec![text("snake_case_name *not italic*", TextStyle::default())]
+        );
+    }
+
+    #[test]
+    fn masked_links_and_timestamps() {
+        let pieces = tokenize("[docs](<https://x.com/a>) at <t:1618953630:R>");
+        assert_eq!(
+            pieces[0],
+            Piece::Link {
+                text: "docs".to_owned(),
+                href: "https://x.com/a".to_owned()
+            }
+        );
+        assert_eq!(
+            pieces[2],
+            Piece::Timestamp {
+                at: DateTime::from_timestamp(1618953630, 0).unwrap(),
+                style: TimestampStyle::Relative
+            }
+        );
+    }
+
+    #[test]
+    fn mentions() {
+        let mention = |target| Piece::Mention { target, name: None };
+        let pieces = tokenize("<@1> <@!2> <@&3> <#4> @here <@x> <#>");
+        assert_eq!(pieces[0], mention(Mention::User(1)));
+        assert_eq!(pieces[2], mention(Mention::User(2)));
+        assert_eq!(pieces[4], mention(Mention::Role(3)));
+        assert_eq!(pieces[6], mention(Mention::Channel(4)));
+        assert_eq!(
+            pieces[8],
+            Piece::Mention {
+                target: Mention::Everyone,
+                name: Some("here".to_owned())
+            }
+        );
+        assert_eq!(pieces[9], text(" <@x> <#>", TextStyle::default()));
+    }
+
+    #[test]
+    fn mention_names_fall_back_to_unknown() {
+        let names = |target| (target == Mention::User(1)).then(|| "ana".to_owned());
+        let spans: Vec<String> = tokenize("<@1> <@2> <#3>")
+            .into_iter()
+            .map(|piece| into_span(piece, &HashMap::new(), &names).to_plain())
+            .collect();
+        assert_eq!(spans, ["@ana", " ", "@unknown-user", " ", "#unknown"]);
+    }
+
+    #[test]
+    fn blocks_sit_between_inline_runs() {
+        let pieces =
+            tokenize("# Title\nsee:\n```rust\nfn main() {}\n```\n> quoted\n> *twice*\nafter");
+        assert_eq!(
+            pieces,
+            vec![
+                Piece::Heading {
+                    level: 1,
+                    pieces: vec![text("Title", TextStyle::default())]
+                },
+                text("see:", TextStyle::default()),
+                Piece::CodeBlock {
+                    language: Some("rust".to_owned()),
+                    code: "fn main() {}".to_owned()
+                },
+                Piece::Quote(vec![
+                    text("quoted\n", TextStyle::default()),
+                    text(
+                        "twice",
+                        TextStyle {
+                            italic: true,
+                            ..Default::default()
+                        }
+                    ),
+                ]),
+                text("after", TextStyle::default()),
+            ]
+        );
+    }
+
+    #[test]
+    fn markdown_escapes_what_would_parse() {
+        let text = RichText::plain(r"**not bold** <@1> @here \o/ snake_case https://x.com/a_b");
+        assert_eq!(
+            to_markdown(&text),
+            r"\*\*not bold\*\* \<@1\> \@here \\o/ snake\_case https://x.com/a_b"
+        );
+    }
+
+    #[test]
+    fn drafts_keep_their_markdown() {
+        for draft in [
+            "**bold *both*** and __~~struck~~__",
+            "||`code`|| <:wave:1> <@2> <#3> @everyone <t:1618953630:R>",
+            "[docs](https://x.com/a) or <https://x.com/b>",
+            "# Title\n> quoted\n> *twice*\n```rust\nfn main() {}\n```\nafter",
+        ] {
+            assert_eq!(to_markdown(&parse_draft(draft, |_| None)), draft);
+        }
+    }
+
+    #[test]
+    fn plain_text_drops_the_markup() {
+        let names = |mention| (mention == Mention::User(2)).then(|| "bob".to_owned());
+        assert_eq!(
+            to_plain("**hi** <@2>, ||secret|| `code`", names),
+            "hi @bob, secret code"
+        );
+    }
+
+    mod round_trip {
+        use proptest::prelude::*;
+
+        use super::*;
+
+        fn style() -> impl Strategy<Value = TextStyle> {
+            any::<[bool; 6]>().prop_map(
+                |[bold, italic, underline, strikethrough, spoiler, code]| TextStyle {
+                    bold,
+                    italic,
+                    underline,
+                    strikethrough,
+                    spoiler,
+                    code,
+                },
+            )
+        }
+
+        fn text() -> impl Strategy<Value = Span> {
+            style().prop_flat_map(|style| {
+                // Heavy on markdown punctuation, to exercise the escaping.
+                // Emphasis can't open on whitespace, and a double tick in
+                // code can't be fenced.
+                let text = if style.code {
+                    "[a-z *_~|<>#@\\[\\\\]{1,6}"
+                } else if style == TextStyle::default() {
+                    "[a-z *_~|`<>#@\\[\\\\\n]{1,8}|@everyone|@here"
+                } else {
+                    "[a-z*_~|`<>#@\\[\\\\][a-z *_~|`<>#@\\[\\\\\n]{0,6}"
+                };
+                text.prop_map(move |text| Span::Text { text, style })
+            })
+        }
+
+        fn inline() -> impl Strategy<Value = Span> {
+            let emoji = ("[a-z_]{1,8}", any::<u64>(), any::<bool>()).prop_map(
+                |(shortcode, id, animated)| {
+                    Span::Emoji(Emoji {
+                        shortcode,
+                        image: None,
+                        id: Some(id),
+                        animated,
+                    })
+                },
+            );
+            let link = (
+                "https://[a-z]{1,8}\\.com(/[a-z]{1,5})?",
+                "([a-z ]{0,5}[a-z])?",
+            )
+                .prop_map(|(href, text)| Span::Link {
+                    text: if text.is_empty() { href.clone() } else { text },
+                    href,
+                });
+            let mention = prop_oneof![
+                any::<u64>().prop_map(Mention::User),
+                any::<u64>().prop_map(Mention::Role),
+                any::<u64>().prop_map(Mention::Channel),
+                Just(Mention::Everyone),
+            ]
+            .prop_flat_map(|target| {
+                let names = match target {
+                    Mention::Everyone => vec!["everyone", "here"],
+                    _ => vec![unknown_name(target)],
+                };
+                prop::sample::select(names).prop_map(move |name| Span::Mention {
+                    target,
+                    name: name.to_owned(),
+                })
+            });
+            let timestamp =
+                (0..4_000_000_000i64, 0..TIMESTAMP_STYLES.len()).prop_map(|(seconds, style)| {
+                    Span::Timestamp {
+                        at: DateTime::from_timestamp(seconds, 0).unwrap(),
+                        style: TIMESTAMP_STYLES[style].1,
+                    }
+                });
+            prop_oneof![4 => text(), 1 => emoji, 1 => link, 1 => mention, 1 => timestamp]
+        }
+
+        fn rendered(spans: &[Span]) -> String {
+            let mut out = String::new();
+            push_spans(&mut out, spans);
+            out
+        }
+
+        fn block() -> impl Strategy<Value = Span> {
+            let code_block = (prop::option::of("[a-z]{1,4}"), "[a-z][a-z ;{}\n]{0,10}")
+                .prop_map(|(language, code)| Span::CodeBlock { language, code });
+            let quote = prop::collection::vec(inline(), 1..4)
+                .prop_map(|spans| Span::Quote(distinguishable(spans)));
+            let heading = (1..=3u8, prop::collection::vec(inline(), 1..4))
+                .prop_map(|(level, spans)| Span::Heading {
+                    level,
+                    spans: distinguishable(spans),
+                })
+                // A heading is one line, trimmed.
+                .prop_filter("multi-line heading", |heading| {
+                    let Span::Heading { spans, .. } = heading else {
+                        unreachable!()
+                    };
+                    let line = rendered(spans);
+                    !line.contains('\n') && line.trim() == line
+                });
+            prop_oneof![code_block, quote, heading]
+        }
+
+        /// What markdown can tell apart: texts of unrelated styles side by
+        /// side get a space between them (`**a***b*` is ambiguous), texts of
+        /// one style merge, and a quote right after another is dropped.
+        fn distinguishable(spans: Vec<Span>) -> Vec<Span> {
+            let mut kept: Vec<Span> = Vec::new();
+            for span in spans {
+                match (kept.last_mut(), span) {
+                    (
+                        Some(Span::Text { text, style }),
+                        Span::Text {
+                            text: next,
+                            style: next_style,
+                        },
+                    ) if *style == next_style => text.push_str(&next),
+                    (
+                        Some(Span::Text { style, .. }),
+                        Span::Text {
+                            text,
+                            style: next_style,
+                        },
+                    ) if *style != TextStyle::default() && next_style != TextStyle::default() => {
+                        kept.push(Span::Text {
+                            text: " ".to_owned(),
+                            style: TextStyle::default(),
+                        });
+                        kept.push(Span::Text {
+                            text,
+                            style: next_style,
+                        });
+                    }
+                    (Some(Span::Quote(_)), Span::Quote(_)) => {}
+                    (_, span) => kept.push(span),
+                }
+            }
+            kept
+        }
+
+        proptest! {
+            #[test]
+            fn markdown_reads_back_as_written(
+                spans in prop::collection::vec(prop_oneof![4 => inline(), 1 => block()], 0..8)
+            ) {
+                let text = RichText { spans: distinguishable(spans) };
+                let markdown = to_markdown(&text);
+                prop_assert_eq!(parse_draft(&markdown, |_| None), text, "{}", markdown);
+            }
+        }
+    }
 }
@@ -5,20 +5,92 @@
 //! events captured in a recording window (see
 //! `crate/messenger_interface/docs/races.md`). The companion `ArcStream` impl
 //! drains the buffered `TextEvent` queue, pumping the gateway when it's empty.
-use std::{error::Error, sync::Arc};
+use std::{collections::HashMap, error::Error, path::PathBuf, sync::Arc};
 
 use async_trait::async_trait;
-use futures::future::join_all;
+use futures::future::{join_all, try_join_all};
 use messenger_interface::{
     interface::{Ordering, Text, TextEvent},
     stream::{ArcStream, WeakSocketStream},
-    types::{Identifier, Message, Place, Room, User},
+    types::{ID, Identifier, Message, Place, ReadState, RichText, Room, User},
 };
 
 use crate::{
-    Discord, InnerDiscord, Owned, StreamPollGuard, TextDiscord, api_types, downloaders::CdnImage,
+    ChannelLocation, Discord, InnerDiscord, Owned, StreamPollGuard, TextDiscord, api_types,
+    downloaders::CdnImage, rich,
 };
 
+impl InnerDiscord<Owned> {
+    /// Send `contents` with `files` as `(filename, contents)` uploads, and
+    /// convert the created message.
+    async fn send(
+        &self,
+        location: &Identifier<Place<Room>>,
+        contents: Message,
+        files: Vec<(String, Vec<u8>)>,
+    ) -> Result<Identifier<Message>, Box<dyn Error + Sync + Send>> {
+        let channel_location = *self
+            .channel_id_mappings
+            .get(location.id())
+            .ok_or("No discord channel id mapping for this room")?;
+
+        let reply_to = match &contents.reply {
+            Some(reply) => Some(
+                *self
+                    .message_id_mappings
+                    .get(&reply.message_id)
+                    .ok_or("No discord message id mapping for the replied-to message")?,
+            ),
+            None => None,
+        };
+
+        // A typed draft goes out as typed: markdown the tokenizer doesn't
+        // know (subtext, slash-command mentions, ...) would otherwise be
+        // escaped into literal text.
+        let content = contents
+            .draft
+            .unwrap_or_else(|| rich::to_markdown(&contents.content.text));
+        let msg = self
+            .rest_send_message(channel_location.channel_id(), content, reply_to, files)
+            .await?;
+
+        // Register the mapping immediately: reacting to (or paginating
+        // before) a just-sent message must not depend on the gateway
+        // MessageCreate echo arriving first.
+        self.message_id_mappings.insert(msg.id, msg.id);
+
+        let (content, history) = msg.revisions(|mention| self.mention_name(mention)).await;
+        let mentions_me = self.mentions_me(&msg);
+        let reply = msg.reply(|mention| self.mention_name(mention));
+        let attachments = msg.interface_attachments().await;
+        let icon = match &msg.author.avatar {
+            Some(hash) => CdnImage::avatar(msg.author.id, hash).fetch().await.ok(),
+            None => None,
+        };
+        let author = Identifier::new(
+            msg.author.id,
+            User {
+                name: msg.author.username,
+                icon,
+            },
+        );
+
+        Ok(Identifier::new(
+            msg.id,
+            Message {
+                content,
+                history,
+                reactions: Vec::new(),
+                attachments,
+                author: Some(author),
+                reply,
+                draft: None,
+                mentions_me,
+            },
+        ))
+    }
+}
+
 #[async_trait]
 impl Text for InnerDiscord<Owned> {
     async fn get_messages(
@@ -75,8 +147,13 @@ impl Text for InnerDiscord<Owned> {
             Ordering::Unordered => Box::new(messages.into_iter()),
         };
         let identifiers = join_all(messages_iter.map(async |message| {
-            let (content, history) = message.revisions().await;
+            let (content, history) = message
+                .revisions(|mention| self.mention_name(mention))
+                .await;
+            let mentions_me = self.mentions_me(&message);
             let reactions = message.interface_reactions().await;
+            let reply = message.reply(|mention| self.mention_name(mention));
+            let attachments = message.interface_attachments().await;
 
             let icon = match &message.author.avatar {
                 Some(hash) => CdnImage::avatar(message.author.id, hash).fetch().await.ok(),
@@ -96,7 +173,11 @@ impl Text for InnerDiscord<Owned> {
                     content,
                     history,
                     reactions,
+                    attachments,
                     author: Some(author),
+                    reply,
+                    draft: None,
+                    mentions_me,
                 },
             );
 
@@ -150,51 +231,179 @@ impl Text for InnerDiscord<Owned> {
             .await
     }
 
+    fn parse_draft(&self, draft: &str) -> RichText {
+        rich::parse_draft(draft, |mention| self.mention_name(mention))
+    }
+
+    fn write_draft(&self, text: &RichText) -> String {
+        rich::to_markdown(text)
+    }
+
     async fn send_message(
         &self,
         location: &Identifier<Place<Room>>,
         contents: Message,
+    ) -> Result<Identifier<Message>, Box<dyn Error + Sync + Send>> {
+        self.send(location, contents, Vec::new()).await
+    }
+
+    async fn send_files(
+        &self,
+        location: &Identifier<Place<Room>>,
+        contents: Message,
+        files: Vec<PathBuf>,
+    ) -> Result<Identifier<Message>, Box<dyn Error + Sync + Send>> {
+        let files = try_join_all(files.into_iter().map(async |path| {
+            let filename = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .ok_or_else(|| format!("Discord: {} has no file name", path.display()))?
+                .to_owned();
+            let contents = async_fs::read(&path)
+                .await
+                .map_err(|err| format!("Discord: reading {}: {err}", path.display()))?;
+            Ok::<_, Box<dyn Error + Sync + Send>>((filename, contents))
+        }))
+        .await?;
+        self.send(location, contents, files).await
+    }
+
+    fn can_edit(&self) -> bool {
+        true
+    }
+
+    async fn edit_message(
+        &self,
+        location: &Identifier<Place<Room>>,
+        message: &Identifier<Message>,
+        draft: &str,
     ) -> Result<Identifier<Message>, Box<dyn Error + Sync + Send>> {
         let channel_location = *self
             .channel_id_mappings
             .get(location.id())
             .ok_or("No discord channel id mapping for this room")?;
+        let msg_id = *self
+            .message_id_mappings
+            .get(message.id())
+            .ok_or("No discord message id mapping")?;
 
         let msg = self
-            .rest_send_message(
-                channel_location.channel_id(),
-                contents.content.text.to_plain(),
-            )
+            .rest_edit_message(channel_location.channel_id(), msg_id, draft.to_owned())
             .await?;
 
-        // Register the mapping immediately: reacting to (or paginating
-        // before) a just-sent message must not depend on the gateway
-        // MessageCreate echo arriving first.
-        self.message_id_mappings.insert(msg.id, msg.id);
+        // Discord doesn't return previous revisions, so build on the
+        // caller's copy: it keeps the reactions and the known history, and
+        // the text it had becomes the latest past revision.
+        let (content, _) = msg.revisions(|mention| self.mention_name(mention)).await;
+        let mut edited = message.clone();
+        edited.edit(content);
+        Ok(edited)
+    }
 
-        let (content, history) = msg.revisions().await;
-        let icon = match &msg.author.avatar {
-            Some(hash) => CdnImage::avatar(msg.author.id, hash).fetch().await.ok(),
-            None => None,
-        };
-        let author = Identifier::new(
-            msg.author.id,
-            User {
-                name: msg.author.username,
-                icon,
+    async fn delete_message(
+        &self,
+        location: &Identifier<Place<Room>>,
+        message: &Identifier<Message>,
+    ) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let channel_location = *self
+            .channel_id_mappings
+            .get(location.id())
+            .ok_or("No discord channel id mapping for this room")?;
+        let msg_id = *self
+            .message_id_mappings
+            .get(message.id())
+            .ok_or("No discord message id mapping")?;
+        self.rest_delete_message(channel_location.channel_id(), msg_id)
+            .await?;
+        self.message_id_mappings.remove(message.id());
+        Ok(())
+    }
+
+    /// Discord keeps no unread count, only the read marker. Messages since
+    /// `Ready` are counted as they come; a channel whose last message was
+    /// already past the marker reports at least one unread.
+    async fn read_states(&self) -> Result<HashMap<ID, ReadState>, Box<dyn Error + Sync + Send>> {
+        self.ensure_gateway().await?;
+        Ok(self
+            .read_states
+            .iter()
+            .map(|entry| {
+                let mut state = *entry.value();
+                if state.unread == 0 && self.last_message_id(*entry.key()) > state.last_read {
+                    state.unread = 1;
+                }
+                (*entry.key(), state)
+            })
+            .collect())
+    }
+
+    async fn mark_read(
+        &self,
+        location: &Identifier<Place<Room>>,
+        message: &Identifier<Message>,
+    ) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let channel_location = *self
+            .channel_id_mappings
+            .get(location.id())
+            .ok_or("No discord channel id mapping for this room")?;
+        let msg_id = *self
+            .message_id_mappings
+            .get(message.id())
+            .ok_or("No discord message id mapping")?;
+        let channel_id = channel_location.channel_id();
+        self.rest_ack_message(channel_id, msg_id).await?;
+        self.read_states.insert(
+            channel_id,
+            ReadState {
+                last_read: Some(msg_id),
+                ..Default::default()
             },
         );
+        Ok(())
+    }
 
-        Ok(Identifier::new(
-            msg.id,
-            Message {
-                content,
-                history,
-                reactions: Vec::new(),
-                author: Some(author),
-            },
+    async fn send_typing(
+        &self,
+        location: &Identifier<Place<Room>>,
+    ) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let channel_location = *self
+            .channel_id_mappings
+            .get(location.id())
+            .ok_or("No discord channel id mapping for this room")?;
+        self.rest_send_typing(channel_location.channel_id()).await
+    }
+
+    /// The cached DM with `user`, or a new one from REST. Discord hands back
+    /// the existing DM if there is one, so this never opens a second.
+    async fn direct_room(
+        &self,
+        user: ID,
+    ) -> Result<Identifier<Place<Room>>, Box<dyn Error + Sync + Send>> {
+        let cached = self.dm_channels.load().as_ref().and_then(|channels| {
+            channels
+                .iter()
+                .find(|channel| {
+                    matches!(channel.channel_type, api_types::ChannelTypes::DM)
+                        && channel
+                            .recipients
+                            .as_deref()
+                            .is_some_and(|recipients| recipients.iter().any(|r| r.id == user))
+                })
+                .cloned()
+        });
+        let channel = match cached {
+            Some(channel) => channel,
+            None => self.rest_open_dm(user).await?,
+        };
+        let location =
+            ChannelLocation::from_api(&channel, None).ok_or("DM channel has no location")?;
+        self.channel_id_mappings.insert(channel.id, location);
+        Ok(Discord::identifier_generator(
+            channel.id,
+            channel.to_room_data().await,
         ))
     }
+
     async fn listen(
         self: Arc<Self>,
     ) -> Result<WeakSocketStream<TextEvent>, Box<dyn Error + Sync + Send>> {
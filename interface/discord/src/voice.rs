@@ -5,18 +5,26 @@
 //! gateway and the per-call media setup in `gateways::voice`. The companion
 //! `ArcStream` impl drains the buffered `VoiceEvent` queue (the audio media
 //! loop itself lives in `gateways::voice::audio`).
-use std::{error::Error, io, sync::Arc};
+use std::{
+    error::Error,
+    io,
+    path::PathBuf,
+    sync::{Arc, atomic::Ordering},
+};
 
 use async_trait::async_trait;
 use messenger_interface::{
-    interface::{CallStatus, Voice as VoiceTrait, VoiceEvent},
+    interface::{CallStatus, RecordingSettings, Voice as VoiceTrait, VoiceEvent},
     stream::{ArcStream, WeakSocketStream},
     types::{Identifier, Place, Room},
 };
 use surf::http::convert::json;
-use tracing::{debug, error, warn};
+use tracing::{debug, error, info, warn};
 
-use crate::{InnerDiscord, Owned, StreamPollGuard, VoiceDiscord, gateways::general::Opcode};
+use crate::{
+    InnerDiscord, Owned, StreamPollGuard, VoiceDiscord,
+    gateways::{general::Opcode, voice::RecordingThread},
+};
 
 #[async_trait]
 impl VoiceTrait for InnerDiscord<Owned> {
@@ -58,8 +66,8 @@ impl VoiceTrait for InnerDiscord<Owned> {
             "d": {
                 "guild_id": channel.guild_id(),
                 "channel_id": channel.channel_id(),
-                "self_mute": false,
-                "self_deaf": false
+                "self_mute": self.self_mute.load(Ordering::Relaxed),
+                "self_deaf": self.self_deaf.load(Ordering::Relaxed)
               }
         });
         debug!("Sending opcode 4 (VoiceStateUpdate): {}", payload);
@@ -78,6 +86,11 @@ impl VoiceTrait for InnerDiscord<Owned> {
             return;
         };
         gateway.voice.disconnect().await;
+        match self.stop_recording().await {
+            Ok(paths) if !paths.is_empty() => info!("Call recording saved to {paths:?}"),
+            Ok(_) => {}
+            Err(err) => error!("Failed to finish the call recording: {err}"),
+        }
 
         let channel = match self.channel_id_mappings.get(location.id()) {
             Some(c) => c.clone(),
@@ -94,8 +107,8 @@ impl VoiceTrait for InnerDiscord<Owned> {
             "d": {
                 "guild_id": channel.guild_id(),
                 "channel_id": null,
-                "self_mute": false,
-                "self_deaf": false
+                "self_mute": self.self_mute.load(Ordering::Relaxed),
+                "self_deaf": self.self_deaf.load(Ordering::Relaxed)
               }
         });
 
@@ -104,6 +117,38 @@ impl VoiceTrait for InnerDiscord<Owned> {
         }
     }
 
+    async fn set_self_mute(&self, muted: bool) -> Result<(), Box<dyn Error + Sync + Send>> {
+        self.self_mute.store(muted, Ordering::Relaxed);
+        self.self_voice_toggled.notify_one();
+        self.send_self_voice_state().await
+    }
+
+    async fn set_self_deaf(&self, deafened: bool) -> Result<(), Box<dyn Error + Sync + Send>> {
+        self.self_deaf.store(deafened, Ordering::Relaxed);
+        self.self_voice_toggled.notify_one();
+        self.send_self_voice_state().await
+    }
+
+    async fn start_recording(
+        &self,
+        settings: RecordingSettings,
+    ) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let recorder = RecordingThread::start(settings)?;
+        let previous = self.recorder.lock().unwrap().replace(recorder);
+        if let Some(previous) = previous {
+            previous.finish().await?;
+        }
+        Ok(())
+    }
+
+    async fn stop_recording(&self) -> Result<Vec<PathBuf>, Box<dyn Error + Sync + Send>> {
+        let recorder = self.recorder.lock().unwrap().take();
+        Ok(match recorder {
+            Some(recorder) => recorder.finish().await?,
+            None => Vec::new(),
+        })
+    }
+
     async fn listen(
         self: Arc<Self>,
     ) -> Result<WeakSocketStream<VoiceEvent>, Box<dyn Error + Sync + Send>> {
@@ -111,6 +156,35 @@ impl VoiceTrait for InnerDiscord<Owned> {
     }
 }
 
+impl InnerDiscord<Owned> {
+    /// Re-send opcode 4 for the call we are in, if any, so the server (and
+    /// everyone in the call) picks up the mute and deafen toggles. The
+    /// resulting `VOICE_STATE_UPDATE` reports them back to the app.
+    async fn send_self_voice_state(&self) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let load_gateway = self.gateway.load();
+        let Some(gateway) = load_gateway.as_ref() else {
+            // Not online: the toggles go out with the next connect.
+            return Ok(());
+        };
+        let Some(channel) = gateway.voice.channel().await else {
+            return Ok(());
+        };
+
+        let payload = json!({
+            "op": Opcode::VoiceStateUpdate as u8,
+            "d": {
+                "guild_id": channel.guild_id(),
+                "channel_id": channel.channel_id(),
+                "self_mute": self.self_mute.load(Ordering::Relaxed),
+                "self_deaf": self.self_deaf.load(Ordering::Relaxed)
+              }
+        });
+        debug!("Sending opcode 4 (VoiceStateUpdate): {}", payload);
+        gateway.send(payload.to_string().into()).await?;
+        Ok(())
+    }
+}
+
 #[async_trait]
 impl ArcStream for InnerDiscord<VoiceDiscord> {
     type Item = VoiceEvent;
@@ -0,0 +1,1103 @@
+//! Adapter tests against the local fake Discord in `support`; no network or
+//! token required.
+
+use std::{sync::Arc, time::Duration};
+
+use discord::Discord;
+use futures::StreamExt;
+use messenger_interface::{
+    interface::{Messenger, Ordering, PresenceEvent, QueryEvent, TextEvent, WeakSocketStream},
+    types::{
+        Identifier, Mention, Message, Place, ReadState, Reply, Revision, RichText, Room,
+        RoomCapabilities, Span, Status, UserPresence,
+    },
+};
+use serde_json::json;
+
+mod support;
+use support::{
+    DM_CHANNEL_ID, DM_MESSAGE_IDS, FRIEND_ID, FakeDiscord, GUILD_ID, ROLE_ID, SELF_ID, TOKEN,
+    eventually, within,
+};
+
+fn ids<D>(identifiers: &[Identifier<D>]) -> Vec<u64> {
+    identifiers
+        .iter()
+        .map(|identifier| *identifier.id())
+        .collect()
+}
+
+/// The seeded DM, listed through `messenger` itself so it holds the channel
+/// mapping `get_messages` needs.
+async fn dm_room(messenger: &Arc<dyn Messenger>) -> Identifier<Place<Room>> {
+    let rooms = messenger.query().unwrap().rooms().await.unwrap();
+    rooms
+        .into_iter()
+        .find(|room| *room.id() == DM_CHANNEL_ID)
+        .expect("the seeded DM is listed")
+}
+
+async fn next_presence(events: &mut WeakSocketStream<PresenceEvent>) -> (u64, UserPresence) {
+    match within(events.next()).await {
+        Some(PresenceEvent::PresenceUpdated { user_id, presence }) => (user_id, presence),
+        None => panic!("the presence stream ended"),
+    }
+}
+
+#[test]
+fn rest_queries_authenticate_with_the_token() {
+    smol::block_on(async {
+        let server = FakeDiscord::start().await;
+        let query = server.messenger().arc_query().unwrap();
+
+        let me = within(query.client_user()).await.unwrap();
+        assert_eq!(*me.id(), SELF_ID);
+        assert_eq!(me.name, "me");
+
+        let contacts = within(query.contacts()).await.unwrap();
+        assert_eq!(ids(&contacts), [FRIEND_ID]);
+        assert_eq!(
+            server.requests(),
+            ["GET /users/@me", "GET /users/@me/relationships"]
+        );
+    });
+}
+
+#[test]
+fn rejected_token_surfaces_the_rest_error() {
+    smol::block_on(async {
+        let server = FakeDiscord::start().await;
+        let messenger = Discord::with_endpoints("not-the-token", server.endpoints());
+        let query = messenger.arc_query().unwrap();
+
+        let err = within(query.rooms()).await.unwrap_err();
+        assert!(err.to_string().contains("401"), "{err}");
+        // The Ready fallback could not identify either.
+        assert!(server.identified().is_empty());
+    });
+}
+
+#[test]
+fn identify_and_ready_seed_the_caches() {
+    smol::block_on(async {
+        let server = FakeDiscord::start().await;
+        let messenger = server.messenger();
+        let query = messenger.clone().arc_query().unwrap();
+        let mut events = within(query.clone().listen()).await.unwrap();
+        eventually(|| server.identified() == [TOKEN]).await;
+
+        // Ordered behind Ready on the same connection, so seeing it means
+        // Ready was handled.
+        server.dispatch(
+            "CHANNEL_CREATE",
+            json!({
+                "id": "503",
+                "guild_id": GUILD_ID.to_string(),
+                "type": 0,
+                "name": "announcements",
+                "position": 2,
+            }),
+        );
+        let Some(QueryEvent::ChannelCreated { r#where, room }) = within(events.next()).await else {
+            panic!("expected ChannelCreated");
+        };
+        assert_eq!(r#where.map(|house| *house.id()), Some(GUILD_ID));
+        assert_eq!(room.name, "announcements");
+
+        let houses = within(query.houses()).await.unwrap();
+        assert_eq!(ids(&houses), [GUILD_ID]);
+        let house = within(query.house_details(houses[0].clone()))
+            .await
+            .unwrap();
+        let names = house
+            .rooms
+            .iter()
+            .flatten()
+            .map(|room| room.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["general", "voice", "announcements"]);
+
+        // Served from the Ready cache, never from REST.
+        assert!(server.requests().is_empty(), "{:?}", server.requests());
+    });
+}
+
+#[test]
+fn messages_are_fetched_oldest_first_and_paginated() {
+    smol::block_on(async {
+        let server = FakeDiscord::start().await;
+        let messenger = server.messenger();
+        let room = within(dm_room(&messenger)).await;
+        let text = messenger.arc_text().unwrap();
+
+        let messages = within(text.get_messages(&room, None, Ordering::Time))
+            .await
+            .unwrap();
+        assert_eq!(ids(&messages), DM_MESSAGE_IDS);
+        assert_eq!(messages[0].content.text.to_plain(), "message 1");
+
+        let newest_first = within(text.get_messages(&room, None, Ordering::Unordered))
+            .await
+            .unwrap();
+        assert_eq!(ids(&newest_first), [1003, 1002, 1001]);
+
+        let older = within(text.get_messages(&room, Some(messages[1].clone()), Ordering::Time))
+            .await
+            .unwrap();
+        assert_eq!(ids(&older), [1001]);
+        assert!(server.requests().contains(&format!(
+            "GET /channels/{DM_CHANNEL_ID}/messages?before=1002"
+        )));
+    });
+}
+
+#[test]
+fn own_messages_are_edited_and_deleted_over_rest() {
+    smol::block_on(async {
+        let server = FakeDiscord::start().await;
+        let messenger = server.messenger();
+        let room = within(dm_room(&messenger)).await;
+        let text = messenger.arc_text().unwrap();
+
+        let outgoing = Message {
+            content: Revision {
+                at: None,
+                text: RichText::plain("helo"),
+            },
+            ..Default::default()
+        };
+        assert!(text.can_edit());
+        let sent = within(text.send_message(&room, outgoing)).await.unwrap();
+        let edited = within(text.edit_message(&room, &sent, "hello"))
+            .await
+            .unwrap();
+        assert_eq!(edited.id(), sent.id());
+        assert_eq!(edited.content.text.to_plain(), "hello");
+        assert_eq!(edited.history.last().unwrap().text.to_plain(), "helo");
+
+        // The fake, like Discord, refuses edits to someone else's message.
+        let messages = within(text.get_messages(&room, None, Ordering::Time))
+            .await
+            .unwrap();
+        let theirs = &messages[0];
+        let err = within(text.edit_message(&room, theirs, "no"))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("403"), "{err}");
+
+        within(text.delete_message(&room, &edited)).await.unwrap();
+        let messages = within(text.get_messages(&room, None, Ordering::Time))
+            .await
+            .unwrap();
+        assert_eq!(ids(&messages), DM_MESSAGE_IDS);
+        let sent_id = sent.id();
+        assert!(server.requests().ends_with(&[
+            format!(
+                "PATCH /channels/{DM_CHANNEL_ID}/messages/{}",
+                DM_MESSAGE_IDS[0]
+            ),
+            format!("DELETE /channels/{DM_CHANNEL_ID}/messages/{sent_id}"),
+            format!("GET /channels/{DM_CHANNEL_ID}/messages"),
+        ]));
+    });
+}
+
+#[test]
+fn replies_reference_and_quote_their_target() {
+    smol::block_on(async {
+        let server = FakeDiscord::start().await;
+        let messenger = server.messenger();
+        let room = within(dm_room(&messenger)).await;
+        let text = messenger.arc_text().unwrap();
+        // Registers the message ID mappings the reply is resolved through.
+        within(text.get_messages(&room, None, Ordering::Time))
+            .await
+            .unwrap();
+
+        let reply = Message {
+            content: Revision {
+                at: None,
+                text: RichText::plain("agreed"),
+            },
+            reply: Some(Reply {
+                message_id: DM_MESSAGE_IDS[1],
+                preview: None,
+            }),
+            ..Default::default()
+        };
+        let sent = within(text.send_message(&room, reply)).await.unwrap();
+        let reply = sent.reply.as_ref().expect("sent as a reply");
+        assert_eq!(reply.message_id, DM_MESSAGE_IDS[1]);
+        let preview = reply.preview.as_ref().unwrap();
+        assert_eq!(preview.author, "alice");
+        assert_eq!(preview.text, "message 2");
+
+        // Fetched history carries the reference too.
+        let messages = within(text.get_messages(&room, None, Ordering::Time))
+            .await
+            .unwrap();
+        let fetched = messages.last().unwrap();
+        assert_eq!(fetched.id(), sent.id());
+        assert_eq!(
+            fetched.reply.as_ref().map(|reply| reply.message_id),
+            Some(DM_MESSAGE_IDS[1])
+        );
+        assert!(messages[0].reply.is_none());
+    });
+}
+
+#[test]
+fn files_are_uploaded_as_multipart_attachments() {
+    smol::block_on(async {
+        let server = FakeDiscord::start().await;
+        let messenger = server.messenger();
+        let room = within(dm_room(&messenger)).await;
+        let text = messenger.arc_text().unwrap();
+        let file =
+            std::env::temp_dir().join(format!("fake-discord-upload-{}.txt", std::process::id()));
+        std::fs::write(&file, "twelve bytes").unwrap();
+
+        let caption = Message {
+            content: Revision {
+                at: None,
+                text: RichText::plain("see attached"),
+            },
+            ..Default::default()
+        };
+        let sent = within(text.send_files(&room, caption, vec![file.clone()])).await;
+        std::fs::remove_file(&file).unwrap();
+
+        let sent = sent.unwrap();
+        assert_eq!(sent.content.text.to_plain(), "see attached");
+        let [attachment] = sent.attachments.as_slice() else {
+            panic!("expected one attachment, got {:?}", sent.attachments);
+        };
+        assert_eq!(
+            attachment.filename,
+            file.file_name().unwrap().to_str().unwrap()
+        );
+        assert_eq!(attachment.size, 12);
+        // Only images are downloaded.
+        assert!(attachment.path.is_none());
+    });
+}
+
+#[test]
+fn typing_is_sent_and_reported_for_others_only() {
+    smol::block_on(async {
+        let server = FakeDiscord::start().await;
+        let messenger = server.messenger();
+        let room = within(dm_room(&messenger)).await;
+        let text = messenger.clone().arc_text().unwrap();
+        let mut events = within(text.clone().listen()).await.unwrap();
+        eventually(|| server.identified() == [TOKEN]).await;
+
+        within(text.send_typing(&room)).await.unwrap();
+        assert!(
+            server
+                .requests()
+                .contains(&format!("POST /channels/{DM_CHANNEL_ID}/typing"))
+        );
+
+        // The client's own typing echoes back and is dropped; a DM carries
+        // no member, so the name comes from the channel's recipients.
+        for user_id in [SELF_ID, FRIEND_ID] {
+            server.dispatch(
+                "TYPING_START",
+                json!({
+                    "channel_id": DM_CHANNEL_ID.to_string(),
+                    "user_id": user_id.to_string(),
+                    "timestamp": 1767225600,
+                }),
+            );
+        }
+        match within(events.next()).await {
+            Some(TextEvent::TypingStarted { room, user }) => {
+                assert_eq!(*room.id(), DM_CHANNEL_ID);
+                assert_eq!(*user.id(), FRIEND_ID);
+                assert_eq!(user.name, "alice");
+            }
+            _ => panic!("expected TypingStarted"),
+        }
+    });
+}
+
+#[test]
+fn drafts_go_out_as_markdown() {
+    smol::block_on(async {
+        let server = FakeDiscord::start().await;
+        let messenger = server.messenger();
+        let room = within(dm_room(&messenger)).await;
+        let text = messenger.arc_text().unwrap();
+
+        let draft = r"**hi** `a*b` \*not italic\*";
+        let rich = text.parse_draft(draft);
+        assert_eq!(
+            rich.to_plain(),
+            "hi a*b *not italic*",
+            "parsed as markdown: {rich:?}"
+        );
+        let outgoing = Message {
+            content: Revision {
+                at: None,
+                text: rich.clone(),
+            },
+            ..Default::default()
+        };
+        // The fake echoes the content it was sent, so it reads back as sent.
+        let sent = within(text.send_message(&room, outgoing)).await.unwrap();
+        assert_eq!(sent.content.text, rich);
+        assert_eq!(text.write_draft(&sent.content.text), draft);
+
+        // Typed drafts go out as typed, markup the parser doesn't know
+        // included, instead of being written back from the parsed text.
+        let draft = r"-# small </cmd:123> \<3";
+        let outgoing = Message {
+            content: Revision {
+                at: None,
+                text: text.parse_draft(draft),
+            },
+            draft: Some(draft.to_owned()),
+            ..Default::default()
+        };
+        let sent = within(text.send_message(&room, outgoing)).await.unwrap();
+        let content = || server.content(DM_CHANNEL_ID, *sent.id());
+        assert_eq!(content().as_deref(), Some(draft));
+        within(text.edit_message(&room, &sent, r"C\#"))
+            .await
+            .unwrap();
+        assert_eq!(content().as_deref(), Some(r"C\#"));
+    });
+}
+
+#[test]
+fn mentions_are_named_from_the_caches_and_flag_the_client() {
+    smol::block_on(async {
+        let server = FakeDiscord::start().await;
+        let messenger = server.messenger();
+        let text = messenger.clone().arc_text().unwrap();
+        let mut events = within(text.clone().listen()).await.unwrap();
+        eventually(|| server.identified() == [TOKEN]).await;
+
+        let me = json!({ "id": SELF_ID.to_string(), "username": "me", "avatar": null });
+        let alice = json!({ "id": FRIEND_ID.to_string(), "username": "alice", "avatar": null });
+        let mentioning = format!("<@{SELF_ID}> <@{FRIEND_ID}> see <#501>, <@&{ROLE_ID}> <@9>");
+        let role_only = format!("<@&{ROLE_ID}>");
+        let role = json!([ROLE_ID.to_string()]);
+        for (id, author, content, mentions, mention_roles, everyone) in [
+            (2001, &alice, mentioning, json!([me]), json!([]), false),
+            (2002, &alice, "hi".into(), json!([]), json!([]), false),
+            // The client holds the role (see `merged_members`).
+            (2003, &alice, role_only, json!([]), role, false),
+            // The client's own @everyone doesn't flag it.
+            (2004, &me, "@everyone".into(), json!([]), json!([]), true),
+        ] {
+            server.dispatch(
+                "MESSAGE_CREATE",
+                json!({
+                    "id": id.to_string(),
+                    "channel_id": "501",
+                    "author": author,
+                    "content": content,
+                    "timestamp": "2026-01-01T00:00:00+00:00",
+                    "edited_timestamp": null,
+                    // Discord sends the mentioned users along; alice isn't
+                    // among them here, so her name comes from the caches.
+                    "mentions": mentions,
+                    "mention_roles": mention_roles,
+                    "mention_everyone": everyone,
+                }),
+            );
+        }
+        for (id, mentions_me) in [(2001, true), (2002, false), (2003, true), (2004, false)] {
+            let Some(TextEvent::MessageCreated { message, .. }) = within(events.next()).await
+            else {
+                panic!("expected MessageCreated");
+            };
+            assert_eq!(message.mentions_me, mentions_me);
+            if id == 2001 {
+                assert_eq!(
+                    message.content.text.to_plain(),
+                    "@me @alice see #general, @mods @unknown-user"
+                );
+                assert!(matches!(
+                    message.content.text.spans[4],
+                    Span::Mention {
+                        target: Mention::Channel(501),
+                        ..
+                    }
+                ));
+            }
+        }
+
+        // A guild joined after Ready lists the client among its members.
+        server.dispatch(
+            "GUILD_CREATE",
+            json!({
+                "id": "600",
+                "properties": { "id": "600", "name": "Second Guild", "icon": null },
+                "channels": [{ "id": "601", "type": 0, "name": "lobby", "position": 0 }],
+                "members": [{ "user": me, "roles": ["610"] }],
+                "voice_states": [],
+            }),
+        );
+        server.dispatch(
+            "MESSAGE_CREATE",
+            json!({
+                "id": "2005",
+                "channel_id": "601",
+                "author": alice,
+                "content": "<@&610>",
+                "timestamp": "2026-01-01T00:00:00+00:00",
+                "edited_timestamp": null,
+                "mentions": [],
+                "mention_roles": ["610"],
+                "mention_everyone": false,
+            }),
+        );
+        let Some(TextEvent::MessageCreated { message, .. }) = within(events.next()).await else {
+            panic!("expected MessageCreated");
+        };
+        assert!(message.mentions_me);
+    });
+}
+
+#[test]
+fn direct_rooms_are_reused_or_opened() {
+    smol::block_on(async {
+        let server = FakeDiscord::start().await;
+        let messenger = server.messenger();
+        let text = messenger.clone().arc_text().unwrap();
+
+        let existing = within(text.direct_room(FRIEND_ID)).await.unwrap();
+        assert_eq!(*existing.id(), DM_CHANNEL_ID);
+        let opened = within(text.direct_room(3)).await.unwrap();
+        assert_eq!(opened.name, "stranger");
+        // The new DM is usable right away.
+        within(text.send_typing(&opened)).await.unwrap();
+        assert!(server.requests().ends_with(&[
+            "POST /users/@me/channels".to_owned(),
+            format!("POST /channels/{}/typing", opened.id()),
+        ]));
+    });
+}
+
+#[test]
+fn read_states_are_seeded_acked_and_marked() {
+    smol::block_on(async {
+        let server = FakeDiscord::start().await;
+        let messenger = server.messenger();
+        let room = within(dm_room(&messenger)).await;
+        let text = messenger.clone().arc_text().unwrap();
+        let mut events = within(text.clone().listen()).await.unwrap();
+        eventually(|| server.identified() == [TOKEN]).await;
+
+        // Read on another device.
+        server.dispatch(
+            "MESSAGE_ACK",
+            json!({
+                "channel_id": DM_CHANNEL_ID.to_string(),
+                "message_id": DM_MESSAGE_IDS[0].to_string(),
+                "mention_count": 2,
+                "version": 7,
+            }),
+        );
+        let acked = ReadState {
+            last_read: Some(DM_MESSAGE_IDS[0]),
+            unread: 0,
+            mentions: 2,
+        };
+        match within(events.next()).await {
+            Some(TextEvent::ReadStateUpdated { room, state }) => {
+                assert_eq!(*room.id(), DM_CHANNEL_ID);
+                assert_eq!(state, acked);
+            }
+            _ => panic!("expected ReadStateUpdated"),
+        }
+
+        // Channels whose last message is past the marker count as unread;
+        // Ready's non-channel entry is left out.
+        let states = within(text.read_states()).await.unwrap();
+        assert_eq!(states.len(), 2);
+        assert_eq!(states[&DM_CHANNEL_ID], ReadState { unread: 1, ..acked });
+        assert_eq!(
+            states[&501],
+            ReadState {
+                last_read: Some(5009),
+                unread: 1,
+                mentions: 1,
+            }
+        );
+
+        // New messages count on top, mentions too.
+        let me = json!({ "id": SELF_ID.to_string(), "username": "me", "avatar": null });
+        let friend = json!({ "id": FRIEND_ID.to_string(), "username": "alice", "avatar": null });
+        let mut newest = None;
+        for (id, mentions) in [(1004, json!([me])), (1005, json!([]))] {
+            server.dispatch(
+                "MESSAGE_CREATE",
+                json!({
+                    "id": id.to_string(),
+                    "channel_id": DM_CHANNEL_ID.to_string(),
+                    "author": friend,
+                    "content": "hey",
+                    "timestamp": "2026-01-01T00:00:00+00:00",
+                    "edited_timestamp": null,
+                    "mentions": mentions,
+                    "mention_roles": [],
+                    "mention_everyone": false,
+                }),
+            );
+            let Some(TextEvent::MessageCreated { message, .. }) = within(events.next()).await
+            else {
+                panic!("expected MessageCreated");
+            };
+            newest = Some(message);
+        }
+        let states = within(text.read_states()).await.unwrap();
+        assert_eq!(
+            states[&DM_CHANNEL_ID],
+            ReadState {
+                unread: 3,
+                mentions: 3,
+                ..acked
+            }
+        );
+
+        within(text.mark_read(&room, &newest.unwrap()))
+            .await
+            .unwrap();
+        assert!(
+            server
+                .requests()
+                .contains(&format!("POST /channels/{DM_CHANNEL_ID}/messages/1005/ack"))
+        );
+        let states = within(text.read_states()).await.unwrap();
+        assert!(!states[&DM_CHANNEL_ID].is_unread());
+        assert_eq!(states[&DM_CHANNEL_ID].last_read, Some(1005));
+    });
+}
+
+#[test]
+fn presences_are_seeded_updated_and_set() {
+    smol::block_on(async {
+        let server = FakeDiscord::start().await;
+        let messenger = server.messenger();
+        let presence = messenger.clone().arc_presence().unwrap();
+        let mut events = within(presence.clone().listen()).await.unwrap();
+
+        // Ready's presences come through the stream like any later change.
+        let online = UserPresence {
+            status: Status::Online,
+            activity: Some("Playing Fake Game".to_owned()),
+        };
+        let (user_id, seeded) = next_presence(&mut events).await;
+        assert_eq!((user_id, &seeded), (FRIEND_ID, &online));
+        assert_eq!(
+            within(presence.presences()).await.unwrap().get(&FRIEND_ID),
+            Some(&online)
+        );
+
+        // A custom status wins over the game; guild-scoped copies are
+        // dropped, so the guild one below never arrives.
+        server.dispatch(
+            "PRESENCE_UPDATE",
+            json!({
+                "user": { "id": FRIEND_ID.to_string() },
+                "guild_id": GUILD_ID.to_string(),
+                "status": "offline",
+                "activities": [],
+            }),
+        );
+        server.dispatch(
+            "PRESENCE_UPDATE",
+            json!({
+                "user": { "id": FRIEND_ID.to_string() },
+                "status": "idle",
+                "activities": [
+                    { "name": "Fake Game", "type": 0 },
+                    { "name": "Custom Status", "type": 4, "state": "brb" },
+                ],
+            }),
+        );
+        let (user_id, idle) = next_presence(&mut events).await;
+        assert_eq!(user_id, FRIEND_ID);
+        assert_eq!(idle.status, Status::Idle);
+        assert_eq!(idle.activity.as_deref(), Some("brb"));
+
+        within(presence.set_status(Status::Offline)).await.unwrap();
+        eventually(|| server.statuses() == ["invisible"]).await;
+        let (user_id, own) = next_presence(&mut events).await;
+        assert_eq!((user_id, own.status), (SELF_ID, Status::Invisible));
+    });
+}
+
+#[test]
+fn events_during_a_fetch_are_folded_into_the_snapshot() {
+    smol::block_on(async {
+        let server = FakeDiscord::start().await;
+        let messenger = server.messenger();
+        let room = within(dm_room(&messenger)).await;
+        let text = messenger.clone().arc_text().unwrap();
+        let mut events = within(text.clone().listen()).await.unwrap();
+        eventually(|| server.identified() == [TOKEN]).await;
+
+        let held = server.hold_next_message_fetch();
+        let fetch = smol::spawn({
+            let text = text.clone();
+            let room = room.clone();
+            async move { text.get_messages(&room, None, Ordering::Time).await }
+        });
+        within(held.arrived).await.unwrap();
+
+        // The snapshot still holds 1002 and no reaction on 1003.
+        server.delete_message(DM_CHANNEL_ID, 1002);
+        server.dispatch(
+            "MESSAGE_REACTION_ADD",
+            json!({
+                "user_id": FRIEND_ID.to_string(),
+                "channel_id": DM_CHANNEL_ID.to_string(),
+                "message_id": "1003",
+                "emoji": { "id": null, "name": "👍" },
+            }),
+        );
+        assert!(matches!(
+            within(events.next()).await,
+            Some(TextEvent::MessageDeleted {
+                message_id: 1002,
+                ..
+            })
+        ));
+        assert!(matches!(
+            within(events.next()).await,
+            Some(TextEvent::ReactionAdded {
+                message_id: 1003,
+                ..
+            })
+        ));
+        held.release.send(()).unwrap();
+
+        let messages = within(fetch).await.unwrap();
+        assert_eq!(ids(&messages), [1001, 1003]);
+        let reactions = &messages[1].reactions;
+        assert_eq!(reactions.len(), 1);
+        assert_eq!(reactions[0].emoji.shortcode, "👍");
+        assert_eq!(reactions[0].count, 1);
+        assert!(!reactions[0].reacted);
+    });
+}
+
+#[test]
+fn dropping_the_messenger_closes_the_gateway() {
+    smol::block_on(async {
+        let server = FakeDiscord::start().await;
+        let messenger = server.messenger();
+        let weak = Arc::downgrade(&messenger);
+
+        let query = within(messenger.clone().arc_query().unwrap().listen())
+            .await
+            .unwrap();
+        let text = within(messenger.clone().arc_text().unwrap().listen())
+            .await
+            .unwrap();
+        let voice = within(messenger.clone().arc_voice().unwrap().listen())
+            .await
+            .unwrap();
+        eventually(|| server.open_gateways() == 1).await;
+
+        drop(messenger);
+        drop((query, text, voice));
+
+        assert!(weak.upgrade().is_none());
+        eventually(|| server.open_gateways() == 0).await;
+    });
+}
+
+#[test]
+fn in_flight_stream_ends_once_the_owner_is_gone() {
+    smol::block_on(async {
+        let server = FakeDiscord::start().await;
+        let messenger = server.messenger();
+        let weak = Arc::downgrade(&messenger);
+        let mut events = within(messenger.clone().arc_text().unwrap().listen())
+            .await
+            .unwrap();
+        eventually(|| server.identified() == [TOKEN]).await;
+
+        let poller = smol::spawn(async move { events.next().await.is_none() });
+        // Let the poll park on the gateway while the owner still exists.
+        smol::Timer::after(Duration::from_millis(50)).await;
+        drop(messenger);
+
+        // Any gateway traffic wakes the parked poll, which must notice it is
+        // the only thing left holding the messenger.
+        server.dispatch(
+            "MESSAGE_DELETE",
+            json!({ "id": "1001", "channel_id": DM_CHANNEL_ID.to_string() }),
+        );
+        assert!(within(poller).await, "the stream ended");
+        assert!(weak.upgrade().is_none());
+        eventually(|| server.open_gateways() == 0).await;
+    });
+}
+
+#[test]
+fn dropped_gateway_resumes_and_replays_missed_events() {
+    smol::block_on(async {
+        let server = FakeDiscord::start().await;
+        let messenger = server.messenger();
+        let mut events = within(messenger.clone().arc_text().unwrap().listen())
+            .await
+            .unwrap();
+        eventually(|| server.identified() == [TOKEN]).await;
+
+        server.sever_gateways();
+        eventually(|| server.open_gateways() == 0).await;
+        // Sent while nobody is connected; only the resume replay delivers it.
+        server.dispatch(
+            "MESSAGE_DELETE",
+            json!({ "id": "1002", "channel_id": DM_CHANNEL_ID.to_string() }),
+        );
+
+        assert!(matches!(
+            within(events.next()).await,
+            Some(TextEvent::MessageDeleted {
+                message_id: 1002,
+                ..
+            })
+        ));
+        // Ready was sequence 1; the delete came after it.
+        assert_eq!(server.resumed(), [1]);
+        assert_eq!(server.identified(), [TOKEN]);
+    });
+}
+
+#[test]
+fn invalidated_session_identifies_again() {
+    smol::block_on(async {
+        let server = FakeDiscord::start().await;
+        let messenger = server.messenger();
+        let mut events = within(messenger.clone().arc_text().unwrap().listen())
+            .await
+            .unwrap();
+        eventually(|| server.identified() == [TOKEN]).await;
+
+        let poller = smol::spawn(async move { events.next().await });
+        server.invalidate_session();
+        eventually(|| server.identified() == [TOKEN, TOKEN]).await;
+
+        server.dispatch(
+            "MESSAGE_DELETE",
+            json!({ "id": "1001", "channel_id": DM_CHANNEL_ID.to_string() }),
+        );
+        assert!(matches!(
+            within(poller).await,
+            Some(TextEvent::MessageDeleted {
+                message_id: 1001,
+                ..
+            })
+        ));
+        assert!(server.resumed().is_empty());
+    });
+}
+
+#[test]
+fn session_timeout_close_identifies_again() {
+    smol::block_on(async {
+        let server = FakeDiscord::start().await;
+        let messenger = server.messenger();
+        let mut events = within(messenger.clone().arc_text().unwrap().listen())
+            .await
+            .unwrap();
+        eventually(|| server.identified() == [TOKEN]).await;
+
+        let poller = smol::spawn(async move { events.next().await });
+        // 4009 Session timed out: the session can't be resumed.
+        server.close_gateways(4009);
+        eventually(|| server.identified() == [TOKEN, TOKEN]).await;
+
+        server.dispatch(
+            "MESSAGE_DELETE",
+            json!({ "id": "1002", "channel_id": DM_CHANNEL_ID.to_string() }),
+        );
+        assert!(matches!(
+            within(poller).await,
+            Some(TextEvent::MessageDeleted {
+                message_id: 1002,
+                ..
+            })
+        ));
+        assert!(server.resumed().is_empty());
+    });
+}
+
+#[test]
+fn refused_session_ends_the_streams_until_listen_again() {
+    smol::block_on(async {
+        let server = FakeDiscord::start().await;
+        let messenger = server.messenger();
+        let mut events = within(messenger.clone().arc_text().unwrap().listen())
+            .await
+            .unwrap();
+        eventually(|| server.identified() == [TOKEN]).await;
+
+        let poller = smol::spawn(async move { events.next().await });
+        // 4004 Authentication failed: reconnecting can't help.
+        server.close_gateways(4004);
+        assert!(within(poller).await.is_none());
+        assert_eq!(server.identified(), [TOKEN]);
+
+        let mut events = within(messenger.clone().arc_text().unwrap().listen())
+            .await
+            .unwrap();
+        eventually(|| server.identified() == [TOKEN, TOKEN]).await;
+        server.dispatch(
+            "MESSAGE_DELETE",
+            json!({ "id": "1002", "channel_id": DM_CHANNEL_ID.to_string() }),
+        );
+        assert!(matches!(
+            within(events.next()).await,
+            Some(TextEvent::MessageDeleted {
+                message_id: 1002,
+                ..
+            })
+        ));
+    });
+}
+
+#[test]
+fn channel_and_guild_events_keep_the_caches_fresh() {
+    smol::block_on(async {
+        let server = FakeDiscord::start().await;
+        let messenger = server.messenger();
+        let query = messenger.clone().arc_query().unwrap();
+        let mut events = within(query.clone().listen()).await.unwrap();
+        eventually(|| server.identified() == [TOKEN]).await;
+        let room_names = async |house| {
+            let house = within(query.house_details(house)).await.unwrap();
+            house
+                .rooms
+                .iter()
+                .flatten()
+                .map(|room| room.name.clone())
+                .collect::<Vec<_>>()
+        };
+
+        server.dispatch(
+            "CHANNEL_UPDATE",
+            json!({
+                "id": "501",
+                "guild_id": GUILD_ID.to_string(),
+                "type": 0,
+                "name": "chat",
+                "position": 0,
+            }),
+        );
+        server.dispatch(
+            "CHANNEL_DELETE",
+            json!({ "id": "502", "guild_id": GUILD_ID.to_string(), "type": 2 }),
+        );
+        let Some(QueryEvent::ChannelUpdated { room, .. }) = within(events.next()).await else {
+            panic!("expected ChannelUpdated");
+        };
+        assert_eq!((*room.id(), room.name.as_str()), (501, "chat"));
+        assert!(matches!(
+            within(events.next()).await,
+            Some(QueryEvent::ChannelDeleted { room_id: 502, .. })
+        ));
+        // Both events came after Ready, so this is served from its cache.
+        let houses = within(query.houses()).await.unwrap();
+        assert_eq!(room_names(houses[0].clone()).await, ["chat"]);
+
+        server.dispatch(
+            "GUILD_CREATE",
+            json!({
+                "id": "600",
+                "properties": { "id": "600", "name": "Second Guild", "icon": null },
+                "channels": [{ "id": "601", "type": 0, "name": "lobby", "position": 0 }],
+                "members": [],
+                "voice_states": [],
+            }),
+        );
+        let Some(QueryEvent::HouseJoined { house }) = within(events.next()).await else {
+            panic!("expected HouseJoined");
+        };
+        assert_eq!(house.name, "Second Guild");
+        assert_eq!(room_names(house).await, ["lobby"]);
+
+        server.dispatch("GUILD_DELETE", json!({ "id": GUILD_ID.to_string() }));
+        assert!(matches!(
+            within(events.next()).await,
+            Some(QueryEvent::HouseLeft { house_id: GUILD_ID })
+        ));
+        assert_eq!(ids(&within(query.houses()).await.unwrap()), [600]);
+        // Still served from the gateway caches.
+        assert!(server.requests().is_empty(), "{:?}", server.requests());
+    });
+}
+
+#[test]
+fn threads_are_listed_under_their_channel_and_kept_fresh() {
+    smol::block_on(async {
+        let server = FakeDiscord::start().await;
+        let messenger = server.messenger();
+        let query = messenger.clone().arc_query().unwrap();
+        let mut events = within(query.clone().listen()).await.unwrap();
+        eventually(|| server.identified() == [TOKEN]).await;
+
+        server.dispatch(
+            "CHANNEL_CREATE",
+            json!({
+                "id": "530",
+                "guild_id": GUILD_ID.to_string(),
+                "type": 15,
+                "name": "help",
+                "position": 2,
+            }),
+        );
+        assert!(matches!(
+            within(events.next()).await,
+            Some(QueryEvent::ChannelCreated { .. })
+        ));
+        let houses = within(query.houses()).await.unwrap();
+        let rooms = within(query.house_details(houses[0].clone()))
+            .await
+            .unwrap()
+            .rooms
+            .unwrap();
+        let room = |id| rooms.iter().find(|room| *room.id() == id).unwrap();
+        let (general, forum) = (room(501), room(530));
+        assert!(
+            forum.room_capabilities.contains(RoomCapabilities::Threads)
+                && !forum.room_capabilities.contains(RoomCapabilities::Text)
+        );
+
+        // Active threads come from Ready; forum posts from the search.
+        let threads = within(query.threads(general)).await.unwrap();
+        assert_eq!(ids(&threads), [520]);
+        assert_eq!(
+            (threads[0].name.as_str(), threads[0].parent),
+            ("plans", Some(501))
+        );
+        assert!(
+            threads[0]
+                .room_capabilities
+                .contains(RoomCapabilities::Text)
+        );
+        assert!(server.requests().is_empty(), "{:?}", server.requests());
+        let posts = within(query.threads(forum)).await.unwrap();
+        assert_eq!(ids(&posts), [531]);
+        assert_eq!(posts[0].parent, Some(530));
+        assert!(
+            server.requests()[0].starts_with("GET /channels/530/threads/search?archived=false"),
+            "{:?}",
+            server.requests()
+        );
+
+        let thread = |id: &str, archived: bool| {
+            json!({
+                "id": id,
+                "guild_id": GUILD_ID.to_string(),
+                "type": 11,
+                "name": format!("thread {id}"),
+                "parent_id": "501",
+                "thread_metadata": { "archived": archived },
+            })
+        };
+        server.dispatch("THREAD_CREATE", thread("521", false));
+        let Some(QueryEvent::ChannelCreated { r#where, room }) = within(events.next()).await else {
+            panic!("expected ChannelCreated");
+        };
+        assert_eq!(r#where.map(|house| *house.id()), Some(GUILD_ID));
+        assert_eq!((*room.id(), room.parent), (521, Some(501)));
+
+        server.dispatch("THREAD_UPDATE", thread("520", true));
+        assert!(matches!(
+            within(events.next()).await,
+            Some(QueryEvent::ChannelDeleted { room_id: 520, .. })
+        ));
+        // Forum posts come over REST and aren't cached, but still close.
+        server.dispatch(
+            "THREAD_UPDATE",
+            json!({
+                "id": "531",
+                "guild_id": GUILD_ID.to_string(),
+                "type": 11,
+                "name": "post",
+                "parent_id": "530",
+                "thread_metadata": { "archived": true },
+            }),
+        );
+        assert!(matches!(
+            within(events.next()).await,
+            Some(QueryEvent::ChannelDeleted { room_id: 531, .. })
+        ));
+        server.dispatch(
+            "THREAD_DELETE",
+            json!({
+                "id": "521",
+                "guild_id": GUILD_ID.to_string(),
+                "parent_id": "501",
+                "type": 11,
+            }),
+        );
+        assert!(matches!(
+            within(events.next()).await,
+            Some(QueryEvent::ChannelDeleted { room_id: 521, .. })
+        ));
+        server.dispatch(
+            "THREAD_LIST_SYNC",
+            json!({
+                "guild_id": GUILD_ID.to_string(),
+                "channel_ids": ["501"],
+                "threads": [thread("522", false)],
+                "members": [],
+            }),
+        );
+        let Some(QueryEvent::ChannelCreated { room, .. }) = within(events.next()).await else {
+            panic!("expected ChannelCreated");
+        };
+        assert_eq!(*room.id(), 522);
+
+        let threads = within(query.threads(general)).await.unwrap();
+        assert_eq!(ids(&threads), [522]);
+    });
+}
+
+#[test]
+fn relationship_events_update_the_contacts() {
+    smol::block_on(async {
+        let server = FakeDiscord::start().await;
+        let messenger = server.messenger();
+        let query = messenger.clone().arc_query().unwrap();
+        let mut events = within(query.clone().listen()).await.unwrap();
+        eventually(|| server.identified() == [TOKEN]).await;
+
+        server.dispatch(
+            "RELATIONSHIP_ADD",
+            json!({
+                "id": "3",
+                "type": 1,
+                "user": { "id": "3", "username": "bob", "avatar": null },
+            }),
+        );
+        let Some(QueryEvent::ContactAdded { user }) = within(events.next()).await else {
+            panic!("expected ContactAdded");
+        };
+        assert_eq!((*user.id(), user.name.as_str()), (3, "bob"));
+        assert_eq!(
+            ids(&within(query.contacts()).await.unwrap()),
+            [FRIEND_ID, 3]
+        );
+
+        server.dispatch(
+            "RELATIONSHIP_REMOVE",
+            json!({ "id": FRIEND_ID.to_string(), "type": 1 }),
+        );
+        assert!(matches!(
+            within(events.next()).await,
+            Some(QueryEvent::ContactRemoved { user_id: FRIEND_ID })
+        ));
+        assert_eq!(ids(&within(query.contacts()).await.unwrap()), [3]);
+    });
+}
@@ -0,0 +1,850 @@
+//! A local fake Discord for offline adapter tests.
+//!
+//! [`FakeDiscord::start`] binds two loopback listeners: a plain-HTTP REST
+//! server and a websocket gateway speaking the JSON gateway protocol (Hello,
+//! Identify, Ready, Dispatch, Heartbeat/HeartbeatAck, Resume, InvalidSession).
+//! Both serve one small seeded account, and the messenger is pointed at them through
+//! [`Endpoints`]. Tests drive the gateway side with [`FakeDiscord::dispatch`]
+//! and observe the client through the request log, the identify log and the
+//! open-connection count.
+//!
+//! Avatars and icons are all `null`, so nothing reaches the CDN.
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::pin,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_tungstenite::tungstenite::{
+    Message as WebsocketMessage,
+    protocol::{CloseFrame, frame::coding::CloseCode},
+};
+use discord::{Discord, Endpoints};
+use facet::Facet;
+use futures::{
+    AsyncReadExt, AsyncWriteExt, StreamExt,
+    channel::{
+        mpsc::{UnboundedSender, unbounded},
+        oneshot,
+    },
+    future::{Either, select},
+};
+use messenger_interface::interface::Messenger;
+use serde_json::{Value, json};
+use smol::{
+    Task, Timer,
+    net::{TcpListener, TcpStream},
+};
+
+/// The token the fake accepts; anything else gets a 401.
+pub const TOKEN: &str = "fake-token";
+/// How long [`within`] and [`eventually`] wait before failing the test.
+const TEST_TIMEOUT: Duration = Duration::from_secs(10);
+const REST_PREFIX: &str = "/api/v10";
+/// Discord's default (and maximum) messages page.
+const PAGE_SIZE: usize = 50;
+
+pub const SELF_ID: u64 = 1;
+pub const FRIEND_ID: u64 = 2;
+pub const DM_CHANNEL_ID: u64 = 100;
+pub const GUILD_ID: u64 = 500;
+pub const ROLE_ID: u64 = 510;
+/// The DM history, oldest first.
+pub const DM_MESSAGE_IDS: [u64; 3] = [1001, 1002, 1003];
+
+fn user(id: u64, username: &str) -> Value {
+    json!({ "id": id.to_string(), "username": username, "avatar": null })
+}
+
+fn message(id: u64, channel_id: u64, author: Value, content: &str) -> Value {
+    json!({
+        "id": id.to_string(),
+        "channel_id": channel_id.to_string(),
+        "author": author,
+        "content": content,
+        "timestamp": format!("2026-01-01T00:00:{:02}+00:00", id % 60),
+        "edited_timestamp": null,
+    })
+}
+
+struct State {
+    profile: Value,
+    relationships: Vec<Value>,
+    dm_channels: Vec<Value>,
+    guild: Value,
+    guild_channels: Vec<Value>,
+    /// Active threads, sent in `Ready`.
+    guild_threads: Vec<Value>,
+    /// Open forum posts, served by the thread search.
+    forum_posts: Vec<Value>,
+    /// Per channel, oldest first.
+    messages: HashMap<u64, Vec<(u64, Value)>>,
+    /// `"{METHOD} {path}"` of every REST request, without the API prefix.
+    requests: Vec<String>,
+    /// Tokens received in Identify payloads, in order.
+    identified: Vec<String>,
+    /// Sequence numbers received in accepted Resume payloads, in order.
+    resumed: Vec<u64>,
+    /// Statuses received in Presence Update payloads, in order.
+    statuses: Vec<String>,
+    /// The resumable session, if any. Each Identify starts a new one.
+    session_id: Option<String>,
+    /// Outboxes of the identified or resumed gateway connections.
+    sessions: Vec<UnboundedSender<Outgoing>>,
+    open_gateways: usize,
+    sequence: u64,
+    /// Every dispatch sent through [`FakeDiscord::dispatch`], replayed to
+    /// resuming clients.
+    backlog: Vec<(u64, String)>,
+    held_fetch: Option<(oneshot::Sender<()>, oneshot::Receiver<()>)>,
+}
+
+impl State {
+    fn seeded() -> Self {
+        let friend = user(FRIEND_ID, "alice");
+        let history = DM_MESSAGE_IDS
+            .iter()
+            .enumerate()
+            .map(|(n, &id)| {
+                let content = format!("message {}", n + 1);
+                (id, message(id, DM_CHANNEL_ID, friend.clone(), &content))
+            })
+            .collect();
+        Self {
+            profile: user(SELF_ID, "me"),
+            relationships: vec![json!({ "id": FRIEND_ID.to_string(), "type": 1, "user": friend })],
+            dm_channels: vec![json!({
+                "id": DM_CHANNEL_ID.to_string(),
+                "type": 1,
+                "last_message_id": DM_MESSAGE_IDS[2].to_string(),
+                "recipients": [friend],
+            })],
+            guild: json!({ "id": GUILD_ID.to_string(), "name": "Fake Guild", "icon": null }),
+            guild_channels: vec![
+                json!({
+                    "id": "501",
+                    "type": 0,
+                    "name": "general",
+                    "position": 0,
+                    "last_message_id": "5010",
+                }),
+                json!({ "id": "502", "type": 2, "name": "voice", "position": 1 }),
+            ],
+            guild_threads: vec![json!({
+                "id": "520",
+                "type": 11,
+                "name": "plans",
+                "parent_id": "501",
+                "last_message_id": "5200",
+                "thread_metadata": { "archived": false, "auto_archive_duration": 1440 },
+            })],
+            forum_posts: vec![json!({
+                "id": "531",
+                "guild_id": GUILD_ID.to_string(),
+                "type": 11,
+                "name": "how do I",
+                "parent_id": "530",
+                "thread_metadata": { "archived": false, "auto_archive_duration": 1440 },
+            })],
+            messages: HashMap::from([(DM_CHANNEL_ID, history)]),
+            requests: Vec::new(),
+            identified: Vec::new(),
+            resumed: Vec::new(),
+            statuses: Vec::new(),
+            session_id: None,
+            sessions: Vec::new(),
+            open_gateways: 0,
+            sequence: 0,
+            backlog: Vec::new(),
+            held_fetch: None,
+        }
+    }
+
+    fn next_sequence(&mut self) -> u64 {
+        self.sequence += 1;
+        self.sequence
+    }
+
+    fn ready(&mut self, gateway_host: &str) -> String {
+        let session_id = format!("fake-session-{}", self.identified.len());
+        self.session_id = Some(session_id.clone());
+        json!({
+            "op": 0,
+            "t": "READY",
+            "s": self.next_sequence(),
+            "d": {
+                "v": 9,
+                "user": self.profile,
+                "session_id": session_id,
+                "resume_gateway_url": gateway_host,
+                "private_channels": self.dm_channels,
+                "relationships": self.relationships,
+                "read_state": {
+                    "version": 1,
+                    "partial": false,
+                    "entries": [
+                        { "id": "501", "last_message_id": "5009", "mention_count": 1 },
+                        // Not a channel: its ID is the guild's.
+                        { "id": GUILD_ID.to_string(), "read_state_type": 1, "badge_count": 3 },
+                    ],
+                },
+                "presences": [{
+                    "user": { "id": FRIEND_ID.to_string() },
+                    "status": "online",
+                    "activities": [{ "name": "Fake Game", "type": 0 }],
+                }],
+                "guilds": [{
+                    "id": GUILD_ID.to_string(),
+                    "properties": self.guild,
+                    "channels": self.guild_channels,
+                    "threads": self.guild_threads,
+                    "roles": [{ "id": ROLE_ID.to_string(), "name": "mods", "position": 1 }],
+                    "members": [],
+                    "voice_states": [],
+                }],
+                // The client's own member in each guild, by `user_id`.
+                "merged_members": [[
+                    { "user_id": SELF_ID.to_string(), "roles": [ROLE_ID.to_string()] },
+                ]],
+            },
+        })
+        .to_string()
+    }
+}
+
+/// A REST request parked until the test releases it; see
+/// [`FakeDiscord::hold_next_message_fetch`].
+pub struct HeldFetch {
+    /// Resolves once the request arrived and its (now frozen) response body
+    /// was taken.
+    pub arrived: oneshot::Receiver<()>,
+    /// Send to let the response go out.
+    pub release: oneshot::Sender<()>,
+}
+
+pub struct FakeDiscord {
+    state: Arc<Mutex<State>>,
+    endpoints: Endpoints,
+    _servers: [Task<()>; 2],
+}
+
+impl FakeDiscord {
+    pub async fn start() -> Self {
+        let state = Arc::new(Mutex::new(State::seeded()));
+        let rest = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let gateway = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        // What Ready hands out as `resume_gateway_url`: no path, no query.
+        let gateway_host = format!("ws://{}", gateway.local_addr().unwrap());
+        let endpoints = Endpoints {
+            api: format!("http://{}{REST_PREFIX}", rest.local_addr().unwrap()),
+            gateway: format!("{gateway_host}/?encoding=json&v=9"),
+        };
+
+        let rest_server = smol::spawn(serve(rest, state.clone(), |stream, state| {
+            smol::spawn(handle_rest(stream, state)).detach();
+        }));
+        let gateway_server = smol::spawn(serve(gateway, state.clone(), move |stream, state| {
+            smol::spawn(handle_gateway(stream, state, gateway_host.clone())).detach();
+        }));
+
+        Self {
+            state,
+            endpoints,
+            _servers: [rest_server, gateway_server],
+        }
+    }
+
+    pub fn endpoints(&self) -> Endpoints {
+        self.endpoints.clone()
+    }
+
+    /// A messenger logged in with [`TOKEN`] against this server.
+    pub fn messenger(&self) -> Arc<dyn Messenger> {
+        Discord::with_endpoints(TOKEN, self.endpoints())
+    }
+
+    pub fn requests(&self) -> Vec<String> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// The `content` a message is stored with, as the client sent it.
+    pub fn content(&self, channel_id: u64, message_id: u64) -> Option<String> {
+        let state = self.state.lock().unwrap();
+        let (_, message) = state
+            .messages
+            .get(&channel_id)?
+            .iter()
+            .find(|(id, _)| *id == message_id)?;
+        message["content"].as_str().map(str::to_owned)
+    }
+
+    pub fn identified(&self) -> Vec<String> {
+        self.state.lock().unwrap().identified.clone()
+    }
+
+    pub fn open_gateways(&self) -> usize {
+        self.state.lock().unwrap().open_gateways
+    }
+
+    pub fn resumed(&self) -> Vec<u64> {
+        self.state.lock().unwrap().resumed.clone()
+    }
+
+    pub fn statuses(&self) -> Vec<String> {
+        self.state.lock().unwrap().statuses.clone()
+    }
+
+    /// Send a dispatch (opcode 0) to every live session. With none live it
+    /// only waits in the backlog for the next resume.
+    pub fn dispatch(&self, event: &str, data: Value) {
+        let mut state = self.state.lock().unwrap();
+        let sequence = state.next_sequence();
+        let payload = json!({ "op": 0, "t": event, "s": sequence, "d": data }).to_string();
+        state.backlog.push((sequence, payload.clone()));
+        state.sessions.retain(|session| {
+            session
+                .unbounded_send(Outgoing::Payload(payload.clone()))
+                .is_ok()
+        });
+    }
+
+    /// Drop every gateway connection without a close frame, like a network
+    /// blip. The session stays resumable.
+    pub fn sever_gateways(&self) {
+        for session in self.state.lock().unwrap().sessions.drain(..) {
+            let _ = session.unbounded_send(Outgoing::Sever);
+        }
+    }
+
+    /// Close every gateway connection with `code`. The session id is kept,
+    /// so a client that resumes anyway is let back in (and shows up in
+    /// [`FakeDiscord::resumed`]).
+    pub fn close_gateways(&self, code: u16) {
+        for session in self.state.lock().unwrap().sessions.drain(..) {
+            let _ = session.unbounded_send(Outgoing::Close(code));
+        }
+    }
+
+    /// Send Invalid Session (opcode 9, not resumable) on every live
+    /// connection, so the client has to identify again.
+    pub fn invalidate_session(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.session_id = None;
+        let invalid = invalid_session().to_string();
+        for session in state.sessions.drain(..) {
+            let _ = session.unbounded_send(Outgoing::Payload(invalid.clone()));
+        }
+    }
+
+    /// Delete a message server-side and announce it, like Discord would.
+    pub fn delete_message(&self, channel_id: u64, message_id: u64) {
+        if let Some(history) = self.state.lock().unwrap().messages.get_mut(&channel_id) {
+            history.retain(|(id, _)| *id != message_id);
+        }
+        self.dispatch(
+            "MESSAGE_DELETE",
+            json!({ "id": message_id.to_string(), "channel_id": channel_id.to_string() }),
+        );
+    }
+
+    /// Park the next `GET /channels/{id}/messages` after its snapshot is
+    /// taken, so gateway events can land while the request is in flight.
+    pub fn hold_next_message_fetch(&self) -> HeldFetch {
+        let (arrived_sender, arrived) = oneshot::channel();
+        let (release, release_receiver) = oneshot::channel();
+        self.state.lock().unwrap().held_fetch = Some((arrived_sender, release_receiver));
+        HeldFetch { arrived, release }
+    }
+}
+
+async fn serve(
+    listener: TcpListener,
+    state: Arc<Mutex<State>>,
+    on_accept: impl Fn(TcpStream, Arc<Mutex<State>>),
+) {
+    while let Ok((stream, _)) = listener.accept().await {
+        on_accept(stream, state.clone());
+    }
+}
+
+/// Fail the test if `future` has not finished within [`TEST_TIMEOUT`].
+pub async fn within<F: Future>(future: F) -> F::Output {
+    match select(pin!(future), Timer::after(TEST_TIMEOUT)).await {
+        Either::Left((output, _)) => output,
+        Either::Right(_) => panic!("timed out after {TEST_TIMEOUT:?}"),
+    }
+}
+
+/// Poll `condition` until it holds, failing the test after [`TEST_TIMEOUT`].
+pub async fn eventually(condition: impl Fn() -> bool) {
+    within(async {
+        while !condition() {
+            Timer::after(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+}
+
+// === REST ===
+
+async fn handle_rest(mut stream: TcpStream, state: Arc<Mutex<State>>) {
+    let mut request = Vec::new();
+    let mut buffer = [0; 4096];
+    let head_end = loop {
+        match stream.read(&mut buffer).await {
+            Ok(0) | Err(_) => return,
+            Ok(read) => request.extend_from_slice(&buffer[..read]),
+        }
+        if let Some(at) = request.windows(4).position(|window| window == b"\r\n\r\n") {
+            break at;
+        }
+    };
+    let head = String::from_utf8_lossy(&request[..head_end]).into_owned();
+    let content_length = head
+        .lines()
+        .find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.eq_ignore_ascii_case("content-length")
+                .then(|| value.trim().parse::<usize>().ok())?
+        })
+        .unwrap_or(0);
+    let body_start = head_end + 4;
+    while request.len() < body_start + content_length {
+        match stream.read(&mut buffer).await {
+            Ok(0) | Err(_) => return,
+            Ok(read) => request.extend_from_slice(&buffer[..read]),
+        }
+    }
+    let body = String::from_utf8_lossy(&request[body_start..]).into_owned();
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let method = request_line.next().unwrap_or_default().to_owned();
+    let target = request_line.next().unwrap_or_default();
+    let target = target
+        .strip_prefix(REST_PREFIX)
+        .unwrap_or(target)
+        .to_owned();
+    let authorized = lines.any(|line| {
+        line.split_once(':').is_some_and(|(name, value)| {
+            name.eq_ignore_ascii_case("authorization") && value.trim() == TOKEN
+        })
+    });
+
+    let (status, body) = route(&state, &method, &target, authorized, &body).await;
+    let body = body.to_string();
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.close().await;
+}
+
+async fn route(
+    state: &Mutex<State>,
+    method: &str,
+    target: &str,
+    authorized: bool,
+    body: &str,
+) -> (&'static str, Value) {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    state
+        .lock()
+        .unwrap()
+        .requests
+        .push(format!("{method} {target}"));
+    if !authorized {
+        return (
+            "401 Unauthorized",
+            json!({ "message": "401: Unauthorized", "code": 0 }),
+        );
+    }
+
+    let segments = path.trim_start_matches('/').split('/').collect::<Vec<_>>();
+    let mut held = None;
+    let response = {
+        let mut state = state.lock().unwrap();
+        match (method, segments.as_slice()) {
+            ("GET", ["users", "@me"]) => ("200 OK", state.profile.clone()),
+            ("GET", ["users", "@me", "relationships"]) => {
+                ("200 OK", Value::from(state.relationships.clone()))
+            }
+            ("GET", ["users", "@me", "channels"]) => {
+                ("200 OK", Value::from(state.dm_channels.clone()))
+            }
+            ("POST", ["users", "@me", "channels"]) => {
+                let Ok(open) = facet_json::from_str::<OpenDmBody>(body) else {
+                    return bad_request();
+                };
+                let [recipient] = open.recipients.as_slice() else {
+                    return bad_request();
+                };
+                let existing = state.dm_channels.iter().find(|channel| {
+                    channel["type"] == 1 && channel["recipients"][0]["id"] == *recipient
+                });
+                let channel = match existing {
+                    Some(channel) => channel.clone(),
+                    None => {
+                        let id = DM_CHANNEL_ID + state.dm_channels.len() as u64;
+                        let recipient_id = recipient.parse().unwrap_or_default();
+                        let channel = json!({
+                            "id": id.to_string(),
+                            "type": 1,
+                            "last_message_id": null,
+                            "recipients": [user(recipient_id, "stranger")],
+                        });
+                        state.dm_channels.push(channel.clone());
+                        channel
+                    }
+                };
+                ("200 OK", channel)
+            }
+            ("GET", ["users", "@me", "guilds"]) => ("200 OK", json!([state.guild])),
+            ("GET", ["guilds", id, "channels"]) if *id == GUILD_ID.to_string() => {
+                ("200 OK", Value::from(state.guild_channels.clone()))
+            }
+            ("GET", ["channels", id, "messages"]) => {
+                let before = query
+                    .split('&')
+                    .find_map(|pair| pair.strip_prefix("before="))
+                    .and_then(|id| id.parse::<u64>().ok())
+                    .unwrap_or(u64::MAX);
+                let history = id
+                    .parse::<u64>()
+                    .ok()
+                    .and_then(|id| state.messages.get(&id));
+                match history {
+                    // Newest first, like Discord.
+                    Some(history) => {
+                        let page = history
+                            .iter()
+                            .rev()
+                            .filter(|(id, _)| *id < before)
+                            .take(PAGE_SIZE)
+                            .map(|(_, message)| message.clone())
+                            .collect::<Vec<_>>();
+                        held = state.held_fetch.take();
+                        ("200 OK", Value::from(page))
+                    }
+                    None => not_found(),
+                }
+            }
+            ("POST", ["channels", id, "messages"]) => {
+                let (payload, files) = match multipart_parts(body) {
+                    Some(parts) => parts,
+                    None => (body, Vec::new()),
+                };
+                let Ok(create) = facet_json::from_str::<MessageBody>(payload) else {
+                    return bad_request();
+                };
+                let Some(channel_id) = id.parse::<u64>().ok() else {
+                    return not_found();
+                };
+                let author = state.profile.clone();
+                let history = state.messages.entry(channel_id).or_default();
+                let id = history.last().map_or(1, |(id, _)| id + 1);
+                let mut created =
+                    message(id, channel_id, author, &create.content.unwrap_or_default());
+                if let Some(target) = create.message_reference.and_then(|r| r.message_id) {
+                    created["message_reference"] = json!({
+                        "message_id": target.to_string(),
+                        "channel_id": channel_id.to_string(),
+                    });
+                    created["referenced_message"] = history
+                        .iter()
+                        .find(|(id, _)| *id == target)
+                        .map_or(Value::Null, |(_, message)| message.clone());
+                }
+                created["attachments"] = files
+                    .iter()
+                    .zip(1..)
+                    .map(|((filename, contents), n)| {
+                        let attachment_id = id * 100 + n;
+                        json!({
+                            "id": attachment_id.to_string(),
+                            "filename": filename,
+                            "size": contents.len(),
+                            "url": format!("https://cdn.discordapp.com/attachments/{channel_id}/{attachment_id}/{filename}"),
+                        })
+                    })
+                    .collect();
+                history.push((id, created.clone()));
+                ("200 OK", created)
+            }
+            ("PATCH", ["channels", channel_id, "messages", id]) => {
+                let Ok(edit) = facet_json::from_str::<MessageBody>(body) else {
+                    return bad_request();
+                };
+                let self_id = SELF_ID.to_string();
+                match find_message(&mut state, channel_id, id) {
+                    Some(message) if message["author"]["id"] != self_id.as_str() => (
+                        "403 Forbidden",
+                        json!({
+                            "message": "Cannot edit a message authored by another user",
+                            "code": 50005,
+                        }),
+                    ),
+                    Some(message) => {
+                        if let Some(content) = edit.content {
+                            message["content"] = Value::from(content);
+                        }
+                        message["edited_timestamp"] = json!("2026-01-01T00:01:00+00:00");
+                        ("200 OK", message.clone())
+                    }
+                    None => not_found(),
+                }
+            }
+            ("DELETE", ["channels", channel_id, "messages", id]) => {
+                let history = channel_id
+                    .parse::<u64>()
+                    .ok()
+                    .and_then(|channel_id| state.messages.get_mut(&channel_id));
+                match (history, id.parse::<u64>()) {
+                    (Some(history), Ok(id)) if history.iter().any(|(m, _)| *m == id) => {
+                        history.retain(|(m, _)| *m != id);
+                        ("204 No Content", Value::Null)
+                    }
+                    _ => not_found(),
+                }
+            }
+            ("GET", ["channels", id, "threads", "search"]) => {
+                let posts = state
+                    .forum_posts
+                    .iter()
+                    .filter(|post| post["parent_id"] == *id)
+                    .cloned()
+                    .collect::<Vec<_>>();
+                (
+                    "200 OK",
+                    json!({ "threads": posts, "members": [], "has_more": false }),
+                )
+            }
+            ("PUT" | "DELETE", ["channels", _, "messages", _, "reactions", _, "@me"])
+            | ("POST", ["channels", _, "typing"]) => ("204 No Content", Value::Null),
+            ("POST", ["channels", _, "messages", _, "ack"]) => ("200 OK", json!({ "token": null })),
+            _ => not_found(),
+        }
+    };
+
+    if let Some((arrived, release)) = held {
+        let _ = arrived.send(());
+        let _ = release.await;
+    }
+    response
+}
+
+/// The JSON body of a create or edit message request.
+#[derive(Facet)]
+struct MessageBody {
+    content: Option<String>,
+    message_reference: Option<MessageReferenceBody>,
+}
+
+/// The JSON body of an open DM request.
+#[derive(Facet)]
+struct OpenDmBody {
+    recipients: Vec<String>,
+}
+
+#[derive(Facet)]
+struct MessageReferenceBody {
+    message_id: Option<u64>,
+}
+
+/// Split a `multipart/form-data` create-message body into its `payload_json`
+/// and its `(filename, contents)` file parts; `None` for a plain JSON body.
+fn multipart_parts(body: &str) -> Option<(&str, Vec<(&str, &str)>)> {
+    let boundary = body.strip_prefix("--")?.split("\r\n").next()?;
+    let mut payload = "";
+    let mut files = Vec::new();
+    // Between delimiters, each part is `\r\n{headers}\r\n\r\n{contents}\r\n`;
+    // the closing delimiter leaves a trailing `--\r\n`.
+    for part in body.split(&format!("--{boundary}")) {
+        let Some(part) = part
+            .strip_prefix("\r\n")
+            .and_then(|p| p.strip_suffix("\r\n"))
+        else {
+            continue;
+        };
+        let Some((headers, contents)) = part.split_once("\r\n\r\n") else {
+            continue;
+        };
+        match headers.split("filename=\"").nth(1) {
+            Some(filename) => files.push((filename.split('"').next()?, contents)),
+            None if headers.contains("name=\"payload_json\"") => payload = contents,
+            None => {}
+        }
+    }
+    Some((payload, files))
+}
+
+fn find_message<'a>(state: &'a mut State, channel_id: &str, id: &str) -> Option<&'a mut Value> {
+    let (channel_id, id) = (channel_id.parse::<u64>().ok()?, id.parse::<u64>().ok()?);
+    state
+        .messages
+        .get_mut(&channel_id)?
+        .iter_mut()
+        .find(|(m, _)| *m == id)
+        .map(|(_, message)| message)
+}
+
+fn bad_request() -> (&'static str, Value) {
+    (
+        "400 Bad Request",
+        json!({ "message": "Invalid Form Body", "code": 50035 }),
+    )
+}
+
+fn not_found() -> (&'static str, Value) {
+    (
+        "404 Not Found",
+        json!({ "message": "Unknown route", "code": 0 }),
+    )
+}
+
+// === Gateway ===
+
+#[derive(Facet)]
+struct ClientPayload {
+    op: u8,
+    d: facet_value::Value,
+}
+
+#[derive(Facet)]
+struct IdentifyData {
+    token: String,
+}
+
+#[derive(Facet)]
+struct PresenceData {
+    status: String,
+}
+
+#[derive(Facet)]
+struct ResumeData {
+    token: String,
+    session_id: String,
+    seq: u64,
+}
+
+/// What the test side pushes into a live connection.
+enum Outgoing {
+    Payload(String),
+    /// Drop the socket as if the network went away.
+    Sever,
+    /// Close the socket with this close code.
+    Close(u16),
+}
+
+fn invalid_session() -> Value {
+    json!({ "op": 9, "s": null, "t": null, "d": false })
+}
+
+/// The payloads to answer `payload` with; `None` closes the connection.
+fn respond(
+    state: &Mutex<State>,
+    payload: ClientPayload,
+    outbox: &UnboundedSender<Outgoing>,
+    gateway_host: &str,
+) -> Option<Vec<String>> {
+    match payload.op {
+        // Heartbeat
+        1 => Some(vec![
+            json!({ "op": 11, "s": null, "t": null, "d": null }).to_string(),
+        ]),
+        // Identify
+        2 => {
+            let identify = facet_value::from_value::<IdentifyData>(payload.d).ok()?;
+            if identify.token != TOKEN {
+                // Discord closes with 4004 (authentication failed).
+                return None;
+            }
+            let mut state = state.lock().unwrap();
+            state.identified.push(identify.token);
+            state.sessions.push(outbox.clone());
+            Some(vec![state.ready(gateway_host)])
+        }
+        // Presence Update: recorded, never echoed, like Discord does for
+        // the client's own presence.
+        3 => {
+            let presence = facet_value::from_value::<PresenceData>(payload.d).ok()?;
+            state.lock().unwrap().statuses.push(presence.status);
+            Some(Vec::new())
+        }
+        // Resume: replay everything after the client's sequence, then RESUMED.
+        6 => {
+            let resume = facet_value::from_value::<ResumeData>(payload.d).ok()?;
+            let mut state = state.lock().unwrap();
+            if resume.token != TOKEN || state.session_id.as_ref() != Some(&resume.session_id) {
+                return Some(vec![invalid_session().to_string()]);
+            }
+            state.resumed.push(resume.seq);
+            state.sessions.push(outbox.clone());
+            let mut replies = state
+                .backlog
+                .iter()
+                .filter(|(sequence, _)| *sequence > resume.seq)
+                .map(|(_, payload)| payload.clone())
+                .collect::<Vec<_>>();
+            let sequence = state.next_sequence();
+            replies.push(json!({ "op": 0, "t": "RESUMED", "s": sequence, "d": {} }).to_string());
+            Some(replies)
+        }
+        _ => Some(Vec::new()),
+    }
+}
+
+async fn handle_gateway(stream: TcpStream, state: Arc<Mutex<State>>, gateway_host: String) {
+    let Ok(websocket) = async_tungstenite::accept_async(stream).await else {
+        return;
+    };
+    state.lock().unwrap().open_gateways += 1;
+    let (mut sender, mut receiver) = websocket.split();
+    let (outbox, mut outgoing) = unbounded::<Outgoing>();
+
+    let hello = json!({
+        "op": 10,
+        "s": null,
+        "t": null,
+        "d": { "heartbeat_interval": 41250, "_trace": ["fake-gateway"] },
+    });
+    let mut replies = vec![hello.to_string()];
+    'connection: loop {
+        for reply in replies.drain(..) {
+            if sender
+                .send(WebsocketMessage::Text(reply.into()))
+                .await
+                .is_err()
+            {
+                break 'connection;
+            }
+        }
+        replies = match select(receiver.next(), outgoing.next()).await {
+            Either::Left((Some(Ok(WebsocketMessage::Text(text))), _)) => {
+                let Ok(payload) = facet_json::from_str::<ClientPayload>(&text) else {
+                    continue;
+                };
+                match respond(&state, payload, &outbox, &gateway_host) {
+                    Some(replies) => replies,
+                    None => break,
+                }
+            }
+            Either::Left((Some(Ok(WebsocketMessage::Close(_)) | Err(_)) | None, _)) => break,
+            Either::Left(_) => continue,
+            Either::Right((Some(Outgoing::Payload(payload)), _)) => vec![payload],
+            Either::Right((Some(Outgoing::Close(code)), _)) => {
+                let frame = CloseFrame {
+                    code: CloseCode::from(code),
+                    reason: "".into(),
+                };
+                let _ = sender.send(WebsocketMessage::Close(Some(frame))).await;
+                break;
+            }
+            Either::Right((Some(Outgoing::Sever) | None, _)) => break,
+        };
+    }
+    state.lock().unwrap().open_gateways -= 1;
+}
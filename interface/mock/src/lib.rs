@@ -0,0 +1,642 @@
+//! In-process mock messenger backend.
+//!
+//! Implements [`Query`](messenger_interface::interface::Query),
+//! [`Text`](messenger_interface::interface::Text),
+//! [`Voice`](messenger_interface::interface::Voice) and
+//! [`Presence`](messenger_interface::interface::Presence) entirely in memory, driven
+//! by a scriptable [`World`]. No network and no account are involved, so the UI
+//! and any other consumer of `messenger_interface` can be developed and tested
+//! offline.
+//!
+//! # Module layout
+//!
+//! - [`world`]: the [`World`] model, the built-in scenarios, and how released
+//!   events fold back into the world.
+//! - [`listener`]: the event queues and the `ArcStream` impls behind `listen`.
+//! - [`query`]: the `Query`/`Text` implementations.
+//! - [`voice`]: the `Voice` capability, with a loopback "echo test" call.
+//! - [`presence`]: the `Presence` capability, served from the world.
+//!
+//! # Scenarios
+//!
+//! The auth string names a built-in scenario (see [`World::scenario`]); an
+//! empty string loads the demo. [`Messenger::auth`] returns the name, so a
+//! saved `Test:demo` entry reloads the same world. Custom worlds built with
+//! [`Mock::with_world`] are not persisted and reload as the demo.
+//!
+//! # Every mutation is an event
+//!
+//! Timeline entries, [`MockHandle::inject`] and capability calls such as
+//! `send_message` or `add_reaction` all go through [`Shared::dispatch`], which
+//! folds the event into the world *and* queues it for listeners. A fetch made
+//! after an event therefore always agrees with what the streams reported, the
+//! same guarantee a real backend gives once its caches settle.
+//!
+//! The timeline advances lazily: due entries are released whenever a listen
+//! stream is polled or a capability method reads the world.
+
+use std::{
+    mem,
+    sync::{Arc, Mutex, MutexGuard, Weak},
+    time::{Duration, Instant},
+};
+
+use tracing::warn;
+
+use messenger_interface::interface::{Messenger, PresenceEvent, QueryEvent, TextEvent, VoiceEvent};
+use messenger_interface::types::ID;
+
+use crate::listener::{Listener, Queue};
+use crate::voice::{CallAudio, SelfVoice};
+use crate::world::DEMO_SCENARIO;
+
+pub use crate::world::{Scheduled, ScriptedEvent, World};
+
+mod listener;
+mod presence;
+mod query;
+mod voice;
+mod world;
+
+const INTERFACE_NAME: &str = "Test";
+/// Scenario name reported by worlds built with [`Mock::with_world`].
+const CUSTOM_SCENARIO: &str = "custom";
+/// Upper bound on how long a listener parks before re-checking the timeline,
+/// so entries scheduled at runtime are released promptly.
+const TIMELINE_TICK: Duration = Duration::from_millis(100);
+
+/// Public entry point, mirroring `discord::Discord` and `steam::Steam`.
+pub struct Mock;
+impl Mock {
+    /// `auth` is the name of a built-in scenario; unknown names load the demo.
+    pub fn new_messenger(auth: &str) -> Arc<dyn Messenger> {
+        MockMessenger::create_messenger(auth)
+    }
+
+    /// Build a mock serving `world`, plus a handle to inject events into it
+    /// while it runs.
+    pub fn with_world(world: World) -> (Arc<dyn Messenger>, MockHandle) {
+        let messenger = MockMessenger::build(CUSTOM_SCENARIO.to_owned(), world);
+        let handle = MockHandle {
+            shared: Arc::downgrade(&messenger.shared),
+        };
+        (messenger, handle)
+    }
+}
+
+/// Drives a running mock from outside (tests, dev tooling). Holds the world
+/// weakly, so it never keeps a dropped messenger alive.
+#[derive(Clone)]
+pub struct MockHandle {
+    shared: Weak<Shared>,
+}
+impl MockHandle {
+    /// Release `event` now. Returns `false` if the messenger is gone.
+    pub fn inject(&self, event: impl Into<ScriptedEvent>) -> bool {
+        match self.shared.upgrade() {
+            Some(shared) => {
+                shared.dispatch(event.into());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Release `event` once `after` has elapsed. Returns `false` if the
+    /// messenger is gone.
+    pub fn schedule(&self, after: Duration, event: impl Into<ScriptedEvent>) -> bool {
+        match self.shared.upgrade() {
+            Some(shared) => {
+                shared.schedule(after, event.into());
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// State shared between the messenger and its listen streams.
+pub(crate) struct Shared {
+    world: Mutex<World>,
+    /// Zero point of the timeline.
+    epoch: Instant,
+    /// Pending entries, sorted by `after` (measured from `epoch`).
+    timeline: Mutex<Vec<Scheduled>>,
+    pub(crate) query_events: Queue<QueryEvent>,
+    pub(crate) text_events: Queue<TextEvent>,
+    pub(crate) voice_events: Queue<VoiceEvent>,
+    pub(crate) presence_events: Queue<PresenceEvent>,
+}
+
+impl Shared {
+    /// Lock the world, releasing any timeline entries that fell due first.
+    pub(crate) fn world(&self) -> MutexGuard<'_, World> {
+        self.release_due();
+        self.world.lock().unwrap()
+    }
+
+    /// Fold `event` into the world and queue it for listeners.
+    pub(crate) fn dispatch(&self, event: ScriptedEvent) {
+        self.world.lock().unwrap().apply(&event);
+        match event {
+            ScriptedEvent::Query(event) => self.query_events.push(event),
+            ScriptedEvent::Text(event) => self.text_events.push(event),
+            ScriptedEvent::Voice(event) => self.voice_events.push(event),
+            ScriptedEvent::Presence(event) => self.presence_events.push(event),
+        }
+    }
+
+    pub(crate) fn schedule(&self, after: Duration, event: ScriptedEvent) {
+        let after = self.epoch.elapsed() + after;
+        let mut timeline = self.timeline.lock().unwrap();
+        let at = timeline.partition_point(|scheduled| scheduled.after <= after);
+        timeline.insert(at, Scheduled { after, event });
+    }
+
+    /// Dispatch every due timeline entry. Returns how long the caller may park
+    /// before the next entry falls due, capped at [`TIMELINE_TICK`].
+    pub(crate) fn release_due(&self) -> Duration {
+        let elapsed = self.epoch.elapsed();
+        let (due, next) = {
+            let mut timeline = self.timeline.lock().unwrap();
+            let split = timeline.partition_point(|scheduled| scheduled.after <= elapsed);
+            let due: Vec<Scheduled> = timeline.drain(..split).collect();
+            (
+                due,
+                timeline.first().map(|scheduled| scheduled.after - elapsed),
+            )
+        };
+        for scheduled in due {
+            self.dispatch(scheduled.event);
+        }
+        next.map_or(TIMELINE_TICK, |next| next.min(TIMELINE_TICK))
+    }
+
+    /// End every listen stream.
+    fn close(&self) {
+        self.query_events.close();
+        self.text_events.close();
+        self.voice_events.close();
+        self.presence_events.close();
+    }
+}
+
+pub(crate) struct MockMessenger {
+    scenario: String,
+    pub(crate) shared: Arc<Shared>,
+    // The messenger owns the only long-lived strong reference to each
+    // listener, so the `WeakSocketStream`s handed out by `listen` end with it.
+    pub(crate) query_listener: Arc<Listener<QueryEvent>>,
+    pub(crate) text_listener: Arc<Listener<TextEvent>>,
+    pub(crate) voice_listener: Arc<Listener<VoiceEvent>>,
+    pub(crate) presence_listener: Arc<Listener<PresenceEvent>>,
+    /// The room we are in a call with, and that call's audio stream.
+    pub(crate) call: Mutex<Option<(ID, Arc<CallAudio>)>>,
+    pub(crate) self_voice: Arc<SelfVoice>,
+}
+
+impl MockMessenger {
+    fn build(scenario: String, mut world: World) -> Arc<Self> {
+        let mut timeline = mem::take(&mut world.timeline);
+        timeline.sort_by_key(|scheduled| scheduled.after);
+        let shared = Arc::new(Shared {
+            world: Mutex::new(world),
+            epoch: Instant::now(),
+            timeline: Mutex::new(timeline),
+            query_events: Queue::new(),
+            text_events: Queue::new(),
+            voice_events: Queue::new(),
+            presence_events: Queue::new(),
+        });
+        Arc::new(MockMessenger {
+            scenario,
+            query_listener: Listener::new(&shared),
+            text_listener: Listener::new(&shared),
+            voice_listener: Listener::new(&shared),
+            presence_listener: Listener::new(&shared),
+            shared,
+            call: Mutex::new(None),
+            self_voice: Default::default(),
+        })
+    }
+}
+
+impl Drop for MockMessenger {
+    fn drop(&mut self) {
+        // In-flight `next()` futures hold the listeners (and so `shared`)
+        // alive; closing the queues is what lets them return `None`.
+        self.shared.close();
+        if let Some((_, call)) = self.call.lock().unwrap().take() {
+            call.end();
+        }
+    }
+}
+
+impl Messenger for MockMessenger {
+    /// `auth_obj` is a scenario name (see [`World::scenario`]).
+    fn create_messenger(auth_obj: &str) -> Arc<dyn Messenger>
+    where
+        Self: Sized,
+    {
+        let scenario = match auth_obj.trim() {
+            "" => DEMO_SCENARIO,
+            scenario => scenario,
+        };
+        let world = World::scenario(scenario).unwrap_or_else(|| {
+            warn!("Mock: unknown scenario {scenario:?}; loading the demo");
+            World::scenario(DEMO_SCENARIO).expect("the demo scenario is built in")
+        });
+        MockMessenger::build(scenario.to_owned(), world)
+    }
+
+    fn name(&self) -> &'static str {
+        INTERFACE_NAME
+    }
+    fn id(&self) -> String {
+        format!("{INTERFACE_NAME}{}", self.scenario)
+    }
+    /// The scenario name, so a saved entry reloads the same world.
+    fn auth(&self) -> String {
+        self.scenario.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+    use messenger_interface::interface::Ordering;
+    use messenger_interface::types::{
+        Identifier, Message, Place, Reply, Revision, RichText, Room, Status, UserPresence,
+    };
+
+    use super::*;
+
+    fn dm_world() -> (World, ID) {
+        let mut world = World::new("You");
+        let alice = world.add_user("Alice");
+        let dm = world.add_dm(&alice);
+        for n in 0..5 {
+            world.post(dm, &alice, &format!("message {n}"));
+        }
+        (world, dm)
+    }
+
+    fn location(world: &World, room: ID) -> Identifier<Place<Room>> {
+        world.room(room).unwrap().clone()
+    }
+
+    fn outgoing(text: &str) -> Message {
+        Message {
+            content: Revision {
+                at: None,
+                text: RichText::plain(text),
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn auth_round_trips_the_scenario() {
+        let messenger = Mock::new_messenger("empty");
+        assert_eq!(messenger.name(), "Test");
+        assert_eq!(messenger.auth(), "empty");
+        assert_eq!(Mock::new_messenger("").auth(), DEMO_SCENARIO);
+    }
+
+    #[test]
+    fn sent_message_is_fetched_and_streamed() {
+        futures::executor::block_on(async {
+            let (world, dm) = dm_world();
+            let room = location(&world, dm);
+            let (messenger, _handle) = Mock::with_world(world);
+            let text = messenger.clone().arc_text().unwrap();
+            let mut events = text.clone().listen().await.unwrap();
+
+            let sent = text.send_message(&room, outgoing("hi")).await.unwrap();
+
+            match events.next().await {
+                Some(TextEvent::MessageCreated { room: r, message }) => {
+                    assert_eq!(*r.id(), dm);
+                    assert_eq!(message.id(), sent.id());
+                }
+                _ => panic!("expected MessageCreated"),
+            }
+            let history = text
+                .get_messages(&room, None, Ordering::Time)
+                .await
+                .unwrap();
+            assert_eq!(history.last().unwrap().id(), sent.id());
+            assert_eq!(history.last().unwrap().content.text.to_plain(), "hi");
+        });
+    }
+
+    #[test]
+    fn own_messages_can_be_edited_and_deleted() {
+        futures::executor::block_on(async {
+            let (world, dm) = dm_world();
+            let room = location(&world, dm);
+            let (messenger, _handle) = Mock::with_world(world);
+            let text = messenger.text().unwrap();
+
+            assert!(text.can_edit());
+            let sent = text.send_message(&room, outgoing("helo")).await.unwrap();
+            let edited = text.edit_message(&room, &sent, "hello").await.unwrap();
+            assert_eq!(edited.content.text.to_plain(), "hello");
+            assert_eq!(edited.history.last().unwrap().text.to_plain(), "helo");
+
+            let history = text
+                .get_messages(&room, None, Ordering::Time)
+                .await
+                .unwrap();
+            let theirs = history[0].clone();
+            assert!(history.last().unwrap().is_edited());
+            assert!(text.edit_message(&room, &theirs, "no").await.is_err());
+            assert!(text.delete_message(&room, &theirs).await.is_err());
+
+            text.delete_message(&room, &edited).await.unwrap();
+            let history = text
+                .get_messages(&room, None, Ordering::Time)
+                .await
+                .unwrap();
+            assert!(history.iter().all(|m| m.id() != sent.id()));
+            assert_eq!(history.len(), 5);
+        });
+    }
+
+    #[test]
+    fn replies_quote_their_target() {
+        futures::executor::block_on(async {
+            let (world, dm) = dm_world();
+            let room = location(&world, dm);
+            let (messenger, _handle) = Mock::with_world(world);
+            let text = messenger.text().unwrap();
+
+            let history = text
+                .get_messages(&room, None, Ordering::Time)
+                .await
+                .unwrap();
+            let target = *history[1].id();
+            let reply = Message {
+                reply: Some(Reply {
+                    message_id: target,
+                    preview: None,
+                }),
+                ..outgoing("agreed")
+            };
+            let sent = text.send_message(&room, reply).await.unwrap();
+
+            let reply = sent.reply.as_ref().unwrap();
+            assert_eq!(reply.message_id, target);
+            let preview = reply.preview.as_ref().unwrap();
+            assert_eq!(preview.author, "Alice");
+            assert_eq!(preview.text, "message 1");
+        });
+    }
+
+    #[test]
+    fn sent_files_become_attachments() {
+        futures::executor::block_on(async {
+            let (world, dm) = dm_world();
+            let room = location(&world, dm);
+            let (messenger, _handle) = Mock::with_world(world);
+            let text = messenger.text().unwrap();
+            let file = std::env::temp_dir().join(format!("mock-upload-{}.txt", std::process::id()));
+            std::fs::write(&file, "twelve bytes").unwrap();
+
+            let sent = text
+                .send_files(&room, outgoing(""), vec![file.clone()])
+                .await;
+            std::fs::remove_file(&file).unwrap();
+
+            let sent = sent.unwrap();
+            let attachment = &sent.attachments[0];
+            assert_eq!(
+                attachment.filename,
+                file.file_name().unwrap().to_str().unwrap()
+            );
+            assert_eq!(attachment.size, 12);
+            assert_eq!(attachment.path.as_ref(), Some(&file));
+            assert!(
+                text.send_files(&room, outgoing(""), vec![file])
+                    .await
+                    .is_err()
+            );
+        });
+    }
+
+    #[test]
+    fn scripted_typing_is_streamed_without_touching_history() {
+        futures::executor::block_on(async {
+            let mut world = World::new("You");
+            let alice = world.add_user("Alice");
+            let dm = world.add_dm(&alice);
+            world.post(dm, &alice, "hi");
+            let room = location(&world, dm);
+            let (messenger, handle) = Mock::with_world(world);
+            let text = messenger.clone().arc_text().unwrap();
+            let mut events = text.clone().listen().await.unwrap();
+
+            text.send_typing(&room).await.unwrap();
+            assert!(handle.inject(TextEvent::TypingStarted {
+                room: room.swap_data(()),
+                user: alice.clone(),
+            }));
+            match events.next().await {
+                Some(TextEvent::TypingStarted { room: r, user }) => {
+                    assert_eq!(r.id(), room.id());
+                    assert_eq!(user.id(), alice.id());
+                }
+                _ => panic!("expected TypingStarted"),
+            }
+            let history = text
+                .get_messages(&room, None, Ordering::Time)
+                .await
+                .unwrap();
+            assert_eq!(history.len(), 1);
+        });
+    }
+
+    #[test]
+    fn read_state_follows_incoming_reads_and_sends() {
+        futures::executor::block_on(async {
+            let (mut world, dm) = dm_world();
+            let room = location(&world, dm);
+            let alice = world.contacts[0].clone();
+            let ping = world.message(&alice, "ping");
+            let (messenger, handle) = Mock::with_world(world);
+            let text = messenger.clone().arc_text().unwrap();
+            let mut events = text.clone().listen().await.unwrap();
+            assert!(text.read_states().await.unwrap().is_empty());
+
+            assert!(handle.inject(TextEvent::MessageCreated {
+                room: room.swap_data(()),
+                message: ping,
+            }));
+            events.next().await;
+            assert_eq!(text.read_states().await.unwrap()[&dm].unread, 1);
+
+            let history = text
+                .get_messages(&room, None, Ordering::Time)
+                .await
+                .unwrap();
+            text.mark_read(&room, &history[2]).await.unwrap();
+            match events.next().await {
+                Some(TextEvent::ReadStateUpdated { room: r, state }) => {
+                    assert_eq!(*r.id(), dm);
+                    assert_eq!(state.last_read, Some(*history[2].id()));
+                    assert_eq!(state.unread, 3);
+                }
+                _ => panic!("expected ReadStateUpdated"),
+            }
+
+            let sent = text.send_message(&room, outgoing("pong")).await.unwrap();
+            let state = text.read_states().await.unwrap()[&dm];
+            assert_eq!(state.last_read, Some(*sent.id()));
+            assert!(!state.is_unread());
+        });
+    }
+
+    #[test]
+    fn presence_changes_are_streamed_and_applied() {
+        futures::executor::block_on(async {
+            let mut world = World::new("You");
+            let alice = world.add_user("Alice");
+            let client_id = *world.client.id();
+            let (messenger, handle) = Mock::with_world(world);
+            let presence = messenger.clone().arc_presence().unwrap();
+            let mut events = presence.clone().listen().await.unwrap();
+            assert!(presence.presences().await.unwrap().is_empty());
+
+            let playing = UserPresence {
+                status: Status::Online,
+                activity: Some("Playing Chess".to_owned()),
+            };
+            assert!(handle.inject(PresenceEvent::PresenceUpdated {
+                user_id: *alice.id(),
+                presence: playing.clone(),
+            }));
+            match events.next().await {
+                Some(PresenceEvent::PresenceUpdated { user_id, presence }) => {
+                    assert_eq!(user_id, *alice.id());
+                    assert_eq!(presence, playing);
+                }
+                None => panic!("expected PresenceUpdated"),
+            }
+
+            presence.set_status(Status::Offline).await.unwrap();
+            match events.next().await {
+                Some(PresenceEvent::PresenceUpdated { user_id, presence }) => {
+                    assert_eq!(user_id, client_id);
+                    assert_eq!(presence.status, Status::Invisible);
+                }
+                None => panic!("expected the client's PresenceUpdated"),
+            }
+            let presences = presence.presences().await.unwrap();
+            assert_eq!(presences[alice.id()], playing);
+            assert_eq!(presences[&client_id].status, Status::Invisible);
+        });
+    }
+
+    #[test]
+    fn self_mute_and_deaf_are_reported_back() {
+        futures::executor::block_on(async {
+            let world = World::new("You");
+            let client_id = *world.client.id();
+            let (messenger, _handle) = Mock::with_world(world);
+            let voice = messenger.clone().arc_voice().unwrap();
+            let mut events = voice.clone().listen().await.unwrap();
+
+            voice.set_self_mute(true).await.unwrap();
+            voice.set_self_deaf(true).await.unwrap();
+            voice.set_self_mute(false).await.unwrap();
+
+            let mut states = Vec::new();
+            for _ in 0..3 {
+                match events.next().await {
+                    Some(VoiceEvent::MuteStateChanged { user_id, state }) => {
+                        assert_eq!(user_id, client_id);
+                        states.push((state.self_mute, state.self_deaf));
+                    }
+                    _ => panic!("expected MuteStateChanged"),
+                }
+            }
+            assert_eq!(states, [(true, false), (true, true), (false, true)]);
+        });
+    }
+
+    #[test]
+    fn pagination_returns_older_messages_only() {
+        futures::executor::block_on(async {
+            let (world, dm) = dm_world();
+            let room = location(&world, dm);
+            let (messenger, _handle) = Mock::with_world(world);
+            let text = messenger.text().unwrap();
+
+            let all = text
+                .get_messages(&room, None, Ordering::Time)
+                .await
+                .unwrap();
+            let before = all[2].clone();
+            let older = text
+                .get_messages(&room, Some(before), Ordering::Time)
+                .await
+                .unwrap();
+            let ids: Vec<ID> = older.iter().map(|m| *m.id()).collect();
+            assert_eq!(ids, [*all[0].id(), *all[1].id()]);
+        });
+    }
+
+    #[test]
+    fn scheduled_events_are_released_and_applied() {
+        futures::executor::block_on(async {
+            let (mut world, dm) = dm_world();
+            let room = location(&world, dm);
+            let alice = world.contacts[0].clone();
+            let late = world.message(&alice, "late");
+            let late_id = *late.id();
+            let (messenger, handle) = Mock::with_world(world);
+            let text = messenger.clone().arc_text().unwrap();
+            let mut events = text.clone().listen().await.unwrap();
+
+            assert!(handle.schedule(
+                Duration::from_millis(20),
+                TextEvent::MessageCreated {
+                    room: Identifier::new(dm, ()),
+                    message: late,
+                },
+            ));
+            match events.next().await {
+                Some(TextEvent::MessageCreated { message, .. }) => {
+                    assert_eq!(*message.id(), late_id)
+                }
+                _ => panic!("expected the scheduled message"),
+            }
+            let history = text
+                .get_messages(&room, None, Ordering::Time)
+                .await
+                .unwrap();
+            assert!(history.iter().any(|m| *m.id() == late_id));
+        });
+    }
+
+    #[test]
+    fn streams_end_when_the_messenger_is_dropped() {
+        futures::executor::block_on(async {
+            let (world, _) = dm_world();
+            let (messenger, handle) = Mock::with_world(world);
+            let mut events = messenger
+                .clone()
+                .arc_text()
+                .unwrap()
+                .listen()
+                .await
+                .unwrap();
+            drop(messenger);
+            assert!(events.next().await.is_none());
+            assert!(!handle.inject(TextEvent::MessageDeleted {
+                room: Identifier::new(0, ()),
+                message_id: 0,
+            }));
+        });
+    }
+}
@@ -0,0 +1,102 @@
+//! The per-capability event queues and the `ArcStream` impls behind `listen`.
+//!
+//! Each capability gets an unbounded queue in [`Shared`]; a [`Listener`] drains
+//! its queue and, while parked, keeps releasing due timeline entries so a
+//! scripted event shows up on time even if nothing else touches the mock.
+
+use std::{marker::PhantomData, sync::Arc};
+
+use async_trait::async_trait;
+use futures::{
+    StreamExt,
+    channel::mpsc::{UnboundedReceiver, UnboundedSender, unbounded},
+    future::{Either, select},
+    lock::Mutex as AsyncMutex,
+};
+use futures_timer::Delay;
+
+use messenger_interface::interface::{ArcStream, PresenceEvent, QueryEvent, TextEvent, VoiceEvent};
+
+use crate::Shared;
+
+pub(crate) struct Queue<E> {
+    sender: UnboundedSender<E>,
+    receiver: AsyncMutex<UnboundedReceiver<E>>,
+}
+
+impl<E> Queue<E> {
+    pub(crate) fn new() -> Self {
+        let (sender, receiver) = unbounded();
+        Self {
+            sender,
+            receiver: AsyncMutex::new(receiver),
+        }
+    }
+
+    pub(crate) fn push(&self, event: E) {
+        // Only fails once closed, i.e. the messenger is being dropped and
+        // nobody is left to listen.
+        let _ = self.sender.unbounded_send(event);
+    }
+
+    /// Wake the consumer with end-of-stream once the queue is drained.
+    pub(crate) fn close(&self) {
+        self.sender.close_channel();
+    }
+}
+
+/// Maps an event type to its queue in [`Shared`].
+pub(crate) trait Routed: Sized + Send + 'static {
+    fn queue(shared: &Shared) -> &Queue<Self>;
+}
+impl Routed for QueryEvent {
+    fn queue(shared: &Shared) -> &Queue<Self> {
+        &shared.query_events
+    }
+}
+impl Routed for TextEvent {
+    fn queue(shared: &Shared) -> &Queue<Self> {
+        &shared.text_events
+    }
+}
+impl Routed for VoiceEvent {
+    fn queue(shared: &Shared) -> &Queue<Self> {
+        &shared.voice_events
+    }
+}
+impl Routed for PresenceEvent {
+    fn queue(shared: &Shared) -> &Queue<Self> {
+        &shared.presence_events
+    }
+}
+
+pub(crate) struct Listener<E> {
+    shared: Arc<Shared>,
+    _marker: PhantomData<fn() -> E>,
+}
+
+impl<E> Listener<E> {
+    pub(crate) fn new(shared: &Arc<Shared>) -> Arc<Self> {
+        Arc::new(Self {
+            shared: shared.clone(),
+            _marker: PhantomData,
+        })
+    }
+}
+
+#[async_trait]
+impl<E: Routed> ArcStream for Listener<E> {
+    type Item = E;
+
+    async fn next(self: Arc<Self>) -> Option<Self::Item> {
+        let mut receiver = E::queue(&self.shared).receiver.lock().await;
+        loop {
+            let park = self.shared.release_due();
+            match select(receiver.next(), Delay::new(park)).await {
+                // `None` once the messenger closed the queue on drop.
+                Either::Left((event, _)) => return event,
+                Either::Right(_) => continue,
+            }
+        }
+    }
+}
@@ -0,0 +1,50 @@
+//! The `Presence` capability, served from [`World::presences`](crate::World).
+//!
+//! Setting the client's status is dispatched like any other mutation, so it
+//! shows up in the presence stream the same way a scripted change does.
+
+use std::{collections::HashMap, error::Error, sync::Arc};
+
+use async_trait::async_trait;
+
+use messenger_interface::interface::{Presence, PresenceEvent, WeakSocketStream};
+use messenger_interface::types::{ID, Status, UserPresence};
+
+use crate::{MockMessenger, ScriptedEvent};
+
+#[async_trait]
+impl Presence for MockMessenger {
+    async fn presences(&self) -> Result<HashMap<ID, UserPresence>, Box<dyn Error + Sync + Send>> {
+        Ok(self.shared.world().presences.clone())
+    }
+
+    /// `Offline` is reported as `Invisible`, as on the real backends: the
+    /// client stays connected either way.
+    async fn set_status(&self, status: Status) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let (user_id, activity) = {
+            let world = self.shared.world();
+            let user_id = *world.client.id();
+            let activity = world
+                .presences
+                .get(&user_id)
+                .and_then(|presence| presence.activity.clone());
+            (user_id, activity)
+        };
+        let status = match status {
+            Status::Offline => Status::Invisible,
+            status => status,
+        };
+        self.shared
+            .dispatch(ScriptedEvent::Presence(PresenceEvent::PresenceUpdated {
+                user_id,
+                presence: UserPresence { status, activity },
+            }));
+        Ok(())
+    }
+
+    async fn listen(
+        self: Arc<Self>,
+    ) -> Result<WeakSocketStream<PresenceEvent>, Box<dyn Error + Sync + Send>> {
+        Ok(WeakSocketStream::from_arc(self.presence_listener.clone()))
+    }
+}
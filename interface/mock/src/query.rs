@@ -0,0 +1,390 @@
+//! `Query` and `Text` implementations over the in-memory [`World`](crate::World).
+//!
+//! Rooms are handed out *unfetched* (`messages: None`), like a real backend,
+//! so consumers go through `get_messages` to load history. Mutating calls
+//! build the matching event and [`dispatch`](crate::Shared::dispatch) it, so
+//! the caller sees the same echo on its `listen` stream that a server would
+//! send.
+
+use std::{collections::HashMap, error::Error, path::PathBuf, sync::Arc};
+
+use async_trait::async_trait;
+use chrono::Utc;
+
+use messenger_interface::interface::{
+    Ordering, Query, QueryEvent, Text, TextEvent, WeakSocketStream,
+};
+use messenger_interface::types::{
+    Attachment, House, ID, Identifier, Message, Place, ReadState, Reply, ReplyPreview, Revision,
+    Room, RoomCapabilities, User,
+};
+
+use crate::{MockMessenger, ScriptedEvent, World};
+
+/// Messages returned per `get_messages` page, matching Discord's default.
+const PAGE_SIZE: usize = 50;
+
+fn unfetched(room: &Identifier<Place<Room>>) -> Identifier<Place<Room>> {
+    let mut room = room.clone();
+    room.messages = None;
+    room
+}
+
+#[async_trait]
+impl Query for MockMessenger {
+    async fn client_user(&self) -> Result<Identifier<User>, Box<dyn Error + Sync + Send>> {
+        Ok(self.shared.world().client.clone())
+    }
+
+    async fn contacts(&self) -> Result<Vec<Identifier<User>>, Box<dyn Error + Sync + Send>> {
+        Ok(self.shared.world().contacts.clone())
+    }
+
+    async fn rooms(&self) -> Result<Vec<Identifier<Place<Room>>>, Box<dyn Error + Sync + Send>> {
+        Ok(self.shared.world().rooms.iter().map(unfetched).collect())
+    }
+
+    async fn houses(&self) -> Result<Vec<Identifier<Place<House>>>, Box<dyn Error + Sync + Send>> {
+        Ok(self
+            .shared
+            .world()
+            .houses
+            .iter()
+            .map(|house| {
+                house.swap_data(Place::new(
+                    house.name.clone(),
+                    house.icon.clone(),
+                    House::new(None),
+                ))
+            })
+            .collect())
+    }
+
+    async fn room_details(
+        &self,
+        room: Identifier<Place<Room>>,
+    ) -> Result<Room, Box<dyn Error + Sync + Send>> {
+        let world = self.shared.world();
+        let room = world
+            .room(*room.id())
+            .ok_or_else(|| format!("Mock: no room {}", room.id()))?;
+        Ok(Room::new(
+            room.room_capabilities,
+            room.participants.clone(),
+            None,
+        ))
+    }
+
+    async fn house_details(
+        &self,
+        house: Identifier<Place<House>>,
+    ) -> Result<House, Box<dyn Error + Sync + Send>> {
+        let world = self.shared.world();
+        let house = world
+            .houses
+            .iter()
+            .find(|h| h.id() == house.id())
+            .ok_or_else(|| format!("Mock: no house {}", house.id()))?;
+        Ok(House::new(Some(
+            house.rooms.iter().flatten().map(unfetched).collect(),
+        )))
+    }
+
+    async fn listen(
+        self: Arc<Self>,
+    ) -> Result<WeakSocketStream<QueryEvent>, Box<dyn Error + Sync + Send>> {
+        Ok(WeakSocketStream::from_arc(self.query_listener.clone()))
+    }
+}
+
+/// The world's copy of `message`, provided the client wrote it.
+fn own_message(
+    world: &mut World,
+    location: &Identifier<Place<Room>>,
+    message: &Identifier<Message>,
+) -> Result<Identifier<Message>, Box<dyn Error + Sync + Send>> {
+    let client_id = *world.client.id();
+    let existing = world
+        .message_mut(*location.id(), *message.id())
+        .ok_or_else(|| format!("Mock: no message {}", message.id()))?;
+    if existing.author.as_ref().map(|author| *author.id()) != Some(client_id) {
+        return Err(format!("Mock: message {} isn't the client's", message.id()).into());
+    }
+    Ok(Identifier::new(*message.id(), existing.clone()))
+}
+
+impl MockMessenger {
+    /// Post a message by the client into `location`, like a server accepting
+    /// a send.
+    fn post(
+        &self,
+        location: &Identifier<Place<Room>>,
+        contents: Message,
+        attachments: Vec<Attachment>,
+    ) -> Result<Identifier<Message>, Box<dyn Error + Sync + Send>> {
+        let message = {
+            let mut world = self.shared.world();
+            let room = world
+                .room(*location.id())
+                .ok_or_else(|| format!("Mock: no room {}", location.id()))?;
+            if !room.room_capabilities.contains(RoomCapabilities::Text) {
+                return Err(format!("Mock: room {} has no text chat", location.id()).into());
+            }
+            // Like Discord, a reply to a message that's gone still sends,
+            // just without the quote.
+            let reply = contents.reply.map(|reply| Reply {
+                preview: room
+                    .messages
+                    .iter()
+                    .flatten()
+                    .find(|message| *message.id() == reply.message_id)
+                    .map(|target| ReplyPreview {
+                        author: target
+                            .author
+                            .as_ref()
+                            .map(|author| author.name.clone())
+                            .unwrap_or_default(),
+                        text: target.content.text.to_plain(),
+                    }),
+                ..reply
+            });
+            let author = world.client.clone();
+            Identifier::new(
+                world.next_id(),
+                Message {
+                    content: Revision {
+                        at: Some(Utc::now()),
+                        text: contents.content.text,
+                    },
+                    attachments,
+                    author: Some(author),
+                    reply,
+                    ..Default::default()
+                },
+            )
+        };
+        self.shared
+            .dispatch(ScriptedEvent::Text(TextEvent::MessageCreated {
+                room: location.swap_data(()),
+                message: message.clone(),
+            }));
+        Ok(message)
+    }
+}
+
+#[async_trait]
+impl Text for MockMessenger {
+    /// `Ordering::Unordered` returns newest first, the way most backends page,
+    /// so callers that forget to ask for `Time` are caught in tests.
+    async fn get_messages(
+        &self,
+        location: &Identifier<Place<Room>>,
+        load_messages_before: Option<Identifier<Message>>,
+        ordering: Ordering,
+    ) -> Result<Vec<Identifier<Message>>, Box<dyn Error + Sync + Send>> {
+        let world = self.shared.world();
+        let room = world
+            .room(*location.id())
+            .ok_or_else(|| format!("Mock: no room {}", location.id()))?;
+        let history = room.messages.as_deref().unwrap_or_default();
+        let end = match &load_messages_before {
+            Some(before) => history.partition_point(|m| m.id() < before.id()),
+            None => history.len(),
+        };
+        let mut page = history[end.saturating_sub(PAGE_SIZE)..end].to_vec();
+        if ordering == Ordering::Unordered {
+            page.reverse();
+        }
+        Ok(page)
+    }
+
+    async fn add_reaction(
+        &self,
+        location: &Identifier<Place<Room>>,
+        message: &Identifier<Message>,
+        emoji: &str,
+    ) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let user_id = {
+            let mut world = self.shared.world();
+            let user_id = *world.client.id();
+            let message = world
+                .message_mut(*location.id(), *message.id())
+                .ok_or_else(|| format!("Mock: no message {}", message.id()))?;
+            if message
+                .reactions
+                .iter()
+                .any(|r| r.reacted && r.emoji.shortcode == emoji)
+            {
+                return Ok(());
+            }
+            user_id
+        };
+        self.shared
+            .dispatch(ScriptedEvent::Text(TextEvent::ReactionAdded {
+                room: location.swap_data(()),
+                message_id: *message.id(),
+                user_id,
+                emoji: emoji.to_owned(),
+            }));
+        Ok(())
+    }
+
+    async fn remove_reaction(
+        &self,
+        location: &Identifier<Place<Room>>,
+        message: &Identifier<Message>,
+        emoji: &str,
+    ) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let user_id = {
+            let mut world = self.shared.world();
+            let user_id = *world.client.id();
+            let message = world
+                .message_mut(*location.id(), *message.id())
+                .ok_or_else(|| format!("Mock: no message {}", message.id()))?;
+            if !message
+                .reactions
+                .iter()
+                .any(|r| r.reacted && r.emoji.shortcode == emoji)
+            {
+                return Ok(());
+            }
+            user_id
+        };
+        self.shared
+            .dispatch(ScriptedEvent::Text(TextEvent::ReactionRemoved {
+                room: location.swap_data(()),
+                message_id: *message.id(),
+                user_id,
+                emoji: emoji.to_owned(),
+            }));
+        Ok(())
+    }
+
+    async fn send_message(
+        &self,
+        location: &Identifier<Place<Room>>,
+        contents: Message,
+    ) -> Result<Identifier<Message>, Box<dyn Error + Sync + Send>> {
+        self.post(location, contents, Vec::new())
+    }
+
+    /// Nothing is uploaded: the attachments point at the picked files.
+    async fn send_files(
+        &self,
+        location: &Identifier<Place<Room>>,
+        contents: Message,
+        files: Vec<PathBuf>,
+    ) -> Result<Identifier<Message>, Box<dyn Error + Sync + Send>> {
+        let attachments = files
+            .into_iter()
+            .map(|path| {
+                let metadata = std::fs::metadata(&path)
+                    .map_err(|err| format!("Mock: reading {}: {err}", path.display()))?;
+                Ok(Attachment {
+                    filename: path
+                        .file_name()
+                        .map(|name| name.to_string_lossy().into_owned())
+                        .unwrap_or_default(),
+                    size: metadata.len(),
+                    mime: None,
+                    path: Some(path),
+                    dimensions: None,
+                })
+            })
+            .collect::<Result<_, Box<dyn Error + Sync + Send>>>()?;
+        self.post(location, contents, attachments)
+    }
+
+    fn can_edit(&self) -> bool {
+        true
+    }
+
+    /// Only the client's own messages can be edited or deleted, as in a
+    /// Discord DM.
+    async fn edit_message(
+        &self,
+        location: &Identifier<Place<Room>>,
+        message: &Identifier<Message>,
+        draft: &str,
+    ) -> Result<Identifier<Message>, Box<dyn Error + Sync + Send>> {
+        let mut edited = own_message(&mut self.shared.world(), location, message)?;
+        edited.edit(Revision {
+            at: Some(Utc::now()),
+            text: self.parse_draft(draft),
+        });
+        self.shared
+            .dispatch(ScriptedEvent::Text(TextEvent::MessageUpdated {
+                room: location.swap_data(()),
+                message: edited.clone(),
+            }));
+        Ok(edited)
+    }
+
+    async fn delete_message(
+        &self,
+        location: &Identifier<Place<Room>>,
+        message: &Identifier<Message>,
+    ) -> Result<(), Box<dyn Error + Sync + Send>> {
+        own_message(&mut self.shared.world(), location, message)?;
+        self.shared
+            .dispatch(ScriptedEvent::Text(TextEvent::MessageDeleted {
+                room: location.swap_data(()),
+                message_id: *message.id(),
+            }));
+        Ok(())
+    }
+
+    async fn read_states(&self) -> Result<HashMap<ID, ReadState>, Box<dyn Error + Sync + Send>> {
+        Ok(self.shared.world().read_states.clone())
+    }
+
+    /// Whatever others wrote after `message` stays unread.
+    async fn mark_read(
+        &self,
+        location: &Identifier<Place<Room>>,
+        message: &Identifier<Message>,
+    ) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let unread = {
+            let world = self.shared.world();
+            let client_id = *world.client.id();
+            let room = world
+                .room(*location.id())
+                .ok_or_else(|| format!("Mock: no room {}", location.id()))?;
+            room.messages
+                .iter()
+                .flatten()
+                .filter(|m| *m.id() > *message.id())
+                .filter(|m| m.author.as_ref().map(|author| *author.id()) != Some(client_id))
+                .count() as u32
+        };
+        self.shared
+            .dispatch(ScriptedEvent::Text(TextEvent::ReadStateUpdated {
+                room: location.swap_data(()),
+                state: ReadState {
+                    last_read: Some(*message.id()),
+                    unread,
+                    mentions: 0,
+                },
+            }));
+        Ok(())
+    }
+
+    /// Nobody else is watching the mock, so the indicator goes nowhere; the
+    /// room is still checked so callers see the same errors as `send_message`.
+    async fn send_typing(
+        &self,
+        location: &Identifier<Place<Room>>,
+    ) -> Result<(), Box<dyn Error + Sync + Send>> {
+        self.shared
+            .world()
+            .room(*location.id())
+            .map(|_| ())
+            .ok_or_else(|| format!("Mock: no room {}", location.id()).into())
+    }
+
+    async fn listen(
+        self: Arc<Self>,
+    ) -> Result<WeakSocketStream<TextEvent>, Box<dyn Error + Sync + Send>> {
+        Ok(WeakSocketStream::from_arc(self.text_listener.clone()))
+    }
+}
@@ -0,0 +1,277 @@
+//! The `Voice` capability: joinable calls with a loopback "echo test" audio path.
+//!
+//! Connecting puts the client in the room's voice roster and, after a short
+//! delay standing in for the signaling handshake, emits
+//! [`VoiceEvent::CallStreamReady`]. The call's [`CallAudio`] stream asks the
+//! app for a microphone input and an output source, then plays the microphone
+//! straight back, so the whole capture → mix → playback path runs with no
+//! remote end.
+//!
+//! The channels are requested in the same formats Discord voice uses (f32
+//! stereo in, i16 stereo out, both 48 kHz), since that is what the app
+//! configures for `AddAudioInput`/`AddAudioSource`.
+
+use std::{
+    error::Error,
+    mem,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use futures::{
+    channel::oneshot,
+    future::{Either, join, select},
+    lock::Mutex as AsyncMutex,
+};
+use futures_timer::Delay;
+use tracing::{debug, warn};
+
+use messenger_interface::interface::{
+    ArcStream, AudioEvent, CallStatus, MuteState, Voice, VoiceEvent, WeakSocketStream,
+};
+use messenger_interface::types::{Identifier, Place, Room, RoomCapabilities};
+use simple_audio_channels::{input::SampleConsumer, output::SampleProducer};
+
+use crate::{MockMessenger, ScriptedEvent};
+
+/// Stand-in for the signaling round trips before a real call's audio is up.
+const CALL_CONNECT_DELAY: Duration = Duration::from_millis(300);
+/// How long the loopback waits on the microphone before re-checking whether
+/// the call ended.
+const LOOPBACK_IDLE_CHECK: Duration = Duration::from_millis(100);
+/// One 20 ms stereo frame at 48 kHz, Discord's voice frame size.
+const LOOPBACK_FRAME_SAMPLES: usize = 1920;
+
+enum Loopback {
+    RequestInput,
+    RequestOutput(oneshot::Receiver<SampleConsumer>),
+    Attaching {
+        microphone: oneshot::Receiver<SampleConsumer>,
+        speaker: oneshot::Receiver<SampleProducer>,
+    },
+    Running {
+        microphone: Box<SampleConsumer>,
+        speaker: SampleProducer,
+    },
+    Ended,
+}
+
+/// The client's mute and deafen toggles, kept across calls like Discord's.
+#[derive(Default)]
+pub(crate) struct SelfVoice {
+    muted: AtomicBool,
+    deafened: AtomicBool,
+}
+
+impl SelfVoice {
+    fn state(&self) -> MuteState {
+        MuteState {
+            self_mute: self.muted.load(Ordering::Relaxed),
+            self_deaf: self.deafened.load(Ordering::Relaxed),
+            ..Default::default()
+        }
+    }
+}
+
+/// Audio stream of one mock call. Yields the two channel requests, then keeps
+/// pumping microphone samples to the speaker inside `next()` until the call
+/// ends, the same shape as the Discord audio loop.
+pub(crate) struct CallAudio {
+    ended: AtomicBool,
+    loopback: AsyncMutex<Loopback>,
+    self_voice: Arc<SelfVoice>,
+}
+
+impl CallAudio {
+    fn new(self_voice: Arc<SelfVoice>) -> Arc<Self> {
+        Arc::new(Self {
+            ended: AtomicBool::new(false),
+            loopback: AsyncMutex::new(Loopback::RequestInput),
+            self_voice,
+        })
+    }
+
+    pub(crate) fn end(&self) {
+        self.ended.store(true, Ordering::Release);
+    }
+}
+
+fn to_i16(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16
+}
+
+#[async_trait]
+impl ArcStream for CallAudio {
+    type Item = AudioEvent;
+
+    async fn next(self: Arc<Self>) -> Option<Self::Item> {
+        let mut loopback = self.loopback.lock().await;
+        let mut frame = [0f32; LOOPBACK_FRAME_SAMPLES];
+        let mut converted = [0i16; LOOPBACK_FRAME_SAMPLES];
+        loop {
+            if self.ended.load(Ordering::Acquire) {
+                *loopback = Loopback::Ended;
+                return None;
+            }
+            match mem::replace(&mut *loopback, Loopback::Ended) {
+                Loopback::RequestInput => {
+                    let (sender, receiver) = oneshot::channel();
+                    *loopback = Loopback::RequestOutput(receiver);
+                    return Some(AudioEvent::AddAudioInput(sender));
+                }
+                Loopback::RequestOutput(microphone) => {
+                    let (sender, receiver) = oneshot::channel();
+                    *loopback = Loopback::Attaching {
+                        microphone,
+                        speaker: receiver,
+                    };
+                    return Some(AudioEvent::AddAudioSource(sender));
+                }
+                Loopback::Attaching {
+                    microphone,
+                    speaker,
+                } => match join(microphone, speaker).await {
+                    (Ok(microphone), Ok(speaker)) => {
+                        debug!("Mock: call audio attached; looping the microphone back");
+                        *loopback = Loopback::Running {
+                            microphone: Box::new(microphone),
+                            speaker,
+                        };
+                    }
+                    _ => {
+                        warn!("Mock: the app dropped a call audio channel request");
+                        return None;
+                    }
+                },
+                Loopback::Running {
+                    mut microphone,
+                    mut speaker,
+                } => {
+                    let popped = {
+                        let pop = microphone.pop(&mut frame);
+                        futures::pin_mut!(pop);
+                        match select(pop, Delay::new(LOOPBACK_IDLE_CHECK)).await {
+                            Either::Left((popped, _)) => popped,
+                            Either::Right(_) => 0,
+                        }
+                    };
+                    for (out, sample) in converted.iter_mut().zip(&frame[..popped]) {
+                        *out = to_i16(*sample);
+                    }
+                    // Muted, nothing reaches the far end to come back;
+                    // deafened, nothing is heard. Either way the microphone
+                    // keeps draining so unmuting doesn't replay a backlog.
+                    // A full speaker buffer drops the overflow, like a late
+                    // network frame.
+                    if !self.self_voice.state().is_muted() {
+                        speaker.push_iter(&converted[..popped]);
+                    }
+                    *loopback = Loopback::Running {
+                        microphone,
+                        speaker,
+                    };
+                }
+                Loopback::Ended => return None,
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Voice for MockMessenger {
+    async fn connect(
+        &self,
+        location: &Identifier<Place<Room>>,
+    ) -> Result<CallStatus, Box<dyn Error + Sync + Send>> {
+        let client = {
+            let world = self.shared.world();
+            let room = world
+                .room(*location.id())
+                .ok_or_else(|| format!("Mock: no room {}", location.id()))?;
+            if !room.room_capabilities.contains(RoomCapabilities::Voice) {
+                return Err(format!("Mock: room {} has no voice chat", location.id()).into());
+            }
+            world.client.clone()
+        };
+
+        // Joining a second call leaves the first, as on Discord.
+        let audio = CallAudio::new(self.self_voice.clone());
+        if let Some((_, previous)) = self
+            .call
+            .lock()
+            .unwrap()
+            .replace((*location.id(), audio.clone()))
+        {
+            previous.end();
+        }
+
+        self.shared
+            .dispatch(ScriptedEvent::Voice(VoiceEvent::CallStatusUpdate(
+                CallStatus::Connecting("Ringing mock call"),
+            )));
+        self.shared
+            .dispatch(ScriptedEvent::Voice(VoiceEvent::ParticipantJoined {
+                room: location.swap_data(()),
+                user: client,
+            }));
+        self.shared.schedule(
+            CALL_CONNECT_DELAY,
+            ScriptedEvent::Voice(VoiceEvent::CallStreamReady(WeakSocketStream::from_arc(
+                audio,
+            ))),
+        );
+
+        Ok(CallStatus::Connecting("Joining mock call"))
+    }
+
+    async fn disconnect(&self, location: &Identifier<Place<Room>>) {
+        let call = {
+            let mut call = self.call.lock().unwrap();
+            let in_room = call.as_ref().is_some_and(|(room, _)| room == location.id());
+            if in_room { call.take() } else { None }
+        };
+        let Some((_, audio)) = call else {
+            return;
+        };
+        audio.end();
+        let user_id = *self.shared.world().client.id();
+        self.shared
+            .dispatch(ScriptedEvent::Voice(VoiceEvent::ParticipantLeft {
+                user_id,
+            }));
+    }
+
+    async fn set_self_mute(&self, muted: bool) -> Result<(), Box<dyn Error + Sync + Send>> {
+        self.self_voice.muted.store(muted, Ordering::Relaxed);
+        self.report_self_voice();
+        Ok(())
+    }
+
+    async fn set_self_deaf(&self, deafened: bool) -> Result<(), Box<dyn Error + Sync + Send>> {
+        self.self_voice.deafened.store(deafened, Ordering::Relaxed);
+        self.report_self_voice();
+        Ok(())
+    }
+
+    async fn listen(
+        self: Arc<Self>,
+    ) -> Result<WeakSocketStream<VoiceEvent>, Box<dyn Error + Sync + Send>> {
+        Ok(WeakSocketStream::from_arc(self.voice_listener.clone()))
+    }
+}
+
+impl MockMessenger {
+    /// Echo the toggles back the way a server confirms them.
+    fn report_self_voice(&self) {
+        let user_id = *self.shared.world().client.id();
+        self.shared
+            .dispatch(ScriptedEvent::Voice(VoiceEvent::MuteStateChanged {
+                user_id,
+                state: self.self_voice.state(),
+            }));
+    }
+}
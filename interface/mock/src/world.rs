@@ -0,0 +1,582 @@
+//! The scriptable in-memory state behind the mock messenger.
+//!
+//! A [`World`] holds everything a real backend would serve — the client user,
+//! contacts and their presence, direct-message rooms, houses with their rooms,
+//! per-room message history and read state — plus a timeline of [`Scheduled`]
+//! events that the mock releases into its listen streams as time passes.
+//! [`World::apply`] folds every released event back into the world, so a later
+//! fetch agrees with what the streams reported.
+
+use std::{collections::HashMap, time::Duration};
+
+use chrono::Utc;
+
+use messenger_interface::interface::{PresenceEvent, QueryEvent, TextEvent, VoiceEvent};
+use messenger_interface::types::{
+    Emoji, House, ID, Identifier, Message, Place, Reaction, ReadState, Revision, RichText, Room,
+    RoomCapabilities, Status, User, UserPresence,
+};
+
+/// Scenario loaded for an empty auth string.
+pub(crate) const DEMO_SCENARIO: &str = "demo";
+/// A world with only the client user in it.
+const EMPTY_SCENARIO: &str = "empty";
+
+/// One event of any capability, as injected into a running mock.
+pub enum ScriptedEvent {
+    Query(QueryEvent),
+    Text(TextEvent),
+    Voice(VoiceEvent),
+    Presence(PresenceEvent),
+}
+impl From<QueryEvent> for ScriptedEvent {
+    fn from(value: QueryEvent) -> Self {
+        Self::Query(value)
+    }
+}
+impl From<TextEvent> for ScriptedEvent {
+    fn from(value: TextEvent) -> Self {
+        Self::Text(value)
+    }
+}
+impl From<VoiceEvent> for ScriptedEvent {
+    fn from(value: VoiceEvent) -> Self {
+        Self::Voice(value)
+    }
+}
+impl From<PresenceEvent> for ScriptedEvent {
+    fn from(value: PresenceEvent) -> Self {
+        Self::Presence(value)
+    }
+}
+
+/// A timeline entry: `event` is released `after` the mock was built.
+pub struct Scheduled {
+    pub after: Duration,
+    pub event: ScriptedEvent,
+}
+impl Scheduled {
+    pub fn new(after: Duration, event: impl Into<ScriptedEvent>) -> Self {
+        Self {
+            after,
+            event: event.into(),
+        }
+    }
+}
+
+/// Everything the mock serves. Fields are public so tests can shape a world
+/// directly; the helper methods cover the common cases and keep IDs unique.
+pub struct World {
+    pub client: Identifier<User>,
+    pub contacts: Vec<Identifier<User>>,
+    /// Direct-message rooms, as returned by `Query::rooms`.
+    pub rooms: Vec<Identifier<Place<Room>>>,
+    /// Houses with their rooms always loaded; `Query::houses` strips them.
+    pub houses: Vec<Identifier<Place<House>>>,
+    /// Known presences by user ID; users missing here are offline.
+    pub presences: HashMap<ID, UserPresence>,
+    /// The client's read state by room ID; rooms missing here are read.
+    pub read_states: HashMap<ID, ReadState>,
+    pub timeline: Vec<Scheduled>,
+    next_id: ID,
+}
+
+impl World {
+    /// An empty world containing only the client user.
+    pub fn new(client_name: &str) -> Self {
+        Self {
+            client: Identifier::new(
+                1,
+                User {
+                    name: client_name.to_owned(),
+                    icon: None,
+                },
+            ),
+            contacts: Vec::new(),
+            rooms: Vec::new(),
+            houses: Vec::new(),
+            presences: HashMap::new(),
+            read_states: HashMap::new(),
+            timeline: Vec::new(),
+            next_id: 2,
+        }
+    }
+
+    /// Look up a built-in scenario by name. An empty name loads the demo.
+    pub fn scenario(name: &str) -> Option<Self> {
+        match name {
+            "" | DEMO_SCENARIO => Some(Self::demo()),
+            EMPTY_SCENARIO => Some(Self::new("You")),
+            _ => None,
+        }
+    }
+
+    /// Allocate a fresh ID. IDs only grow, so message IDs sort chronologically.
+    pub fn next_id(&mut self) -> ID {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    /// Add a user to the client's contacts.
+    pub fn add_user(&mut self, name: &str) -> Identifier<User> {
+        let user = Identifier::new(
+            self.next_id(),
+            User {
+                name: name.to_owned(),
+                icon: None,
+            },
+        );
+        self.contacts.push(user.clone());
+        user
+    }
+
+    /// Add a text-only direct-message room named after `user`.
+    pub fn add_dm(&mut self, user: &Identifier<User>) -> ID {
+        let id = self.next_id();
+        self.rooms.push(Identifier::new(
+            id,
+            Place::new(
+                user.name.clone(),
+                user.icon.clone(),
+                Room::new(RoomCapabilities::Text, None, Some(Vec::new())),
+            ),
+        ));
+        id
+    }
+
+    pub fn add_house(&mut self, name: &str) -> ID {
+        let id = self.next_id();
+        self.houses.push(Identifier::new(
+            id,
+            Place::new(name.to_owned(), None, House::new(Some(Vec::new()))),
+        ));
+        id
+    }
+
+    /// Add a room to `house`. Returns `None` if the house does not exist.
+    pub fn add_house_room(
+        &mut self,
+        house: ID,
+        name: &str,
+        capabilities: RoomCapabilities,
+    ) -> Option<ID> {
+        let id = self.next_id();
+        let house = self.houses.iter_mut().find(|h| *h.id() == house)?;
+        let participants = capabilities
+            .contains(RoomCapabilities::Voice)
+            .then(Vec::new);
+        house
+            .rooms
+            .get_or_insert_with(Vec::new)
+            .push(Identifier::new(
+                id,
+                Place::new(
+                    name.to_owned(),
+                    None,
+                    Room::new(capabilities, participants, Some(Vec::new())),
+                ),
+            ));
+        Some(id)
+    }
+
+    /// Build a new message by `author`, stamped now. Not added to any room.
+    pub fn message(&mut self, author: &Identifier<User>, text: &str) -> Identifier<Message> {
+        Identifier::new(
+            self.next_id(),
+            Message {
+                content: Revision {
+                    at: Some(Utc::now()),
+                    text: RichText::plain(text),
+                },
+                author: Some(author.clone()),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Append a message by `author` to `room`'s history.
+    pub fn post(&mut self, room: ID, author: &Identifier<User>, text: &str) -> Option<ID> {
+        let message = self.message(author, text);
+        let id = *message.id();
+        self.room_mut(room)?
+            .messages
+            .get_or_insert_with(Vec::new)
+            .push(message);
+        Some(id)
+    }
+
+    /// Put `user` in `room`'s voice roster, leaving any other room first.
+    pub fn join_voice(&mut self, room: ID, user: &Identifier<User>) {
+        self.leave_voice(*user.id());
+        if let Some(room) = self.room_mut(room) {
+            room.participants
+                .get_or_insert_with(Vec::new)
+                .push(user.clone());
+        }
+    }
+
+    pub fn leave_voice(&mut self, user_id: ID) {
+        for room in self.all_rooms_mut() {
+            if let Some(participants) = room.participants.as_mut() {
+                participants.retain(|participant| *participant.id() != user_id);
+            }
+        }
+    }
+
+    /// Schedule `event` to be released `after` the mock is built.
+    pub fn at(&mut self, after: Duration, event: impl Into<ScriptedEvent>) {
+        self.timeline.push(Scheduled::new(after, event));
+    }
+
+    fn all_rooms_mut(&mut self) -> impl Iterator<Item = &mut Identifier<Place<Room>>> {
+        self.rooms.iter_mut().chain(
+            self.houses
+                .iter_mut()
+                .filter_map(|house| house.rooms.as_mut())
+                .flatten(),
+        )
+    }
+
+    pub(crate) fn room(&self, room_id: ID) -> Option<&Identifier<Place<Room>>> {
+        self.rooms
+            .iter()
+            .chain(
+                self.houses
+                    .iter()
+                    .filter_map(|house| house.rooms.as_deref())
+                    .flatten(),
+            )
+            .find(|room| *room.id() == room_id)
+    }
+
+    pub(crate) fn room_mut(&mut self, room_id: ID) -> Option<&mut Identifier<Place<Room>>> {
+        self.all_rooms_mut().find(|room| *room.id() == room_id)
+    }
+
+    pub(crate) fn message_mut(&mut self, room_id: ID, message_id: ID) -> Option<&mut Message> {
+        self.room_mut(room_id)?
+            .messages
+            .as_mut()?
+            .iter_mut()
+            .find(|message| *message.id() == message_id)
+            .map(|message| &mut **message)
+    }
+
+    /// Fold a released event into the world.
+    pub(crate) fn apply(&mut self, event: &ScriptedEvent) {
+        match event {
+            ScriptedEvent::Query(QueryEvent::ChannelCreated { r#where, room }) => {
+                let rooms = match r#where {
+                    None => Some(&mut self.rooms),
+                    Some(house) => self
+                        .houses
+                        .iter_mut()
+                        .find(|h| h.id() == house.id())
+                        .map(|h| h.rooms.get_or_insert_with(Vec::new)),
+                };
+                if let Some(rooms) = rooms
+                    && !rooms.iter().any(|r| r.id() == room.id())
+                {
+                    rooms.push(room.clone());
+                }
+            }
+            ScriptedEvent::Query(QueryEvent::ChannelUpdated { r#where: _, room }) => {
+                if let Some(existing) = self.room_mut(*room.id()) {
+                    existing.name = room.name.clone();
+                    existing.icon = room.icon.clone();
+                    existing.room_capabilities = room.room_capabilities;
+                }
+            }
+            ScriptedEvent::Query(QueryEvent::ChannelDeleted {
+                r#where: _,
+                room_id,
+            }) => {
+                self.rooms.retain(|r| r.id() != room_id);
+                for house in &mut self.houses {
+                    if let Some(rooms) = house.rooms.as_mut() {
+                        rooms.retain(|r| r.id() != room_id);
+                    }
+                }
+            }
+            ScriptedEvent::Query(QueryEvent::HouseJoined { house }) => {
+                if !self.houses.iter().any(|h| h.id() == house.id()) {
+                    let mut house = house.clone();
+                    house.rooms.get_or_insert_with(Vec::new);
+                    self.houses.push(house);
+                }
+            }
+            ScriptedEvent::Query(QueryEvent::HouseUpdated { house }) => {
+                if let Some(existing) = self.houses.iter_mut().find(|h| h.id() == house.id()) {
+                    existing.name = house.name.clone();
+                    existing.icon = house.icon.clone();
+                }
+            }
+            ScriptedEvent::Query(QueryEvent::HouseLeft { house_id }) => {
+                self.houses.retain(|h| h.id() != house_id);
+            }
+            ScriptedEvent::Query(QueryEvent::ContactAdded { user }) => {
+                match self.contacts.iter_mut().find(|c| c.id() == user.id()) {
+                    Some(existing) => *existing = user.clone(),
+                    None => self.contacts.push(user.clone()),
+                }
+            }
+            ScriptedEvent::Query(QueryEvent::ContactRemoved { user_id }) => {
+                self.contacts.retain(|c| c.id() != user_id);
+            }
+            ScriptedEvent::Text(TextEvent::MessageCreated { room, message }) => {
+                let mut created = false;
+                if let Some(room) = self.room_mut(*room.id()) {
+                    let messages = room.messages.get_or_insert_with(Vec::new);
+                    if !messages.iter().any(|m| m.id() == message.id()) {
+                        messages.push(message.clone());
+                        created = true;
+                    }
+                }
+                if created {
+                    let from_client = message.author.as_ref().map(|author| *author.id())
+                        == Some(*self.client.id());
+                    // Like the real servers, sending reads the room up to the sent message.
+                    if from_client {
+                        self.read_states.insert(
+                            *room.id(),
+                            ReadState {
+                                last_read: Some(*message.id()),
+                                ..Default::default()
+                            },
+                        );
+                    } else {
+                        self.read_states.entry(*room.id()).or_default().unread += 1;
+                    }
+                }
+            }
+            ScriptedEvent::Text(TextEvent::MessageUpdated { room, message }) => {
+                if let Some(existing) = self.message_mut(*room.id(), *message.id()) {
+                    *existing = (**message).clone();
+                }
+            }
+            ScriptedEvent::Text(TextEvent::MessageDeleted { room, message_id }) => {
+                if let Some(room) = self.room_mut(*room.id())
+                    && let Some(messages) = room.messages.as_mut()
+                {
+                    messages.retain(|m| *m.id() != *message_id);
+                }
+            }
+            ScriptedEvent::Text(TextEvent::ReactionAdded {
+                room,
+                message_id,
+                user_id,
+                emoji,
+            }) => {
+                let is_client = *user_id == *self.client.id();
+                if let Some(message) = self.message_mut(*room.id(), *message_id) {
+                    match message
+                        .reactions
+                        .iter_mut()
+                        .find(|r| r.emoji.shortcode == *emoji)
+                    {
+                        Some(reaction) => {
+                            reaction.count += 1;
+                            reaction.reacted |= is_client;
+                        }
+                        None => message.reactions.push(Reaction {
+                            emoji: Emoji::shortcode(emoji.as_str()),
+                            count: 1,
+                            reacted: is_client,
+                        }),
+                    }
+                }
+            }
+            ScriptedEvent::Text(TextEvent::ReactionRemoved {
+                room,
+                message_id,
+                user_id,
+                emoji,
+            }) => {
+                let is_client = *user_id == *self.client.id();
+                if let Some(message) = self.message_mut(*room.id(), *message_id) {
+                    if let Some(reaction) = message
+                        .reactions
+                        .iter_mut()
+                        .find(|r| r.emoji.shortcode == *emoji)
+                    {
+                        reaction.count = reaction.count.saturating_sub(1);
+                        if is_client {
+                            reaction.reacted = false;
+                        }
+                    }
+                    message.reactions.retain(|r| r.count > 0);
+                }
+            }
+            // Typing is transient; consumers expire it themselves.
+            ScriptedEvent::Text(TextEvent::TypingStarted { .. }) => {}
+            ScriptedEvent::Text(TextEvent::ReadStateUpdated { room, state }) => {
+                self.read_states.insert(*room.id(), *state);
+            }
+            ScriptedEvent::Voice(VoiceEvent::ParticipantJoined { room, user }) => {
+                self.join_voice(*room.id(), user);
+            }
+            ScriptedEvent::Voice(VoiceEvent::ParticipantLeft { user_id }) => {
+                self.leave_voice(*user_id);
+            }
+            ScriptedEvent::Voice(
+                VoiceEvent::CallStatusUpdate(_)
+                | VoiceEvent::CallStreamReady(_)
+                | VoiceEvent::SpeakingChanged { .. }
+                | VoiceEvent::MuteStateChanged { .. },
+            ) => {}
+            ScriptedEvent::Presence(PresenceEvent::PresenceUpdated { user_id, presence }) => {
+                self.presences.insert(*user_id, presence.clone());
+            }
+        }
+    }
+
+    /// A small server, two DMs and a voice room, with a minute of scripted
+    /// activity so every live path in the UI has something to render.
+    fn demo() -> Self {
+        let mut world = Self::new("You");
+        let client = world.client.clone();
+        let alice = world.add_user("Alice");
+        let bob = world.add_user("Bob");
+        let carol = world.add_user("Carol");
+        world.presences.insert(
+            *alice.id(),
+            UserPresence {
+                status: Status::Online,
+                activity: Some("Playing Mock Quest".to_owned()),
+            },
+        );
+        world.presences.insert(
+            *bob.id(),
+            UserPresence {
+                status: Status::Idle,
+                activity: None,
+            },
+        );
+        world.presences.insert(
+            *carol.id(),
+            UserPresence {
+                status: Status::DoNotDisturb,
+                activity: None,
+            },
+        );
+
+        let alice_dm = world.add_dm(&alice);
+        world.post(alice_dm, &alice, "Hey! Is the new build working for you?");
+        world.post(alice_dm, &client, "Mostly. Still chasing a reconnect bug.");
+        let bob_dm = world.add_dm(&bob);
+        world.post(bob_dm, &bob, "Call later?");
+        world.read_states.insert(
+            bob_dm,
+            ReadState {
+                last_read: None,
+                unread: 1,
+                mentions: 0,
+            },
+        );
+
+        let house = world.add_house("Mock Server");
+        let general = world
+            .add_house_room(house, "general", RoomCapabilities::Text)
+            .expect("house was just added");
+        world.add_house_room(house, "random", RoomCapabilities::Text);
+        let lounge = world
+            .add_house_room(house, "Lounge", RoomCapabilities::Voice)
+            .expect("house was just added");
+        world.join_voice(lounge, &carol);
+        let welcome = world
+            .post(general, &carol, "Welcome to the mock server.")
+            .expect("room was just added");
+        let served = world
+            .post(general, &bob, "Everything here is served from memory.")
+            .expect("room was just added");
+        world.read_states.insert(
+            general,
+            ReadState {
+                last_read: Some(welcome),
+                unread: 1,
+                mentions: 0,
+            },
+        );
+
+        let ping = world.message(&alice, "Are you there?");
+        world.at(
+            Duration::from_secs(1),
+            TextEvent::TypingStarted {
+                room: Identifier::new(alice_dm, ()),
+                user: alice.clone(),
+            },
+        );
+        world.at(
+            Duration::from_secs(3),
+            TextEvent::MessageCreated {
+                room: Identifier::new(alice_dm, ()),
+                message: ping,
+            },
+        );
+        world.at(
+            Duration::from_secs(6),
+            TextEvent::ReactionAdded {
+                room: Identifier::new(general, ()),
+                message_id: welcome,
+                user_id: *alice.id(),
+                emoji: "👋".to_owned(),
+            },
+        );
+        world.at(
+            Duration::from_secs(9),
+            VoiceEvent::ParticipantJoined {
+                room: Identifier::new(lounge, ()),
+                user: bob.clone(),
+            },
+        );
+        let announcements = world.next_id();
+        world.at(
+            Duration::from_secs(12),
+            QueryEvent::ChannelCreated {
+                r#where: Some(Identifier::new(house, ())),
+                room: Identifier::new(
+                    announcements,
+                    Place::new(
+                        "announcements".to_owned(),
+                        None,
+                        Room::new(RoomCapabilities::Text, None, Some(Vec::new())),
+                    ),
+                ),
+            },
+        );
+        let mut edited = world
+            .room(general)
+            .and_then(|room| room.messages.as_ref())
+            .and_then(|messages| messages.iter().find(|m| *m.id() == served))
+            .cloned()
+            .expect("message was just posted");
+        edited.edit(Revision {
+            at: Some(Utc::now()),
+            text: RichText::plain("Everything here is served from memory, no network."),
+        });
+        world.at(
+            Duration::from_secs(15),
+            TextEvent::MessageUpdated {
+                room: Identifier::new(general, ()),
+                message: edited,
+            },
+        );
+        world.at(
+            Duration::from_secs(20),
+            PresenceEvent::PresenceUpdated {
+                user_id: *bob.id(),
+                presence: UserPresence {
+                    status: Status::Online,
+                    activity: None,
+                },
+            },
+        );
+        world.at(
+            Duration::from_secs(30),
+            VoiceEvent::ParticipantLeft { user_id: *bob.id() },
+        );
+
+        world
+    }
+}
@@ -5,16 +5,50 @@
 //! ID helpers translate between Steam's wire identifiers and the app's local
 //! [`ID`] namespace.
 
-use messenger_interface::types::{CacheCategory, ID};
+use messenger_interface::types::{CacheCategory, ID, Status, UserPresence};
 
 /// `k_EFriendRelationshipFriend` — the relationship value for an actual,
 /// mutually-accepted friend (as opposed to a pending/blocked entry).
 pub(crate) const EFRIENDRELATIONSHIP_FRIEND: u32 = 3;
 /// `k_EChatEntryTypeChatMsg` — a normal chat message.
 pub(crate) const CHAT_ENTRY_TYPE_CHAT_MSG: i32 = 1;
+/// `k_EChatEntryTypeTyping` — the sender is typing; carries no text.
+pub(crate) const CHAT_ENTRY_TYPE_TYPING: i32 = 2;
+
+// `EPersonaState` values, as carried by `CMsgClientPersonaState` and sent in
+// `CMsgClientChangeStatus`.
+pub(crate) const PERSONA_STATE_OFFLINE: u32 = 0;
+pub(crate) const PERSONA_STATE_ONLINE: u32 = 1;
+pub(crate) const PERSONA_STATE_BUSY: u32 = 2;
+pub(crate) const PERSONA_STATE_AWAY: u32 = 3;
+pub(crate) const PERSONA_STATE_SNOOZE: u32 = 4;
+pub(crate) const PERSONA_STATE_INVISIBLE: u32 = 7;
+
+/// Fold an `EPersonaState` into the interface's status. "Looking to
+/// trade/play" are online states with a flair Steam no longer shows.
+pub(crate) fn status_from_persona_state(persona_state: u32) -> Status {
+    match persona_state {
+        PERSONA_STATE_OFFLINE => Status::Offline,
+        PERSONA_STATE_BUSY => Status::DoNotDisturb,
+        PERSONA_STATE_AWAY | PERSONA_STATE_SNOOZE => Status::Idle,
+        PERSONA_STATE_INVISIBLE => Status::Invisible,
+        _ => Status::Online,
+    }
+}
+
+/// The `EPersonaState` to request for `status`. Going offline would sign the
+/// client out of friends entirely, so `Offline` asks for invisible instead.
+pub(crate) fn persona_state_for(status: Status) -> u32 {
+    match status {
+        Status::Online => PERSONA_STATE_ONLINE,
+        Status::Idle => PERSONA_STATE_AWAY,
+        Status::DoNotDisturb => PERSONA_STATE_BUSY,
+        Status::Invisible | Status::Offline => PERSONA_STATE_INVISIBLE,
+    }
+}
 
 /// Cached per-friend data, merged from two Steam pushes: the friends list
-/// (relationship) and persona state (display name + avatar hash).
+/// (relationship) and persona state (display name, avatar hash, presence).
 #[derive(Default, Clone)]
 pub(crate) struct FriendEntry {
     pub(crate) name: Option<String>,
@@ -22,6 +56,7 @@ pub(crate) struct FriendEntry {
     /// Hex-encoded avatar SHA1, used to build the CDN URL. `None` means no
     /// custom avatar.
     pub(crate) avatar_hash: Option<String>,
+    pub(crate) presence: UserPresence,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -16,6 +16,7 @@
 //! - [`session`]: the live [`Connected`](session::Connected) session and the
 //!   friend/persona/message update streams it drives.
 //! - [`query`]: the `Query`/`Text` implementations.
+//! - [`presence`]: the `Presence` capability and its event stream.
 //! - [`voice`]: the `Voice` capability — currently a signaling-only scaffold
 //!   (join/leave a Steam voice session; no audio transport yet).
 //! - [`gns`]: GameNetworkingSockets P2P media — an integration skeleton for the
@@ -77,6 +78,7 @@ use tracing::{error, info};
 
 use messenger_interface::interface::Messenger;
 
+use crate::presence::PresenceStream;
 use crate::session::Connected;
 
 mod api_types;
@@ -84,6 +86,7 @@ mod api_types;
 mod capture;
 mod downloaders;
 mod gns;
+mod presence;
 mod query;
 mod rich;
 mod session;
@@ -119,15 +122,17 @@ pub(crate) struct SteamMessenger {
     /// Lazily-established session, behind an async mutex so concurrent first
     /// callers log in exactly once.
     connected: AsyncMutex<Option<Arc<Connected>>>,
+    presence_stream: Arc<PresenceStream>,
 }
 
 impl SteamMessenger {
     fn build(username: String, secret: String, guard_code: Option<String>) -> Arc<dyn Messenger> {
-        Arc::new(SteamMessenger {
+        Arc::new_cyclic(|messenger| SteamMessenger {
             username,
             secret: Mutex::new(secret.into()),
             guard_code,
             connected: AsyncMutex::new(None),
+            presence_stream: PresenceStream::new(messenger.clone()),
         })
     }
 
@@ -139,11 +144,12 @@ impl SteamMessenger {
         secret: String,
         guard_code: Option<String>,
     ) -> Arc<Self> {
-        Arc::new(SteamMessenger {
+        Arc::new_cyclic(|messenger| SteamMessenger {
             username,
             secret: Mutex::new(secret.into()),
             guard_code,
             connected: AsyncMutex::new(None),
+            presence_stream: PresenceStream::new(messenger.clone()),
         })
     }
 
@@ -0,0 +1,127 @@
+//! The `Presence` capability: friends' persona states and the client's own.
+//!
+//! Presence rides the same persona-state pushes that carry names and avatars,
+//! so the [`session`](crate::session) records it in the friends cache and
+//! reports changes through its update poll. Whoever polls (queries, the text
+//! stream, or [`PresenceStream`]) queues them for this stream to hand out.
+//!
+//! `SteamMessenger` is already the `ArcStream` of text events, so the presence
+//! stream is a separate object the messenger owns. It only holds the messenger
+//! weakly, and ends once the app drops its last handle.
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    sync::{Arc, Weak},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use futures_timer::Delay;
+use steam_vent::ConnectionTrait;
+use steam_vent_proto::steammessages_clientserver_friends::CMsgClientChangeStatus;
+use tracing::warn;
+
+use messenger_interface::interface::{ArcStream, Presence, PresenceEvent, WeakSocketStream};
+use messenger_interface::types::{ID, Status, UserPresence};
+
+use crate::SteamMessenger;
+use crate::api_types::{EFRIENDRELATIONSHIP_FRIEND, persona_state_for};
+use crate::session::PresencePoll;
+
+const PRESENCE_IDLE_CHECK: Duration = Duration::from_millis(250);
+/// The text stream owns reconnecting; this stream only waits for it.
+const PRESENCE_RECONNECT_WAIT: Duration = Duration::from_secs(5);
+
+pub(crate) struct PresenceStream {
+    messenger: Weak<SteamMessenger>,
+}
+
+impl PresenceStream {
+    pub(crate) fn new(messenger: Weak<SteamMessenger>) -> Arc<Self> {
+        Arc::new(Self { messenger })
+    }
+}
+
+#[async_trait]
+impl ArcStream for PresenceStream {
+    type Item = PresenceEvent;
+
+    async fn next(self: Arc<Self>) -> Option<Self::Item> {
+        loop {
+            // Upgrade per poll and let go before waiting, so this stream never
+            // keeps the messenger (or the text stream) alive by itself.
+            let connected = {
+                let messenger = self.messenger.upgrade()?;
+                if Arc::strong_count(&messenger) == 1 {
+                    return None;
+                }
+                messenger.connected().await
+            };
+            let connected = match connected {
+                Ok(connected) => connected,
+                Err(err) => {
+                    warn!("Steam: presence stream has no session ({err})");
+                    Delay::new(PRESENCE_RECONNECT_WAIT).await;
+                    continue;
+                }
+            };
+
+            match connected.next_presence_event(PRESENCE_IDLE_CHECK).await {
+                PresencePoll::Presence(event) => return Some(event),
+                PresencePoll::Idle => continue,
+                PresencePoll::Disconnected => Delay::new(PRESENCE_RECONNECT_WAIT).await,
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Presence for SteamMessenger {
+    async fn presences(&self) -> Result<HashMap<ID, UserPresence>, Box<dyn Error + Sync + Send>> {
+        let connected = self.connected().await?;
+        Ok(connected
+            .friends
+            .iter()
+            .filter(|entry| entry.relationship == EFRIENDRELATIONSHIP_FRIEND)
+            .map(|entry| (*entry.key(), entry.presence.clone()))
+            .collect())
+    }
+
+    async fn set_status(&self, status: Status) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let connected = self.connected().await?;
+        let conn = connected.conn.clone();
+        self.run(async move {
+            conn.send(CMsgClientChangeStatus {
+                persona_state: Some(persona_state_for(status)),
+                persona_set_by_user: Some(true),
+                ..Default::default()
+            })
+            .await
+        })
+        .await??;
+
+        // The client is not in its own friends cache, so report it here.
+        let status = match status {
+            Status::Offline => Status::Invisible,
+            status => status,
+        };
+        connected
+            .queue_presence_events([PresenceEvent::PresenceUpdated {
+                user_id: connected.client_steamid,
+                presence: UserPresence {
+                    status,
+                    activity: None,
+                },
+            }])
+            .await;
+        Ok(())
+    }
+
+    async fn listen(
+        self: Arc<Self>,
+    ) -> Result<WeakSocketStream<PresenceEvent>, Box<dyn Error + Sync + Send>> {
+        self.connected().await?;
+        Ok(WeakSocketStream::from_arc(self.presence_stream.clone()))
+    }
+}
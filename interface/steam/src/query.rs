@@ -1,4 +1,4 @@
-//! `Query` (profile/friends) and `Text` (history/send) implementations.
+//! `Query` (profile/friends) and `Text` (history/send/delete) implementations.
 //!
 //! Each method resolves the live [`Connected`](crate::Connected) session, then
 //! either reads a background-populated cache (friends) or runs a Steam RPC
@@ -19,31 +19,33 @@ use futures::lock::Mutex as AsyncMutex;
 use futures_timer::Delay;
 
 use messenger_interface::interface::{
-    ArcStream, Ordering, Query, Text, TextEvent, WeakSocketStream,
+    ArcStream, MessengerError, Ordering, Query, Text, TextEvent, WeakSocketStream,
 };
 use messenger_interface::types::{
-    Emoji, House, ID, Identifier, Message, Place, Reaction, Revision, Room, RoomCapabilities, User,
+    Emoji, House, ID, Identifier, Message, Place, Reaction, ReadState, Revision, RichText, Room,
+    RoomCapabilities, User,
 };
 use steam_vent::{Connection, ConnectionTrait};
 use steam_vent_proto::steammessages_chat_steamclient::{
+    CChatRoom_AckChatMessage_Notification, CChatRoom_DeleteChatMessages_Request,
     CChatRoom_GetChatRoomGroupState_Request, CChatRoom_GetChatRoomGroupSummary_Response,
     CChatRoom_GetMessageHistory_Request, CChatRoom_GetMyChatRoomGroups_Request,
     CChatRoom_SendChatMessage_Request, CChatRoom_SetSessionActiveChatRoomGroups_Request,
     CChatRoomGroupState, CChatRoomState, EChatRoomJoinState,
-    cchat_room_get_message_history_response,
+    cchat_room_delete_chat_messages_request, cchat_room_get_message_history_response,
 };
 use steam_vent_proto::steammessages_friendmessages_steamclient::{
-    CFriendMessages_GetRecentMessages_Request, CFriendMessages_SendMessage_Request,
-    CFriendsMessages_GetActiveMessageSessions_Request,
+    CFriendMessages_AckMessage_Notification, CFriendMessages_GetRecentMessages_Request,
+    CFriendMessages_SendMessage_Request, CFriendsMessages_GetActiveMessageSessions_Request,
 };
 use steam_vent_proto::steammessages_player_steamclient::CPlayer_GetPlayerLinkDetails_Request;
 use tracing::{debug, error, warn};
 
 use crate::SteamMessenger;
 use crate::api_types::{
-    CHAT_ENTRY_TYPE_CHAT_MSG, ChatGroupEntry, ChatRoomEntry, ChatRoomLocation,
-    EFRIENDRELATIONSHIP_FRIEND, FriendEntry, account_id_to_steam_id, hex_avatar, message_id,
-    steam_group_room_id,
+    CHAT_ENTRY_TYPE_CHAT_MSG, CHAT_ENTRY_TYPE_TYPING, ChatGroupEntry, ChatRoomEntry,
+    ChatRoomLocation, EFRIENDRELATIONSHIP_FRIEND, FriendEntry, account_id_to_steam_id, hex_avatar,
+    message_id, steam_group_room_id,
 };
 use crate::downloaders::{cache_avatar, steam_user_identifier};
 use crate::session::{Connected, TextPoll};
@@ -952,6 +954,14 @@ impl Text for SteamMessenger {
         Ok(messages)
     }
 
+    fn parse_draft(&self, draft: &str) -> RichText {
+        crate::rich::parse_draft(draft)
+    }
+
+    fn write_draft(&self, text: &RichText) -> String {
+        crate::rich::to_bbcode(text)
+    }
+
     async fn send_message(
         &self,
         location: &Identifier<Place<Room>>,
@@ -959,8 +969,12 @@ impl Text for SteamMessenger {
     ) -> Result<Identifier<Message>, Box<dyn Error + Sync + Send>> {
         let connected = self.connected().await?;
         let conn = connected.conn.clone();
-        // The user composes plain text; flatten to a string to send.
-        let outgoing = contents.content.text.to_plain();
+        // A typed draft goes out as typed, so BBCode the parser doesn't know
+        // (`[quote=…]`, ...) isn't escaped into literal text.
+        let outgoing = contents
+            .draft
+            .clone()
+            .unwrap_or_else(|| crate::rich::to_bbcode(&contents.content.text));
         let room_location = connected
             .chat_room_locations
             .get(location.id())
@@ -1041,6 +1055,166 @@ impl Text for SteamMessenger {
         ))
     }
 
+    // Steam has no message editing, so `can_edit` and `edit_message` keep the
+    // defaults.
+
+    async fn delete_message(
+        &self,
+        location: &Identifier<Place<Room>>,
+        message: &Identifier<Message>,
+    ) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let connected = self.connected().await?;
+        let conn = connected.conn.clone();
+        let room_location = connected
+            .chat_room_locations
+            .get(location.id())
+            .map(|location| *location)
+            .unwrap_or(ChatRoomLocation::Direct {
+                steamid: *location.id(),
+            });
+
+        let ChatRoomLocation::Group {
+            chat_group_id,
+            chat_id,
+        } = room_location
+        else {
+            return Err("Steam: friend messages can't be deleted".into());
+        };
+
+        // Message IDs are `message_id(server_timestamp, ordinal)`; split
+        // them back into the pair Steam addresses messages by.
+        let id = *message.id();
+        self.run(async move {
+            conn.service_method(CChatRoom_DeleteChatMessages_Request {
+                chat_group_id: Some(chat_group_id),
+                chat_id: Some(chat_id),
+                messages: vec![cchat_room_delete_chat_messages_request::Message {
+                    server_timestamp: Some((id >> 32) as u32),
+                    ordinal: Some(id as u32),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            })
+            .await
+        })
+        .await??;
+        Ok(())
+    }
+
+    /// Only friend DMs: Steam keeps their unread counts with the message
+    /// sessions. It tracks when a chat was last viewed rather than up to
+    /// which message, so `last_read` is a marker sorting after every message
+    /// sent by then.
+    async fn read_states(&self) -> Result<HashMap<ID, ReadState>, Box<dyn Error + Sync + Send>> {
+        let connected = self.connected().await?;
+        let conn = connected.conn.clone();
+        let response = self
+            .run(async move {
+                conn.service_method(CFriendsMessages_GetActiveMessageSessions_Request {
+                    lastmessage_since: Some(0),
+                    only_sessions_with_messages: Some(true),
+                    ..Default::default()
+                })
+                .await
+            })
+            .await??;
+
+        let client_steamid = connected.client_steamid;
+        Ok(response
+            .message_sessions
+            .into_iter()
+            .filter_map(|session| {
+                let steamid = account_id_to_steam_id(client_steamid, session.accountid_friend?);
+                let last_view = session.last_view.unwrap_or(0);
+                let state = ReadState {
+                    last_read: (last_view != 0).then(|| message_id(last_view, u32::MAX)),
+                    unread: session.unread_message_count.unwrap_or(0),
+                    mentions: 0,
+                };
+                Some((steamid, state))
+            })
+            .collect())
+    }
+
+    async fn mark_read(
+        &self,
+        location: &Identifier<Place<Room>>,
+        message: &Identifier<Message>,
+    ) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let connected = self.connected().await?;
+        let conn = connected.conn.clone();
+        let room_location = connected
+            .chat_room_locations
+            .get(location.id())
+            .map(|location| *location)
+            .unwrap_or(ChatRoomLocation::Direct {
+                steamid: *location.id(),
+            });
+        // Steam acks by time; message IDs carry the server timestamp on top.
+        let timestamp = (*message.id() >> 32) as u32;
+
+        match room_location {
+            ChatRoomLocation::Direct { steamid } => {
+                self.run(async move {
+                    conn.send_notification(CFriendMessages_AckMessage_Notification {
+                        steamid_partner: Some(steamid),
+                        timestamp: Some(timestamp),
+                        ..Default::default()
+                    })
+                    .await
+                })
+                .await??
+            }
+            ChatRoomLocation::Group {
+                chat_group_id,
+                chat_id,
+            } => {
+                self.run(async move {
+                    conn.send_notification(CChatRoom_AckChatMessage_Notification {
+                        chat_group_id: Some(chat_group_id),
+                        chat_id: Some(chat_id),
+                        timestamp: Some(timestamp),
+                        ..Default::default()
+                    })
+                    .await
+                })
+                .await??
+            }
+        }
+        Ok(())
+    }
+
+    async fn send_typing(
+        &self,
+        location: &Identifier<Place<Room>>,
+    ) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let connected = self.connected().await?;
+        let conn = connected.conn.clone();
+        let room_location = connected
+            .chat_room_locations
+            .get(location.id())
+            .map(|location| *location)
+            .unwrap_or(ChatRoomLocation::Direct {
+                steamid: *location.id(),
+            });
+
+        // Group chats have no typing indicator.
+        let ChatRoomLocation::Direct { steamid: friend } = room_location else {
+            return Err(Box::new(MessengerError::NotImplemented));
+        };
+
+        self.run(async move {
+            conn.service_method(CFriendMessages_SendMessage_Request {
+                steamid: Some(friend),
+                chat_entry_type: Some(CHAT_ENTRY_TYPE_TYPING),
+                ..Default::default()
+            })
+            .await
+        })
+        .await??;
+        Ok(())
+    }
+
     async fn listen(
         self: Arc<Self>,
     ) -> Result<WeakSocketStream<TextEvent>, Box<dyn Error + Sync + Send>> {
@@ -1,11 +1,15 @@
 //! Parse Steam chat content into the interface [`RichText`] model.
 //!
 //! Steam carries everything in one string: plain text, BBCode (`[b]`, `[i]`,
-//! `[u]`, `[url]`), inline emoticons as `[emoticon]name[/emoticon]` tags, and stickers as a
-//! `[sticker …]` tag. (The proto has no structured emoji/sticker field — see
+//! `[u]`, `[strike]`, `[spoiler]`, `[code]`, `[url]`), inline emoticons as
+//! `[emoticon]name[/emoticon]` tags, and stickers as a `[sticker …]` tag; a
+//! literal `[` is escaped as `\[`, and a backslash as `\\`. (The proto has
+//! no structured emoji/sticker field — see
 //! [[project_steam_vent_no_disconnect_signal]] siblings.) We parse it here so
 //! the UI renders one uniform span model. The tokenizer is pure and unit
 //! tested; emoticon/sticker image resolution happens in the async pass.
+//! [`to_bbcode`] goes the other way for sending rich text that wasn't typed;
+//! drafts go out as typed.
 //!
 //! Sticker note: Steam sends stickers as `[sticker type="name" limit="0"][/sticker]`
 //! (confirmed from live data). [`resolve_sticker`] reads the name from `type` and
@@ -26,6 +30,9 @@ const EMOTICON_CDN: &str = "https://community.steamstatic.com/economy/emoticon";
 /// emoticons; the path segment is the sticker's name/path (see [`resolve_sticker`]).
 const STICKER_CDN: &str = "https://community.steamstatic.com/economy/sticker";
 
+/// The styling tags, outermost first as [`to_bbcode`] nests them.
+const STYLE_TAGS: [&str; 5] = ["spoiler", "u", "strike", "b", "i"];
+
 #[derive(Debug, PartialEq)]
 enum Piece {
     Text { text: String, style: TextStyle },
@@ -89,18 +96,54 @@ fn match_url(rest: &str) -> Option<(usize, Piece)> {
     ))
 }
 
-/// `[tag]inner[/tag]` for a simple styling tag. `[u]` maps to no style (we have
-/// no underline yet) but its tags are still stripped.
-fn match_simple_tag(rest: &str, tag: &str, style: TextStyle) -> Option<(usize, Piece)> {
+/// Where `close` next appears in `inner`, skipping escaped brackets and
+/// `[code]` spans.
+fn find_close(inner: &str, close: &str) -> Option<usize> {
+    let mut at = 0;
+    while at < inner.len() {
+        let rest = &inner[at..];
+        if rest.starts_with(close) {
+            return Some(at);
+        }
+        at += match match_code(rest, TextStyle::default()) {
+            Some((consumed, _)) => consumed,
+            None if rest.starts_with("\\[") || rest.starts_with("\\\\") => 2,
+            None => rest.chars().next()?.len_utf8(),
+        };
+    }
+    None
+}
+
+/// `[tag]inner[/tag]` for a styling tag, the inner content itself parsed
+/// with the tag's style added.
+fn match_simple_tag(rest: &str, tag: &str, style: TextStyle) -> Option<(usize, Vec<Piece>)> {
     let open = ["[", tag, "]"].concat();
     let close = ["[/", tag, "]"].concat();
     let inner = rest.strip_prefix(&open)?;
-    let end = inner.find(&close)?;
+    let end = find_close(inner, &close)?;
     Some((
         open.len() + end + close.len(),
+        inline(&inner[..end], tagged(tag, style)),
+    ))
+}
+
+/// `[code]code[/code]`: shown verbatim, nothing inside is BBCode.
+fn match_code(rest: &str, style: TextStyle) -> Option<(usize, Piece)> {
+    const OPEN: &str = "[code]";
+    const CLOSE: &str = "[/code]";
+    let inner = rest.strip_prefix(OPEN)?;
+    let end = inner.find(CLOSE)?;
+    if end == 0 {
+        return None;
+    }
+    Some((
+        OPEN.len() + end + CLOSE.len(),
         Piece::Text {
             text: inner[..end].to_owned(),
-            style,
+            style: TextStyle {
+                code: true,
+                ..style
+            },
         },
     ))
 }
@@ -125,37 +168,65 @@ fn match_emoticon(rest: &str) -> Option<(usize, Piece)> {
     ))
 }
 
-fn match_special(rest: &str) -> Option<(usize, Piece)> {
-    let bold = TextStyle {
-        bold: true,
-        ..Default::default()
-    };
-    let italic = TextStyle {
-        italic: true,
-        ..Default::default()
-    };
-    match_sticker(rest)
-        .or_else(|| match_url(rest))
-        .or_else(|| match_simple_tag(rest, "b", bold))
-        .or_else(|| match_simple_tag(rest, "i", italic))
-        .or_else(|| match_simple_tag(rest, "u", TextStyle::default()))
-        .or_else(|| match_emoticon(rest))
+/// `style` with the styling `tag` stands for added.
+fn tagged(tag: &str, mut style: TextStyle) -> TextStyle {
+    match tag {
+        "b" => style.bold = true,
+        "i" => style.italic = true,
+        "u" => style.underline = true,
+        "strike" => style.strikethrough = true,
+        _ => style.spoiler = true,
+    }
+    style
 }
 
-fn tokenize(content: &str) -> Vec<Piece> {
+fn has_tag(style: TextStyle, tag: &str) -> bool {
+    match tag {
+        "b" => style.bold,
+        "i" => style.italic,
+        "u" => style.underline,
+        "strike" => style.strikethrough,
+        _ => style.spoiler,
+    }
+}
+
+fn match_special(rest: &str, style: TextStyle) -> Option<(usize, Vec<Piece>)> {
+    let single =
+        |matched: Option<(usize, Piece)>| matched.map(|(consumed, piece)| (consumed, vec![piece]));
+    single(match_sticker(rest))
+        .or_else(|| single(match_url(rest)))
+        .or_else(|| single(match_code(rest, style)))
+        .or_else(|| {
+            STYLE_TAGS
+                .into_iter()
+                .find_map(|tag| match_simple_tag(rest, tag, style))
+        })
+        .or_else(|| single(match_emoticon(rest)))
+}
+
+/// Split `content` into [`Piece`]s, styled on top of `style`. Plain runs
+/// accumulate into `Text` pieces; `\[` is a literal bracket and `\\` a
+/// literal backslash.
+fn inline(content: &str, style: TextStyle) -> Vec<Piece> {
     let mut pieces = Vec::new();
     let mut text = String::new();
     let mut rest = content;
 
     while !rest.is_empty() {
-        if let Some((consumed, piece)) = match_special(rest) {
+        if let Some(after) = rest.strip_prefix("\\[") {
+            text.push('[');
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("\\\\") {
+            text.push('\\');
+            rest = after;
+        } else if let Some((consumed, special)) = match_special(rest, style) {
             if !text.is_empty() {
                 pieces.push(Piece::Text {
                     text: std::mem::take(&mut text),
-                    style: TextStyle::default(),
+                    style,
                 });
             }
-            pieces.push(piece);
+            pieces.extend(special);
             rest = &rest[consumed..];
         } else {
             let ch = rest.chars().next().expect("rest is non-empty");
@@ -164,14 +235,15 @@ fn tokenize(content: &str) -> Vec<Piece> {
         }
     }
     if !text.is_empty() {
-        pieces.push(Piece::Text {
-            text,
-            style: TextStyle::default(),
-        });
+        pieces.push(Piece::Text { text, style });
     }
     pieces
 }
 
+fn tokenize(content: &str) -> Vec<Piece> {
+    inline(content, TextStyle::default())
+}
+
 /// Download+cache a Steam emoticon image, returning the local path.
 async fn resolve_emoticon(name: &str) -> Option<PathBuf> {
     let url = format!("{EMOTICON_CDN}/{name}");
@@ -276,8 +348,8 @@ pub async fn build_content(raw: &str) -> RichText {
             Piece::Emoticon { name } => {
                 let image = resolve_emoticon(&name).await;
                 spans.push(Span::Emoji(Emoji {
-                    shortcode: name,
                     image,
+                    ..Emoji::shortcode(name)
                 }));
             }
             Piece::Sticker { raw } => {
@@ -289,6 +361,88 @@ pub async fn build_content(raw: &str) -> RichText {
     RichText { spans }
 }
 
+/// Parse BBCode the user typed, for the local echo of the draft (which is
+/// itself sent as typed). Nothing is fetched, so emoticons and stickers
+/// come without images.
+pub fn parse_draft(draft: &str) -> RichText {
+    let spans = tokenize(draft)
+        .into_iter()
+        .map(|piece| match piece {
+            Piece::Text { text, style } => Span::Text { text, style },
+            Piece::Link { text, href } => Span::Link { text, href },
+            Piece::Emoticon { name } => Span::Emoji(Emoji::shortcode(name)),
+            Piece::Sticker { .. } => Span::Sticker {
+                alt: "[sticker]".to_owned(),
+                image: None,
+            },
+        })
+        .collect();
+    RichText { spans }
+}
+
+/// Append `text` with its brackets and backslashes escaped, so no tag in it
+/// is read back, nor is a tag after it escaped.
+fn push_escaped(out: &mut String, text: &str) {
+    out.push_str(&text.replace('\\', "\\\\").replace('[', "\\["));
+}
+
+/// Append a run of text, inside a tag for each style it has.
+fn push_text(out: &mut String, text: &str, style: TextStyle) {
+    if text.is_empty() {
+        return;
+    }
+    let tags: Vec<&str> = STYLE_TAGS
+        .into_iter()
+        .filter(|tag| has_tag(style, tag))
+        .collect();
+    for tag in &tags {
+        out.push_str(&format!("[{tag}]"));
+    }
+    if style.code && !text.contains("[/code]") {
+        out.push_str(&format!("[code]{text}[/code]"));
+    } else {
+        push_escaped(out, text);
+    }
+    for tag in tags.iter().rev() {
+        out.push_str(&format!("[/{tag}]"));
+    }
+}
+
+/// Serialize `text` to the BBCode Steam sends it as; it reads back through
+/// [`tokenize`] as the same spans. Steam has no syntax for mentions,
+/// timestamps, quotes or headings, so those go out as their plain text,
+/// and code blocks as inline `[code]`. Stickers can't be sent in text.
+pub fn to_bbcode(text: &RichText) -> String {
+    let mut out = String::new();
+    for span in &text.spans {
+        match span {
+            Span::Text { text, style } => push_text(&mut out, text, *style),
+            Span::Emoji(emoji) if emoji.shortcode.is_ascii() && !emoji.shortcode.contains('[') => {
+                out.push_str(&format!("[emoticon]{}[/emoticon]", emoji.shortcode));
+            }
+            // Unicode emoji are text.
+            Span::Emoji(emoji) => push_escaped(&mut out, &emoji.shortcode),
+            Span::Sticker { .. } => {}
+            Span::Link { text, href } if text == href && !href.contains('[') => {
+                out.push_str(&format!("[url]{href}[/url]"));
+            }
+            Span::Link { text, href } if !text.contains('[') && !href.contains(']') => {
+                out.push_str(&format!("[url={href}]{text}[/url]"));
+            }
+            Span::Link { text, .. } => push_escaped(&mut out, text),
+            Span::CodeBlock { code, .. } => {
+                let code_style = TextStyle {
+                    code: true,
+                    ..Default::default()
+                };
+                push_text(&mut out, code, code_style);
+            }
+            span => push_escaped(&mut out, &span.to_plain()),
+        }
+    }
+    out
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -420,6 +574,123 @@ mod tests {
         );
     }
 
+    #[test]
+    fn tags_nest_and_brackets_escape() {
+        let bold = TextStyle {
+            bold: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            tokenize(r"[b]bold [i]both[/i][/b] [code][b][/code] \[b]literal"),
+            vec![
+                Piece::Text {
+                    text: "bold ".to_owned(),
+                    style: bold
+                },
+                Piece::Text {
+                    text: "both".to_owned(),
+                    style: TextStyle {
+                        italic: true,
+                        ..bold
+                    }
+                },
+                Piece::Text {
+                    text: " ".to_owned(),
+                    style: TextStyle::default()
+                },
+                Piece::Text {
+                    text: "[b]".to_owned(),
+                    style: TextStyle {
+                        code: true,
+                        ..Default::default()
+                    }
+                },
+                Piece::Text {
+                    text: " [b]literal".to_owned(),
+                    style: TextStyle::default()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn drafts_keep_their_bbcode() {
+        for draft in [
+            "[spoiler][strike]gone[/strike][/spoiler] [u][b][i]all[/i][/b][/u]",
+            "[emoticon]steamhappy[/emoticon] [url=https://x.com]docs[/url] [url]https://y.com[/url]",
+            r"\[b] is how you write [b]bold[/b]",
+            r"C:\\[b]bold[/b]",
+        ] {
+            assert_eq!(to_bbcode(&parse_draft(draft)), draft);
+        }
+    }
+
+    mod round_trip {
+        use proptest::prelude::*;
+
+        use super::*;
+
+        fn text() -> impl Strategy<Value = Span> {
+            any::<[bool; 6]>().prop_flat_map(
+                |[bold, italic, underline, strikethrough, spoiler, code]| {
+                    let style = TextStyle {
+                        bold,
+                        italic,
+                        underline,
+                        strikethrough,
+                        spoiler,
+                        code,
+                    };
+                    // Heavy on brackets and backslashes, to exercise the
+                    // escaping.
+                    "[a-z \\[\\]/=\\\\]{1,8}".prop_map(move |text| Span::Text { text, style })
+                },
+            )
+        }
+
+        fn span() -> impl Strategy<Value = Span> {
+            let emoticon = "[a-z_]{1,10}".prop_map(|name| Span::Emoji(Emoji::shortcode(name)));
+            let link = (
+                "https://[a-z]{1,8}\\.com(/[a-z]{1,5})?",
+                "([a-z ]{0,5}[a-z])?",
+            )
+                .prop_map(|(href, text)| Span::Link {
+                    text: if text.is_empty() { href.clone() } else { text },
+                    href,
+                });
+            prop_oneof![4 => text(), 1 => emoticon, 1 => link]
+        }
+
+        /// Neighbouring plain texts read back as one.
+        fn merged(spans: Vec<Span>) -> Vec<Span> {
+            let mut kept: Vec<Span> = Vec::new();
+            for span in spans {
+                match (kept.last_mut(), span) {
+                    (
+                        Some(Span::Text { text, style }),
+                        Span::Text {
+                            text: next,
+                            style: next_style,
+                        },
+                    ) if *style == TextStyle::default() && next_style == TextStyle::default() => {
+                        text.push_str(&next)
+                    }
+                    (_, span) => kept.push(span),
+                }
+            }
+            kept
+        }
+
+        proptest! {
+            #[test]
+            fn bbcode_reads_back_as_written(spans in prop::collection::vec(span(), 0..8)) {
+                let text = RichText { spans: merged(spans) };
+                let bbcode = to_bbcode(&text);
+                prop_assert_eq!(parse_draft(&bbcode), text, "{}", bbcode);
+            }
+        }
+    }
+
     #[test]
     fn emoticon_tag_only_message() {
         assert_eq!(
@@ -32,8 +32,8 @@ use futures_timer::Delay;
 use tracing::{debug, info, warn};
 
 use messenger_interface::{
-    interface::TextEvent,
-    types::{ID, Identifier, Message, Revision},
+    interface::{PresenceEvent, TextEvent},
+    types::{ID, Identifier, Message, Revision, UserPresence},
 };
 
 use steam_vent::auth::{
@@ -53,8 +53,8 @@ use steam_vent_proto::{
 };
 
 use crate::api_types::{
-    CHAT_ENTRY_TYPE_CHAT_MSG, ChatGroupEntry, ChatRoomLocation, FriendEntry, hex_avatar,
-    message_id, steam_group_room_id,
+    CHAT_ENTRY_TYPE_CHAT_MSG, CHAT_ENTRY_TYPE_TYPING, ChatGroupEntry, ChatRoomLocation,
+    FriendEntry, hex_avatar, message_id, status_from_persona_state, steam_group_room_id,
 };
 use crate::downloaders::steam_user_identifier;
 
@@ -158,12 +158,18 @@ async fn process_friends_list(
     }
 }
 
-/// Fold a persona-state payload into `friends`: display names and avatar
-/// hashes. Shared by the logon-buffer drain and the live-update listener.
-/// Returns how many friends had a name resolved for the first time.
+/// Caps [`Connected::pending_presence_events`] so presence changes seen while
+/// nobody listens for them cannot pile up; the oldest are dropped first.
+const PENDING_PRESENCE_LIMIT: usize = 256;
+
+/// Fold a persona-state payload into `friends`: display names, avatar hashes
+/// and presence. Shared by the logon-buffer drain and the live-update listener.
+/// Returns how many friends had a name resolved for the first time; presence
+/// changes are appended to `presence_changes`.
 fn process_persona_state(
     friends: &DashMap<ID, FriendEntry>,
     state: &CMsgClientPersonaState,
+    presence_changes: &mut Vec<PresenceEvent>,
 ) -> usize {
     let mut newly_named = 0;
     for friend in &state.friends {
@@ -184,6 +190,28 @@ fn process_persona_state(
                 debug!(steam_id = id, "Steam: resolved friend avatar hash");
                 entry.avatar_hash = Some(hash);
             }
+            // Pushes without the status flag leave presence untouched. Steam
+            // names only non-Steam games; a Steam app is just an app ID.
+            if let Some(persona_state) = friend.persona_state {
+                let activity = match friend.game_name.as_deref().map(str::trim) {
+                    Some(game) if !game.is_empty() => Some(format!("Playing {game}")),
+                    _ if friend.game_played_app_id.unwrap_or(0) != 0 => {
+                        Some("Playing a game".to_owned())
+                    }
+                    _ => None,
+                };
+                let presence = UserPresence {
+                    status: status_from_persona_state(persona_state),
+                    activity,
+                };
+                if entry.presence != presence {
+                    entry.presence = presence.clone();
+                    presence_changes.push(PresenceEvent::PresenceUpdated {
+                        user_id: id,
+                        presence,
+                    });
+                }
+            }
         }
     }
     newly_named
@@ -196,13 +224,17 @@ async fn incoming_notification_text_event(
     username: &str,
     fallback_ordinal: &mut u32,
 ) -> Option<TextEvent> {
-    if incoming
-        .chat_entry_type
-        .is_some_and(|kind| kind != CHAT_ENTRY_TYPE_CHAT_MSG)
-    {
-        return None;
-    }
     let friend = incoming.steamid_friend?;
+    match incoming.chat_entry_type {
+        None | Some(CHAT_ENTRY_TYPE_CHAT_MSG) => {}
+        // Our own typing is echoed to the client's other sessions.
+        Some(CHAT_ENTRY_TYPE_TYPING) if !incoming.local_echo.unwrap_or(false) => {
+            let user = steam_user_identifier(friend, friends, client_steamid, username).await;
+            let room = Identifier::new(friend, ());
+            return Some(TextEvent::TypingStarted { room, user });
+        }
+        Some(_) => return None,
+    }
 
     // Parse the BBCode form so emoticons/stickers survive (the no-bbcode
     // variant strips them); fall back to the no-bbcode text only if absent.
@@ -311,6 +343,7 @@ enum SteamUpdate {
 
 enum UpdatePoll {
     Text(TextEvent),
+    Presence(Vec<PresenceEvent>),
     Cache,
     Timeout,
     /// The session is no longer usable (Steam logged us off, or every event
@@ -325,6 +358,12 @@ pub(crate) enum TextPoll {
     Disconnected,
 }
 
+pub(crate) enum PresencePoll {
+    Presence(PresenceEvent),
+    Idle,
+    Disconnected,
+}
+
 struct SteamStreams {
     updates: SelectAll<BoxStream<'static, SteamUpdate>>,
     resolved_persona_names: usize,
@@ -403,7 +442,8 @@ impl SteamStreams {
                 UpdatePoll::Cache
             }
             Some(SteamUpdate::Persona(Ok(state))) => {
-                let newly_named = process_persona_state(friends, &state);
+                let mut presence_changes = Vec::new();
+                let newly_named = process_persona_state(friends, &state, &mut presence_changes);
                 self.resolved_persona_names += newly_named;
                 // Steam sends a persona-state push per friend *and* for every
                 // presence/avatar refresh, so most resolve no new name. Only
@@ -415,7 +455,11 @@ impl SteamStreams {
                         self.resolved_persona_names
                     );
                 }
-                UpdatePoll::Cache
+                if presence_changes.is_empty() {
+                    UpdatePoll::Cache
+                } else {
+                    UpdatePoll::Presence(presence_changes)
+                }
             }
             Some(SteamUpdate::Persona(Err(err))) => {
                 // Same benign broadcast lag as above; persona updates were
@@ -491,6 +535,9 @@ pub(crate) struct Connected {
     pub(crate) profile_details_loaded: Arc<AsyncMutex<bool>>,
     streams: AsyncMutex<SteamStreams>,
     pending_text_events: AsyncMutex<VecDeque<TextEvent>>,
+    /// Presence changes seen by whichever caller polled the streams, for the
+    /// `Presence` stream to hand out.
+    pending_presence_events: AsyncMutex<VecDeque<PresenceEvent>>,
     /// Cleared once the session is observed dead (Steam logged us off, or all
     /// event streams closed). [`SteamMessenger::connected`](crate::SteamMessenger::connected)
     /// rebuilds while this is `false`, so queries and the live stream
@@ -555,7 +602,9 @@ impl Connected {
             } else if kind == persona_kind
                 && let Ok(state) = raw.into_message::<CMsgClientPersonaState>()
             {
-                process_persona_state(&friends, &state);
+                // The presences seeded here are served by `presences()`, so
+                // they are not reported as changes.
+                process_persona_state(&friends, &state, &mut Vec::new());
             }
         }
 
@@ -569,6 +618,7 @@ impl Connected {
             profile_details_loaded: Arc::new(AsyncMutex::new(false)),
             streams: AsyncMutex::new(streams),
             pending_text_events: AsyncMutex::new(pending_text_events),
+            pending_presence_events: AsyncMutex::new(VecDeque::new()),
             alive: AtomicBool::new(true),
         })
     }
@@ -647,12 +697,27 @@ impl Connected {
         outcome
     }
 
+    /// Queue presence changes for the `Presence` stream.
+    pub(crate) async fn queue_presence_events(
+        &self,
+        events: impl IntoIterator<Item = PresenceEvent>,
+    ) {
+        let mut pending = self.pending_presence_events.lock().await;
+        pending.extend(events);
+        let overflow = pending.len().saturating_sub(PENDING_PRESENCE_LIMIT);
+        pending.drain(..overflow);
+    }
+
     pub(crate) async fn drive_update_for_cache(&self, timeout: Duration) -> bool {
         match self.poll_update_once(timeout).await {
             UpdatePoll::Text(event) => {
                 self.pending_text_events.lock().await.push_back(event);
                 true
             }
+            UpdatePoll::Presence(events) => {
+                self.queue_presence_events(events).await;
+                true
+            }
             UpdatePoll::Cache => true,
             UpdatePoll::Timeout | UpdatePoll::Disconnected => false,
         }
@@ -665,8 +730,33 @@ impl Connected {
 
         match self.poll_update_once(timeout).await {
             UpdatePoll::Text(event) => TextPoll::Text(event),
+            UpdatePoll::Presence(events) => {
+                self.queue_presence_events(events).await;
+                TextPoll::Idle
+            }
             UpdatePoll::Cache | UpdatePoll::Timeout => TextPoll::Idle,
             UpdatePoll::Disconnected => TextPoll::Disconnected,
         }
     }
+
+    pub(crate) async fn next_presence_event(&self, timeout: Duration) -> PresencePoll {
+        if let Some(event) = self.pending_presence_events.lock().await.pop_front() {
+            return PresencePoll::Presence(event);
+        }
+
+        match self.poll_update_once(timeout).await {
+            UpdatePoll::Text(event) => {
+                self.pending_text_events.lock().await.push_back(event);
+                PresencePoll::Idle
+            }
+            UpdatePoll::Presence(events) => {
+                let mut events = events.into_iter();
+                let first = events.next();
+                self.queue_presence_events(events).await;
+                first.map_or(PresencePoll::Idle, PresencePoll::Presence)
+            }
+            UpdatePoll::Cache | UpdatePoll::Timeout => PresencePoll::Idle,
+            UpdatePoll::Disconnected => PresencePoll::Disconnected,
+        }
+    }
 }
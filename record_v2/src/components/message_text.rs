@@ -2,12 +2,14 @@ use iced::{
     Alignment, Color, Element, Font, Length, Padding,
     advanced::graphics::core::font,
     widget::{
-        Button, Column, Row, Text, column, container, image, row,
-        text::{Rich, Span},
+        Button, Column, Row, Text, container, image, mouse_area, row,
+        text::{self, Rich, Span},
     },
 };
 use iced_aw::Wrap;
-use messenger_interface::types::{Emoji, Identifier, Message, Span as RichSpan, TextStyle};
+use messenger_interface::types::{
+    Emoji, Identifier, Mention, Message, Reply, Span as RichSpan, TextStyle,
+};
 
 /// IDs at or above this value are pending (optimistic send, not yet confirmed).
 const PENDING_ID_THRESHOLD: u64 = u64::MAX - 1_000_000;
@@ -15,9 +17,26 @@ const PENDING_ID_THRESHOLD: u64 = u64::MAX - 1_000_000;
 const LINK_COLOR: Color = Color::from_rgb(0.0, 0.0, 1.0);
 const PENDING_COLOR: Color = Color::from_rgb(0.0, 0.8, 0.0);
 const EDITED_COLOR: Color = Color::from_rgb(0.5, 0.5, 0.5);
+const QUOTE_COLOR: Color = Color::from_rgb(0.5, 0.5, 0.5);
+/// Behind inline code, code blocks, timestamps and block quotes.
+const CODE_BACKGROUND: Color = Color::from_rgba(0.5, 0.5, 0.5, 0.2);
+/// Text and background of a spoiler alike, so it reads as a blacked-out bar
+/// until clicked.
+const SPOILER_COLOR: Color = Color::from_rgb(0.2, 0.2, 0.2);
+/// Behind a spoiler once revealed, so it still stands out as one.
+const REVEALED_SPOILER_BACKGROUND: Color = Color::from_rgba(0.2, 0.2, 0.2, 0.15);
+const MENTION_COLOR: Color = Color::from_rgb(0.25, 0.3, 0.85);
+const MENTION_BACKGROUND: Color = Color::from_rgba(0.35, 0.4, 0.95, 0.2);
+/// Behind a message that mentions the client user.
+const MENTIONED_BACKGROUND: Color = Color::from_rgba(0.95, 0.75, 0.2, 0.15);
+/// Text size of headings by level.
+const HEADING_SIZES: [f32; 3] = [24.0, 20.0, 17.0];
+/// Quoted reply text longer than this is cut off with an ellipsis.
+const QUOTE_MAX_CHARS: usize = 80;
 /// Inline emoji image edge length, ~matching the text line height.
 const EMOJI_SIZE: f32 = 20.0;
 const STICKER_SIZE: f32 = 120.0;
+const ATTACHMENT_IMAGE_HEIGHT: f32 = 240.0;
 
 fn font_for(style: TextStyle) -> Font {
     Font {
@@ -31,53 +50,102 @@ fn font_for(style: TextStyle) -> Font {
         } else {
             font::Style::Normal
         },
-        ..Default::default()
+        ..if style.code {
+            Font::MONOSPACE
+        } else {
+            Font::default()
+        }
+    }
+}
+
+/// One iced rich-text span for a styled run. Spoilers stay blacked out
+/// unless `revealed`.
+fn styled_span<'a, L>(
+    text: impl text::IntoFragment<'a>,
+    style: TextStyle,
+    revealed: bool,
+) -> Span<'a, L> {
+    let span = Span::new(text)
+        .font(font_for(style))
+        .underline(style.underline)
+        .strikethrough(style.strikethrough);
+    if style.spoiler && !revealed {
+        span.color(SPOILER_COLOR).background(SPOILER_COLOR)
+    } else if style.spoiler {
+        span.background(REVEALED_SPOILER_BACKGROUND)
+    } else if style.code {
+        span.background(CODE_BACKGROUND)
+    } else {
+        span
     }
 }
 
-/// One iced rich-text span for a styled run (the no-inline-image path).
-fn styled_span<'a>(text: &'a str, style: TextStyle) -> Span<'a> {
-    Span::new(text).font(font_for(style))
+/// Whether any text in `spans`, blocks included, is a spoiler.
+fn has_spoiler(spans: &[RichSpan]) -> bool {
+    spans.iter().any(|span| match span {
+        RichSpan::Text { style, .. } => style.spoiler,
+        RichSpan::Quote(spans) | RichSpan::Heading { spans, .. } => has_spoiler(spans),
+        _ => false,
+    })
 }
 
-/// One word as a `Text` widget (the inline-image flow path).
-fn word<'a>(text: &'a str, style: TextStyle, color: Option<Color>) -> Text<'a> {
-    let text = Text::new(text).font(font_for(style));
-    match color {
-        Some(color) => text.color(color),
-        None => text,
+/// Mentions that lead somewhere when clicked: a user's DM, a room.
+fn clickable(target: Mention) -> bool {
+    matches!(target, Mention::User(_) | Mention::Channel(_))
+}
+
+fn mentioned_style(_theme: &iced::Theme) -> container::Style {
+    container::Style {
+        background: Some(MENTIONED_BACKGROUND.into()),
+        ..Default::default()
     }
 }
 
-pub fn message_text<'a, M: Clone + 'static>(
-    msg: &'a Identifier<Message>,
-    on_reaction: impl Fn(&'a Identifier<Message>, &'a str, bool) -> M + 'a,
-) -> Element<'a, M> {
-    let pending = *msg.id() >= PENDING_ID_THRESHOLD;
-    // === Author ===
-    let icon: std::path::PathBuf = msg
-        .author
-        .as_ref()
-        .and_then(|a| a.icon.clone())
-        .unwrap_or_else(|| "./public/imgs/placeholder.jpg".into());
-    let image_height = Length::Fixed(36.0);
-    let author_name: &str = msg
-        .author
-        .as_ref()
-        .map(|a| a.name.as_str())
-        .unwrap_or("Unknown");
-    let author = Text::from(author_name);
+fn code_style(_theme: &iced::Theme) -> container::Style {
+    container::Style {
+        background: Some(CODE_BACKGROUND.into()),
+        border: iced::Border {
+            radius: 4.0.into(),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
 
-    let spans = &msg.content.text.spans;
-    let stickers: Vec<&'a std::path::PathBuf> = spans
-        .iter()
-        .filter_map(|span| match span {
-            RichSpan::Sticker {
-                image: Some(path), ..
-            } => Some(path),
-            _ => None,
-        })
-        .collect();
+/// One word as its own widget (the inline-image flow path), styled like
+/// [`styled_span`] would.
+fn word<'a, M: 'a>(
+    text: impl text::IntoFragment<'a>,
+    style: TextStyle,
+    color: Option<Color>,
+    revealed: bool,
+) -> Rich<'a, (), M> {
+    let span = styled_span(text, style, revealed);
+    let span = match color {
+        // A hidden spoiler keeps its blacked-out color.
+        Some(color) if !style.spoiler || revealed => span.color(color),
+        _ => span,
+    };
+    Rich::with_spans([span])
+}
+
+/// A run of inline spans as one paragraph, `heading_size` making a heading
+/// of it.
+fn paragraph<'a, M: Clone + 'a>(
+    spans: &'a [RichSpan],
+    pending: bool,
+    edited: bool,
+    revealed: bool,
+    heading_size: Option<f32>,
+    on_mention: fn(Mention) -> M,
+) -> Element<'a, M> {
+    let heading = |style: TextStyle| match heading_size {
+        Some(_) => TextStyle {
+            bold: true,
+            ..style
+        },
+        None => style,
+    };
     // iced rich text can't embed images inline, so only switch to the flowing
     // word/image layout when an emoji actually resolved to an image; plain and
     // emoji-less messages keep the simpler, better-shaped `Rich` text.
@@ -85,7 +153,7 @@ pub fn message_text<'a, M: Clone + 'static>(
         .iter()
         .any(|span| matches!(span, RichSpan::Emoji(Emoji { image: Some(_), .. })));
 
-    let text_content: Element<'a, M> = if has_inline_image {
+    if has_inline_image {
         let text_color = pending.then_some(PENDING_COLOR);
         let link_color = if pending { PENDING_COLOR } else { LINK_COLOR };
         let mut elements: Vec<Element<'a, M>> = Vec::new();
@@ -93,14 +161,44 @@ pub fn message_text<'a, M: Clone + 'static>(
             match span {
                 RichSpan::Text { text, style } => {
                     for w in text.split_whitespace() {
-                        elements.push(word(w, *style, text_color).into());
+                        elements.push(word(w, heading(*style), text_color, revealed).into());
                     }
                 }
                 RichSpan::Link { text, .. } => {
                     for w in text.split_whitespace() {
-                        elements.push(word(w, TextStyle::default(), Some(link_color)).into());
+                        elements.push(
+                            word(w, heading(TextStyle::default()), Some(link_color), revealed)
+                                .into(),
+                        );
+                    }
+                }
+                RichSpan::Timestamp { at, style } => {
+                    for w in style.format(*at).split_whitespace() {
+                        elements.push(
+                            word(
+                                w.to_owned(),
+                                heading(TextStyle::default()),
+                                text_color,
+                                revealed,
+                            )
+                            .into(),
+                        );
                     }
                 }
+                RichSpan::Mention { target, .. } => {
+                    let color = text_color.unwrap_or(MENTION_COLOR);
+                    let mention = word(
+                        span.to_plain(),
+                        heading(TextStyle::default()),
+                        Some(color),
+                        revealed,
+                    );
+                    elements.push(if clickable(*target) {
+                        mouse_area(mention).on_press(on_mention(*target)).into()
+                    } else {
+                        mention.into()
+                    });
+                }
                 RichSpan::Emoji(emoji) => match &emoji.image {
                     Some(path) => elements.push(
                         image(path)
@@ -117,10 +215,13 @@ pub fn message_text<'a, M: Clone + 'static>(
                         elements.push(shortcode.into());
                     }
                 },
-                RichSpan::Sticker { .. } => {}
+                RichSpan::Sticker { .. }
+                | RichSpan::CodeBlock { .. }
+                | RichSpan::Quote(_)
+                | RichSpan::Heading { .. } => {}
             }
         }
-        if msg.is_edited() {
+        if edited {
             elements.push(Text::new("(edited)").size(12.0).color(EDITED_COLOR).into());
         }
         Wrap::with_elements(elements)
@@ -130,15 +231,33 @@ pub fn message_text<'a, M: Clone + 'static>(
             .width_items(Length::Fill)
             .into()
     } else {
-        let mut rich_spans: Vec<Span<'_>> = Vec::new();
+        let mut rich_spans: Vec<Span<'_, Mention>> = Vec::new();
         for span in spans {
             match span {
-                RichSpan::Text { text, style } => rich_spans.push(styled_span(text, *style)),
+                RichSpan::Text { text, style } => {
+                    rich_spans.push(styled_span(text.as_str(), heading(*style), revealed))
+                }
                 RichSpan::Emoji(emoji) => {
                     rich_spans.push(Span::new(format!(":{}:", emoji.shortcode)))
                 }
-                RichSpan::Link { text, .. } => {
-                    rich_spans.push(Span::new(text.as_str()).color(LINK_COLOR))
+                RichSpan::Link { text, .. } => rich_spans.push(
+                    styled_span(text.as_str(), heading(TextStyle::default()), revealed)
+                        .color(LINK_COLOR),
+                ),
+                RichSpan::Timestamp { at, style } => rich_spans.push(
+                    styled_span(style.format(*at), heading(TextStyle::default()), revealed)
+                        .background(CODE_BACKGROUND),
+                ),
+                RichSpan::Mention { target, .. } => {
+                    let mention =
+                        styled_span(span.to_plain(), heading(TextStyle::default()), revealed)
+                            .color(MENTION_COLOR)
+                            .background(MENTION_BACKGROUND);
+                    rich_spans.push(if clickable(*target) {
+                        mention.link(*target)
+                    } else {
+                        mention
+                    });
                 }
                 // Sticker images render below; a sticker with no image falls
                 // back to its alt text here.
@@ -147,6 +266,12 @@ pub fn message_text<'a, M: Clone + 'static>(
                         rich_spans.push(Span::new(alt.as_str()));
                     }
                 }
+                RichSpan::CodeBlock { .. } | RichSpan::Quote(_) | RichSpan::Heading { .. } => {}
+            }
+        }
+        if let Some(size) = heading_size {
+            for span in &mut rich_spans {
+                *span = span.clone().size(size);
             }
         }
         if pending {
@@ -154,17 +279,188 @@ pub fn message_text<'a, M: Clone + 'static>(
                 *span = span.clone().color(PENDING_COLOR);
             }
         }
-        if msg.is_edited() {
+        if edited {
             rich_spans.push(Span::new(" (edited)").color(EDITED_COLOR).size(12.0));
         }
-        Rich::from_iter(rich_spans).into()
+        Rich::from_iter(rich_spans).on_link_click(on_mention).into()
+    }
+}
+
+/// A code block, quote or heading, on lines of its own.
+fn block<'a, M: Clone + 'a>(
+    span: &'a RichSpan,
+    pending: bool,
+    revealed: bool,
+    on_mention: fn(Mention) -> M,
+) -> Element<'a, M> {
+    match span {
+        RichSpan::CodeBlock { language, code } => {
+            let mut lines = Column::new();
+            if let Some(language) = language {
+                lines = lines.push(Text::new(language.as_str()).size(11.0).color(EDITED_COLOR));
+            }
+            let code = Text::new(code.as_str()).font(Font::MONOSPACE);
+            let code = if pending {
+                code.color(PENDING_COLOR)
+            } else {
+                code
+            };
+            container(lines.push(code))
+                .padding(6.0)
+                .width(Length::Fill)
+                .style(code_style)
+                .into()
+        }
+        RichSpan::Quote(spans) => {
+            container(rich_content(spans, pending, false, revealed, on_mention))
+                .padding(Padding::new(2.0).left(8.0))
+                .width(Length::Fill)
+                .style(code_style)
+                .into()
+        }
+        RichSpan::Heading { level, spans } => {
+            let size = HEADING_SIZES[(*level as usize).clamp(1, HEADING_SIZES.len()) - 1];
+            paragraph(spans, pending, false, revealed, Some(size), on_mention)
+        }
+        inline => paragraph(
+            std::slice::from_ref(inline),
+            pending,
+            false,
+            revealed,
+            None,
+            on_mention,
+        ),
+    }
+}
+
+/// A message's content: its runs of inline spans as paragraphs, with the
+/// blocks between them.
+fn rich_content<'a, M: Clone + 'a>(
+    spans: &'a [RichSpan],
+    pending: bool,
+    edited: bool,
+    revealed: bool,
+    on_mention: fn(Mention) -> M,
+) -> Element<'a, M> {
+    let ends_inline = spans.last().is_none_or(|span| !span.is_block());
+    if spans.is_empty() {
+        return paragraph(spans, pending, edited, revealed, None, on_mention);
+    }
+    let chunks: Vec<&'a [RichSpan]> = spans
+        .chunk_by(|a, b| !a.is_block() && !b.is_block())
+        .collect();
+    let last = chunks.len() - 1;
+    let mut content = Column::new().spacing(2.0);
+    for (index, chunk) in chunks.into_iter().enumerate() {
+        content = content.push(match chunk {
+            [span] if span.is_block() => block(span, pending, revealed, on_mention),
+            run => paragraph(
+                run,
+                pending,
+                edited && index == last,
+                revealed,
+                None,
+                on_mention,
+            ),
+        });
+    }
+    if edited && !ends_inline {
+        content = content.push(Text::new("(edited)").size(12.0).color(EDITED_COLOR));
+    }
+    content.into()
+}
+
+/// The size of a file for display: `bytes` in the largest unit that
+/// keeps it at or above 1.
+fn file_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+/// The one-line "replying to" header above a reply.
+fn reply_header<'a>(reply: &'a Reply) -> Text<'a> {
+    let quote = match &reply.preview {
+        Some(preview) => {
+            let mut text: String = preview.text.chars().take(QUOTE_MAX_CHARS).collect();
+            if preview.text.chars().count() > QUOTE_MAX_CHARS {
+                text.push('…');
+            }
+            format!("↪ {}: {text}", preview.author)
+        }
+        None => "↪ Original message was deleted".to_owned(),
     };
+    Text::new(quote).size(12.0).color(QUOTE_COLOR)
+}
 
-    // === Message body: text content, then any sticker images ===
+/// `on_mention` is what clicking a user or room mention sends. Spoilers show
+/// when `revealed`; clicking the text of a message with spoilers sends
+/// `on_spoiler`.
+pub fn message_text<'a, M: Clone + 'static>(
+    msg: &'a Identifier<Message>,
+    on_reaction: impl Fn(&'a Identifier<Message>, &'a str, bool) -> M + 'a,
+    on_mention: fn(Mention) -> M,
+    revealed: bool,
+    on_spoiler: M,
+) -> Element<'a, M> {
+    let pending = *msg.id() >= PENDING_ID_THRESHOLD;
+    // === Author ===
+    let icon: std::path::PathBuf = msg
+        .author
+        .as_ref()
+        .and_then(|a| a.icon.clone())
+        .unwrap_or_else(|| "./public/imgs/placeholder.jpg".into());
+    let image_height = Length::Fixed(36.0);
+    let author_name: &str = msg
+        .author
+        .as_ref()
+        .map(|a| a.name.as_str())
+        .unwrap_or("Unknown");
+    let author = Text::from(author_name);
+
+    let spans = &msg.content.text.spans;
+    let stickers: Vec<&'a std::path::PathBuf> = spans
+        .iter()
+        .filter_map(|span| match span {
+            RichSpan::Sticker {
+                image: Some(path), ..
+            } => Some(path),
+            _ => None,
+        })
+        .collect();
+    let text_content = rich_content(spans, pending, msg.is_edited(), revealed, on_mention);
+    let text_content = if has_spoiler(spans) {
+        mouse_area(text_content).on_press(on_spoiler).into()
+    } else {
+        text_content
+    };
+
+    // === Message body: text content, then any sticker images, then attachments ===
     let mut body = Column::new().push(text_content);
     for sticker in stickers {
         body = body.push(image(sticker).height(Length::Fixed(STICKER_SIZE)));
     }
+    for attachment in &msg.attachments {
+        body = match &attachment.path {
+            Some(path) if attachment.is_image() => {
+                body.push(image(path).height(Length::Fixed(ATTACHMENT_IMAGE_HEIGHT)))
+            }
+            _ => body.push(Text::new(format!(
+                "📎 {} ({})",
+                attachment.filename,
+                file_size(attachment.size)
+            ))),
+        };
+    }
 
     // === Reactions ===
     let reactions = Row::from_iter(msg.reactions.iter().map(|reaction| {
@@ -180,11 +476,21 @@ pub fn message_text<'a, M: Clone + 'static>(
         .into()
     }));
 
-    row![
+    let mut details = Column::new();
+    if let Some(reply) = &msg.reply {
+        details = details.push(reply_header(reply));
+    }
+    details = details.push(author).push(body).push(reactions);
+
+    let message = row![
         image(&icon).height(image_height),
-        container(column![author, body, reactions])
+        container(details)
             .width(Length::Fill)
             .padding(Padding::new(0.0).left(5.0))
-    ]
-    .into()
+    ];
+    if msg.mentions_me {
+        container(message).style(mentioned_style).into()
+    } else {
+        message.into()
+    }
 }
@@ -1,8 +1,19 @@
-use futures::StreamExt;
+use futures::{StreamExt, channel::oneshot};
+use futures_timer::Delay;
 use iced::Task;
-use messenger_interface::interface::{AudioEvent, CallState, QueryEvent, TextEvent, VoiceEvent};
+use messenger_interface::interface::{
+    AudioEvent, CallState, PresenceEvent, QueryEvent, TYPING_INDICATOR_TIMEOUT, TextEvent,
+    VoiceEvent,
+};
 use messenger_interface::types::{Emoji, ID, Identifier, User};
-use simple_audio_channels::{AudioMixer, SampleFormat, StreamFormat, effects::{Gate, GateSettings}};
+use simple_audio_channels::{
+    AudioMixer, Resampling, SampleFormat, StreamFormat,
+    effects::{
+        AutoGain, EchoCanceller, EffectChain, Gate, GateSettings, Limiter, NoiseSuppressor,
+        SwitchHandle,
+    },
+    output::{ChannelControl, SampleProducer},
+};
 use tracing::{debug, trace, warn};
 
 use crate::pages::{AppMessage, StreamDirection};
@@ -78,6 +89,71 @@ pub fn process_query_event(
                 }
             }
         }
+        QueryEvent::ChannelUpdated { r#where: _, room } => {
+            if let Some(data) = messengers.data_mut(id)
+                && let Some(existing) = data.room_mut(*room.id())
+            {
+                // Keep the loaded messages and participants.
+                existing.name = room.name.clone();
+                existing.icon = room.icon.clone();
+                existing.room_capabilities = room.room_capabilities;
+            }
+        }
+        QueryEvent::ChannelDeleted { r#where, room_id } => {
+            if let Some(data) = messengers.data_mut(id) {
+                match r#where {
+                    None => data.conversations.retain(|r| *r.id() != room_id),
+                    Some(server_id) => {
+                        if let Some(server) =
+                            data.guilds.iter_mut().find(|g| g.id() == server_id.id())
+                            && let Some(rooms) = server.rooms.as_mut()
+                        {
+                            // A deleted channel takes its threads with it.
+                            rooms.retain(|r| *r.id() != room_id && r.parent != Some(room_id));
+                        }
+                    }
+                }
+                data.pending_sends.retain(|p| p.room_id != room_id);
+            }
+        }
+        QueryEvent::HouseJoined { house } => {
+            if let Some(data) = messengers.data_mut(id) {
+                match data.guilds.iter_mut().find(|g| g.id() == house.id()) {
+                    // Back from an outage: refresh name and icon, keep rooms.
+                    Some(existing) => {
+                        existing.name = house.name.clone();
+                        existing.icon = house.icon.clone();
+                    }
+                    None => data.guilds.push(house),
+                }
+            }
+        }
+        QueryEvent::HouseUpdated { house } => {
+            if let Some(data) = messengers.data_mut(id)
+                && let Some(existing) = data.guilds.iter_mut().find(|g| g.id() == house.id())
+            {
+                existing.name = house.name.clone();
+                existing.icon = house.icon.clone();
+            }
+        }
+        QueryEvent::HouseLeft { house_id } => {
+            if let Some(data) = messengers.data_mut(id) {
+                data.guilds.retain(|g| *g.id() != house_id);
+            }
+        }
+        QueryEvent::ContactAdded { user } => {
+            if let Some(data) = messengers.data_mut(id) {
+                match data.contacts.iter_mut().find(|c| c.id() == user.id()) {
+                    Some(existing) => *existing = user,
+                    None => data.contacts.push(user),
+                }
+            }
+        }
+        QueryEvent::ContactRemoved { user_id } => {
+            if let Some(data) = messengers.data_mut(id) {
+                data.contacts.retain(|c| *c.id() != user_id);
+            }
+        }
     }
     Task::none()
 }
@@ -91,6 +167,12 @@ pub fn process_text_event(
         TextEvent::MessageCreated { room, message } => {
             if let Some(data) = messengers.data_mut(id) {
                 let room_id = *room.id();
+                let message_id = *message.id();
+                let message_author = message.author.as_ref().map(|author| *author.id());
+                let mentions_me = message.mentions_me;
+                if let Some(author) = message_author {
+                    data.stop_typing(room_id, author);
+                }
                 let pending_id = data
                     .pending_sends
                     .iter()
@@ -113,6 +195,19 @@ pub fn process_text_event(
                     }
                 }
 
+                // Sending reads the room; the open chat marks itself read
+                // separately, see `App::update`.
+                if data
+                    .profile
+                    .as_ref()
+                    .zip(message_author)
+                    .is_some_and(|(profile, author)| *profile.id() == author)
+                {
+                    data.mark_read(room_id, message_id);
+                } else {
+                    data.note_unread(room_id, mentions_me);
+                }
+
                 data.move_conversation_to_front(room_id);
             }
         }
@@ -192,6 +287,23 @@ pub fn process_text_event(
                 }
             }
         }
+        TextEvent::TypingStarted { room, user } => {
+            if let Some(data) = messengers.data_mut(id) {
+                if data.profile.as_ref().is_some_and(|p| p.id() == user.id()) {
+                    return Task::none();
+                }
+                data.set_typing(*room.id(), user);
+                // Indicators lapse without an event, so wake up to drop this
+                // one; a repeat in the meantime schedules its own wake-up.
+                return Task::future(Delay::new(TYPING_INDICATOR_TIMEOUT))
+                    .map(move |()| AppMessage::modify_data(id, MessengerData::prune_typing));
+            }
+        }
+        TextEvent::ReadStateUpdated { room, state } => {
+            if let Some(data) = messengers.data_mut(id) {
+                data.read_states.insert(*room.id(), state);
+            }
+        }
     }
     Task::none()
 }
@@ -248,6 +360,8 @@ pub fn process_voice_event(
             trace!("{user_id:?} left vc");
             if let Some(data) = messengers.data_mut(id) {
                 let left_rooms = remove_voice_participant(data, user_id);
+                data.speaking.remove(&user_id);
+                data.mute_states.remove(&user_id);
 
                 if data
                     .profile
@@ -260,57 +374,147 @@ pub fn process_voice_event(
                         data.calls
                             .retain(|call| !left_rooms.iter().any(|room_id| call.id() == *room_id));
                     }
+                    if data.calls.is_empty() {
+                        data.speaking.clear();
+                        data.mute_states.clear();
+                    }
+                }
+            }
+        }
+        VoiceEvent::SpeakingChanged {
+            user_id,
+            speaking,
+            level,
+        } => {
+            if let Some(data) = messengers.data_mut(id) {
+                if speaking {
+                    data.speaking.insert(user_id, level);
+                } else {
+                    data.speaking.remove(&user_id);
                 }
             }
         }
+        VoiceEvent::MuteStateChanged { user_id, state } => {
+            if let Some(data) = messengers.data_mut(id) {
+                data.mute_states.insert(user_id, state);
+            }
+        }
+    }
+    Task::none()
+}
+
+pub fn process_presence_event(
+    id: MessengerId,
+    event: PresenceEvent,
+    messengers: &mut MessengerRegistry,
+) -> Task<AppMessage> {
+    match event {
+        PresenceEvent::PresenceUpdated { user_id, presence } => {
+            trace!("{user_id:?} is now {:?}", presence.status);
+            if let Some(data) = messengers.data_mut(id) {
+                data.presences.insert(user_id, presence);
+            }
+        }
     }
     Task::none()
 }
 
+/// Create an output channel for a call's audio, hand it to the adapter through
+/// `sender` and `register` its control with the messenger's call audio.
+fn add_playback_channel(
+    id: MessengerId,
+    sender: oneshot::Sender<SampleProducer>,
+    audio: &mut AudioMixer,
+    register: impl FnOnce(&mut MessengerData, ChannelControl) + Send + 'static,
+) -> Task<AppMessage> {
+    // Discord voice pushes opus-decoded i16 stereo at 48kHz.
+    let producer = audio
+        .create_output_channel(
+            StreamFormat {
+                channels: 2,
+                sample_format: SampleFormat::I16,
+                sample_rate: 48_000,
+            },
+            // Devices often run at 44.1kHz; keep the conversion clean.
+            Resampling::Sinc,
+            Vec::new(),
+        )
+        .unwrap();
+
+    // The call's settings, and the participant's if any, apply from the start.
+    let control = producer.control();
+    let register = Task::done(AppMessage::modify_data(id, move |data| {
+        register(data, control)
+    }));
+
+    if sender.send(producer).is_err() {
+        warn!("Couldn't send audio channel to the adapter");
+    }
+
+    if !audio.is_streaming_output() {
+        return register.chain(Task::done(AppMessage::StartStream(StreamDirection::Output)));
+    }
+    register
+}
+
 pub fn process_audio_event(
-    _id: MessengerId,
+    id: MessengerId,
     event: AudioEvent,
     audio: &mut AudioMixer,
+    talk: &SwitchHandle,
 ) -> Task<AppMessage> {
     match event {
         AudioEvent::AddAudioSource(sender) => {
-            // Discord voice pushes opus-decoded i16 stereo at 48kHz.
-            let producer = audio
-                .create_output_channel(
-                    StreamFormat {
-                        channels: 2,
-                        sample_format: SampleFormat::I16,
-                        sample_rate: 48_000,
-                    },
-                    Vec::new(),
-                )
-                .unwrap();
-
-            if sender.send(producer).is_err() {
-                warn!("Couldn't send audio channel to the adapter");
-            }
-
-            if !audio.is_streaming_output() {
-                return Task::done(AppMessage::StartStream(StreamDirection::Output));
-            }
+            return add_playback_channel(id, sender, audio, |data, control| {
+                data.call_audio.add_source(control)
+            });
+        }
+        AudioEvent::AddParticipantSource { user_id, sender } => {
+            return add_playback_channel(id, sender, audio, move |data, control| {
+                data.call_audio.add_participant_source(user_id, control)
+            });
         }
         AudioEvent::AddAudioInput(sender) => {
             // Discord voice encodes f32 stereo frames at 48kHz.
+            let format = StreamFormat {
+                channels: 2,
+                sample_format: SampleFormat::F32,
+                sample_rate: 48_000,
+            };
+            let mut effects: EffectChain = Vec::new();
+            // Cancel what the speakers play back into the microphone, using
+            // the output mix as the reference. It has to see the microphone
+            // continuously, so it runs before anything can drop a buffer.
+            match audio.create_monitor_channel(
+                StreamFormat {
+                    channels: 1,
+                    ..format
+                },
+                Resampling::Sinc,
+                Vec::new(),
+            ) {
+                Ok(monitor) => {
+                    effects.push(Box::new(EchoCanceller::new(monitor, Default::default())))
+                }
+                Err(err) => warn!("Echo cancellation unavailable: {err}"),
+            }
+            // Then take out steady background noise. Nothing passes while
+            // push-to-talk is held back. Past that, noise-gate the microphone
+            // near the device noise floor, holding across the gaps between
+            // words so transmission is not chopped, then closing fully so
+            // silence parks. What gets through is levelled out, so quiet and
+            // loud microphones sound alike, and limited so the raised gain
+            // never clips.
+            effects.push(Box::new(NoiseSuppressor::new(Default::default())));
+            effects.push(Box::new(talk.switch()));
+            effects.push(Box::new(Gate::new(GateSettings {
+                threshold_db: -54.0,
+                ..Default::default()
+            })));
+            effects.push(Box::new(AutoGain::new(Default::default())));
+            effects.push(Box::new(Limiter::new(Default::default())));
             let input = audio
-                .create_input_channel(
-                    StreamFormat {
-                        channels: 2,
-                        sample_format: SampleFormat::F32,
-                        sample_rate: 48_000,
-                    },
-                    // Noise-gate the microphone near the device noise floor,
-                    // holding across the gaps between words so transmission is
-                    // not chopped, then closing fully so silence parks.
-                    vec![Box::new(Gate::new(GateSettings {
-                        threshold_db: -54.0,
-                        ..Default::default()
-                    }))],
-                )
+                .create_input_channel(format, Resampling::Sinc, effects)
                 .unwrap();
 
             if sender.send(input).is_err() {
@@ -3,10 +3,14 @@ use std::borrow::Cow;
 use auth::MessengersGenerator;
 use font_kit::{family_name::FamilyName, source::SystemSource};
 use futures::{StreamExt, future::join_all, join};
-use iced::{Element, Subscription, Task, window};
-use messenger_interface::interface::{CallState, CallStatus};
-use pages::{AppMessage, StreamDirection, login, messenger};
-use simple_audio_channels::AudioMixer;
+use iced::{
+    Element, Subscription, Task,
+    keyboard::{self, key},
+    window,
+};
+use messenger_interface::interface::{CallState, CallStatus, TextEvent};
+use pages::{AppMessage, StreamDirection, login, messenger, settings};
+use simple_audio_channels::{AudioMixer, effects::SwitchHandle};
 use state::MessengerRegistry;
 
 mod auth;
@@ -15,14 +19,19 @@ mod events;
 mod pages;
 mod state;
 
-use tracing::{error, trace, warn};
+use tracing::{error, info, trace, warn};
 use tracing_subscriber::FmtSubscriber;
 
+/// Held to transmit in [`settings::VoiceMode::PushToTalk`]. Matched by
+/// position, so it is the key left of 1 on any layout.
+pub const PUSH_TO_TALK_KEY: key::Code = key::Code::Backquote;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Screen {
     Loading,
     Login,
     Messenger,
+    Settings,
 }
 
 pub fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -68,8 +77,12 @@ struct App {
     // Page states persist independently of which screen is active
     login: login::Login,
     messenger: messenger::Messenger,
+    settings: settings::Settings,
     // Subsystems
     audio: AudioMixer,
+    /// Lets the microphone through; push-to-talk keeps it off until the key
+    /// is held.
+    talk: SwitchHandle,
     messengers: MessengerRegistry,
 }
 
@@ -97,7 +110,9 @@ impl App {
             screen,
             login: login::Login::default(),
             messenger: messenger::Messenger::new(),
+            settings: settings::Settings::default(),
             audio: AudioMixer::default(),
+            talk: SwitchHandle::new(true),
             messengers,
         };
 
@@ -147,9 +162,16 @@ impl App {
                         // Voice is optional: messengers without it (e.g. Steam)
                         // must still load. Query/Text above remain required.
                         let voice = api.clone().arc_voice().ok();
+                        // Presence is optional too; contacts just show offline.
+                        let presence = api.clone().arc_presence().ok();
 
-                        let (profile, contacts, conversations, servers) =
-                            join!(q.client_user(), q.contacts(), q.rooms(), q.houses());
+                        let (profile, contacts, conversations, servers, read_states) = join!(
+                            q.client_user(),
+                            q.contacts(),
+                            q.rooms(),
+                            q.houses(),
+                            t.read_states()
+                        );
 
                         let profile = match profile {
                             Ok(p) => Some(p),
@@ -159,18 +181,30 @@ impl App {
                             }
                         };
 
+                        let (presences, presence_socket) = match presence {
+                            Some(p) => (p.presences().await, p.listen().await),
+                            None => (
+                                Err("Presence not supported".into()),
+                                Err("Presence not supported".into()),
+                            ),
+                        };
+
                         Some((
                             id,
                             profile,
                             contacts.unwrap_or_default(),
                             conversations.unwrap_or_default(),
                             servers.unwrap_or_default(),
+                            presences.unwrap_or_default(),
+                            // Without read state nothing shows as unread.
+                            read_states.unwrap_or_default(),
                             q.listen().await,
                             t.listen().await,
                             match voice {
                                 Some(v) => v.listen().await,
                                 None => Err("Voice not supported".into()),
                             },
+                            presence_socket,
                         ))
                     }
                 })))
@@ -182,9 +216,12 @@ impl App {
                             contacts,
                             conversations,
                             servers,
+                            presences,
+                            read_states,
                             query_socket,
                             text_socket,
                             voice_socket,
+                            presence_socket,
                         ) = m?;
 
                         let task = Task::done(AppMessage::modify_data(id, move |data| {
@@ -192,6 +229,8 @@ impl App {
                             data.contacts = contacts;
                             data.conversations = conversations;
                             data.guilds = servers;
+                            data.presences = presences;
+                            data.read_states = read_states;
                         }));
 
                         let mut streams = Vec::new();
@@ -211,6 +250,11 @@ impl App {
                                 socket.map(move |event| AppMessage::VoiceEvent((id, event))),
                             ));
                         }
+                        if let Ok(socket) = presence_socket {
+                            streams.push(Task::stream(
+                                socket.map(move |event| AppMessage::PresenceEvent((id, event))),
+                            ));
+                        }
 
                         Some(task.chain(Task::batch(streams)))
                     });
@@ -225,15 +269,42 @@ impl App {
                 events::process_query_event(id, event, &mut self.messengers)
             }
             AppMessage::TextEvent((id, event)) => {
-                events::process_text_event(id, event, &mut self.messengers)
+                // A message landing in the open chat is read on arrival.
+                let mark_read = match &event {
+                    TextEvent::MessageCreated { room, message }
+                        if self.screen == Screen::Messenger
+                            && self.messenger.viewing() == Some((id, *room.id())) =>
+                    {
+                        Some(messenger::Message::MarkRead {
+                            id,
+                            room_id: *room.id(),
+                            message_id: *message.id(),
+                        })
+                    }
+                    _ => None,
+                };
+                let task = events::process_text_event(id, event, &mut self.messengers);
+                match mark_read {
+                    Some(message) => Task::batch([task, Task::done(AppMessage::Chat(message))]),
+                    None => task,
+                }
             }
             AppMessage::VoiceEvent((id, event)) => {
                 events::process_voice_event(id, event, &mut self.messengers)
             }
+            AppMessage::PresenceEvent((id, event)) => {
+                events::process_presence_event(id, event, &mut self.messengers)
+            }
             AppMessage::AudioEvent((id, event)) => {
-                events::process_audio_event(id, event, &mut self.audio)
+                events::process_audio_event(id, event, &mut self.audio, &self.talk)
             }
             // === Audio ===
+            AppMessage::PushToTalk(held) => {
+                if self.settings.voice_mode() == settings::VoiceMode::PushToTalk {
+                    self.talk.set(held);
+                }
+                Task::none()
+            }
             AppMessage::StartStream(dir) => {
                 let result = match dir {
                     StreamDirection::Input => self.audio.start_stream_input(),
@@ -274,8 +345,8 @@ impl App {
                             Ok(query) => {
                                 query.client_user().await.map(|_| ()).map_err(|err| err.to_string())
                             }
-                            // No query capability to verify against (e.g. Test):
-                            // let it through unchanged.
+                            // No query capability to verify against: let it
+                            // through unchanged.
                             Err(_) => Ok(()),
                         };
                         (api, result)
@@ -350,9 +421,80 @@ impl App {
                         let call_id = call.id();
                         Task::done(AppMessage::modify_data(call.messenger_id(), move |data| {
                             data.calls.retain(|c| c.id() != call_id);
+                            if data.calls.is_empty() {
+                                data.call_audio.clear_sources();
+                                data.speaking.clear();
+                                data.mute_states.clear();
+                                // Leaving the call stopped the recording.
+                                data.recording = false;
+                            }
                         }))
                     })
                 }
+                messenger::Action::SetRecording {
+                    interface,
+                    recording,
+                } => {
+                    let api = interface.api.clone();
+                    let messenger_id = interface.id;
+                    let settings = self.settings.recording();
+
+                    Task::future(async move {
+                        let result = match api.voice() {
+                            Ok(vc) if recording => vc.start_recording(settings).await,
+                            Ok(vc) => vc
+                                .stop_recording()
+                                .await
+                                .map(|paths| info!("Call recording saved to {paths:?}")),
+                            Err(err) => Err(err.into()),
+                        };
+                        match result {
+                            Ok(()) => {
+                                Task::done(AppMessage::modify_data(messenger_id, move |data| {
+                                    data.recording = recording
+                                }))
+                            }
+                            Err(err) => {
+                                error!("Failed to set call recording: {err}");
+                                Task::none()
+                            }
+                        }
+                    })
+                    .then(|task| task)
+                }
+                messenger::Action::OpenSettings => {
+                    self.settings.refresh();
+                    Task::done(AppMessage::Navigate(Screen::Settings))
+                }
+            },
+            AppMessage::Settings(message) => match self.settings.update(message) {
+                settings::Action::None => Task::none(),
+                settings::Action::SetOutput(choice) => {
+                    // Live channels (a call in progress) move along with it.
+                    let message = match self.audio.set_output_device(choice.output_backend()) {
+                        Ok(()) => settings::Message::OutputChanged(choice),
+                        Err(err) => {
+                            error!("Failed to switch to {choice}: {err}");
+                            settings::Message::DeviceFailed(format!("{choice}: {err}"))
+                        }
+                    };
+                    Task::done(AppMessage::Settings(message))
+                }
+                settings::Action::SetInput(choice) => {
+                    let message = match self.audio.set_input_device(choice.input_backend()) {
+                        Ok(()) => settings::Message::InputChanged(choice),
+                        Err(err) => {
+                            error!("Failed to switch to {choice}: {err}");
+                            settings::Message::DeviceFailed(format!("{choice}: {err}"))
+                        }
+                    };
+                    Task::done(AppMessage::Settings(message))
+                }
+                settings::Action::SetVoiceMode(mode) => {
+                    self.talk.set(mode == settings::VoiceMode::VoiceActivity);
+                    Task::none()
+                }
+                settings::Action::Back => Task::done(AppMessage::Navigate(Screen::Messenger)),
             },
         }
     }
@@ -362,10 +504,31 @@ impl App {
             Screen::Loading => iced::widget::text("Loading").into(),
             Screen::Login => self.login.view().map(AppMessage::Login),
             Screen::Messenger => self.messenger.view(&self.messengers).map(AppMessage::Chat),
+            Screen::Settings => self.settings.view().map(AppMessage::Settings),
         }
     }
 
     fn subscription(&self) -> Subscription<AppMessage> {
-        Subscription::none()
+        match self.settings.voice_mode() {
+            settings::VoiceMode::PushToTalk => keyboard::listen().filter_map(push_to_talk),
+            settings::VoiceMode::VoiceActivity => Subscription::none(),
+        }
+    }
+}
+
+/// Presses and releases of [`PUSH_TO_TALK_KEY`]. Only keys no widget took
+/// arrive here, so typing it into a text field doesn't transmit.
+fn push_to_talk(event: keyboard::Event) -> Option<AppMessage> {
+    match event {
+        keyboard::Event::KeyPressed {
+            physical_key: key::Physical::Code(PUSH_TO_TALK_KEY),
+            repeat: false,
+            ..
+        } => Some(AppMessage::PushToTalk(true)),
+        keyboard::Event::KeyReleased {
+            physical_key: key::Physical::Code(PUSH_TO_TALK_KEY),
+            ..
+        } => Some(AppMessage::PushToTalk(false)),
+        _ => None,
     }
 }
@@ -1,9 +1,12 @@
 pub mod login;
 pub mod messenger;
+pub mod settings;
 
 use crate::Screen;
 use crate::state::{MessengerId, MessengerRegistry};
-use messenger_interface::interface::{AudioEvent, QueryEvent, TextEvent, VoiceEvent};
+use messenger_interface::interface::{
+    AudioEvent, PresenceEvent, QueryEvent, TextEvent, VoiceEvent,
+};
 
 #[derive(Debug, Clone, Copy)]
 pub enum StreamDirection {
@@ -29,15 +32,19 @@ pub(crate) enum AppMessage {
     StartUp,
     SaveCredentials,
     // === Audio ===
+    /// The push-to-talk key went down (`true`) or up.
+    PushToTalk(bool),
     StartStream(StreamDirection),
     StopStream(StreamDirection),
     // === Pages ===
     Login(login::Message),
     Chat(messenger::Message),
+    Settings(settings::Message),
     // === Socket ===
     QueryEvent((MessengerId, QueryEvent)),
     TextEvent((MessengerId, TextEvent)),
     VoiceEvent((MessengerId, VoiceEvent)),
+    PresenceEvent((MessengerId, PresenceEvent)),
     AudioEvent((MessengerId, AudioEvent)),
 }
 
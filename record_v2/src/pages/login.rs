@@ -4,6 +4,7 @@ use iced::{
     widget::{Button, Checkbox, Column, ComboBox, Container, TextInput, column, combo_box::State},
 };
 use messenger_interface::interface::Messenger as NeoMessenger;
+use mock::Mock;
 use std::{fmt::Display, sync::Arc};
 use steam::Steam;
 use strum::EnumString;
@@ -30,9 +31,8 @@ impl Platform {
             Self::Discord => Discord::new_messenger(auth),
             // Steam expects `auth` as "username:password" (see steam::Steam).
             Self::Steam => Steam::new_messenger(auth),
-            Self::Test => {
-                todo!()
-            }
+            // `auth` names a built-in mock scenario; empty loads the demo.
+            Self::Test => Mock::new_messenger(auth),
         }
     }
     /// Which input fields to show for this platform. Discord additionally
@@ -17,9 +17,10 @@ use iced::{
     widget::{Responsive, Text, column, row},
 };
 use messenger_interface::types::{
-    ID, Identifier, Message as InterfaceMessage, Place, Revision, RichText, Room,
+    Attachment, ID, Identifier, Mention, Message as InterfaceMessage, Place, Revision, RichText,
+    Room,
 };
-use tracing::error;
+use tracing::{debug, error};
 
 mod chat;
 mod contacts;
@@ -54,6 +55,14 @@ impl Messenger {
         self.pending_counter = self.pending_counter.wrapping_sub(1);
         id
     }
+
+    /// The messenger and room of the open chat, if one is open.
+    pub(crate) fn viewing(&self) -> Option<(MessengerId, ID)> {
+        match &self.main {
+            Main::Chat(chat) => Some((chat.interface.id, *chat.room.id())),
+            Main::Contacts(_) => None,
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -71,6 +80,12 @@ pub(crate) enum Message {
         guild_id: ID,
         rooms: Vec<Identifier<Place<Room>>>,
     },
+    /// The open threads of the channel `parent_id`.
+    ThreadsLoaded {
+        id: MessengerId,
+        parent_id: ID,
+        threads: Vec<Identifier<Place<Room>>>,
+    },
     UpdateChat {
         id: MessengerId,
         kv: (ID, Vec<Identifier<InterfaceMessage>>),
@@ -95,6 +110,31 @@ pub(crate) enum Message {
         room_id: ID,
         pending_id: ID,
     },
+    /// The server accepted an edit; replace the message with its edited form.
+    EditConfirmed {
+        id: MessengerId,
+        room_id: ID,
+        message: Identifier<InterfaceMessage>,
+    },
+    /// The server deleted a message; drop it without waiting for the echo.
+    DeleteConfirmed {
+        id: MessengerId,
+        room_id: ID,
+        message_id: ID,
+    },
+    /// The user has seen the room up to `message_id`; tell the server if
+    /// anything there was still unread.
+    MarkRead {
+        id: MessengerId,
+        room_id: ID,
+        message_id: ID,
+    },
+    /// Record locally that the room was read up to `message_id`.
+    ReadMarked {
+        id: MessengerId,
+        room_id: ID,
+        message_id: ID,
+    },
 }
 
 pub enum Action {
@@ -109,6 +149,13 @@ pub enum Action {
         channel: Identifier<Place<Room>>,
     },
     DisconnectFromCall(Call),
+    /// Start or stop recording the messenger's calls, with the settings
+    /// only the app has.
+    SetRecording {
+        interface: MessengerInterface,
+        recording: bool,
+    },
+    OpenSettings,
 }
 
 impl Messenger {
@@ -152,6 +199,34 @@ impl Messenger {
                     }),
                 }
             }
+            Message::ThreadsLoaded {
+                id,
+                parent_id,
+                threads,
+            } => Action::ModifyMessengerData {
+                id,
+                modify: Box::new(move |data| {
+                    let Some(rooms) = data
+                        .guilds
+                        .iter_mut()
+                        .filter_map(|guild| guild.rooms.as_mut())
+                        .find(|rooms| rooms.iter().any(|room| *room.id() == parent_id))
+                    else {
+                        return;
+                    };
+                    // Threads missing from the listing were closed meanwhile;
+                    // the ones still open keep their loaded messages.
+                    rooms.retain(|room| {
+                        room.parent != Some(parent_id)
+                            || threads.iter().any(|thread| thread.id() == room.id())
+                    });
+                    for thread in threads {
+                        if !rooms.iter().any(|room| room.id() == thread.id()) {
+                            rooms.push(thread);
+                        }
+                    }
+                }),
+            },
             Message::PendingMessage {
                 id,
                 room_id,
@@ -207,6 +282,81 @@ impl Messenger {
                     }
                 }),
             },
+            Message::EditConfirmed {
+                id,
+                room_id,
+                message,
+            } => Action::ModifyMessengerData {
+                id,
+                modify: Box::new(move |data| {
+                    if let Some(room) = data.room_mut(room_id)
+                        && let Some(msgs) = room.messages.as_mut()
+                        && let Some(existing) = msgs.iter_mut().find(|m| m.id() == message.id())
+                    {
+                        *existing = message;
+                    }
+                }),
+            },
+            Message::DeleteConfirmed {
+                id,
+                room_id,
+                message_id,
+            } => Action::ModifyMessengerData {
+                id,
+                modify: Box::new(move |data| {
+                    if let Some(room) = data.room_mut(room_id)
+                        && let Some(msgs) = room.messages.as_mut()
+                    {
+                        msgs.retain(|m| *m.id() != message_id);
+                    }
+                }),
+            },
+            Message::ReadMarked {
+                id,
+                room_id,
+                message_id,
+            } => Action::ModifyMessengerData {
+                id,
+                modify: Box::new(move |data| data.mark_read(room_id, message_id)),
+            },
+            Message::MarkRead {
+                id,
+                room_id,
+                message_id,
+            } => {
+                let Some(interface) = messengers.interface(id) else {
+                    return Action::None;
+                };
+                let Some(room) = messengers
+                    .data(id)
+                    .filter(|data| data.read_state(room_id).is_unread())
+                    .and_then(|data| data.room(room_id))
+                else {
+                    return Action::None;
+                };
+                let room = room.clone();
+                let interface = interface.clone();
+                Action::Run(Task::batch([
+                    // Clear the badge right away rather than waiting on the server.
+                    Task::done(Message::ReadMarked {
+                        id,
+                        room_id,
+                        message_id,
+                    }),
+                    Task::future(async move {
+                        let message = Identifier::new(message_id, InterfaceMessage::default());
+                        let result = match interface.api.text() {
+                            Ok(text) => text.mark_read(&room, &message).await,
+                            Err(e) => Err(Box::new(e) as Box<dyn Error + Send + Sync>),
+                        };
+                        // Not every messenger keeps read state.
+                        if let Err(e) = result {
+                            debug!("Read marker not sent: {e:?}");
+                        }
+                    })
+                    .then(|_| Task::none()),
+                ]))
+            }
             Message::Chat(msg) => {
                 if let Main::Chat(chat) = &mut self.main {
                     return match msg {
@@ -227,12 +377,38 @@ impl Messenger {
                                 interface,
                                 room,
                                 contents,
+                                reply,
+                                files,
                             } => {
                                 let id = interface.id;
                                 let room_id = *room.id();
                                 let pending_id = self.next_pending_id();
                                 let author =
                                     messengers.data(id).and_then(|data| data.profile.clone());
+                                // Read in the platform's markup, so the echo
+                                // shows formatted. The draft itself is sent as
+                                // typed.
+                                let echo = match interface.api.text() {
+                                    Ok(text) => text.parse_draft(&contents),
+                                    Err(_) => RichText::plain(contents.clone()),
+                                };
+                                // Show the picked files straight from disk until the
+                                // server's copies arrive with the confirmation.
+                                let attachments = files
+                                    .iter()
+                                    .map(|path| Attachment {
+                                        filename: path
+                                            .file_name()
+                                            .map(|name| name.to_string_lossy().into_owned())
+                                            .unwrap_or_default(),
+                                        size: std::fs::metadata(path)
+                                            .map(|metadata| metadata.len())
+                                            .unwrap_or_default(),
+                                        mime: None,
+                                        path: Some(path.clone()),
+                                        dimensions: None,
+                                    })
+                                    .collect();
 
                                 // TODO: Verify if we actually need to create an InterfaceMessage here,
                                 // as we are already in the UI and all data in here is in the unified format.
@@ -241,11 +417,15 @@ impl Messenger {
                                     InterfaceMessage {
                                         content: Revision {
                                             at: None,
-                                            text: RichText::plain(contents.clone()),
+                                            text: echo.clone(),
                                         },
                                         history: Vec::new(),
                                         reactions: Vec::new(),
+                                        attachments,
                                         author,
+                                        reply: reply.clone(),
+                                        draft: None,
+                                        mentions_me: false,
                                     },
                                 );
 
@@ -263,20 +443,20 @@ impl Messenger {
                                             let text = api.text().map_err(|e| {
                                                 Box::new(e) as Box<dyn Error + Send + Sync>
                                             })?;
-                                            let confirmed = text
-                                                .send_message(
-                                                    &room,
-                                                    InterfaceMessage {
-                                                        content: Revision {
-                                                            at: None,
-                                                            text: RichText::plain(contents),
-                                                        },
-                                                        history: Vec::new(),
-                                                        reactions: Vec::new(),
-                                                        author: None,
-                                                    },
-                                                )
-                                                .await?;
+                                            let outgoing = InterfaceMessage {
+                                                content: Revision {
+                                                    at: None,
+                                                    text: echo,
+                                                },
+                                                reply,
+                                                draft: Some(contents),
+                                                ..Default::default()
+                                            };
+                                            let confirmed = if files.is_empty() {
+                                                text.send_message(&room, outgoing).await?
+                                            } else {
+                                                text.send_files(&room, outgoing, files).await?
+                                            };
                                             Ok(confirmed)
                                         }
                                     })
@@ -332,6 +512,133 @@ impl Messenger {
                                 })
                                 .then(|_| Task::none()),
                             ),
+                            ChatUpdateResult::Edit {
+                                interface,
+                                room,
+                                message,
+                                text,
+                            } => {
+                                let id = interface.id;
+                                let room_id = *room.id();
+                                Action::Run(
+                                    Task::future(async move {
+                                        let text_api = interface.api.text().map_err(|e| {
+                                            Box::new(e) as Box<dyn Error + Send + Sync>
+                                        })?;
+                                        text_api.edit_message(&room, &message, &text).await
+                                    })
+                                    .then(
+                                        move |result: Result<_, Box<dyn Error + Send + Sync>>| {
+                                            match result {
+                                                Ok(message) => Task::done(Message::EditConfirmed {
+                                                    id,
+                                                    room_id,
+                                                    message,
+                                                }),
+                                                Err(e) => {
+                                                    error!("Failed to edit message: {e:#?}");
+                                                    Task::none()
+                                                }
+                                            }
+                                        },
+                                    ),
+                                )
+                            }
+                            ChatUpdateResult::Delete {
+                                interface,
+                                room,
+                                message_id,
+                            } => {
+                                let id = interface.id;
+                                let room_id = *room.id();
+                                Action::Run(
+                                    Task::future(async move {
+                                        let text = interface.api.text().map_err(|e| {
+                                            Box::new(e) as Box<dyn Error + Send + Sync>
+                                        })?;
+                                        let msg_ident = Identifier::new(
+                                            message_id,
+                                            InterfaceMessage::default(),
+                                        );
+                                        text.delete_message(&room, &msg_ident).await
+                                    })
+                                    .then(
+                                        move |result: Result<_, Box<dyn Error + Send + Sync>>| {
+                                            match result {
+                                                Ok(()) => Task::done(Message::DeleteConfirmed {
+                                                    id,
+                                                    room_id,
+                                                    message_id,
+                                                }),
+                                                Err(e) => {
+                                                    error!("Failed to delete message: {e:#?}");
+                                                    Task::none()
+                                                }
+                                            }
+                                        },
+                                    ),
+                                )
+                            }
+                            ChatUpdateResult::Typing { interface, room } => Action::Run(
+                                Task::future(async move {
+                                    let result = match interface.api.text() {
+                                        Ok(text) => text.send_typing(&room).await,
+                                        Err(e) => Err(Box::new(e) as Box<dyn Error + Send + Sync>),
+                                    };
+                                    // Not every room supports typing (Steam
+                                    // group chats); that's no reason to shout.
+                                    if let Err(e) = result {
+                                        debug!("Typing indicator not sent: {e:?}");
+                                    }
+                                })
+                                .then(|_| Task::none()),
+                            ),
+                            ChatUpdateResult::OpenMention { interface, mention } => match mention {
+                                Mention::Channel(room_id) => {
+                                    match messengers
+                                        .data(interface.id)
+                                        .and_then(|d| d.room(room_id))
+                                    {
+                                        Some(room) => Action::Run(Task::done(Message::Sidebar(
+                                            SidebarAction::OpenChat {
+                                                id: interface.id,
+                                                conversation: room.clone(),
+                                            },
+                                        ))),
+                                        None => {
+                                            debug!("Mentioned room {room_id} isn't loaded");
+                                            Action::None
+                                        }
+                                    }
+                                }
+                                Mention::User(user_id) => Action::Run(
+                                    Task::future(async move {
+                                        let text = interface.api.text().map_err(|e| {
+                                            Box::new(e) as Box<dyn Error + Send + Sync>
+                                        })?;
+                                        let room = text.direct_room(user_id).await?;
+                                        Ok((interface.id, room))
+                                    })
+                                    .then(
+                                        |result: Result<_, Box<dyn Error + Send + Sync>>| {
+                                            match result {
+                                                Ok((id, conversation)) => Task::done(
+                                                    Message::Sidebar(SidebarAction::OpenChat {
+                                                        id,
+                                                        conversation,
+                                                    }),
+                                                ),
+                                                Err(e) => {
+                                                    error!("Failed to open the DM: {e:?}");
+                                                    Task::none()
+                                                }
+                                            }
+                                        },
+                                    ),
+                                ),
+                                // Nowhere to go.
+                                Mention::Role(_) | Mention::Everyone => Action::None,
+                            },
                         },
                     };
                 }
@@ -345,6 +652,7 @@ impl Messenger {
             }
             Message::Navbar(action) => match action {
                 NavbarAction::GetDMs => Action::Run(Task::done(Message::SetSidebarServer(None))),
+                NavbarAction::OpenSettings => Action::OpenSettings,
                 NavbarAction::GetGuild { id, server } => {
                     let Some(interface) = messengers.interface(id) else {
                         return Action::None;
@@ -397,6 +705,79 @@ impl Messenger {
             },
             Message::Sidebar(action) => match action {
                 SidebarAction::Disconnect(call) => Action::DisconnectFromCall(call),
+                SidebarAction::SetCallVolume { id, volume } => Action::ModifyMessengerData {
+                    id,
+                    modify: Box::new(move |data| data.call_audio.set_volume(volume)),
+                },
+                SidebarAction::SetCallMuted { id, muted } => Action::ModifyMessengerData {
+                    id,
+                    modify: Box::new(move |data| data.call_audio.set_muted(muted)),
+                },
+                SidebarAction::SetParticipantVolume {
+                    id,
+                    user_id,
+                    volume,
+                } => Action::ModifyMessengerData {
+                    id,
+                    modify: Box::new(move |data| {
+                        data.call_audio.set_participant_volume(user_id, volume)
+                    }),
+                },
+                SidebarAction::SetParticipantMuted { id, user_id, muted } => {
+                    Action::ModifyMessengerData {
+                        id,
+                        modify: Box::new(move |data| {
+                            data.call_audio.set_participant_muted(user_id, muted)
+                        }),
+                    }
+                }
+                SidebarAction::SetSelfMute { id, muted } => {
+                    let Some(interface) = messengers.interface(id) else {
+                        return Action::None;
+                    };
+                    let api = interface.api.clone();
+                    // The buttons follow the MuteStateChanged the platform
+                    // sends back, so there is nothing to update here.
+                    Action::Run(
+                        Task::future(async move {
+                            let result = match api.voice() {
+                                Ok(voice) => voice.set_self_mute(muted).await,
+                                Err(e) => Err(Box::new(e) as Box<dyn Error + Send + Sync>),
+                            };
+                            if let Err(e) = result {
+                                error!("Failed to set self-mute: {e:#?}");
+                            }
+                        })
+                        .then(|_| Task::none()),
+                    )
+                }
+                SidebarAction::SetSelfDeaf { id, deafened } => {
+                    let Some(interface) = messengers.interface(id) else {
+                        return Action::None;
+                    };
+                    let api = interface.api.clone();
+                    Action::Run(
+                        Task::future(async move {
+                            let result = match api.voice() {
+                                Ok(voice) => voice.set_self_deaf(deafened).await,
+                                Err(e) => Err(Box::new(e) as Box<dyn Error + Send + Sync>),
+                            };
+                            if let Err(e) = result {
+                                error!("Failed to set self-deafen: {e:#?}");
+                            }
+                        })
+                        .then(|_| Task::none()),
+                    )
+                }
+                SidebarAction::SetRecording { id, recording } => {
+                    let Some(interface) = messengers.interface(id) else {
+                        return Action::None;
+                    };
+                    Action::SetRecording {
+                        interface: interface.clone(),
+                        recording,
+                    }
+                }
                 SidebarAction::Call(channel) => {
                     let server = self.sidebar.server_selected.as_ref().unwrap();
                     let Some(interface) = messengers.interface(server.messenger_id) else {
@@ -408,6 +789,39 @@ impl Messenger {
                         channel,
                     }
                 }
+                SidebarAction::ToggleThreads { id, channel } => {
+                    let parent_id = *channel.id();
+                    if !self.sidebar.expanded.insert(parent_id) {
+                        self.sidebar.expanded.remove(&parent_id);
+                        return Action::None;
+                    }
+                    let Some(interface) = messengers.interface(id) else {
+                        return Action::None;
+                    };
+
+                    // Refetched on every expand: forum posts are not pushed.
+                    let interface = interface.clone();
+                    Action::Run(
+                        Task::future(async move {
+                            let query = interface.query()?;
+                            let threads = query.threads(&channel).await?;
+                            Ok((interface.id, threads))
+                        })
+                        .then(
+                            move |result: Result<_, Box<dyn Error + Send + Sync>>| match result {
+                                Ok((id, threads)) => Task::done(Message::ThreadsLoaded {
+                                    id,
+                                    parent_id,
+                                    threads,
+                                }),
+                                Err(e) => {
+                                    error!("Failed to load threads: {e:#?}");
+                                    Task::none()
+                                }
+                            },
+                        ),
+                    )
+                }
                 SidebarAction::OpenContacts => {
                     self.main = Main::Contacts(Contacts::default());
                     Action::None
@@ -423,9 +837,20 @@ impl Messenger {
                             .room(*conversation.id())
                             .is_some_and(|room| room.messages.is_some())
                     {
-                        return Action::Run(Task::done(Message::ChangeMain(Main::Chat(
-                            Chat::new(interface.clone(), conversation),
+                        let room_id = *conversation.id();
+                        let newest = data.newest_sent(room_id);
+                        let open = Task::done(Message::ChangeMain(Main::Chat(Chat::new(
+                            interface.clone(),
+                            conversation,
                         ))));
+                        return Action::Run(match newest {
+                            Some(message_id) => open.chain(Task::done(Message::MarkRead {
+                                id,
+                                room_id,
+                                message_id,
+                            })),
+                            None => open,
+                        });
                     }
 
                     // Otherwise fetch
@@ -455,10 +880,24 @@ impl Messenger {
                         })
                         .then(
                             |t: Result<_, Box<dyn Error + Send + Sync>>| match t {
-                                Ok((id, conversation, msgs)) => Task::done(Message::UpdateChat {
-                                    id,
-                                    kv: (*conversation.id(), msgs),
-                                }),
+                                Ok((id, conversation, msgs)) => {
+                                    let room_id = *conversation.id();
+                                    let newest = msgs.last().map(|message| *message.id());
+                                    let update = Task::done(Message::UpdateChat {
+                                        id,
+                                        kv: (room_id, msgs),
+                                    });
+                                    match newest {
+                                        Some(message_id) => {
+                                            update.chain(Task::done(Message::MarkRead {
+                                                id,
+                                                room_id,
+                                                message_id,
+                                            }))
+                                        }
+                                        None => update,
+                                    }
+                                }
                                 Err(err) => {
                                     error!("{err}");
                                     Task::none()
@@ -1,8 +1,18 @@
+use std::{
+    collections::HashSet,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
 use iced::{
     Element, Length, Task,
     widget::{Button, Column, Scrollable, Text, TextInput, column, row, text::LineHeight},
 };
-use messenger_interface::types::{ID, Identifier, Place, Room, RoomCapabilities};
+use iced_aw::ContextMenu;
+use messenger_interface::types::{
+    Emoji, ID, Identifier, Mention, Message as InterfaceMessage, Place, Reply, ReplyPreview,
+    RichText, Room, RoomCapabilities, Span, TextStyle,
+};
 
 use crate::{
     components::message_text::message_text,
@@ -14,17 +24,71 @@ pub struct Chat {
     pub interface: MessengerInterface,
     pub room: Identifier<Place<Room>>,
     msg_box: String,
+    /// The message being edited and the draft replacing its text.
+    editing: Option<(Identifier<InterfaceMessage>, String)>,
+    /// The message the next send replies to.
+    replying_to: Option<Identifier<InterfaceMessage>>,
+    /// Files picked to go out with the next send.
+    files: Vec<PathBuf>,
+    /// When the room was last told we are typing.
+    typing_sent: Option<Instant>,
+    /// Messages whose spoilers were clicked open.
+    revealed: HashSet<ID>,
+    /// Whether the room's members are listed for mentioning.
+    picking_mention: bool,
+}
+
+/// How often typing is re-announced while the user keeps typing; under the
+/// indicator's timeout so it doesn't flicker off in between.
+const TYPING_RESEND_INTERVAL: Duration = Duration::from_secs(8);
+
+/// Emoji the composer offers to insert. Unicode, so every platform sends
+/// them as text.
+const COMPOSER_EMOJI: [&str; 6] = ["😀", "😂", "👍", "❤️", "🎉", "😢"];
+
+/// A style the composer toggles over the whole draft.
+#[derive(Debug, Clone, Copy)]
+pub enum Emphasis {
+    Bold,
+    Italic,
+}
+impl Emphasis {
+    fn flag(self, style: &mut TextStyle) -> &mut bool {
+        match self {
+            Self::Bold => &mut style.bold,
+            Self::Italic => &mut style.italic,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum Message {
     MsgInput(String),
     MsgSend,
+    PickFiles,
+    FilesPicked(Vec<PathBuf>),
+    RemoveFile(usize),
     ToggleReaction {
         message_id: ID,
         emoji: String,
         reacted: bool,
     },
+    StartReply(Identifier<InterfaceMessage>),
+    CancelReply,
+    StartEdit(Identifier<InterfaceMessage>),
+    EditInput(String),
+    EditSubmit,
+    CancelEdit,
+    Delete(ID),
+    OpenMention(Mention),
+    ToggleSpoilers(ID),
+    ToggleEmphasis(Emphasis),
+    InsertEmoji(&'static str),
+    ToggleMentionPicker,
+    InsertMention {
+        user_id: ID,
+        name: String,
+    },
 }
 
 #[derive(Clone)]
@@ -43,6 +107,9 @@ pub enum UpdateResult {
         interface: MessengerInterface,
         room: Identifier<Place<Room>>,
         contents: String,
+        reply: Option<Reply>,
+        /// Files to upload with the message; empty for a text-only send.
+        files: Vec<PathBuf>,
     },
     /// User clicked a reaction button — toggle it on the server.
     ToggleReaction {
@@ -52,6 +119,29 @@ pub enum UpdateResult {
         emoji: String,
         reacted: bool,
     },
+    /// User submitted a new text for one of their messages.
+    Edit {
+        interface: MessengerInterface,
+        room: Identifier<Place<Room>>,
+        message: Identifier<InterfaceMessage>,
+        text: String,
+    },
+    /// User picked "Delete" on one of their messages.
+    Delete {
+        interface: MessengerInterface,
+        room: Identifier<Place<Room>>,
+        message_id: ID,
+    },
+    /// User is typing in the message box.
+    Typing {
+        interface: MessengerInterface,
+        room: Identifier<Place<Room>>,
+    },
+    /// User clicked a mention: open the DM with the user, or the channel.
+    OpenMention {
+        interface: MessengerInterface,
+        mention: Mention,
+    },
 }
 
 impl Chat {
@@ -60,9 +150,38 @@ impl Chat {
             interface,
             room,
             msg_box: String::new(),
+            editing: None,
+            replying_to: None,
+            files: Vec::new(),
+            typing_sent: None,
+            revealed: HashSet::new(),
+            picking_mention: false,
+        }
+    }
+
+    /// `text` as the platform's markup, to edit it in.
+    fn draft(&self, text: &RichText) -> String {
+        match self.interface.api.text() {
+            Ok(api) => api.write_draft(text),
+            Err(_) => text.to_plain(),
         }
     }
 
+    /// The message box read as rich text, the way it will be sent.
+    fn parse_msg_box(&self) -> RichText {
+        match self.interface.api.text() {
+            Ok(api) => api.parse_draft(&self.msg_box),
+            Err(_) => RichText::plain(self.msg_box.clone()),
+        }
+    }
+
+    /// Append `span` to the message box in the platform's markup, leaving
+    /// what was typed so far as it is.
+    fn append_to_msg_box(&mut self, span: Span) {
+        let draft = self.draft(&RichText { spans: vec![span] });
+        self.msg_box.push_str(&draft);
+    }
+
     pub fn get_element<'a>(&'a self, messengers: &'a MessengerRegistry) -> Element<'a, Action> {
         let mut channel_info = row![Text::new(self.room.name.clone())];
         if self.room.room_capabilities.contains(RoomCapabilities::Voice) {
@@ -80,21 +199,72 @@ impl Chat {
             .and_then(|room| room.messages.as_ref())
             .or_else(|| self.room.messages.as_ref());
 
+        let client_id = messengers
+            .data(self.interface.id)
+            .and_then(|d| d.profile.as_ref())
+            .map(|profile| *profile.id());
+        let can_edit = self.interface.api.text().is_ok_and(|text| text.can_edit());
+
         let chat = Scrollable::new(match messages {
             Some(messages) => messages
                 .iter()
                 .map(|msg| {
-                    message_text(msg, |msg, emoji, reacted| {
-                        Action::Message(Message::ToggleReaction {
-                            message_id: *msg.id(),
-                            emoji: emoji.to_owned(),
-                            reacted,
-                        })
+                    let text = message_text(
+                        msg,
+                        |msg, emoji, reacted| {
+                            Action::Message(Message::ToggleReaction {
+                                message_id: *msg.id(),
+                                emoji: emoji.to_owned(),
+                                reacted,
+                            })
+                        },
+                        |mention| Action::Message(Message::OpenMention(mention)),
+                        self.revealed.contains(msg.id()),
+                        Action::Message(Message::ToggleSpoilers(*msg.id())),
+                    );
+                    let own = client_id.is_some()
+                        && msg.author.as_ref().map(|author| *author.id()) == client_id;
+
+                    let text = match &self.editing {
+                        Some((editing, draft)) if editing.id() == msg.id() => column![
+                            text,
+                            row![
+                                TextInput::new("Edit msg...", draft)
+                                    .on_input(|s| Action::Message(Message::EditInput(s)))
+                                    .on_submit(Action::Message(Message::EditSubmit))
+                                    .line_height(LineHeight::Absolute(20.into())),
+                                Button::new("Cancel")
+                                    .on_press(Action::Message(Message::CancelEdit)),
+                            ]
+                        ]
+                        .into(),
+                        _ => text,
+                    };
+                    ContextMenu::new(text, move || {
+                        let mut menu = column![
+                            Button::new("Reply")
+                                .on_press(Action::Message(Message::StartReply(msg.clone()))),
+                        ];
+                        if own && can_edit {
+                            menu = menu.push(
+                                Button::new("Edit")
+                                    .on_press(Action::Message(Message::StartEdit(msg.clone()))),
+                            );
+                        }
+                        if own {
+                            menu = menu.push(
+                                Button::new("Delete")
+                                    .on_press(Action::Message(Message::Delete(*msg.id()))),
+                            );
+                        }
+                        menu.into()
                     })
+                    .into()
                 })
-                .fold(Column::new().spacing(15.0), |column, widget| {
-                    column.push(widget)
-                }),
+                .fold(
+                    Column::new().spacing(15.0),
+                    |column, widget: Element<'a, Action>| column.push(widget),
+                ),
             None => Column::new(),
         })
         .anchor_bottom()
@@ -106,26 +276,155 @@ impl Chat {
             .on_submit(Action::Message(Message::MsgSend))
             .line_height(LineHeight::Absolute(20.into()));
 
-        column![channel_info, chat, message_box].into()
+        let mut composer = Column::new();
+        let typing = messengers
+            .data(self.interface.id)
+            .map(|data| {
+                data.typing_in(*self.room.id())
+                    .map(|user| user.name.as_str())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        let typing = match typing.as_slice() {
+            [] => None,
+            [user] => Some(format!("{user} is typing…")),
+            [first, second] => Some(format!("{first} and {second} are typing…")),
+            _ => Some("Several people are typing…".to_owned()),
+        };
+        if let Some(typing) = typing {
+            composer = composer.push(Text::new(typing).size(12));
+        }
+        if let Some(target) = &self.replying_to {
+            let author = target
+                .author
+                .as_ref()
+                .map(|author| author.name.as_str())
+                .unwrap_or("Unknown");
+            composer = composer.push(row![
+                Text::new(format!("Replying to {author}")).width(Length::Fill),
+                Button::new("Cancel").on_press(Action::Message(Message::CancelReply)),
+            ]);
+        }
+        for (n, file) in self.files.iter().enumerate() {
+            let name = file
+                .file_name()
+                .map(|name| name.to_string_lossy())
+                .unwrap_or_default();
+            composer = composer.push(row![
+                Text::new(format!("📎 {name}")).width(Length::Fill),
+                Button::new("Remove").on_press(Action::Message(Message::RemoveFile(n))),
+            ]);
+        }
+        let mut controls = row![
+            Button::new("B").on_press(Action::Message(Message::ToggleEmphasis(Emphasis::Bold))),
+            Button::new("I").on_press(Action::Message(Message::ToggleEmphasis(Emphasis::Italic))),
+        ];
+        for emoji in COMPOSER_EMOJI {
+            controls = controls
+                .push(Button::new(emoji).on_press(Action::Message(Message::InsertEmoji(emoji))));
+        }
+        controls =
+            controls.push(Button::new("@").on_press(Action::Message(Message::ToggleMentionPicker)));
+        composer = composer.push(controls);
+        if self.picking_mention {
+            let members = messengers
+                .data(self.interface.id)
+                .and_then(|d| d.room(*self.room.id()))
+                .and_then(|room| room.participants.as_ref())
+                .or_else(|| self.room.participants.as_ref());
+            let members = members
+                .into_iter()
+                .flatten()
+                .fold(row![], |members, member| {
+                    members.push(
+                        Button::new(Text::new(format!("@{}", member.name))).on_press(
+                            Action::Message(Message::InsertMention {
+                                user_id: *member.id(),
+                                name: member.name.clone(),
+                            }),
+                        ),
+                    )
+                });
+            composer = composer.push(members);
+        }
+        composer = composer.push(row![
+            Button::new("Attach").on_press(Action::Message(Message::PickFiles)),
+            message_box,
+        ]);
+
+        column![channel_info, chat, composer].into()
     }
 
     pub fn update(&mut self, message: Message) -> UpdateResult {
         match message {
             Message::MsgInput(change) => {
                 self.msg_box = change;
-                UpdateResult::Task(Task::none())
+                if self.msg_box.is_empty()
+                    || self
+                        .typing_sent
+                        .is_some_and(|sent| sent.elapsed() < TYPING_RESEND_INTERVAL)
+                {
+                    return UpdateResult::Task(Task::none());
+                }
+                self.typing_sent = Some(Instant::now());
+                UpdateResult::Typing {
+                    interface: self.interface.clone(),
+                    room: self.room.clone(),
+                }
             }
             Message::MsgSend => {
-                let contents = std::mem::take(&mut self.msg_box);
-                if contents.is_empty() {
+                if self.msg_box.is_empty() && self.files.is_empty() {
                     return UpdateResult::Task(Task::none());
                 }
+                let contents = std::mem::take(&mut self.msg_box);
+                let files = std::mem::take(&mut self.files);
+                // Sending ends the indicator on the other side, so the next
+                // keystroke announces typing again.
+                self.typing_sent = None;
+                // Quote the target from what's on screen, so the pending
+                // message shows its reply header before the server confirms.
+                let reply = self.replying_to.take().map(|target| Reply {
+                    message_id: *target.id(),
+                    preview: Some(ReplyPreview {
+                        author: target
+                            .author
+                            .as_ref()
+                            .map(|author| author.name.clone())
+                            .unwrap_or_default(),
+                        text: target.content.text.to_plain(),
+                    }),
+                });
                 UpdateResult::Send {
                     interface: self.interface.clone(),
                     room: self.room.clone(),
                     contents,
+                    reply,
+                    files,
                 }
             }
+            Message::PickFiles => UpdateResult::Task(Task::future(async {
+                let picked = rfd::AsyncFileDialog::new()
+                    .set_title("Attach files")
+                    .pick_files()
+                    .await
+                    .unwrap_or_default();
+                Message::FilesPicked(
+                    picked
+                        .iter()
+                        .map(|file| file.path().to_path_buf())
+                        .collect(),
+                )
+            })),
+            Message::FilesPicked(files) => {
+                self.files.extend(files);
+                UpdateResult::Task(Task::none())
+            }
+            Message::RemoveFile(n) => {
+                if n < self.files.len() {
+                    self.files.remove(n);
+                }
+                UpdateResult::Task(Task::none())
+            }
             Message::ToggleReaction {
                 message_id,
                 emoji,
@@ -137,6 +436,103 @@ impl Chat {
                 emoji,
                 reacted,
             },
+            Message::StartReply(message) => {
+                self.replying_to = Some(message);
+                UpdateResult::Task(Task::none())
+            }
+            Message::CancelReply => {
+                self.replying_to = None;
+                UpdateResult::Task(Task::none())
+            }
+            Message::StartEdit(message) => {
+                let draft = self.draft(&message.content.text);
+                self.editing = Some((message, draft));
+                UpdateResult::Task(Task::none())
+            }
+            Message::EditInput(change) => {
+                if let Some((_, draft)) = &mut self.editing {
+                    *draft = change;
+                }
+                UpdateResult::Task(Task::none())
+            }
+            Message::EditSubmit => match self.editing.take() {
+                // An unchanged or emptied draft is dropped rather than sent;
+                // deleting is its own menu entry.
+                Some((message, text))
+                    if !text.is_empty() && text != self.draft(&message.content.text) =>
+                {
+                    UpdateResult::Edit {
+                        interface: self.interface.clone(),
+                        room: self.room.clone(),
+                        message,
+                        text,
+                    }
+                }
+                _ => UpdateResult::Task(Task::none()),
+            },
+            Message::CancelEdit => {
+                self.editing = None;
+                UpdateResult::Task(Task::none())
+            }
+            Message::Delete(message_id) => {
+                if self
+                    .editing
+                    .as_ref()
+                    .is_some_and(|(editing, _)| *editing.id() == message_id)
+                {
+                    self.editing = None;
+                }
+                UpdateResult::Delete {
+                    interface: self.interface.clone(),
+                    room: self.room.clone(),
+                    message_id,
+                }
+            }
+            Message::OpenMention(mention) => UpdateResult::OpenMention {
+                interface: self.interface.clone(),
+                mention,
+            },
+            Message::ToggleSpoilers(message_id) => {
+                if !self.revealed.remove(&message_id) {
+                    self.revealed.insert(message_id);
+                }
+                UpdateResult::Task(Task::none())
+            }
+            Message::ToggleEmphasis(emphasis) => {
+                // Styled throughout already clears it, otherwise it is set on
+                // all of the draft's text.
+                let mut text = self.parse_msg_box();
+                let mut styles: Vec<&mut TextStyle> = text
+                    .spans
+                    .iter_mut()
+                    .filter_map(|span| match span {
+                        Span::Text { style, .. } => Some(style),
+                        _ => None,
+                    })
+                    .collect();
+                let on = !styles.iter_mut().all(|style| *emphasis.flag(style));
+                for style in styles {
+                    *emphasis.flag(style) = on;
+                }
+                self.msg_box = self.draft(&text);
+                UpdateResult::Task(Task::none())
+            }
+            Message::InsertEmoji(emoji) => {
+                self.append_to_msg_box(Span::Emoji(Emoji::shortcode(emoji)));
+                UpdateResult::Task(Task::none())
+            }
+            Message::ToggleMentionPicker => {
+                self.picking_mention = !self.picking_mention;
+                UpdateResult::Task(Task::none())
+            }
+            Message::InsertMention { user_id, name } => {
+                self.picking_mention = false;
+                self.append_to_msg_box(Span::Mention {
+                    target: Mention::User(user_id),
+                    name,
+                });
+                UpdateResult::Task(Task::none())
+            }
         }
     }
 }
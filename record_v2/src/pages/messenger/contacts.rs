@@ -1,10 +1,16 @@
 use iced::{
-    Element, Task,
-    widget::{Column, Text, TextInput},
+    Alignment, Color, Element, Task,
+    widget::{Column, Text, TextInput, row},
 };
+use messenger_interface::types::{Status, UserPresence};
 
 use crate::state::MessengerRegistry;
 
+const ONLINE_COLOR: Color = Color::from_rgb(0.2, 0.7, 0.3);
+const IDLE_COLOR: Color = Color::from_rgb(0.9, 0.7, 0.1);
+const DND_COLOR: Color = Color::from_rgb(0.85, 0.2, 0.2);
+const OFFLINE_COLOR: Color = Color::from_rgb(0.5, 0.5, 0.5);
+
 #[derive(Debug, Clone, Default)]
 pub struct Contacts {
     search_input: String,
@@ -15,6 +21,28 @@ pub enum Action {
     MsgInput(String),
 }
 
+fn status_color(status: Status) -> Color {
+    match status {
+        Status::Online => ONLINE_COLOR,
+        Status::Idle => IDLE_COLOR,
+        Status::DoNotDisturb => DND_COLOR,
+        Status::Invisible | Status::Offline => OFFLINE_COLOR,
+    }
+}
+
+/// One contact: a status dot, the name and, if any, what they're up to.
+fn contact_row<'a>(name: &'a str, presence: Option<&'a UserPresence>) -> Element<'a, Action> {
+    let status = presence.map_or(Status::Offline, |presence| presence.status);
+    let activity = presence.and_then(|presence| presence.activity.as_deref());
+    let mut contact = row![Text::new("●").color(status_color(status)), Text::new(name)]
+        .spacing(6)
+        .align_y(Alignment::Center);
+    if let Some(activity) = activity {
+        contact = contact.push(Text::new(activity).size(12.0).color(OFFLINE_COLOR));
+    }
+    contact.into()
+}
+
 impl Contacts {
     pub fn get_element<'a>(&self, messengers: &'a MessengerRegistry) -> Element<'a, Action> {
         let widget = Column::new();
@@ -31,7 +59,10 @@ impl Contacts {
                                     .to_lowercase()
                                     .contains(self.search_input.to_lowercase().as_str())
                             {
-                                return Some(Text::from(i.name.as_str()));
+                                return Some(contact_row(
+                                    i.name.as_str(),
+                                    entry.data.presences.get(i.id()),
+                                ));
                             }
                             None
                         })
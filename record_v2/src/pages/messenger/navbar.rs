@@ -1,5 +1,3 @@
-use std::iter;
-
 use iced::widget::scrollable::{Direction, Scrollbar};
 use iced::widget::{Button, Column, Scrollable, image};
 use iced::{ContentFit, Element, Length};
@@ -14,6 +12,7 @@ pub struct Navbar;
 #[derive(Debug, Clone)]
 pub enum Action {
     GetDMs,
+    OpenSettings,
     GetGuild {
         id: MessengerId,
         server: Identifier<Place<House>>,
@@ -23,6 +22,7 @@ pub enum Action {
 impl Navbar {
     pub fn get_element<'a>(messengers: &'a MessengerRegistry) -> Element<'a, Action> {
         let dm_switch = Element::from(Button::new("test").on_press(Action::GetDMs));
+        let settings = Element::from(Button::new("⚙").on_press(Action::OpenSettings));
 
         let servers = messengers.iter().flat_map(|(_, entry)| {
             let id = entry.interface.id;
@@ -47,10 +47,12 @@ impl Navbar {
             })
         });
 
-        Scrollable::new(Column::with_children(iter::once(dm_switch).chain(servers)))
-            .direction(Direction::Vertical(
-                Scrollbar::default().width(0).scroller_width(0),
-            ))
-            .into()
+        Scrollable::new(Column::with_children(
+            [dm_switch, settings].into_iter().chain(servers),
+        ))
+        .direction(Direction::Vertical(
+            Scrollbar::default().width(0).scroller_width(0),
+        ))
+        .into()
     }
 }
@@ -1,15 +1,17 @@
+use std::collections::HashSet;
+
 use iced::{
     Color, ContentFit, Element, Length, Padding,
     widget::{
-        Button, Column, Row, Scrollable, Text, button, column, container, image, row,
+        Button, Column, Scrollable, Text, button, column, container, image, row, slider,
         text::Wrapping,
     },
 };
 use iced_palace::widget::ellipsized_text;
-use messenger_interface::types::{ID, Identifier, Place, Room, RoomCapabilities};
+use messenger_interface::types::{ID, Identifier, Place, ReadState, Room, RoomCapabilities};
 
 use super::PLACEHOLDER_PFP;
-use crate::state::{Call, MessengerId, MessengerRegistry};
+use crate::state::{Call, MessengerData, MessengerId, MessengerRegistry};
 
 /// Green tint marking a voice channel/button as joinable.
 fn call_button_style(_theme: &iced::Theme, _status: button::Status) -> button::Style {
@@ -19,6 +21,36 @@ fn call_button_style(_theme: &iced::Theme, _status: button::Status) -> button::S
     }
 }
 
+const MENTION_COLOR: Color = Color::from_rgb(0.85, 0.2, 0.2);
+const SPEAKING_COLOR: Color = Color::from_rgb(0.0, 0.8, 0.3);
+
+/// Green ring around the avatar of someone speaking.
+fn speaking_style(_theme: &iced::Theme) -> container::Style {
+    container::Style {
+        border: iced::Border {
+            color: SPEAKING_COLOR,
+            width: 2.0,
+            radius: 14.0.into(),
+        },
+        ..Default::default()
+    }
+}
+
+/// A room's unread marker: mentions in red, otherwise how many are unread.
+fn unread_badge<'a>(state: ReadState) -> Option<Element<'a, Action>> {
+    if state.mentions > 0 {
+        Some(
+            Text::new(format!("@{}", state.mentions))
+                .color(MENTION_COLOR)
+                .into(),
+        )
+    } else if state.unread > 0 {
+        Some(Text::new(state.unread.to_string()).into())
+    } else {
+        None
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Server {
     pub messenger_id: MessengerId,
@@ -37,12 +69,49 @@ impl Server {
 pub struct Sidebar {
     pub server_selected: Option<Server>,
     pub width: f32,
+    /// Channels whose threads are shown under them.
+    pub expanded: HashSet<ID>,
 }
 
 #[derive(Debug, Clone)]
 pub enum Action {
     Call(Identifier<Place<Room>>),
     Disconnect(Call),
+    SetCallVolume {
+        id: MessengerId,
+        volume: f32,
+    },
+    SetCallMuted {
+        id: MessengerId,
+        muted: bool,
+    },
+    SetParticipantVolume {
+        id: MessengerId,
+        user_id: ID,
+        volume: f32,
+    },
+    SetParticipantMuted {
+        id: MessengerId,
+        user_id: ID,
+        muted: bool,
+    },
+    SetSelfMute {
+        id: MessengerId,
+        muted: bool,
+    },
+    SetSelfDeaf {
+        id: MessengerId,
+        deafened: bool,
+    },
+    SetRecording {
+        id: MessengerId,
+        recording: bool,
+    },
+    /// Show or hide the threads of `channel`, loading them when shown.
+    ToggleThreads {
+        id: MessengerId,
+        channel: Identifier<Place<Room>>,
+    },
     OpenContacts,
     OpenChat {
         id: MessengerId,
@@ -55,16 +124,17 @@ impl Sidebar {
         Self {
             server_selected: None,
             width,
+            expanded: HashSet::new(),
         }
     }
 
     fn view_server_panel<'a>(
         server: Server,
         messengers: &'a MessengerRegistry,
+        expanded: &'a HashSet<ID>,
     ) -> Column<'a, Action> {
-        let guild = messengers
-            .data(server.messenger_id)
-            .and_then(|d| d.guilds.iter().find(|g| *g.id() == server.guild_id));
+        let data = messengers.data(server.messenger_id);
+        let guild = data.and_then(|d| d.guilds.iter().find(|g| *g.id() == server.guild_id));
 
         let server_name = guild.map(|g| g.name.as_str()).unwrap_or("");
         let channels = guild.and_then(|g| g.rooms.as_deref()).unwrap_or(&[]);
@@ -72,95 +142,140 @@ impl Sidebar {
         let header = container(Text::new(server_name).size(18))
             .padding(Padding::new(0.0).left(8.0).top(6.0).bottom(6.0));
 
-        let rooms_list = Column::from_iter(channels.iter().map(move |chan| {
-            let caps = chan.room_capabilities;
-
-            if caps.is_empty() {
-                return container(Text::new(chan.name.as_str()))
-                    .padding(Padding::new(0.0).left(8.0).top(6.0).bottom(2.0))
-                    .into();
+        // Threads are listed with the rooms but shown under their channel.
+        let top_level = channels.iter().filter(|chan| chan.parent.is_none());
+        let rooms_list = Column::from_iter(top_level.map(move |chan| {
+            let room = Self::view_room(server, data, chan);
+            if !chan.room_capabilities.contains(RoomCapabilities::Threads) {
+                return room;
             }
 
-            let has_text = caps.contains(RoomCapabilities::Text);
-            let has_voice = caps.contains(RoomCapabilities::Voice);
-
-            // The channel name opens the text chat when the room supports text;
-            // a voice-only channel joins its call directly from the name.
-            let name_button = Button::new(chan.name.as_str())
-                .width(Length::Fill)
-                .on_press(if has_text {
-                    Action::OpenChat {
-                        id: server.messenger_id,
-                        conversation: chan.to_owned(),
-                    }
-                } else {
-                    Action::Call(chan.clone())
+            let shown = expanded.contains(chan.id());
+            let toggle =
+                Button::new(if shown { "-" } else { "+" }).on_press(Action::ToggleThreads {
+                    id: server.messenger_id,
+                    channel: chan.clone(),
                 });
-
-            if !has_voice {
-                return name_button.into();
+            let channel = row![toggle, room];
+            if !shown {
+                return channel.into();
             }
 
-            // Voice-capable. Steam group voice rooms are BOTH text and voice, so
-            // keep the name as the text entry and add a dedicated join button;
-            // a voice-only (Discord-style) room's name already calls, so just tint it.
-            let header = if has_text {
-                row![
-                    name_button,
-                    Button::new("Call")
-                        .on_press(Action::Call(chan.clone()))
-                        .style(call_button_style),
-                ]
-            } else {
-                row![name_button.style(call_button_style)]
-            };
-
-            let participants =
-                Column::from_iter(chan.participants.as_deref().unwrap_or(&[]).iter().map(
-                    |participant| {
-                        let avatar = match participant.icon.as_ref() {
-                            Some(icon) => image(icon),
-                            None => image(PLACEHOLDER_PFP),
-                        };
-
-                        Element::from(
-                            row![
-                                container(
-                                    avatar
-                                        .height(Length::Fixed(18.0))
-                                        .width(Length::Fixed(18.0))
-                                        .content_fit(ContentFit::Cover),
-                                )
-                                .padding(Padding::new(0.0).right(6.0).left(18.0)),
-                                ellipsized_text(participant.name.as_str())
-                                    .wrapping(Wrapping::None),
-                            ]
-                            .width(Length::Fill),
-                        )
-                    },
-                ));
-
-            column![header, participants].into()
+            let threads = Column::from_iter(
+                channels
+                    .iter()
+                    .filter(|thread| thread.parent == Some(*chan.id()))
+                    .map(|thread| Self::view_room(server, data, thread)),
+            )
+            .padding(Padding::new(0.0).left(18.0));
+            column![channel, threads].into()
         }));
 
         column![header, rooms_list]
     }
 
+    /// One room of a server: its name, and for a voice room a call button
+    /// and who is in the call.
+    fn view_room<'a>(
+        server: Server,
+        data: Option<&'a MessengerData>,
+        chan: &'a Identifier<Place<Room>>,
+    ) -> Element<'a, Action> {
+        let caps = chan.room_capabilities;
+
+        // Categories, and forums whose posts are all they hold.
+        if !caps.intersects(RoomCapabilities::Text | RoomCapabilities::Voice) {
+            return container(Text::new(chan.name.as_str()))
+                .padding(Padding::new(0.0).left(8.0).top(6.0).bottom(2.0))
+                .into();
+        }
+
+        let has_text = caps.contains(RoomCapabilities::Text);
+        let has_voice = caps.contains(RoomCapabilities::Voice);
+
+        // The channel name opens the text chat when the room supports text;
+        // a voice-only channel joins its call directly from the name.
+        let mut name = row![Text::new(chan.name.as_str()).width(Length::Fill)];
+        if has_text
+            && let Some(badge) =
+                unread_badge(data.map(|d| d.read_state(*chan.id())).unwrap_or_default())
+        {
+            name = name.push(badge);
+        }
+        let name_button = Button::new(name).width(Length::Fill).on_press(if has_text {
+            Action::OpenChat {
+                id: server.messenger_id,
+                conversation: chan.to_owned(),
+            }
+        } else {
+            Action::Call(chan.clone())
+        });
+
+        if !has_voice {
+            return name_button.into();
+        }
+
+        // Voice-capable. Steam group voice rooms are BOTH text and voice, so
+        // keep the name as the text entry and add a dedicated join button;
+        // a voice-only (Discord-style) room's name already calls, so just tint it.
+        let header = if has_text {
+            row![
+                name_button,
+                Button::new("Call")
+                    .on_press(Action::Call(chan.clone()))
+                    .style(call_button_style),
+            ]
+        } else {
+            row![name_button.style(call_button_style)]
+        };
+
+        let participants =
+            Column::from_iter(chan.participants.as_deref().unwrap_or(&[]).iter().map(
+                |participant| {
+                    let avatar = match participant.icon.as_ref() {
+                        Some(icon) => image(icon),
+                        None => image(PLACEHOLDER_PFP),
+                    };
+
+                    Element::from(
+                        row![
+                            container(
+                                avatar
+                                    .height(Length::Fixed(18.0))
+                                    .width(Length::Fixed(18.0))
+                                    .content_fit(ContentFit::Cover),
+                            )
+                            .padding(Padding::new(0.0).right(6.0).left(18.0)),
+                            ellipsized_text(participant.name.as_str()).wrapping(Wrapping::None),
+                        ]
+                        .width(Length::Fill),
+                    )
+                },
+            ));
+
+        column![header, participants].into()
+    }
+
     fn view_dm_panel<'a>(messengers: &'a MessengerRegistry) -> Column<'a, Action> {
         column![Button::new("Contacts").on_press(Action::OpenContacts)].extend(
             messengers.iter().flat_map(|(_, entry)| {
                 let id = entry.interface.id;
-                entry.data.conversations.iter().map(move |conversation| {
+                let data = &entry.data;
+                data.conversations.iter().map(move |conversation| {
                     Button::new({
                         let image = match &conversation.icon {
                             Some(icon) => image(icon),
                             None => image(PLACEHOLDER_PFP),
                         };
-                        row![
+                        let mut contents = row![
                             container(image.height(Length::Fixed(28.0)))
                                 .padding(Padding::new(0.0).right(10.0)),
                             ellipsized_text(conversation.name.as_str()).wrapping(Wrapping::None)
-                        ]
+                        ];
+                        if let Some(badge) = unread_badge(data.read_state(*conversation.id())) {
+                            contents = contents.push(badge);
+                        }
+                        contents
                     })
                     .width(Length::Fill)
                     .on_press(Action::OpenChat {
@@ -175,7 +290,7 @@ impl Sidebar {
 
     pub fn view<'a>(&'a self, messengers: &'a MessengerRegistry) -> Element<'a, Action> {
         let room_list = match &self.server_selected {
-            Some(server) => Self::view_server_panel(*server, messengers),
+            Some(server) => Self::view_server_panel(*server, messengers, &self.expanded),
             None => Self::view_dm_panel(messengers),
         };
 
@@ -198,30 +313,135 @@ impl Sidebar {
                                 .is_none_or(|client_user_id| client_user_id != participant.id())
                         });
 
-                    let call_status =
-                        Element::from(Text::from(call.state_str()).width(Length::Fill));
+                    let speaking = &messenger.data.speaking;
+                    let mut call_status = Text::from(call.state_str()).width(Length::Fill);
+                    if client_user_id.is_some_and(|user_id| speaking.contains_key(user_id)) {
+                        call_status = call_status.color(SPEAKING_COLOR);
+                    }
+                    let call_status = Element::from(call_status);
                     let disconnect_button =
                         Element::from(Button::new("D").on_press(Action::Disconnect(call.clone())));
-                    let call_participents_icons =
-                        Element::from(Row::from_iter(call_participents.map(|participant| {
+                    let id = messenger.interface.id;
+                    let audio = &messenger.data.call_audio;
+                    let mute_states = &messenger.data.mute_states;
+                    // What the platform last reported, not what was last clicked.
+                    let own_state = client_user_id
+                        .and_then(|user_id| mute_states.get(user_id))
+                        .copied()
+                        .unwrap_or_default();
+                    let self_mute_button = Element::from(
+                        Button::new(if own_state.self_mute {
+                            "Mic off"
+                        } else {
+                            "Mic on"
+                        })
+                        .on_press(Action::SetSelfMute {
+                            id,
+                            muted: !own_state.self_mute,
+                        }),
+                    );
+                    let self_deaf_button = Element::from(
+                        Button::new(if own_state.self_deaf {
+                            "Undeafen"
+                        } else {
+                            "Deafen"
+                        })
+                        .on_press(Action::SetSelfDeaf {
+                            id,
+                            deafened: !own_state.self_deaf,
+                        }),
+                    );
+                    let recording = messenger.data.recording;
+                    let record_button = Element::from(
+                        Button::new(if recording { "Stop rec" } else { "Rec" }).on_press(
+                            Action::SetRecording {
+                                id,
+                                recording: !recording,
+                            },
+                        ),
+                    );
+                    // One row per person, with their own mute and volume.
+                    let call_participents_controls =
+                        Element::from(Column::from_iter(call_participents.map(|participant| {
+                            let user_id = *participant.id();
+                            let participant_audio = audio.participant(user_id);
                             let pfp_image = match participant.icon.as_ref() {
                                 Some(icon) => image(icon),
                                 None => image(PLACEHOLDER_PFP),
                             };
+                            let mut avatar = container(
+                                pfp_image
+                                    .height(Length::Fixed(24.0))
+                                    .width(Length::Fixed(24.0))
+                                    .content_fit(ContentFit::Cover),
+                            )
+                            .padding(2.0);
+                            if speaking.contains_key(&user_id) {
+                                avatar = avatar.style(speaking_style);
+                            }
+                            let mute_mark = mute_states.get(&user_id).and_then(|state| {
+                                if state.is_deafened() {
+                                    Some("deaf")
+                                } else if state.is_muted() {
+                                    Some("muted")
+                                } else {
+                                    None
+                                }
+                            });
                             Element::from(
-                                container(
-                                    pfp_image
-                                        .height(Length::Fixed(24.0))
-                                        .width(Length::Fixed(24.0))
-                                        .content_fit(ContentFit::Cover),
-                                )
-                                .padding(Padding::new(0.0).right(4.0)),
+                                row![
+                                    container(avatar).padding(Padding::new(0.0).right(2.0)),
+                                    container(
+                                        ellipsized_text(participant.name.as_str())
+                                            .wrapping(Wrapping::None)
+                                    )
+                                    .width(Length::Fill),
+                                    Text::new(mute_mark.unwrap_or_default()).size(12),
+                                    Button::new(if participant_audio.muted { "U" } else { "M" })
+                                        .on_press(Action::SetParticipantMuted {
+                                            id,
+                                            user_id,
+                                            muted: !participant_audio.muted,
+                                        }),
+                                    slider(0.0..=2.0, participant_audio.volume, move |volume| {
+                                        Action::SetParticipantVolume {
+                                            id,
+                                            user_id,
+                                            volume,
+                                        }
+                                    })
+                                    .step(0.05_f32)
+                                    .width(Length::Fixed(80.0)),
+                                ]
+                                .spacing(4)
+                                .align_y(iced::Alignment::Center),
                             )
                         })));
+                    let mute_button = Element::from(
+                        Button::new(if audio.muted { "Unmute" } else { "Mute" }).on_press(
+                            Action::SetCallMuted {
+                                id,
+                                muted: !audio.muted,
+                            },
+                        ),
+                    );
+                    let volume_slider = Element::from(
+                        slider(0.0..=2.0, audio.volume, move |volume| {
+                            Action::SetCallVolume { id, volume }
+                        })
+                        .step(0.05_f32),
+                    );
 
                     Element::from(column![
-                        row![call_status, disconnect_button],
-                        call_participents_icons,
+                        row![
+                            call_status,
+                            self_mute_button,
+                            self_deaf_button,
+                            disconnect_button
+                        ]
+                        .spacing(4),
+                        row![mute_button, volume_slider, record_button].spacing(4),
+                        call_participents_controls,
                     ])
                 });
 
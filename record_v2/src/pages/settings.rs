@@ -0,0 +1,241 @@
+use std::{error::Error, fmt::Display};
+
+use iced::{
+    Alignment,
+    widget::{Button, Container, PickList, column, row, text},
+};
+use messenger_interface::interface::{RecordingFormat, RecordingLayout, RecordingSettings};
+use simple_audio_channels::backend::{
+    DeviceInfo, InputBackend, OutputBackend, input_devices, output_devices,
+};
+use tracing::error;
+
+/// A sound card to use: whichever the host calls its default, or one by name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum DeviceChoice {
+    #[default]
+    Default,
+    Named(String),
+}
+impl Display for DeviceChoice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeviceChoice::Default => f.write_str("System default"),
+            DeviceChoice::Named(name) => f.write_str(name),
+        }
+    }
+}
+impl DeviceChoice {
+    pub fn output_backend(&self) -> OutputBackend {
+        match self {
+            DeviceChoice::Default => OutputBackend::System,
+            DeviceChoice::Named(name) => OutputBackend::Named(name.clone()),
+        }
+    }
+    pub fn input_backend(&self) -> InputBackend {
+        match self {
+            DeviceChoice::Default => InputBackend::System,
+            DeviceChoice::Named(name) => InputBackend::Named(name.clone()),
+        }
+    }
+}
+
+/// When the microphone transmits: whenever its gate hears speech, or only
+/// while [`PUSH_TO_TALK_KEY`] is held.
+///
+/// [`PUSH_TO_TALK_KEY`]: crate::PUSH_TO_TALK_KEY
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum VoiceMode {
+    #[default]
+    VoiceActivity,
+    PushToTalk,
+}
+impl Display for VoiceMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VoiceMode::VoiceActivity => f.write_str("Voice activity"),
+            VoiceMode::PushToTalk => f.write_str("Push to talk (hold `)"),
+        }
+    }
+}
+impl VoiceMode {
+    const ALL: [VoiceMode; 2] = [VoiceMode::VoiceActivity, VoiceMode::PushToTalk];
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    SelectOutput(DeviceChoice),
+    SelectInput(DeviceChoice),
+    /// The mixer moved onto the picked device.
+    OutputChanged(DeviceChoice),
+    InputChanged(DeviceChoice),
+    /// The mixer couldn't open the picked device and kept the current one.
+    DeviceFailed(String),
+    SelectVoiceMode(VoiceMode),
+    SelectRecordingFormat(RecordingFormat),
+    SelectRecordingLayout(RecordingLayout),
+    Refresh,
+    Back,
+}
+
+pub enum Action {
+    None,
+    SetOutput(DeviceChoice),
+    SetInput(DeviceChoice),
+    SetVoiceMode(VoiceMode),
+    Back,
+}
+
+#[derive(Debug, Default)]
+pub struct Settings {
+    outputs: Vec<DeviceChoice>,
+    inputs: Vec<DeviceChoice>,
+    output: DeviceChoice,
+    input: DeviceChoice,
+    voice_mode: VoiceMode,
+    recording_format: RecordingFormat,
+    recording_layout: RecordingLayout,
+    error: Option<String>,
+}
+
+impl Settings {
+    /// Re-read the host's devices, e.g. after one was plugged in.
+    pub(crate) fn refresh(&mut self) {
+        self.outputs = choices(output_devices(), "output");
+        self.inputs = choices(input_devices(), "input");
+    }
+
+    pub(crate) fn voice_mode(&self) -> VoiceMode {
+        self.voice_mode
+    }
+
+    /// Where and how the next call recording is written.
+    pub(crate) fn recording(&self) -> RecordingSettings {
+        RecordingSettings {
+            directory: "./Recordings".into(),
+            format: self.recording_format,
+            layout: self.recording_layout,
+        }
+    }
+
+    pub(crate) fn update(&mut self, message: Message) -> Action {
+        match message {
+            Message::SelectOutput(choice) if choice != self.output => {
+                return Action::SetOutput(choice);
+            }
+            Message::SelectInput(choice) if choice != self.input => {
+                return Action::SetInput(choice);
+            }
+            Message::SelectOutput(_) | Message::SelectInput(_) => {}
+            Message::OutputChanged(choice) => {
+                self.output = choice;
+                self.error = None;
+            }
+            Message::InputChanged(choice) => {
+                self.input = choice;
+                self.error = None;
+            }
+            Message::DeviceFailed(error) => {
+                self.error = Some(error);
+            }
+            Message::SelectVoiceMode(mode) => {
+                self.voice_mode = mode;
+                return Action::SetVoiceMode(mode);
+            }
+            Message::SelectRecordingFormat(format) => self.recording_format = format,
+            Message::SelectRecordingLayout(layout) => self.recording_layout = layout,
+            Message::Refresh => self.refresh(),
+            Message::Back => return Action::Back,
+        }
+
+        Action::None
+    }
+
+    pub(crate) fn view(&self) -> iced::Element<'_, Message> {
+        let width = 360.0;
+
+        let mut content = column![
+            "Audio",
+            column![
+                "Speakers",
+                PickList::new(
+                    self.outputs.as_slice(),
+                    Some(&self.output),
+                    Message::SelectOutput
+                ),
+            ]
+            .spacing(5),
+            column![
+                "Microphone",
+                PickList::new(
+                    self.inputs.as_slice(),
+                    Some(&self.input),
+                    Message::SelectInput
+                ),
+            ]
+            .spacing(5),
+            column![
+                "Transmit",
+                PickList::new(
+                    VoiceMode::ALL,
+                    Some(self.voice_mode),
+                    Message::SelectVoiceMode
+                ),
+            ]
+            .spacing(5),
+            "Recording",
+            column![
+                "Format",
+                PickList::new(
+                    RecordingFormat::ALL,
+                    Some(self.recording_format),
+                    Message::SelectRecordingFormat
+                ),
+            ]
+            .spacing(5),
+            column![
+                "Tracks",
+                PickList::new(
+                    RecordingLayout::ALL,
+                    Some(self.recording_layout),
+                    Message::SelectRecordingLayout
+                ),
+            ]
+            .spacing(5),
+            row![
+                Button::new("Refresh").on_press(Message::Refresh),
+                Button::new("Back").on_press(Message::Back),
+            ]
+            .spacing(10),
+        ];
+        if let Some(error) = &self.error {
+            content = content.push(text(error.clone()).style(text::danger));
+        }
+        let content = content
+            .width(iced::Length::Fixed(width))
+            .align_x(Alignment::Center)
+            .spacing(20);
+
+        Container::new(content)
+            .height(iced::Length::Fill)
+            .width(iced::Length::Fill)
+            .align_x(Alignment::Center)
+            .align_y(Alignment::Center)
+            .into()
+    }
+}
+
+/// The picks for one direction: the host default, then every named device.
+fn choices(devices: Result<Vec<DeviceInfo>, Box<dyn Error>>, direction: &str) -> Vec<DeviceChoice> {
+    let devices = devices.unwrap_or_else(|err| {
+        error!("Couldn't list {direction} devices: {err}");
+        Vec::new()
+    });
+    std::iter::once(DeviceChoice::Default)
+        .chain(
+            devices
+                .into_iter()
+                .map(|device| DeviceChoice::Named(device.name)),
+        )
+        .collect()
+}
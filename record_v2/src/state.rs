@@ -1,9 +1,15 @@
-use std::{collections::BTreeMap, ops::Deref, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::Deref,
+    sync::Arc,
+    time::Instant,
+};
 
 use messenger_interface::{
-    interface::{CallState, Messenger},
-    types::{House, ID, Identifier, Place, Room, User},
+    interface::{CallState, Messenger, MuteState, TYPING_INDICATOR_TIMEOUT},
+    types::{House, ID, Identifier, Place, ReadState, Room, User, UserPresence},
 };
+use simple_audio_channels::output::ChannelControl;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct MessengerId(usize);
@@ -61,12 +67,110 @@ impl Call {
     }
 }
 
+/// How a messenger's call audio plays. Every source its calls add follows
+/// these settings, and a participant's voice also follows their own.
+#[derive(Debug)]
+pub struct CallAudio {
+    pub volume: f32,
+    pub muted: bool,
+    /// Per-person settings, kept across calls so someone stays as loud as
+    /// they were set when they rejoin.
+    participants: HashMap<ID, ParticipantAudio>,
+    /// Every playing source, tagged with the participant it carries, if any.
+    sources: Vec<(Option<ID>, ChannelControl)>,
+}
+
+/// How one call participant's voice plays, on top of the call's settings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParticipantAudio {
+    pub volume: f32,
+    pub muted: bool,
+}
+
+impl Default for ParticipantAudio {
+    fn default() -> Self {
+        Self {
+            volume: 1.0,
+            muted: false,
+        }
+    }
+}
+
+impl Default for CallAudio {
+    fn default() -> Self {
+        Self {
+            volume: 1.0,
+            muted: false,
+            participants: HashMap::new(),
+            sources: Vec::new(),
+        }
+    }
+}
+
+impl CallAudio {
+    pub fn add_source(&mut self, control: ChannelControl) {
+        self.apply(None, &control);
+        self.sources.push((None, control));
+    }
+    /// Add the source carrying `user_id`'s voice.
+    pub fn add_participant_source(&mut self, user_id: ID, control: ChannelControl) {
+        self.apply(Some(user_id), &control);
+        self.sources.push((Some(user_id), control));
+    }
+    pub fn participant(&self, user_id: ID) -> ParticipantAudio {
+        self.participants.get(&user_id).copied().unwrap_or_default()
+    }
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume;
+        self.apply_all(|_| true);
+    }
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+        self.apply_all(|_| true);
+    }
+    pub fn set_participant_volume(&mut self, user_id: ID, volume: f32) {
+        self.participants.entry(user_id).or_default().volume = volume;
+        self.apply_all(|user| user == Some(user_id));
+    }
+    pub fn set_participant_muted(&mut self, user_id: ID, muted: bool) {
+        self.participants.entry(user_id).or_default().muted = muted;
+        self.apply_all(|user| user == Some(user_id));
+    }
+    /// Forget the sources once no call is left to play them.
+    pub fn clear_sources(&mut self) {
+        self.sources.clear();
+    }
+
+    fn apply(&self, user_id: Option<ID>, control: &ChannelControl) {
+        let participant = user_id
+            .map(|user_id| self.participant(user_id))
+            .unwrap_or_default();
+        control.set_volume(self.volume * participant.volume);
+        control.set_muted(self.muted || participant.muted);
+    }
+    fn apply_all(&self, affected: impl Fn(Option<ID>) -> bool) {
+        for (user_id, control) in &self.sources {
+            if affected(*user_id) {
+                self.apply(*user_id, control);
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct PendingSend {
     pub pending_id: ID,
     pub room_id: ID,
 }
 
+/// Someone typing in a room, as of the last `TypingStarted` for them there.
+#[derive(Debug, Clone)]
+pub struct Typing {
+    pub room_id: ID,
+    pub user: Identifier<User>,
+    pub since: Instant,
+}
+
 #[derive(Debug)]
 pub struct MessengerData {
     pub profile: Option<Identifier<User>>,
@@ -74,8 +178,23 @@ pub struct MessengerData {
     pub conversations: Vec<Identifier<Place<Room>>>,
     pub guilds: Vec<Identifier<Place<House>>>,
     pub calls: Vec<Call>,
+    pub call_audio: CallAudio,
     /// Tracks optimistic messages that haven't been confirmed by the server yet.
     pub pending_sends: Vec<PendingSend>,
+    /// Typing indicators, dropped by [`Self::prune_typing`] once they lapse.
+    pub typing: Vec<Typing>,
+    /// Contacts' presence by user ID; a missing entry reads as offline.
+    pub presences: HashMap<ID, UserPresence>,
+    /// The client's read state by room ID; a missing entry reads as read.
+    pub read_states: HashMap<ID, ReadState>,
+    /// Call participants speaking right now, the client included, with their
+    /// latest level in dBov when known.
+    pub speaking: HashMap<ID, Option<f32>>,
+    /// Call participants' mute and deafen flags, the client included; a
+    /// missing entry reads as neither.
+    pub mute_states: HashMap<ID, MuteState>,
+    /// A call recording runs, until stopped or the call is left.
+    pub recording: bool,
 }
 
 impl MessengerData {
@@ -86,7 +205,14 @@ impl MessengerData {
             conversations: Vec::new(),
             guilds: Vec::new(),
             calls: Vec::new(),
+            call_audio: CallAudio::default(),
             pending_sends: Vec::new(),
+            typing: Vec::new(),
+            presences: HashMap::new(),
+            read_states: HashMap::new(),
+            speaking: HashMap::new(),
+            mute_states: HashMap::new(),
+            recording: false,
         }
     }
 
@@ -136,6 +262,78 @@ impl MessengerData {
             self.conversations.insert(0, conversation);
         }
     }
+
+    /// Users currently typing in `room_id`, oldest indicator first.
+    pub fn typing_in(&self, room_id: ID) -> impl Iterator<Item = &Identifier<User>> {
+        self.typing
+            .iter()
+            .filter(move |typing| {
+                typing.room_id == room_id && typing.since.elapsed() < TYPING_INDICATOR_TIMEOUT
+            })
+            .map(|typing| &typing.user)
+    }
+
+    /// Start or refresh `user`'s indicator in `room_id`.
+    pub fn set_typing(&mut self, room_id: ID, user: Identifier<User>) {
+        self.stop_typing(room_id, *user.id());
+        self.typing.push(Typing {
+            room_id,
+            user,
+            since: Instant::now(),
+        });
+    }
+
+    pub fn stop_typing(&mut self, room_id: ID, user_id: ID) {
+        self.typing
+            .retain(|typing| !(typing.room_id == room_id && *typing.user.id() == user_id));
+    }
+
+    pub fn prune_typing(&mut self) {
+        self.typing
+            .retain(|typing| typing.since.elapsed() < TYPING_INDICATOR_TIMEOUT);
+    }
+
+    /// The newest loaded message in `room_id` that the server knows about,
+    /// i.e. skipping pending sends.
+    pub fn newest_sent(&self, room_id: ID) -> Option<ID> {
+        self.room(room_id)?
+            .messages
+            .iter()
+            .flatten()
+            .rev()
+            .map(|message| *message.id())
+            .find(|message_id| {
+                !self
+                    .pending_sends
+                    .iter()
+                    .any(|pending| pending.pending_id == *message_id)
+            })
+    }
+
+    pub fn read_state(&self, room_id: ID) -> ReadState {
+        self.read_states.get(&room_id).copied().unwrap_or_default()
+    }
+
+    /// Count one more message in `room_id` the client hasn't seen, and one
+    /// more mention if it mentions the client.
+    pub fn note_unread(&mut self, room_id: ID, mentions_me: bool) {
+        let read_state = self.read_states.entry(room_id).or_default();
+        read_state.unread += 1;
+        if mentions_me {
+            read_state.mentions += 1;
+        }
+    }
+
+    /// The client has read `room_id` up to and including `message_id`.
+    pub fn mark_read(&mut self, room_id: ID, message_id: ID) {
+        self.read_states.insert(
+            room_id,
+            ReadState {
+                last_read: Some(message_id),
+                ..Default::default()
+            },
+        );
+    }
 }
 
 pub struct MessengerEntry {